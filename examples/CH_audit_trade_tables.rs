@@ -0,0 +1,38 @@
+use chrono::NaiveDate;
+use hourglass::hourglass::clickhouse_api::{
+    data_audit::{DataAuditConfig, DataQualityPolicy},
+    queries_operations::ClickHouseClient,
+};
+
+#[tokio::main]
+async fn main()
+{
+    // 创建 ClickHouse 客户端实例
+    let client = ClickHouseClient::new();
+
+    // 定义参数
+    let exchange = "binance";
+    let instrument = "futures";
+    let start_date = NaiveDate::from_ymd_opt(2024, 5, 1).expect("Invalid start date"); // 设置开始日期
+    let end_date = NaiveDate::from_ymd_opt(2024, 5, 5).expect("Invalid end date"); // 设置结束日期
+
+    // 间隔超过 60 秒（微秒单位）或偏离滚动均价 10% 以上的成交会被标记
+    let config = DataAuditConfig::default();
+
+    let report = client.audit_trade_tables_between_dates(exchange, instrument, start_date, end_date, &config)
+                       .await
+                       .expect("Failed to audit trade tables");
+
+    // 输出结构化报告与汇总表格
+    println!("{}", report.to_json().expect("Failed to serialise report"));
+    report.summary_table().printstd();
+
+    println!("Dates without tables: {:?}", report.missing_dates);
+    for day in report.dirty_days() {
+        if let Err(e) = day.enforce(DataQualityPolicy::Refuse) {
+            println!("Backtest would refuse {}: {}", day.date, e);
+        }
+    }
+
+    // 回测时使用 `DataSource::CheckedDays(CheckedTradeDays::new(..))`，交易所会在回放的同时审计每天的数据
+}
@@ -8,6 +8,6 @@ async fn main()
     let client = Arc::new(ClickHouseClient::new());
 
     // 调用创建用户表的函数
-    let result = client.create_users_table("accounts").await;
+    let result = client.create_users_table(&client.construct_accounts_database_name()).await;
     println!("{:?}", result);
 }
@@ -94,19 +94,23 @@ use hourglass::{
         token_list::TOKEN_LIST,
         Side,
     },
+    dashboard::summary::attribution::PnLAttribution,
     hourglass::{
         account::{
             account_config::{AccountConfig, CommissionLevel, HourglassMode, MarginMode},
             account_latency::{AccountLatency, FluctuationMode},
             account_orders::AccountOrders,
+            account_vault::AccountVault,
             HourglassAccount,
         },
         clickhouse_api::{datatype::clickhouse_trade_data::MarketTrade, queries_operations::ClickHouseClient},
         hourglass_client_local_mode::HourglassClient,
+        simulation::{SimClock, SimRng},
         DataSource, HourglassExchange,
     },
     hourglass_log,
     hourglass_log::warn,
+    vault::{in_memory::InMemoryVault, Vault, VaultConfig},
     ClientExecution, Exchange,
 };
 use std::{
@@ -150,8 +154,7 @@ fn init() -> LoggerGuard
                            // thread: std::thread::current().name().map(|n| n.to_string()),
                            // file_path: record.file_static(),
                            // line: record.line(),
-                           args: format!("{}", record.args())
-                           /* module_path: record.module_path_static(), */ })
+                           args: format!("{}", record.args()) /* module_path: record.module_path_static(), */ })
         }
     }
 
@@ -206,7 +209,8 @@ async fn main()
 
     #[allow(unused)]
     let mut hourglass_client = HourglassClient { client_event_tx: client_event_tx.clone(),
-                                                 market_event_rx };
+                                                 market_event_rx,
+                                                 session_token: None };
 
     // Creating initial positions with the updated structure
     let positions = AccountPositions::init();
@@ -224,7 +228,9 @@ async fn main()
                                                    execution_mode: HourglassMode::Backtest,
                                                    max_price_deviation: 0.1,
                                                    lazy_account_positions: false,
-                                                   liquidation_threshold: 0.9 };
+                                                   liquidation_threshold: 0.9,
+                                                   vault: VaultConfig::InMemory,
+                                                   equity_sample_interval: None };
 
     // initialise the tokens possibly to be traded
     let mut instruments: Vec<Instrument> = vec![];
@@ -243,7 +249,7 @@ async fn main()
     let account_arc = Arc::new(Mutex::new(HourglassAccount { current_session: Uuid::new_v4(),
                                                              machine_id: 0,
                                                              client_trade_counter: 0.into(),
-                                                             exchange_timestamp: AtomicI64::new(0),
+                                                             exchange_timestamp: Arc::new(AtomicI64::new(0)),
                                                              config: hourglass_account_config,
                                                              account_open_book: Arc::new(RwLock::new(AccountOrders::new(0, instruments, AccountLatency { fluctuation_mode: FluctuationMode::Sine,
                                                                                                                                                          maximum: 100,
@@ -253,8 +259,14 @@ async fn main()
                                                              balances: token_balances,
                                                              positions,
                                                              exited_positions: closed_positions,
+                                                             vault: AccountVault::new(Vault::InMemory(InMemoryVault::new())),
                                                              account_event_tx,
-                                                             account_margin: Arc::new(Default::default()) }));
+                                                             account_margin: Arc::new(Default::default()),
+                                                             rng: SimRng::from_entropy(),
+                                                             clock: SimClock::System,
+                                                             equity_tracker: None,
+                                                             pnl_attribution: PnLAttribution::default(),
+                                                             publish_positions: false }));
 
     // Sample cursor building
     let clickhouse_client = ClickHouseClient::new();
@@ -2,10 +2,12 @@ use crate::{
     common::{
         account_positions::{position_id::PositionId, position_meta::PositionMeta},
         instrument::Instrument,
+        trade::ClientTrade,
         Side,
     },
     Exchange,
 };
+use chrono::Duration;
 use serde::{Deserialize, Serialize};
 
 /// NOTE 这是初步的平仓仓位数据结构设计，可能需要更改。
@@ -16,7 +18,11 @@ pub struct PositionExit
     pub instrument: Instrument,  // 静态数据
     pub side: Side,              // 静态数据
     pub position_id: PositionId, // 仓位的唯一标识符，由交易工具和进入时间戳生成。
+    #[serde(default)]
+    pub enter_ts: i64, // 开仓时间戳
     pub exit_ts: i64,            // 触发 仓位平仓的 [`Order`] 的时间戳。
+    #[serde(default)]
+    pub quantity: f64, // 平仓的数量
     // pub exit_balance: Balance,   // 在退出仓位时计算的投资组合 [`Balance`]。
     pub exit_fees: f64, // 退出仓位时产生的所有费用类型及其关联的费用。
     pub exit_fees_total: f64,
@@ -45,15 +51,17 @@ impl PositionExit
         // 计算退出时的总价值（不考虑费用）
         let exit_quantity = position_meta.current_size;
         let exit_value_gross = exit_quantity * position_meta.current_symbol_price;
-        // 计算实现盈亏 (realised_pnl)
-        let realised_pnl = (position_meta.current_symbol_price - position_meta.current_avg_price) * exit_quantity;
+        // 计算实现盈亏 (realised_pnl)，空头价格下跌时盈利
+        let realised_pnl = (position_meta.current_symbol_price - position_meta.current_avg_price) * exit_quantity * direction(position_meta.side);
 
         // 创建 `PositionExit`
         PositionExit { exchange: position_meta.exchange.clone(),       // 从 PositionMeta 获取静态数据
                        instrument: position_meta.instrument.clone(),   // 从 PositionMeta 获取静态数据
                        side: position_meta.side,                       // 从 PositionMeta 获取静态数据
                        position_id: position_meta.position_id.clone(), // 获取仓位的唯一标识符
-                       exit_ts: position_meta.update_ts,               // 应该使用推出时候的交易时间辍
+                       enter_ts: position_meta.enter_ts,
+                       exit_ts: position_meta.update_ts, // 应该使用推出时候的交易时间辍
+                       quantity: exit_quantity,
                        // exit_balance: Balance::new(exit_quantity, exit_value_gross, Some(realised_pnl)), // 计算平仓时的余额信息 NOTE 不前不确定。
                        exit_fees: position_meta.current_fees_total,                 // 使用 PositionMeta 中累计的费用
                        exit_fees_total: position_meta.current_fees_total,           // 平仓时的总费用
@@ -63,4 +71,61 @@ impl PositionExit
                        liquidation_price: position_meta.current_symbol_price,
                        exit_isolated_margin }
     }
+
+    /// 部分平仓：按平仓成交的价格与数量记录，手续费只计该笔成交的手续费。
+    ///
+    /// 部分平仓与之后的完全平仓属于同一个仓位，`position_id` 改由交易工具与成交编号生成，避免相互覆盖。
+    pub fn from_partial_close(position_meta: &PositionMeta, trade: &ClientTrade, exit_isolated_margin: Option<f64>) -> Self
+    {
+        PositionExit { exchange: position_meta.exchange,
+                       instrument: position_meta.instrument.clone(),
+                       side: position_meta.side,
+                       position_id: PositionId::new(&trade.instrument, trade.trade_id.0),
+                       enter_ts: position_meta.enter_ts,
+                       exit_ts: trade.timestamp,
+                       quantity: trade.size,
+                       exit_fees: trade.fees,
+                       exit_fees_total: trade.fees,
+                       exit_avg_price_gross: position_meta.current_avg_price_gross,
+                       exit_value_gross: trade.size * trade.price,
+                       realised_pnl: (trade.price - position_meta.current_avg_price) * trade.size * direction(position_meta.side),
+                       liquidation_price: trade.price,
+                       exit_isolated_margin }
+    }
+
+    /// 扣除费用后的盈亏。
+    pub fn net_pnl(&self) -> f64
+    {
+        self.realised_pnl - self.exit_fees_total
+    }
+
+    /// 开仓时的名义价值，即数量乘以不含费用的开仓均价。
+    pub fn entry_value(&self) -> f64
+    {
+        self.quantity.abs() * self.exit_avg_price_gross
+    }
+
+    /// 扣除费用后的盈亏相对开仓名义价值的收益率，名义价值为 0 时返回 0。
+    pub fn pnl_return(&self) -> f64
+    {
+        match self.entry_value() == 0.0 {
+            | true => 0.0,
+            | false => self.net_pnl() / self.entry_value(),
+        }
+    }
+
+    /// 从开仓到平仓的持仓时长，时间戳以毫秒计。
+    pub fn duration(&self) -> Duration
+    {
+        Duration::milliseconds(self.exit_ts - self.enter_ts)
+    }
+}
+
+/// 仓位方向对应的盈亏符号，多头为 1，空头为 -1。
+fn direction(side: Side) -> f64
+{
+    match side {
+        | Side::Buy => 1.0,
+        | Side::Sell => -1.0,
+    }
 }
@@ -1,4 +1,4 @@
-use crate::common::account_positions::{exited_position::PositionExit, position_id::PositionId};
+use crate::common::account_positions::{exited_position::PositionExit, position_id::PositionId, read_entries, replace_entries};
 use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
 use std::{collections::HashMap, hash::Hash, sync::Arc};
 use tokio::sync::RwLock;
@@ -131,6 +131,73 @@ impl AccountExitedPositions
         self.option_pos_short_call.write().await.clear();
         self.option_pos_short_put.write().await.clear();
     }
+
+    /// 全部已平仓仓位，按平仓时间排序。
+    pub async fn all_exits(&self) -> Vec<PositionExit>
+    {
+        let mut exits = Vec::new();
+        for map in [&self.margin_pos_long,
+                    &self.margin_pos_short,
+                    &self.perpetual_pos_long,
+                    &self.perpetual_pos_short,
+                    &self.futures_pos_long,
+                    &self.futures_pos_short,
+                    &self.option_pos_long_call,
+                    &self.option_pos_long_put,
+                    &self.option_pos_short_call,
+                    &self.option_pos_short_put]
+        {
+            exits.extend(map.read().await.values().cloned());
+        }
+        exits.sort_by(|a, b| a.exit_ts.cmp(&b.exit_ts).then(a.position_id.as_u64().cmp(&b.position_id.as_u64())));
+        exits
+    }
+
+    /// 当前全部已平仓仓位的快照。
+    pub async fn snapshot(&self) -> AccountExitedPositionsSnapshot
+    {
+        AccountExitedPositionsSnapshot { margin_pos_long: read_entries(&self.margin_pos_long).await,
+                                         margin_pos_short: read_entries(&self.margin_pos_short).await,
+                                         perpetual_pos_long: read_entries(&self.perpetual_pos_long).await,
+                                         perpetual_pos_short: read_entries(&self.perpetual_pos_short).await,
+                                         futures_pos_long: read_entries(&self.futures_pos_long).await,
+                                         futures_pos_short: read_entries(&self.futures_pos_short).await,
+                                         option_pos_long_call: read_entries(&self.option_pos_long_call).await,
+                                         option_pos_long_put: read_entries(&self.option_pos_long_put).await,
+                                         option_pos_short_call: read_entries(&self.option_pos_short_call).await,
+                                         option_pos_short_put: read_entries(&self.option_pos_short_put).await }
+    }
+
+    /// 用快照替换当前的已平仓仓位。
+    pub async fn restore(&self, snapshot: AccountExitedPositionsSnapshot)
+    {
+        replace_entries(&self.margin_pos_long, snapshot.margin_pos_long).await;
+        replace_entries(&self.margin_pos_short, snapshot.margin_pos_short).await;
+        replace_entries(&self.perpetual_pos_long, snapshot.perpetual_pos_long).await;
+        replace_entries(&self.perpetual_pos_short, snapshot.perpetual_pos_short).await;
+        replace_entries(&self.futures_pos_long, snapshot.futures_pos_long).await;
+        replace_entries(&self.futures_pos_short, snapshot.futures_pos_short).await;
+        replace_entries(&self.option_pos_long_call, snapshot.option_pos_long_call).await;
+        replace_entries(&self.option_pos_long_put, snapshot.option_pos_long_put).await;
+        replace_entries(&self.option_pos_short_call, snapshot.option_pos_short_call).await;
+        replace_entries(&self.option_pos_short_put, snapshot.option_pos_short_put).await;
+    }
+}
+
+/// [`AccountExitedPositions`] 的可序列化快照，见 [`AccountExitedPositions::snapshot`]。
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AccountExitedPositionsSnapshot
+{
+    pub margin_pos_long: Vec<(PositionId, PositionExit)>,
+    pub margin_pos_short: Vec<(PositionId, PositionExit)>,
+    pub perpetual_pos_long: Vec<(PositionId, PositionExit)>,
+    pub perpetual_pos_short: Vec<(PositionId, PositionExit)>,
+    pub futures_pos_long: Vec<(PositionId, PositionExit)>,
+    pub futures_pos_short: Vec<(PositionId, PositionExit)>,
+    pub option_pos_long_call: Vec<(PositionId, PositionExit)>,
+    pub option_pos_long_put: Vec<(PositionId, PositionExit)>,
+    pub option_pos_short_call: Vec<(PositionId, PositionExit)>,
+    pub option_pos_short_put: Vec<(PositionId, PositionExit)>,
 }
 
 impl Serialize for AccountExitedPositions
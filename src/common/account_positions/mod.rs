@@ -3,10 +3,12 @@ use crate::{
         account_positions::{
             future::{FuturePosition, FuturePositionConfig},
             leveraged_token::{LeveragedTokenPosition, LeveragedTokenPositionConfig},
-            option::{OptionPosition, OptionPositionConfig},
+            option::{OptionKind, OptionPosition, OptionPositionConfig},
             perpetual::{PerpetualPosition, PerpetualPositionConfig},
+            position_meta::PositionMeta,
         },
         instrument::{kind::InstrumentKind, Instrument},
+        Side,
     },
     hourglass::config_request::ConfigurationRequest,
 };
@@ -33,6 +35,20 @@ pub enum Position
     Option(OptionPosition),
 }
 
+impl Position
+{
+    /// 各类仓位共有的元数据。
+    pub fn meta(&self) -> &PositionMeta
+    {
+        match self {
+            | Position::Perpetual(position) => &position.meta,
+            | Position::LeveragedToken(position) => &position.meta,
+            | Position::Future(position) => &position.meta,
+            | Position::Option(position) => &position.meta,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AccountPositions
 {
@@ -223,6 +239,167 @@ impl AccountPositions
                option_pos_short_call_config: Arc::new(RwLock::new(HashMap::new())),
                option_pos_short_put_config: Arc::new(RwLock::new(HashMap::new())) }
     }
+
+    /// 由仓位快照重建 `AccountPositions`，多空方向取自 `PositionMeta::side`，期权按 `OptionPosition::kind` 区分看涨与看跌。
+    pub async fn from_positions(positions: Vec<Position>) -> Self
+    {
+        let account_positions = Self::init();
+        for position in positions {
+            match position {
+                | Position::LeveragedToken(pos) => {
+                    let map = if pos.meta.side == Side::Buy {
+                        &account_positions.margin_pos_long
+                    }
+                    else {
+                        &account_positions.margin_pos_short
+                    };
+                    map.write().await.insert(pos.meta.instrument.clone(), pos);
+                }
+                | Position::Perpetual(pos) => {
+                    let map = if pos.meta.side == Side::Buy {
+                        &account_positions.perpetual_pos_long
+                    }
+                    else {
+                        &account_positions.perpetual_pos_short
+                    };
+                    map.write().await.insert(pos.meta.instrument.clone(), pos);
+                }
+                | Position::Future(pos) => {
+                    let map = if pos.meta.side == Side::Buy {
+                        &account_positions.futures_pos_long
+                    }
+                    else {
+                        &account_positions.futures_pos_short
+                    };
+                    map.write().await.insert(pos.meta.instrument.clone(), pos);
+                }
+                | Position::Option(pos) => {
+                    let map = match (pos.meta.side, pos.kind) {
+                        | (Side::Buy, OptionKind::Call) => &account_positions.option_pos_long_call,
+                        | (Side::Buy, OptionKind::Put) => &account_positions.option_pos_long_put,
+                        | (Side::Sell, OptionKind::Call) => &account_positions.option_pos_short_call,
+                        | (Side::Sell, OptionKind::Put) => &account_positions.option_pos_short_put,
+                    };
+                    map.write().await.insert(pos.meta.instrument.clone(), pos);
+                }
+            }
+        }
+        account_positions
+    }
+
+    /// 当前所有仓位的快照，按保证金、永续、期货、期权的顺序排列。
+    ///
+    /// 仓位表以 `Instrument` 为键，无法直接序列化为 JSON 对象，对外展示时使用该方法。
+    pub async fn all_positions(&self) -> Vec<Position>
+    {
+        let mut positions = Vec::new();
+        for map in [&self.margin_pos_long, &self.margin_pos_short] {
+            positions.extend(map.read().await.values().cloned().map(Position::LeveragedToken));
+        }
+        for map in [&self.perpetual_pos_long, &self.perpetual_pos_short] {
+            positions.extend(map.read().await.values().cloned().map(Position::Perpetual));
+        }
+        for map in [&self.futures_pos_long, &self.futures_pos_short] {
+            positions.extend(map.read().await.values().cloned().map(Position::Future));
+        }
+        for map in [&self.option_pos_long_call, &self.option_pos_long_put, &self.option_pos_short_call, &self.option_pos_short_put] {
+            positions.extend(map.read().await.values().cloned().map(Position::Option));
+        }
+        positions
+    }
+
+    /// 当前全部仓位与仓位配置的快照。
+    pub async fn snapshot(&self) -> AccountPositionsSnapshot
+    {
+        AccountPositionsSnapshot { margin_pos_long: read_entries(&self.margin_pos_long).await,
+                                   margin_pos_short: read_entries(&self.margin_pos_short).await,
+                                   perpetual_pos_long: read_entries(&self.perpetual_pos_long).await,
+                                   perpetual_pos_short: read_entries(&self.perpetual_pos_short).await,
+                                   futures_pos_long: read_entries(&self.futures_pos_long).await,
+                                   futures_pos_short: read_entries(&self.futures_pos_short).await,
+                                   option_pos_long_call: read_entries(&self.option_pos_long_call).await,
+                                   option_pos_long_put: read_entries(&self.option_pos_long_put).await,
+                                   option_pos_short_call: read_entries(&self.option_pos_short_call).await,
+                                   option_pos_short_put: read_entries(&self.option_pos_short_put).await,
+                                   margin_pos_long_config: read_entries(&self.margin_pos_long_config).await,
+                                   margin_pos_short_config: read_entries(&self.margin_pos_short_config).await,
+                                   perpetual_pos_long_config: read_entries(&self.perpetual_pos_long_config).await,
+                                   perpetual_pos_short_config: read_entries(&self.perpetual_pos_short_config).await,
+                                   futures_pos_long_config: read_entries(&self.futures_pos_long_config).await,
+                                   futures_pos_short_config: read_entries(&self.futures_pos_short_config).await,
+                                   option_pos_long_call_config: read_entries(&self.option_pos_long_call_config).await,
+                                   option_pos_long_put_config: read_entries(&self.option_pos_long_put_config).await,
+                                   option_pos_short_call_config: read_entries(&self.option_pos_short_call_config).await,
+                                   option_pos_short_put_config: read_entries(&self.option_pos_short_put_config).await }
+    }
+
+    /// 用快照替换当前的仓位与仓位配置。
+    pub async fn restore(&self, snapshot: AccountPositionsSnapshot)
+    {
+        replace_entries(&self.margin_pos_long, snapshot.margin_pos_long).await;
+        replace_entries(&self.margin_pos_short, snapshot.margin_pos_short).await;
+        replace_entries(&self.perpetual_pos_long, snapshot.perpetual_pos_long).await;
+        replace_entries(&self.perpetual_pos_short, snapshot.perpetual_pos_short).await;
+        replace_entries(&self.futures_pos_long, snapshot.futures_pos_long).await;
+        replace_entries(&self.futures_pos_short, snapshot.futures_pos_short).await;
+        replace_entries(&self.option_pos_long_call, snapshot.option_pos_long_call).await;
+        replace_entries(&self.option_pos_long_put, snapshot.option_pos_long_put).await;
+        replace_entries(&self.option_pos_short_call, snapshot.option_pos_short_call).await;
+        replace_entries(&self.option_pos_short_put, snapshot.option_pos_short_put).await;
+        replace_entries(&self.margin_pos_long_config, snapshot.margin_pos_long_config).await;
+        replace_entries(&self.margin_pos_short_config, snapshot.margin_pos_short_config).await;
+        replace_entries(&self.perpetual_pos_long_config, snapshot.perpetual_pos_long_config).await;
+        replace_entries(&self.perpetual_pos_short_config, snapshot.perpetual_pos_short_config).await;
+        replace_entries(&self.futures_pos_long_config, snapshot.futures_pos_long_config).await;
+        replace_entries(&self.futures_pos_short_config, snapshot.futures_pos_short_config).await;
+        replace_entries(&self.option_pos_long_call_config, snapshot.option_pos_long_call_config).await;
+        replace_entries(&self.option_pos_long_put_config, snapshot.option_pos_long_put_config).await;
+        replace_entries(&self.option_pos_short_call_config, snapshot.option_pos_short_call_config).await;
+        replace_entries(&self.option_pos_short_put_config, snapshot.option_pos_short_put_config).await;
+    }
+}
+
+/// [`AccountPositions`] 的可序列化快照，各仓位表以 `(Instrument, 值)` 列表保存，见 [`AccountPositions::snapshot`]。
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AccountPositionsSnapshot
+{
+    pub margin_pos_long: Vec<(Instrument, LeveragedTokenPosition)>,
+    pub margin_pos_short: Vec<(Instrument, LeveragedTokenPosition)>,
+    pub perpetual_pos_long: Vec<(Instrument, PerpetualPosition)>,
+    pub perpetual_pos_short: Vec<(Instrument, PerpetualPosition)>,
+    pub futures_pos_long: Vec<(Instrument, FuturePosition)>,
+    pub futures_pos_short: Vec<(Instrument, FuturePosition)>,
+    pub option_pos_long_call: Vec<(Instrument, OptionPosition)>,
+    pub option_pos_long_put: Vec<(Instrument, OptionPosition)>,
+    pub option_pos_short_call: Vec<(Instrument, OptionPosition)>,
+    pub option_pos_short_put: Vec<(Instrument, OptionPosition)>,
+    pub margin_pos_long_config: Vec<(Instrument, LeveragedTokenPositionConfig)>,
+    pub margin_pos_short_config: Vec<(Instrument, LeveragedTokenPositionConfig)>,
+    pub perpetual_pos_long_config: Vec<(Instrument, PerpetualPositionConfig)>,
+    pub perpetual_pos_short_config: Vec<(Instrument, PerpetualPositionConfig)>,
+    pub futures_pos_long_config: Vec<(Instrument, FuturePositionConfig)>,
+    pub futures_pos_short_config: Vec<(Instrument, FuturePositionConfig)>,
+    pub option_pos_long_call_config: Vec<(Instrument, OptionPositionConfig)>,
+    pub option_pos_long_put_config: Vec<(Instrument, OptionPositionConfig)>,
+    pub option_pos_short_call_config: Vec<(Instrument, OptionPositionConfig)>,
+    pub option_pos_short_put_config: Vec<(Instrument, OptionPositionConfig)>,
+}
+
+/// 读出一张仓位表的全部条目，按键排序，相同的仓位总是得到相同的快照。
+pub(crate) async fn read_entries<K, V>(map: &RwLock<HashMap<K, V>>) -> Vec<(K, V)>
+    where K: Clone + PartialOrd,
+          V: Clone
+{
+    let mut entries = map.read().await.iter().map(|(key, value)| (key.clone(), value.clone())).collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    entries
+}
+
+/// 用 `entries` 替换一张仓位表的内容，其他持有同一个 `Arc` 的地方也能看到新的内容。
+pub(crate) async fn replace_entries<K, V>(map: &RwLock<HashMap<K, V>>, entries: Vec<(K, V)>)
+    where K: Eq + Hash
+{
+    *map.write().await = entries.into_iter().collect();
 }
 
 #[derive(Clone, PartialOrd, Debug, PartialEq, Deserialize, Serialize)]
@@ -238,3 +415,32 @@ pub enum PositionMarginMode
     Cross,
     Isolated,
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::instrument::kind::InstrumentKind,
+        test_utils::{create_test_instrument, create_test_perpetual_position},
+    };
+
+    #[tokio::test]
+    async fn from_positions_should_route_options_by_kind_and_side()
+    {
+        let option = |side: Side, kind: OptionKind| {
+            let mut meta = create_test_perpetual_position(create_test_instrument(InstrumentKind::CryptoOption)).meta;
+            meta.side = side;
+            Position::Option(OptionPosition { meta, kind })
+        };
+        let positions = AccountPositions::from_positions(vec![option(Side::Buy, OptionKind::Put), option(Side::Sell, OptionKind::Call)]).await;
+        assert_eq!(positions.option_pos_long_put.read().await.len(), 1);
+        assert_eq!(positions.option_pos_short_call.read().await.len(), 1);
+        assert!(positions.option_pos_long_call.read().await.is_empty());
+        assert!(positions.option_pos_short_put.read().await.is_empty());
+
+        // 经过快照往返后仍在原来的仓位表中
+        let restored = AccountPositions::from_positions(positions.all_positions().await).await;
+        assert_eq!(restored.snapshot().await, positions.snapshot().await);
+    }
+}
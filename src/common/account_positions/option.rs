@@ -5,10 +5,22 @@ use crate::{
     hourglass::config_request::ConfigurationRequest,
 };
 
+/// 期权的类型，决定仓位放入看涨还是看跌期权的仓位表。
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionKind
+{
+    #[default]
+    Call,
+    Put,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct OptionPosition
 {
     pub meta: PositionMeta,
+    #[serde(default)]
+    pub kind: OptionKind, // 早期的快照中没有该字段，按看涨期权读取
 }
 
 #[allow(dead_code)]
@@ -44,6 +44,12 @@ impl Balance
 
     /// 对这个[`Balance`]应用一个[`BalanceDelta`]。
     pub fn apply(&mut self, delta: BalanceDelta) -> Result<(), &'static str>
+    {
+        self.apply_at(delta, Utc::now()) // NOTE not sure about this timestamp, could err.
+    }
+
+    /// 与 [`Balance::apply`] 相同，更新时间由调用方提供。
+    pub fn apply_at(&mut self, delta: BalanceDelta, time: DateTime<Utc>) -> Result<(), &'static str>
     {
         // 确保应用 BalanceDelta 后不会使 total 或 available 余额为负数。
         if self.total + delta.total < 0.0 || self.available + delta.available < 0.0 {
@@ -51,7 +57,7 @@ impl Balance
         }
         self.total += delta.total;
         self.available += delta.available;
-        self.time = Utc::now(); // NOTE not sure about this timestamp, could err.
+        self.time = time;
         Ok(())
     }
 }
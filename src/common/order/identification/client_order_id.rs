@@ -61,6 +61,12 @@ impl ClientOrderId
         }
     }
 
+    /// 第一个 `-` 之前的部分作为策略标签，例如 `mm-000123` 的标签为 `mm`，没有 `-` 时返回 `None`。
+    pub fn strategy_tag(&self) -> Option<&str>
+    {
+        self.0.split_once('-').map(|(tag, _)| tag).filter(|tag| !tag.is_empty())
+    }
+
     // 验证 ID 格式
     pub(crate) fn validate_id_format(id: &str) -> bool
     {
@@ -116,4 +122,13 @@ mod tests
         assert!(!ClientOrderId::validate_id_format("abc!@#")); // 包含不允许的字符
         assert!(!ClientOrderId::validate_id_format(&"a".repeat(21))); // 太长
     }
+
+    #[test]
+    fn strategy_tag_should_be_prefix_before_first_hyphen()
+    {
+        assert_eq!(ClientOrderId("mm-000123".into()).strategy_tag(), Some("mm"));
+        assert_eq!(ClientOrderId("A1_B2-C3-D4".into()).strategy_tag(), Some("A1_B2"));
+        assert_eq!(ClientOrderId("validID123".into()).strategy_tag(), None);
+        assert_eq!(ClientOrderId("-abcdef".into()).strategy_tag(), None);
+    }
 }
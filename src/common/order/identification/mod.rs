@@ -68,7 +68,12 @@ impl OrderId
     {
         // 将随机组件降低到更低的位置
         let random_component: u64 = rand::thread_rng().gen_range(0..8192);
+        Self::with_random_component(timestamp, machine_id, counter, random_component)
+    }
 
+    /// 与 [`OrderId::new`] 相同，随机组件由调用方提供，用于可重复的回测。
+    pub fn with_random_component(timestamp: u64, machine_id: u64, counter: u64, random_component: u64) -> Self
+    {
         // 生成唯一的OrderId
         let id = ((timestamp & 0x1FFFFFFFFFF) << 23) | ((machine_id & 0x3FF) << 13) | ((counter & 0x3FF) << 3) | (random_component & 0x7);
 
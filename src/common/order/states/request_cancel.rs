@@ -1,4 +1,4 @@
-use crate::common::order::identification::OrderId;
+use crate::common::order::{identification::OrderId, states::open::Open, Order};
 use serde::{Deserialize, Serialize};
 
 /// `RequestCancel` 结构体表示一个取消订单的请求。
@@ -21,3 +21,18 @@ impl<Id> From<Id> for RequestCancel where Id: Into<OrderId>
         Self { id: Some(id.into()) }
     }
 }
+
+impl From<&Order<Open>> for Order<RequestCancel>
+{
+    /// 根据已挂出的订单构造撤单请求，沿用原订单的交易工具、方向和客户端订单ID。
+    fn from(order: &Order<Open>) -> Self
+    {
+        Self { instruction: order.instruction,
+               exchange: order.exchange,
+               instrument: order.instrument.clone(),
+               timestamp: order.timestamp,
+               cid: order.cid.clone(),
+               side: order.side,
+               state: RequestCancel { id: Some(order.state.id.clone()) } }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::dashboard::{
+    metrics::{
+        drawdown::{AvgDrawdown, Drawdown, MaxDrawdown},
+        ratio::{CalmarRatio, Ratio, SharpeRatio, SortinoRatio},
+        EquitySnapshot,
+    },
+    summary::pnl::PnLReturnSummary,
+};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+
+/// 按固定的模拟时间间隔对账户总权益采样，得到权益曲线，并随每个采样点实时更新回撤与风险调整收益指标。
+///
+/// 相邻两个采样点之间的权益变化率视为一次收益，用于计算 [`SharpeRatio`]、[`SortinoRatio`] 与 [`CalmarRatio`]。
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct EquityTracker
+{
+    pub interval_millis: i64,        // 采样间隔，以交易所时间计
+    pub curve: Vec<EquitySnapshot>,  // 权益曲线，按时间顺序排列
+    pub current_drawdown: Drawdown,  // 进行中的回撤
+    pub max_drawdown: MaxDrawdown,   // 包括进行中的回撤在内的最大回撤
+    pub avg_drawdown: AvgDrawdown,   // 已结束回撤的平均值
+    pub returns: PnLReturnSummary,   // 每个采样间隔的收益率
+    pub sharpe_ratio: SharpeRatio,   // 无风险收益率为 0
+    pub sortino_ratio: SortinoRatio, // 无风险收益率为 0
+    pub calmar_ratio: CalmarRatio,   // 无风险收益率为 0
+}
+
+impl EquityTracker
+{
+    pub fn new(interval_millis: i64) -> Self
+    {
+        Self { interval_millis,
+               curve: Vec::new(),
+               current_drawdown: Drawdown::default(),
+               max_drawdown: MaxDrawdown::init(),
+               avg_drawdown: AvgDrawdown::init(),
+               returns: PnLReturnSummary::default(),
+               sharpe_ratio: SharpeRatio::init(0.0),
+               sortino_ratio: SortinoRatio::init(0.0),
+               calmar_ratio: CalmarRatio::init(0.0) }
+    }
+
+    /// 距上一个采样点已经过了至少一个采样间隔时返回 `true`，尚未采样时总是返回 `true`。
+    pub fn is_due(&self, time_millis: i64) -> bool
+    {
+        match self.curve.last() {
+            | Some(last) => time_millis - last.time.timestamp_millis() >= self.interval_millis,
+            | None => true,
+        }
+    }
+
+    /// 到了采样时间时记录 `snapshot` 并更新各项指标，返回是否记录。
+    pub fn record(&mut self, snapshot: EquitySnapshot) -> bool
+    {
+        if !self.is_due(snapshot.time.timestamp_millis()) {
+            return false;
+        }
+
+        match self.curve.last().copied() {
+            | None => {
+                self.current_drawdown = Drawdown::init(snapshot.total);
+                self.current_drawdown.start_time = snapshot.time;
+                self.returns.time = snapshot.time;
+            }
+            | Some(previous) => {
+                if previous.total != 0.0 {
+                    self.returns.update_return(snapshot.time, (snapshot.total - previous.total) / previous.total);
+                }
+                if let Some(ended_drawdown) = self.current_drawdown.update(snapshot) {
+                    self.avg_drawdown.update(&ended_drawdown);
+                }
+                self.max_drawdown.update(&self.current_drawdown);
+                self.sharpe_ratio.update(&self.returns);
+                self.sortino_ratio.update(&self.returns);
+                self.calmar_ratio.update(&self.returns, self.max_drawdown.drawdown.drawdown);
+            }
+        }
+        self.curve.push(snapshot);
+        true
+    }
+
+    pub fn latest(&self) -> Option<&EquitySnapshot>
+    {
+        self.curve.last()
+    }
+
+    /// 第一个与最后一个采样点之间的时间跨度。
+    pub fn duration(&self) -> Duration
+    {
+        match (self.curve.first(), self.curve.last()) {
+            | (Some(first), Some(last)) => last.time.signed_duration_since(first.time),
+            | _ => Duration::zero(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use chrono::DateTime;
+
+    fn snapshot(time_millis: i64, total: f64) -> EquitySnapshot
+    {
+        EquitySnapshot { time: DateTime::from_timestamp_millis(time_millis).unwrap(),
+                         total }
+    }
+
+    #[test]
+    fn tracker_should_sample_at_interval_and_update_metrics()
+    {
+        let day = 86_400_000;
+        let mut tracker = EquityTracker::new(day);
+        assert!(tracker.record(snapshot(0, 100.0)));
+        // 未到采样间隔的权益变化被忽略
+        assert!(!tracker.record(snapshot(day / 2, 50.0)));
+        assert!(tracker.record(snapshot(day, 110.0)));
+        assert!(tracker.record(snapshot(2 * day, 99.0)));
+        assert!(tracker.record(snapshot(3 * day, 88.0)));
+        assert!(tracker.record(snapshot(4 * day, 121.0)));
+
+        assert_eq!(tracker.curve.len(), 5);
+        assert_eq!(tracker.duration(), Duration::days(4));
+        assert_eq!(tracker.returns.total.count, 4);
+        assert_eq!(tracker.returns.losses.count, 2);
+        assert!((tracker.returns.trades_per_day - 1.0).abs() < 1e-9);
+
+        // 110 -> 88 的回撤在权益回到 121 时结束，回撤以负数表示
+        assert!((tracker.max_drawdown.drawdown.drawdown + 0.2).abs() < 1e-9);
+        assert_eq!(tracker.max_drawdown.drawdown.duration, Duration::days(1));
+        assert_eq!(tracker.avg_drawdown.count, 1);
+        assert!(tracker.current_drawdown.is_waiting_for_peak());
+
+        assert!(tracker.sharpe_ratio.ratio() > 0.0);
+        assert!(tracker.sortino_ratio.ratio() > 0.0);
+        assert!((tracker.calmar_ratio.ratio() - tracker.returns.total.mean / 0.2).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,622 @@
+use crate::{
+    common::{
+        account_positions::exited_position::PositionExit,
+        event::{AccountEvent, AccountEventKind},
+        instrument::Instrument,
+        order::{
+            identification::{client_order_id::ClientOrderId, OrderId},
+            order_instructions::OrderInstruction,
+            Order,
+        },
+        trade::ClientTrade,
+        Side,
+    },
+    dashboard::{
+        metrics::{drawdown::drawdowns, ratio::Ratio, EquitySnapshot},
+        se_f64_or_sentinel,
+        summary::trading::{self, TradingSummary},
+    },
+    error::ExchangeError,
+};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, collections::BTreeMap, fmt::Write as _, fs, path::Path};
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 260.0;
+const CHART_PADDING: (f64, f64, f64, f64) = (30.0, 20.0, 30.0, 80.0); // 上、右、下、左
+const CHART_COLOURS: [&str; 6] = ["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b"];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus
+{
+    Open,
+    Cancelled,
+    PartiallyFilled,
+    Filled,
+}
+
+impl OrderStatus
+{
+    fn as_str(&self) -> &'static str
+    {
+        match self {
+            | OrderStatus::Open => "open",
+            | OrderStatus::Cancelled => "cancelled",
+            | OrderStatus::PartiallyFilled => "partially_filled",
+            | OrderStatus::Filled => "filled",
+        }
+    }
+}
+
+/// 订单的一次状态变化，取自账户发出的订单事件。撤单事件只带订单 ID，价格与数量为空。
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct OrderRecord
+{
+    pub exchange_timestamp: i64, // 事件发出时的交易所时间
+    pub timestamp: i64,          // 订单的时间戳
+    pub status: OrderStatus,
+    pub order_id: OrderId,
+    pub cid: Option<ClientOrderId>,
+    pub instrument: Instrument,
+    pub side: Side,
+    pub instruction: OrderInstruction,
+    pub price: Option<f64>,
+    pub size: Option<f64>,
+    pub filled_quantity: Option<f64>,
+}
+
+impl OrderRecord
+{
+    fn new<State>(exchange_timestamp: i64, order: &Order<State>, status: OrderStatus, order_id: &OrderId) -> Self
+    {
+        Self { exchange_timestamp,
+               timestamp: order.timestamp,
+               status,
+               order_id: order_id.clone(),
+               cid: order.cid.clone(),
+               instrument: order.instrument.clone(),
+               side: order.side,
+               instruction: order.instruction,
+               price: None,
+               size: None,
+               filled_quantity: None }
+    }
+
+    fn with_quantities(self, price: f64, size: f64, filled_quantity: Option<f64>) -> Self
+    {
+        Self { price: Some(price),
+               size: Some(size),
+               filled_quantity,
+               ..self }
+    }
+}
+
+/// 一次回测的全部结果：成交、订单、已平仓仓位、权益曲线与汇总指标。
+///
+/// 成交与订单来自账户事件，可以在回测过程中逐个 [`record`](BacktestReport::record)，也可以在结束后从审计日志中读取。
+/// 结果可以导出为 JSON、CSV 以及一个不依赖任何外部资源的 HTML 报告。
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct BacktestReport
+{
+    pub config: trading::Config,
+    pub fills: Vec<ClientTrade>,
+    pub orders: Vec<OrderRecord>,
+    pub exited_positions: Vec<PositionExit>, // 按平仓时间排序
+    pub equity_curve: Vec<EquitySnapshot>,   // 未启用权益采样时为空
+    pub summary: TradingSummary,
+}
+
+/// 写入 JSON 时附带按名称排列的汇总指标。
+#[derive(Serialize)]
+struct JsonReport<'a>
+{
+    #[serde(flatten)]
+    report: &'a BacktestReport,
+    metrics: BTreeMap<&'static str, JsonMetric>,
+}
+
+/// 非有限的指标以字符串写出，见 [`se_f64_or_sentinel`]。
+#[derive(Serialize)]
+struct JsonMetric(#[serde(serialize_with = "se_f64_or_sentinel")] f64);
+
+impl BacktestReport
+{
+    pub fn new(config: trading::Config, exited_positions: Vec<PositionExit>, equity_curve: Vec<EquitySnapshot>) -> Self
+    {
+        let summary = TradingSummary::generate(config, &exited_positions);
+        Self { config,
+               fills: Vec::new(),
+               orders: Vec::new(),
+               exited_positions,
+               equity_curve,
+               summary }
+    }
+
+    /// 记录账户事件中的成交与订单状态变化，其余事件被忽略。
+    pub fn record(&mut self, event: &AccountEvent)
+    {
+        let timestamp = event.exchange_timestamp;
+        match &event.kind {
+            | AccountEventKind::Trade(trade) => self.fills.push(trade.clone()),
+            | AccountEventKind::OrdersOpen(orders) => {
+                for order in orders {
+                    let record = OrderRecord::new(timestamp, order, OrderStatus::Open, &order.state.id);
+                    self.orders.push(record.with_quantities(order.state.price, order.state.size, Some(order.state.filled_quantity)));
+                }
+            }
+            | AccountEventKind::OrdersCancelled(orders) => {
+                for order in orders {
+                    self.orders.push(OrderRecord::new(timestamp, order, OrderStatus::Cancelled, &order.state.id));
+                }
+            }
+            | AccountEventKind::OrdersPartiallyFilled(orders) => {
+                for order in orders {
+                    let record = OrderRecord::new(timestamp, order, OrderStatus::PartiallyFilled, &order.state.id);
+                    self.orders.push(record.with_quantities(order.state.price, order.state.size, None));
+                }
+            }
+            | AccountEventKind::OrdersFilled(orders) => {
+                for order in orders {
+                    let record = OrderRecord::new(timestamp, order, OrderStatus::Filled, &order.state.id);
+                    self.orders.push(record.with_quantities(order.state.price, order.state.size, Some(order.state.size)));
+                }
+            }
+            | _ => {}
+        }
+    }
+
+    /// 汇总指标，按展示顺序排列。回撤与比率均以小数表示。
+    ///
+    /// 最大回撤与 Calmar 比率一样取自 [`TearSheet`](trading::TearSheet)，按平仓后的权益计算；
+    /// [`drawdown_series`](Self::drawdown_series) 按权益曲线计算，启用权益采样时还包含持仓期间的浮动盈亏。
+    pub fn metrics(&self) -> Vec<(&'static str, f64)>
+    {
+        let tear_sheet = &self.summary.tear_sheet;
+        let profit_loss = &self.summary.profit_loss;
+        let equity = self.equity_series();
+        vec![("starting_equity", self.config.starting_equity),
+             ("ending_equity", equity.last().map_or(self.config.starting_equity, |(_, total)| *total)),
+             ("net_pnl", profit_loss.total_pnl),
+             ("fees", self.fills.iter().map(|fill| fill.fees).sum()),
+             ("fills", self.fills.len() as f64),
+             ("trades", profit_loss.trades as f64),
+             ("wins", profit_loss.wins as f64),
+             ("losses", profit_loss.losses as f64),
+             ("win_rate", tear_sheet.win_rate),
+             ("profit_factor", tear_sheet.profit_factor),
+             ("avg_win", tear_sheet.avg_win),
+             ("avg_loss", tear_sheet.avg_loss),
+             ("expectancy", tear_sheet.expectancy),
+             ("trades_per_day", tear_sheet.trades_per_day),
+             ("exposure_hours", tear_sheet.exposure.num_seconds() as f64 / 3600.0),
+             ("exposure_ratio", tear_sheet.exposure_ratio),
+             ("sharpe_ratio", tear_sheet.sharpe_ratio.daily()),
+             ("sortino_ratio", tear_sheet.sortino_ratio.daily()),
+             ("calmar_ratio", tear_sheet.calmar_ratio.daily()),
+             ("annual_sharpe_ratio", tear_sheet.sharpe_ratio.annual(tear_sheet.trading_days_per_year)),
+             ("max_drawdown", tear_sheet.max_drawdown),]
+    }
+
+    /// 以毫秒时间戳表示的权益曲线。没有采样时用初始权益加上各仓位平仓时的扣费后盈亏代替。
+    pub fn equity_series(&self) -> Vec<(i64, f64)>
+    {
+        if !self.equity_curve.is_empty() {
+            return self.equity_curve.iter().map(|snapshot| (snapshot.time.timestamp_millis(), snapshot.total)).collect();
+        }
+
+        let mut total = self.config.starting_equity;
+        let start = self.exited_positions.first().map(|position| (position.enter_ts, total));
+        start.into_iter()
+             .chain(self.exited_positions.iter().map(|position| {
+                                                    total += position.net_pnl();
+                                                    (position.exit_ts, total)
+                                                }))
+             .collect()
+    }
+
+    /// 每个权益点相对此前最高点的回撤，为 0 或负数。
+    pub fn drawdown_series(&self) -> Vec<(i64, f64)>
+    {
+        drawdowns(self.equity_series())
+    }
+
+    /// 按成交累计的各交易工具净持仓数量，买入为正、卖出为负，以阶梯形式给出。
+    pub fn position_size_series(&self) -> BTreeMap<String, Vec<(i64, f64)>>
+    {
+        let mut fills = self.fills.iter().collect::<Vec<_>>();
+        fills.sort_by_key(|fill| fill.timestamp);
+
+        let mut series: BTreeMap<String, Vec<(i64, f64)>> = BTreeMap::new();
+        for fill in fills {
+            let points = series.entry(fill.instrument.to_string()).or_default();
+            let size = points.last().map_or(0.0, |(_, size)| *size);
+            let delta = match fill.side {
+                | Side::Buy => fill.size,
+                | Side::Sell => -fill.size,
+            };
+            points.push((fill.timestamp, size));
+            points.push((fill.timestamp, size + delta));
+        }
+        series
+    }
+
+    pub fn write_json(&self, path: impl AsRef<Path>) -> Result<(), ExchangeError>
+    {
+        let report = JsonReport { report: self,
+                                  metrics: self.metrics().into_iter().map(|(name, value)| (name, JsonMetric(value))).collect() };
+        let json = serde_json::to_string_pretty(&report).map_err(|e| ExchangeError::Export(e.to_string()))?;
+        write_file(path.as_ref(), &json)
+    }
+
+    /// 在 `dir` 下写入 `fills.csv`、`orders.csv`、`exited_positions.csv`、`equity_curve.csv` 与 `summary.csv`。
+    pub fn write_csv(&self, dir: impl AsRef<Path>) -> Result<(), ExchangeError>
+    {
+        let dir = dir.as_ref();
+        let fills = self.fills.iter().map(|fill| {
+                                         let mut row = vec![fill.timestamp.to_string(),
+                                                            fill.trade_id.0.to_string(),
+                                                            optional(&fill.order_id),
+                                                            optional(&fill.cid),
+                                                            fill.exchange.to_string()];
+                                         row.extend(instrument_cells(&fill.instrument));
+                                         row.extend([fill.side.to_string(), fill.price.to_string(), fill.size.to_string(), fill.fees.to_string()]);
+                                         row
+                                     });
+        write_csv(&dir.join("fills.csv"),
+                  &["timestamp", "trade_id", "order_id", "cid", "exchange", "base", "quote", "kind", "side", "price", "size", "fees"],
+                  fills)?;
+
+        let orders = self.orders.iter().map(|order| {
+                                           let mut row = vec![order.exchange_timestamp.to_string(),
+                                                              order.timestamp.to_string(),
+                                                              order.status.as_str().to_string(),
+                                                              order.order_id.to_string(),
+                                                              optional(&order.cid)];
+                                           row.extend(instrument_cells(&order.instrument));
+                                           row.extend([order.side.to_string(),
+                                                       order.instruction.to_string(),
+                                                       optional(&order.price),
+                                                       optional(&order.size),
+                                                       optional(&order.filled_quantity)]);
+                                           row
+                                       });
+        write_csv(&dir.join("orders.csv"),
+                  &["exchange_timestamp",
+                    "timestamp",
+                    "status",
+                    "order_id",
+                    "cid",
+                    "base",
+                    "quote",
+                    "kind",
+                    "side",
+                    "instruction",
+                    "price",
+                    "size",
+                    "filled_quantity"],
+                  orders)?;
+
+        let positions = self.exited_positions.iter().map(|position| {
+                                                        let mut row = vec![position.position_id.0.to_string(), position.exchange.to_string()];
+                                                        row.extend(instrument_cells(&position.instrument));
+                                                        row.extend([position.side.to_string(),
+                                                                    position.enter_ts.to_string(),
+                                                                    position.exit_ts.to_string(),
+                                                                    position.quantity.to_string(),
+                                                                    position.exit_avg_price_gross.to_string(),
+                                                                    position.realised_pnl.to_string(),
+                                                                    position.exit_fees_total.to_string(),
+                                                                    position.net_pnl().to_string(),
+                                                                    position.pnl_return().to_string()]);
+                                                        row
+                                                    });
+        write_csv(&dir.join("exited_positions.csv"),
+                  &["position_id",
+                    "exchange",
+                    "base",
+                    "quote",
+                    "kind",
+                    "side",
+                    "enter_ts",
+                    "exit_ts",
+                    "quantity",
+                    "exit_avg_price",
+                    "realised_pnl",
+                    "fees",
+                    "net_pnl",
+                    "pnl_return"],
+                  positions)?;
+
+        let equity = self.equity_series();
+        let drawdowns = drawdowns(equity.iter().copied());
+        write_csv(&dir.join("equity_curve.csv"),
+                  &["timestamp", "equity", "drawdown"],
+                  equity.iter()
+                        .zip(&drawdowns)
+                        .map(|((time, total), (_, drawdown))| vec![time.to_string(), total.to_string(), drawdown.to_string()]))?;
+
+        write_csv(&dir.join("summary.csv"),
+                  &["metric", "value"],
+                  self.metrics().into_iter().map(|(name, value)| vec![name.to_string(), value.to_string()]))
+    }
+
+    /// 生成单个 HTML 文件的报告，图表以内联 SVG 绘制，不引用任何外部脚本、样式或图片。
+    pub fn to_html(&self) -> String
+    {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Backtest Report</title>\n<style>\n");
+        html.push_str("body { font-family: sans-serif; margin: 2em; color: #222; }\n");
+        html.push_str("table { border-collapse: collapse; margin-bottom: 2em; }\n");
+        html.push_str("td, th { border: 1px solid #ccc; padding: 4px 10px; text-align: right; }\n");
+        html.push_str("th { background: #f4f4f4; text-align: left; }\n");
+        html.push_str("svg { display: block; margin-bottom: 2em; background: #fff; border: 1px solid #ddd; }\n");
+        html.push_str("</style>\n</head>\n<body>\n<h1>Backtest Report</h1>\n");
+        let _ = writeln!(html,
+                         "<p>{} fills, {} order events, {} exited positions, {} equity samples</p>",
+                         self.fills.len(),
+                         self.orders.len(),
+                         self.exited_positions.len(),
+                         self.equity_curve.len());
+
+        html.push_str("<h2>Summary</h2>\n<table>\n");
+        for (name, value) in self.metrics() {
+            let _ = writeln!(html, "<tr><th>{}</th><td>{:.4}</td></tr>", name, value);
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Equity</h2>\n");
+        html.push_str(&svg_chart(&[("equity".to_string(), self.equity_series())]));
+        html.push_str("<h2>Drawdown</h2>\n");
+        html.push_str(&svg_chart(&[("drawdown".to_string(), self.drawdown_series())]));
+        html.push_str("<h2>Position Size</h2>\n");
+        html.push_str(&svg_chart(&self.position_size_series().into_iter().collect::<Vec<_>>()));
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    pub fn write_html(&self, path: impl AsRef<Path>) -> Result<(), ExchangeError>
+    {
+        write_file(path.as_ref(), &self.to_html())
+    }
+
+    /// 在 `dir` 下写入 `report.json`、各 CSV 文件与 `report.html`，目录不存在时自动创建。
+    pub fn export(&self, dir: impl AsRef<Path>) -> Result<(), ExchangeError>
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(|e| ExchangeError::Export(format!("{}: {}", dir.display(), e)))?;
+        self.write_json(dir.join("report.json"))?;
+        self.write_csv(dir)?;
+        self.write_html(dir.join("report.html"))
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), ExchangeError>
+{
+    fs::write(path, contents).map_err(|e| ExchangeError::Export(format!("{}: {}", path.display(), e)))
+}
+
+fn write_csv(path: &Path, header: &[&str], rows: impl Iterator<Item = Vec<String>>) -> Result<(), ExchangeError>
+{
+    let mut csv = header.join(",");
+    csv.push('\n');
+    for row in rows {
+        csv.push_str(&row.iter().map(|cell| csv_field(cell)).collect::<Vec<_>>().join(","));
+        csv.push('\n');
+    }
+    write_file(path, &csv)
+}
+
+/// 含有逗号、引号或换行的字段用双引号括起，字段中的双引号写两次。
+fn csv_field(value: &str) -> Cow<'_, str>
+{
+    match value.contains([',', '"', '\n', '\r']) {
+        | true => Cow::Owned(format!("\"{}\"", value.replace('"', "\"\""))),
+        | false => Cow::Borrowed(value),
+    }
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String
+{
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+fn instrument_cells(instrument: &Instrument) -> [String; 3]
+{
+    [instrument.base.to_string(), instrument.quote.to_string(), instrument.kind.to_string()]
+}
+
+fn escape_html(value: &str) -> String
+{
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn format_millis(timestamp: i64) -> String
+{
+    DateTime::from_timestamp_millis(timestamp).map(|time| time.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default()
+}
+
+/// 把若干条 `(毫秒时间戳, 数值)` 序列画成一张折线图，所有序列共用坐标轴。
+fn svg_chart(series: &[(String, Vec<(i64, f64)>)]) -> String
+{
+    let points = series.iter().flat_map(|(_, points)| points.iter()).collect::<Vec<_>>();
+    if points.is_empty() {
+        return "<p>No data.</p>\n".to_string();
+    }
+
+    let (x_min, mut x_max) = points.iter().fold((i64::MAX, i64::MIN), |(min, max), (time, _)| (min.min(*time), max.max(*time)));
+    let (mut y_min, mut y_max) = points.iter().fold((f64::MAX, f64::MIN), |(min, max), (_, value)| (min.min(*value), max.max(*value)));
+    if x_max == x_min {
+        x_max = x_min + 1;
+    }
+    if y_max == y_min {
+        let padding = if y_min == 0.0 { 1.0 } else { y_min.abs() * 0.01 };
+        y_min -= padding;
+        y_max += padding;
+    }
+
+    let (top, right, bottom, left) = CHART_PADDING;
+    let plot_width = CHART_WIDTH - left - right;
+    let plot_height = CHART_HEIGHT - top - bottom;
+    let x = |time: i64| left + (time - x_min) as f64 / (x_max - x_min) as f64 * plot_width;
+    let y = |value: f64| top + (y_max - value) / (y_max - y_min) * plot_height;
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, "<svg width=\"{CHART_WIDTH}\" height=\"{CHART_HEIGHT}\" viewBox=\"0 0 {CHART_WIDTH} {CHART_HEIGHT}\" role=\"img\">");
+    let _ = writeln!(svg,
+                     "<line x1=\"{left}\" y1=\"{top}\" x2=\"{left}\" y2=\"{}\" stroke=\"#888\"/><line x1=\"{left}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#888\"/>",
+                     top + plot_height,
+                     top + plot_height,
+                     left + plot_width,
+                     top + plot_height);
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" font-size=\"11\" text-anchor=\"end\">{:.4}</text>", left - 6.0, top + 4.0, y_max);
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" font-size=\"11\" text-anchor=\"end\">{:.4}</text>", left - 6.0, top + plot_height, y_min);
+    let _ = writeln!(svg, "<text x=\"{left}\" y=\"{}\" font-size=\"11\">{}</text>", CHART_HEIGHT - 10.0, format_millis(x_min));
+    let _ = writeln!(svg,
+                     "<text x=\"{}\" y=\"{}\" font-size=\"11\" text-anchor=\"end\">{}</text>",
+                     left + plot_width,
+                     CHART_HEIGHT - 10.0,
+                     format_millis(x_max));
+
+    for (index, (name, points)) in series.iter().enumerate() {
+        let colour = CHART_COLOURS[index % CHART_COLOURS.len()];
+        let path = points.iter().map(|(time, value)| format!("{:.2},{:.2}", x(*time), y(*value))).collect::<Vec<_>>().join(" ");
+        let _ = writeln!(svg, "<polyline fill=\"none\" stroke=\"{colour}\" stroke-width=\"1.5\" points=\"{path}\"/>");
+        let _ = writeln!(svg,
+                         "<text x=\"{}\" y=\"{}\" font-size=\"12\" fill=\"{colour}\">{}</text>",
+                         left + 10.0 + index as f64 * 180.0,
+                         top - 10.0,
+                         escape_html(name));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::{order::states::cancelled::Cancelled, trade::ClientTradeId},
+        test_utils::{create_test_exited_position, create_test_order_open},
+        Exchange,
+    };
+
+    fn create_report() -> BacktestReport
+    {
+        let hour = 3_600_000;
+        let config = trading::Config { starting_equity: 1_000.0,
+                                       trading_days_per_year: 365,
+                                       risk_free_return: 0.0 };
+        let mut report = BacktestReport::new(config,
+                                             vec![create_test_exited_position(Side::Buy, 0, 6 * hour, 100.0, 110.0),
+                                                  create_test_exited_position(Side::Sell, 12 * hour, 18 * hour, 100.0, 105.0)],
+                                             Vec::new());
+
+        let open = create_test_order_open(Side::Buy, 100.0, 1.0);
+        let cancelled = Order { instruction: open.instruction,
+                                exchange: open.exchange,
+                                instrument: open.instrument.clone(),
+                                timestamp: open.timestamp,
+                                cid: open.cid.clone(),
+                                side: open.side,
+                                state: Cancelled { id: open.state.id.clone() } };
+        let fill = ClientTrade { exchange: Exchange::Hourglass,
+                                 timestamp: 0,
+                                 trade_id: ClientTradeId(1),
+                                 order_id: Some(open.state.id.clone()),
+                                 cid: open.cid.clone(),
+                                 instrument: open.instrument.clone(),
+                                 side: Side::Buy,
+                                 price: 100.0,
+                                 size: 1.0,
+                                 fees: 0.1 };
+        for kind in [AccountEventKind::OrdersOpen(vec![open]),
+                     AccountEventKind::Trade(fill.clone()),
+                     AccountEventKind::Trade(ClientTrade { timestamp: 6 * hour,
+                                                           trade_id: ClientTradeId(2),
+                                                           side: Side::Sell,
+                                                           ..fill }),
+                     AccountEventKind::OrdersCancelled(vec![cancelled]),
+                     AccountEventKind::Balances(Vec::new())]
+        {
+            report.record(&AccountEvent { exchange_timestamp: 0,
+                                          exchange: Exchange::Hourglass,
+                                          kind });
+        }
+        report
+    }
+
+    #[test]
+    fn report_should_collect_events_and_derive_curves()
+    {
+        let report = create_report();
+        assert_eq!(report.fills.len(), 2);
+        assert_eq!(report.orders.iter().map(|order| order.status).collect::<Vec<_>>(), vec![OrderStatus::Open, OrderStatus::Cancelled]);
+        assert_eq!(report.orders[1].price, None);
+
+        // 未启用权益采样时，权益曲线由平仓盈亏累计得到
+        let hour = 3_600_000;
+        assert_eq!(report.equity_series(), vec![(0, 1_000.0), (6 * hour, 1_010.0), (18 * hour, 1_005.0)]);
+        let drawdowns = report.drawdown_series();
+        assert!((drawdowns[2].1 + 5.0 / 1_010.0).abs() < 1e-12);
+
+        let sizes = report.position_size_series();
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(sizes.values().next().unwrap().last(), Some(&(6 * hour, 0.0)));
+
+        let metrics = report.metrics().into_iter().collect::<BTreeMap<_, _>>();
+        assert_eq!(metrics["ending_equity"], 1_005.0);
+        assert_eq!(metrics["trades"], 2.0);
+        assert!((metrics["fees"] - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn report_should_export_json_csv_and_self_contained_html()
+    {
+        let report = create_report();
+        let dir = tempfile::tempdir().unwrap();
+        report.export(dir.path().join("run")).unwrap();
+        let read = |name: &str| fs::read_to_string(dir.path().join("run").join(name)).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&read("report.json")).unwrap();
+        assert_eq!(json["fills"].as_array().unwrap().len(), 2);
+        assert_eq!(json["exited_positions"].as_array().unwrap().len(), 2);
+        assert_eq!(json["metrics"]["trades"], 2.0);
+
+        let fills = read("fills.csv");
+        assert!(fills.starts_with("timestamp,trade_id,order_id,cid,exchange,base,quote,kind,side,price,size,fees\n"));
+        assert_eq!(fills.lines().count(), 3);
+        assert_eq!(read("orders.csv").lines().count(), 3);
+        assert_eq!(read("exited_positions.csv").lines().count(), 3);
+        assert_eq!(read("equity_curve.csv").lines().count(), 4);
+        assert!(read("summary.csv").contains("\nmax_drawdown,"));
+
+        // 三张图全部内联，不引用外部资源
+        let html = read("report.html");
+        assert_eq!(html.matches("<svg").count(), 3);
+        assert!(!html.contains("http") && !html.contains("src="));
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+
+    #[test]
+    fn report_json_should_round_trip_without_losing_trades()
+    {
+        let hour = 3_600_000;
+        let config = trading::Config { starting_equity: 1_000.0,
+                                       trading_days_per_year: 365,
+                                       risk_free_return: 0.0 };
+        let report = BacktestReport::new(config, vec![create_test_exited_position(Side::Buy, 0, 6 * hour, 100.0, 110.0)], Vec::new());
+        assert_eq!(report.summary.tear_sheet.profit_factor, f64::INFINITY);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        report.write_json(&path).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["metrics"]["profit_factor"], "inf");
+        assert_eq!(value["metrics"]["max_drawdown"], report.summary.tear_sheet.max_drawdown);
+        assert_eq!(serde_json::from_str::<BacktestReport>(&json).unwrap(), report);
+    }
+}
@@ -213,3 +213,16 @@ impl AvgDrawdown
         self.mean_duration = Duration::milliseconds(self.mean_duration_milliseconds);
     }
 }
+
+/// 权益曲线上每个点相对此前最高点的回撤，为 0 或负数，最高点不为正时回撤记为 0。
+pub fn drawdowns(equity: impl IntoIterator<Item = (i64, f64)>) -> Vec<(i64, f64)>
+{
+    let mut peak = f64::MIN;
+    equity.into_iter()
+          .map(|(time, total)| {
+              peak = peak.max(total);
+              let drawdown = if peak > 0.0 { (total - peak) / peak } else { 0.0 };
+              (time, drawdown)
+          })
+          .collect()
+}
@@ -1,10 +1,10 @@
-use crate::Deserialize;
+use crate::{
+    common::{account_positions::Position, balance::Balance},
+    dashboard::summary::PositionSummariser,
+    Deserialize,
+};
 use chrono::{DateTime, Utc};
 use serde::Serialize;
-// use serde::{Deserialize, Serialize};
-// use crate::common::balance::Balance;
-// use crate::common::account_positions::Position;
-// use crate::dashboard::summary::PositionSummariser;
 
 /// `EquitySnapshot` 结构体表示在某个时间点上的总权益值，与 [`Balance.total`](Balance) 对应。
 ///
@@ -36,32 +36,31 @@ impl Default for EquitySnapshot
     }
 }
 
-// impl From<Balance> for EquitySnapshot {
-//     fn from(balance: Balance) -> Self {
-//         Self {
-//             time: balance.time,
-//             total: balance.total,
-//         }
-//     }
-// }
-//
-// impl PositionSummariser for EquitySnapshot {
-//     /// Updates using the input [`Position`]'s PnL & associated timestamp.
-//     fn update(&mut self, account_positions: &Position) {
-//         match account_positions.meta.exit_balance {
-//             None => {
-//                 // Position is not exited, so simulate
-//                 self.time = account_positions.meta.update_time;
-//                 self.total += account_positions.unrealised_profit_loss;
-//             }
-//             Some(exit_balance) => {
-//                 self.time = exit_balance.time;
-//                 self.total += account_positions.realised_profit_loss;
-//             }
-//         }
-//     }
-// }
-//
+impl From<Balance> for EquitySnapshot
+{
+    fn from(balance: Balance) -> Self
+    {
+        Self { time: balance.time,
+               total: balance.total }
+    }
+}
+
+impl PositionSummariser for EquitySnapshot
+{
+    /// 持仓中的仓位计入未实现盈亏，已平仓的仓位计入已实现盈亏，时间取仓位的最后更新时间。
+    fn update(&mut self, position: &Position)
+    {
+        let meta = position.meta();
+        if let Some(time) = DateTime::from_timestamp_millis(meta.update_ts) {
+            self.time = time;
+        }
+        self.total += match meta.current_size == 0.0 {
+            | true => meta.realised_pnl,
+            | false => meta.unrealised_pnl,
+        };
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use super::*;
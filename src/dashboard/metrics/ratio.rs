@@ -60,13 +60,16 @@ impl SharpeRatio
     /// - `pnl_returns`: PnL 返回的摘要，用于计算比率。
     pub fn update(&mut self, pnl_returns: &PnLReturnSummary)
     {
-        // 更新每日交易次数
-        self.trades_per_day = pnl_returns.trades_per_day;
+        self.update_with(pnl_returns.total.mean, pnl_returns.total.dispersion.std_dev, pnl_returns.trades_per_day);
+    }
 
-        // 计算每笔交易的 Sharpe Ratio
-        self.sharpe_ratio_per_trade = match pnl_returns.total.dispersion.std_dev == 0.0 {
+    /// 使用每期收益的均值、标准差与每日期数更新，标准差为 0 时比率为 0。
+    pub fn update_with(&mut self, mean: f64, std_dev: f64, trades_per_day: f64)
+    {
+        self.trades_per_day = trades_per_day;
+        self.sharpe_ratio_per_trade = match std_dev == 0.0 {
             | true => 0.0,
-            | false => (pnl_returns.total.mean - self.risk_free_return) / pnl_returns.total.dispersion.std_dev,
+            | false => (mean - self.risk_free_return) / std_dev,
         };
     }
 }
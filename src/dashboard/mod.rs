@@ -1,8 +1,11 @@
 use chrono::Duration;
 
 pub mod dispersion;
+pub mod equity;
 pub mod error;
+pub mod export;
 pub mod metrics;
+pub mod rolling;
 pub mod summary;
 pub mod welford_online;
 use serde::{Deserialize, Deserializer, Serializer};
@@ -29,3 +32,32 @@ pub fn de_duration_from_secs<'de, D>(deserializer: D) -> Result<Duration, D::Err
     let seconds: i64 = Deserialize::deserialize(deserializer)?;
     Ok(Duration::seconds(seconds))
 }
+
+/// 将 `f64` 序列化为数字，非有限值（例如没有亏损时的盈亏比）写为字符串 `"inf"`、`"-inf"` 或 `"NaN"`，
+/// JSON 不支持这些值，直接写出会变成 `null`。
+pub fn se_f64_or_sentinel<S>(value: &f64, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+{
+    match value.is_finite() {
+        | true => serializer.serialize_f64(*value),
+        | false => serializer.serialize_str(&value.to_string()),
+    }
+}
+
+/// 与 [`se_f64_or_sentinel`] 对应，接受数字或 `"inf"`、`"-inf"`、`"NaN"` 字符串。
+pub fn de_f64_or_sentinel<'de, D>(deserializer: D) -> Result<f64, D::Error>
+    where D: Deserializer<'de>
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrSentinel
+    {
+        Number(f64),
+        Sentinel(String),
+    }
+
+    match NumberOrSentinel::deserialize(deserializer)? {
+        | NumberOrSentinel::Number(value) => Ok(value),
+        | NumberOrSentinel::Sentinel(value) => value.parse().map_err(|_| serde::de::Error::custom(format!("invalid number: {}", value))),
+    }
+}
@@ -0,0 +1,356 @@
+use crate::{
+    common::account_positions::exited_position::PositionExit,
+    dashboard::{
+        dispersion::Range,
+        metrics::{
+            drawdown::drawdowns,
+            ratio::{Ratio, SharpeRatio},
+            EquitySnapshot,
+        },
+        summary::{pnl::datetime_from_millis, ExitedPositionSummariser, Initialiser, TableBuilder},
+        welford_online,
+    },
+};
+use prettytable::{row, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// 滚动窗口的大小，时间以交易所时间的毫秒计。
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum Window
+{
+    /// 最近 `n` 个数据点。
+    Count(usize),
+    /// 与最新数据点相距不足 `millis` 的数据点，最多保留 `max_count` 个以限制内存。
+    Duration
+    {
+        millis: i64, max_count: usize
+    },
+}
+
+impl Window
+{
+    /// 窗口中最早的数据点在 `latest` 时是否已经过期，`len` 为当前数据点数量。
+    fn is_expired(&self, len: usize, oldest: i64, latest: i64) -> bool
+    {
+        match *self {
+            | Window::Count(count) => len > count,
+            | Window::Duration { millis, max_count } => len > max_count || latest - oldest >= millis,
+        }
+    }
+}
+
+/// 滚动窗口内数据点的计数、总和、均值与离散度，数据点移出窗口时用 Welford 逆运算更新。
+///
+/// 逆运算会累积浮点误差，每移出与窗口大小相当数量的数据点后，用窗口内的数据点重新计算一次。
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct RollingDataSummary
+{
+    pub window: Window,
+    pub count: u64,
+    pub sum: f64,
+    pub mean: f64,
+    pub recurrence_relation_m: f64,
+    pub variance: f64, // 总体方差，与 [`DataSummary`](crate::dashboard::summary::data::DataSummary) 一致
+    pub std_dev: f64,
+    values: VecDeque<(i64, f64)>,
+    evictions: usize, // 上次重新计算之后移出的数据点数量
+}
+
+impl Initialiser for RollingDataSummary
+{
+    type Config = Window;
+
+    fn init(window: Self::Config) -> Self
+    {
+        Self { window,
+               count: 0,
+               sum: 0.0,
+               mean: 0.0,
+               recurrence_relation_m: 0.0,
+               variance: 0.0,
+               std_dev: 0.0,
+               values: VecDeque::new(),
+               evictions: 0 }
+    }
+}
+
+impl RollingDataSummary
+{
+    /// 加入 `time` 时的数据点，并移出已经过期的数据点。
+    pub fn update(&mut self, time: i64, next_value: f64)
+    {
+        self.values.push_back((time, next_value));
+        self.count += 1;
+        self.sum += next_value;
+        let prev_mean = self.mean;
+        self.mean = welford_online::update_mean(self.mean, next_value, self.count as f64);
+        self.recurrence_relation_m = welford_online::update_variance_accumulator(self.recurrence_relation_m, prev_mean, next_value, self.mean);
+
+        while let Some(&(oldest, removed_value)) = self.values.front() {
+            if !self.window.is_expired(self.values.len(), oldest, time) {
+                break;
+            }
+            self.values.pop_front();
+            self.count -= 1;
+            self.sum -= removed_value;
+            let prev_mean = self.mean;
+            self.mean = welford_online::downdate_mean(self.mean, removed_value, self.count as f64);
+            self.recurrence_relation_m = welford_online::downdate_variance_accumulator(self.recurrence_relation_m, prev_mean, removed_value, self.mean);
+            self.evictions += 1;
+        }
+        // 重新计算的开销均摊到每次移出上，M 为负说明误差已经不可忽略
+        if self.evictions >= self.values.len().max(1) || self.recurrence_relation_m < 0.0 {
+            self.recompute();
+        }
+
+        self.variance = welford_online::compute_population_variance(self.recurrence_relation_m, self.count);
+        self.std_dev = self.variance.sqrt();
+    }
+
+    /// 用窗口内的数据点重新计算总和、均值与递推关系 M。
+    fn recompute(&mut self)
+    {
+        self.sum = 0.0;
+        self.mean = 0.0;
+        self.recurrence_relation_m = 0.0;
+        for (index, (_, value)) in self.values.iter().enumerate() {
+            self.sum += value;
+            let prev_mean = self.mean;
+            self.mean = welford_online::update_mean(self.mean, *value, (index + 1) as f64);
+            self.recurrence_relation_m = welford_online::update_variance_accumulator(self.recurrence_relation_m, prev_mean, *value, self.mean);
+        }
+        self.evictions = 0;
+    }
+
+    /// 窗口内数据点的最高值与最低值。
+    pub fn range(&self) -> Range
+    {
+        self.values.iter().fold(Range::default(), |mut range, (_, value)| {
+                              range.update(*value);
+                              range
+                          })
+    }
+
+    /// 按时间顺序排列的窗口内数据点。
+    pub fn values(&self) -> impl Iterator<Item = &(i64, f64)>
+    {
+        self.values.iter()
+    }
+}
+
+impl TableBuilder for RollingDataSummary
+{
+    fn titles(&self) -> Row
+    {
+        row!["Count", "Sum", "Mean", "Variance", "Std. Dev", "Range High", "Range Low",]
+    }
+
+    fn row(&self) -> Row
+    {
+        let range = self.range();
+        row![self.count,
+             format!("{:.3}", self.sum),
+             format!("{:.3}", self.mean),
+             format!("{:.3}", self.variance),
+             format!("{:.3}", self.std_dev),
+             format!("{:.3}", range.high),
+             format!("{:.3}", range.low),]
+    }
+}
+
+/// 通过 [`Initialiser::init`] 创建 [`RollingPerformance`] 时使用的配置。
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct RollingConfig
+{
+    pub window: Window,
+    pub starting_equity: f64,
+    pub risk_free_return: f64,
+}
+
+/// 滚动窗口内的收益率、夏普比率、回撤与胜率，用于观察策略表现的变化。
+///
+/// 实时监控时用 [`RollingPerformance::record`] 逐个加入权益采样点，回测结束后也可以用已平仓仓位生成，
+/// 每个仓位的扣费后盈亏在平仓时间计入权益。相邻两个权益点之间的变化率视为一期收益。
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct RollingPerformance
+{
+    pub returns: RollingDataSummary,    // 窗口内每期收益率
+    pub sharpe_ratio: SharpeRatio,      // 按窗口内的收益率计算，每日期数按窗口的时间跨度估计
+    pub equity: EquitySnapshot,         // 最新的权益
+    equity_curve: VecDeque<(i64, f64)>, // 窗口内各期收益的起止权益，比收益多一个
+}
+
+impl Initialiser for RollingPerformance
+{
+    type Config = RollingConfig;
+
+    fn init(config: Self::Config) -> Self
+    {
+        let equity = EquitySnapshot { time: Default::default(),
+                                      total: config.starting_equity };
+        Self { returns: RollingDataSummary::init(config.window),
+               sharpe_ratio: SharpeRatio::init(config.risk_free_return),
+               equity,
+               equity_curve: VecDeque::from([(equity.time.timestamp_millis(), equity.total)]) }
+    }
+}
+
+impl ExitedPositionSummariser for RollingPerformance
+{
+    fn update(&mut self, position: &PositionExit)
+    {
+        let total = self.equity.total + position.net_pnl();
+        self.record(EquitySnapshot { time: datetime_from_millis(position.exit_ts),
+                                     total });
+    }
+}
+
+impl RollingPerformance
+{
+    /// 加入一个权益采样点，与上一个权益点之间的变化率计入窗口内的收益率。
+    pub fn record(&mut self, snapshot: EquitySnapshot)
+    {
+        let time = snapshot.time.timestamp_millis();
+        if self.equity.total != 0.0 {
+            self.returns.update(time, (snapshot.total - self.equity.total) / self.equity.total);
+        }
+        self.equity = snapshot;
+
+        self.equity_curve.push_back((time, snapshot.total));
+        while self.equity_curve.len() > self.returns.count as usize + 1 {
+            self.equity_curve.pop_front();
+        }
+        self.sharpe_ratio.update_with(self.returns.mean, self.returns.std_dev, self.periods_per_day());
+    }
+
+    /// 窗口内平均每天的收益期数，按窗口内第一个与最后一个权益点的时间跨度计算，跨度为 0 时为 0。
+    fn periods_per_day(&self) -> f64
+    {
+        match (self.equity_curve.front(), self.equity_curve.back()) {
+            | (Some((first, _)), Some((last, _))) if last > first => self.returns.count as f64 * 86_400_000.0 / (last - first) as f64,
+            | _ => 0.0,
+        }
+    }
+
+    /// 窗口内收益率为正的期数占比。
+    pub fn hit_rate(&self) -> f64
+    {
+        match self.returns.count {
+            | 0 => 0.0,
+            | count => self.returns.values().filter(|(_, value)| *value > 0.0).count() as f64 / count as f64,
+        }
+    }
+
+    /// 窗口内权益从最高点到之后最低点的最大跌幅，以负数表示。
+    pub fn max_drawdown(&self) -> f64
+    {
+        drawdowns(self.equity_curve.iter().copied()).into_iter().map(|(_, drawdown)| drawdown).fold(0.0, f64::min)
+    }
+
+    /// 最新权益相对窗口内最高权益的跌幅，以负数表示。
+    pub fn current_drawdown(&self) -> f64
+    {
+        drawdowns(self.equity_curve.iter().copied()).last().map_or(0.0, |(_, drawdown)| *drawdown)
+    }
+}
+
+impl TableBuilder for RollingPerformance
+{
+    fn titles(&self) -> Row
+    {
+        row!["Periods", "Mean Return", "Std. Dev. Return", "Sharpe Ratio", "Max Drawdown", "Current Drawdown", "Hit Rate",]
+    }
+
+    fn row(&self) -> Row
+    {
+        row![self.returns.count,
+             format!("{:.3}", self.returns.mean),
+             format!("{:.3}", self.returns.std_dev),
+             format!("{:.3}", self.sharpe_ratio.ratio()),
+             format!("{:.3}", self.max_drawdown()),
+             format!("{:.3}", self.current_drawdown()),
+             format!("{:.3}", self.hit_rate()),]
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{common::Side, dashboard::summary::combine, test_utils::create_test_exited_position};
+
+    #[test]
+    fn rolling_data_summary_should_match_window_by_count_and_duration()
+    {
+        let values = [3.0, -1.0, 4.0, 1.0, -5.0, 9.0, 2.0, -6.0];
+        let mut by_count = RollingDataSummary::init(Window::Count(3));
+        let mut by_duration = RollingDataSummary::init(Window::Duration { millis: 2_500, max_count: 100 });
+        for (index, value) in values.iter().enumerate() {
+            by_count.update(index as i64 * 1_000, *value);
+            by_duration.update(index as i64 * 1_000, *value);
+
+            // 与直接用窗口内的数据点计算的结果一致
+            let window = &values[index.saturating_sub(2)..=index];
+            let mean = window.iter().sum::<f64>() / window.len() as f64;
+            let variance = window.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / window.len() as f64;
+            for summary in [&by_count, &by_duration] {
+                assert_eq!(summary.count, window.len() as u64);
+                assert!((summary.mean - mean).abs() < 1e-9);
+                assert!((summary.variance - variance).abs() < 1e-9);
+            }
+        }
+        assert_eq!((by_count.range().high, by_count.range().low), (9.0, -6.0));
+
+        // 按时间的窗口同样受数量上限约束
+        let mut capped = RollingDataSummary::init(Window::Duration { millis: i64::MAX, max_count: 2 });
+        values.iter().for_each(|value| capped.update(0, *value));
+        assert_eq!(capped.values().map(|(_, value)| *value).collect::<Vec<_>>(), vec![2.0, -6.0]);
+    }
+
+    #[test]
+    fn rolling_data_summary_should_not_drift_over_long_streams()
+    {
+        let mut summary = RollingDataSummary::init(Window::Count(3));
+        let values = (0..100_000).map(|index| 1e8 + (index % 7) as f64 * 1e-3).collect::<Vec<_>>();
+        for (index, value) in values.iter().enumerate() {
+            summary.update(index as i64, *value);
+        }
+
+        let window = &values[values.len() - 3..];
+        let mean = window.iter().sum::<f64>() / 3.0;
+        let variance = window.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / 3.0;
+        assert!(summary.variance >= 0.0);
+        assert!((summary.mean - mean).abs() < 1e-6);
+        assert!((summary.variance - variance).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rolling_performance_should_only_reflect_recent_periods()
+    {
+        let hour = 3_600_000;
+        let mut performance = RollingPerformance::init(RollingConfig { window: Window::Count(2),
+                                                                       starting_equity: 100.0,
+                                                                       risk_free_return: 0.0 });
+        performance.generate_summary(&[create_test_exited_position(Side::Buy, 0, hour, 100.0, 90.0),
+                                       create_test_exited_position(Side::Buy, hour, 2 * hour, 100.0, 110.0)]);
+        // 权益 100 -> 90 -> 100
+        assert_eq!(performance.hit_rate(), 0.5);
+        assert!((performance.max_drawdown() + 0.1).abs() < 1e-12);
+        assert_eq!(performance.current_drawdown(), 0.0);
+
+        performance.update(&create_test_exited_position(Side::Buy, 2 * hour, 3 * hour, 100.0, 95.0));
+        performance.update(&create_test_exited_position(Side::Buy, 3 * hour, 4 * hour, 100.0, 96.0));
+        // 只保留最近两期：100 -> 95 -> 91，更早的回撤已移出窗口
+        assert_eq!(performance.returns.count, 2);
+        assert_eq!(performance.hit_rate(), 0.0);
+        assert!((performance.max_drawdown() + 0.09).abs() < 1e-12);
+        assert!((performance.current_drawdown() + 0.09).abs() < 1e-12);
+        assert!(performance.sharpe_ratio.ratio() < 0.0);
+        // 两期收益跨越两小时，每天 24 期
+        assert!((performance.sharpe_ratio.trades_per_day - 24.0).abs() < 1e-9);
+
+        let table = combine(vec![("rolling".to_string(), performance)]);
+        assert!(table.to_string().contains("Hit Rate"));
+    }
+}
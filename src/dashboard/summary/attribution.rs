@@ -0,0 +1,358 @@
+use crate::{
+    common::{account_positions::PositionDirectionMode, instrument::Instrument, trade::ClientTrade, Side},
+    dashboard::summary::{combine, TableBuilder},
+};
+use prettytable::{row, Row, Table};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// 没有策略标签的订单归入的分组。
+pub const UNTAGGED: &str = "untagged";
+
+/// 一组成交的盈亏构成：价格盈亏、未实现盈亏、手续费、资金费用与爆仓损失。
+#[derive(Copy, Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+pub struct PnLBreakdown
+{
+    pub price_pnl: f64,        // 平仓实现的价格盈亏，不含爆仓
+    pub unrealised_pnl: f64,   // 按最新价计算的持仓盈亏
+    pub fees: f64,             // 支付的手续费，为正数
+    pub funding: f64,          // 收到的资金费用为正，支付为负
+    pub liquidation_loss: f64, // 爆仓平仓实现的价格盈亏
+    pub volume: f64,           // 成交额
+}
+
+impl PnLBreakdown
+{
+    pub fn net_pnl(&self) -> f64
+    {
+        self.price_pnl + self.unrealised_pnl + self.funding + self.liquidation_loss - self.fees
+    }
+
+    fn add(&mut self, other: &PnLBreakdown)
+    {
+        self.price_pnl += other.price_pnl;
+        self.unrealised_pnl += other.unrealised_pnl;
+        self.fees += other.fees;
+        self.funding += other.funding;
+        self.liquidation_loss += other.liquidation_loss;
+        self.volume += other.volume;
+    }
+}
+
+impl TableBuilder for PnLBreakdown
+{
+    fn titles(&self) -> Row
+    {
+        row!["Price PnL", "Unrealised PnL", "Fees", "Funding", "Liquidation Loss", "Net PnL", "Volume",]
+    }
+
+    fn row(&self) -> Row
+    {
+        row![format!("{:.3}", self.price_pnl),
+             format!("{:.3}", self.unrealised_pnl),
+             format!("{:.3}", self.fees),
+             format!("{:.3}", self.funding),
+             format!("{:.3}", self.liquidation_loss),
+             format!("{:.3}", self.net_pnl()),
+             format!("{:.3}", self.volume),]
+    }
+}
+
+/// 按交易工具、持仓方向与策略标签计算的持仓，数量总是正数。
+#[derive(Copy, Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+struct Lot
+{
+    quantity: f64,
+    avg_price: f64,
+}
+
+/// 按交易工具、持仓方向与策略标签拆分账户盈亏。
+///
+/// 多空持仓按交易工具与策略标签分别从零开始按平均成本计算。单向持仓模式下方向相反的成交先平掉同一标签的反向持仓再开仓，
+/// 双向持仓模式下成交总是加到同方向的持仓上，与交易所处理仓位的方式一致。策略标签取自成交的 [`ClientOrderId::strategy_tag`]，
+/// 没有订单的成交（交易所生成的爆仓成交）平掉该交易工具同方向的全部持仓，不论标签，计入爆仓损失。
+///
+/// [`ClientOrderId::strategy_tag`]: crate::common::order::identification::client_order_id::ClientOrderId::strategy_tag
+#[derive(Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+pub struct PnLAttribution
+{
+    #[serde(with = "entries")]
+    lots: BTreeMap<(Instrument, Side, Option<String>), Lot>, // 按持仓方向记录，`Side::Buy` 为多头
+    #[serde(with = "entries")]
+    cells: BTreeMap<(Instrument, Side, Option<String>), PnLBreakdown>, // 按持仓方向记录，`Side::Buy` 为多头
+}
+
+/// 以 `(键, 值)` 列表序列化元组为键的表，JSON 对象的键只能是字符串。
+mod entries
+{
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<K, V, S>(map: &BTreeMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+        where K: Serialize,
+              V: Serialize,
+              S: Serializer
+    {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+        where K: Deserialize<'de> + Ord,
+              V: Deserialize<'de>,
+              D: Deserializer<'de>
+    {
+        Vec::<(K, V)>::deserialize(deserializer).map(|entries| entries.into_iter().collect())
+    }
+}
+
+impl PnLAttribution
+{
+    /// 用一笔成交更新持仓与盈亏，`mode` 为该交易工具的持仓模式。跨越零点的成交按数量拆分手续费与成交额。
+    pub fn record_fill(&mut self, trade: &ClientTrade, mode: &PositionDirectionMode)
+    {
+        if trade.size <= 0.0 {
+            return;
+        }
+        let closed_side = trade.side.toggle();
+
+        // 爆仓成交没有订单与标签，按顺序平掉同方向各个标签的持仓，不会开仓
+        if trade.order_id.is_none() {
+            let tags = self.lots
+                           .keys()
+                           .filter(|(instrument, side, _)| *instrument == trade.instrument && *side == closed_side)
+                           .map(|(_, _, tag)| tag.clone())
+                           .collect::<Vec<_>>();
+            let mut remaining = trade.size;
+            for tag in tags {
+                remaining -= self.close_lot(trade, closed_side, tag, remaining);
+                if remaining <= 0.0 {
+                    break;
+                }
+            }
+            return;
+        }
+
+        let tag = trade.cid.as_ref().and_then(|cid| cid.strategy_tag()).map(str::to_string);
+        let closing = match mode {
+            | PositionDirectionMode::Net => self.close_lot(trade, closed_side, tag.clone(), trade.size),
+            | PositionDirectionMode::LongShort => 0.0,
+        };
+        let opening = trade.size - closing;
+        if opening > 0.0 {
+            let lot = self.lots.entry((trade.instrument.clone(), trade.side, tag.clone())).or_default();
+            lot.avg_price = (lot.avg_price * lot.quantity + trade.price * opening) / (lot.quantity + opening);
+            lot.quantity += opening;
+
+            let cell = self.cells.entry((trade.instrument.clone(), trade.side, tag)).or_default();
+            cell.fees += trade.fees * opening / trade.size;
+            cell.volume += trade.price * opening;
+        }
+    }
+
+    /// 用 `trade` 平掉 `side` 方向、标签为 `tag` 的持仓，最多平 `size`，返回实际平仓的数量。
+    fn close_lot(&mut self, trade: &ClientTrade, side: Side, tag: Option<String>, size: f64) -> f64
+    {
+        let key = (trade.instrument.clone(), side, tag);
+        let Some(lot) = self.lots.get_mut(&key)
+        else {
+            return 0.0;
+        };
+        let closing = size.min(lot.quantity);
+        let direction = match side {
+            | Side::Buy => 1.0,
+            | Side::Sell => -1.0,
+        };
+        let realised = closing * (trade.price - lot.avg_price) * direction;
+        lot.quantity -= closing;
+        if lot.quantity < f64::EPSILON {
+            self.lots.remove(&key);
+        }
+
+        let cell = self.cells.entry(key).or_default();
+        match trade.order_id {
+            | Some(_) => cell.price_pnl += realised,
+            | None => cell.liquidation_loss += realised,
+        }
+        cell.fees += trade.fees * closing / trade.size;
+        cell.volume += trade.price * closing;
+        closing
+    }
+
+    /// 按 `rate` 与 `mark_price` 结算 `instrument` 的资金费用：费率为正时多头支付、空头收取。
+    ///
+    /// 交易所目前不结算资金费用，由结算资金费用的调用方记录。
+    pub fn record_funding(&mut self, instrument: &Instrument, rate: f64, mark_price: f64)
+    {
+        for ((lot_instrument, side, tag), lot) in &self.lots {
+            if lot_instrument != instrument {
+                continue;
+            }
+            let payment = lot.quantity * mark_price * rate;
+            let cell = self.cells.entry((instrument.clone(), *side, tag.clone())).or_default();
+            match side {
+                | Side::Buy => cell.funding -= payment,
+                | Side::Sell => cell.funding += payment,
+            }
+        }
+    }
+
+    /// 用 `mark_price` 给出的最新价计算未实现盈亏，并汇总各个维度。没有最新价的持仓不计未实现盈亏。
+    pub fn summary(&self, mark_price: impl Fn(&Instrument) -> Option<f64>) -> AttributionSummary
+    {
+        let mut cells = self.cells.clone();
+        for ((instrument, side, tag), lot) in &self.lots {
+            if let Some(price) = mark_price(instrument) {
+                let direction = match side {
+                    | Side::Buy => 1.0,
+                    | Side::Sell => -1.0,
+                };
+                cells.entry((instrument.clone(), *side, tag.clone())).or_default().unrealised_pnl += (price - lot.avg_price) * lot.quantity * direction;
+            }
+        }
+
+        let mut summary = AttributionSummary::default();
+        for ((instrument, side, tag), breakdown) in &cells {
+            summary.total.add(breakdown);
+            match side {
+                | Side::Buy => summary.long.add(breakdown),
+                | Side::Sell => summary.short.add(breakdown),
+            }
+            summary.by_instrument.entry(instrument.to_string()).or_default().add(breakdown);
+            summary.by_strategy.entry(tag.clone().unwrap_or_else(|| UNTAGGED.to_string())).or_default().add(breakdown);
+        }
+        summary
+    }
+}
+
+/// 账户盈亏的拆分结果，所有维度的合计都等于 `total`。
+#[derive(Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+pub struct AttributionSummary
+{
+    pub total: PnLBreakdown,
+    pub long: PnLBreakdown,
+    pub short: PnLBreakdown,
+    pub by_instrument: BTreeMap<String, PnLBreakdown>,
+    pub by_strategy: BTreeMap<String, PnLBreakdown>, // 没有策略标签的订单记为 [`UNTAGGED`]
+}
+
+impl AttributionSummary
+{
+    /// 每个维度占一行：合计、多头、空头、各交易工具与各策略。
+    pub fn table(&self) -> Table
+    {
+        let rows = [("total".to_string(), self.total), ("long".to_string(), self.long), ("short".to_string(), self.short)];
+        let instruments = self.by_instrument.iter().map(|(instrument, breakdown)| (format!("instrument {}", instrument), *breakdown));
+        let strategies = self.by_strategy.iter().map(|(tag, breakdown)| (format!("strategy {}", tag), *breakdown));
+        combine(rows.into_iter().chain(instruments).chain(strategies))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::{
+            instrument::kind::InstrumentKind,
+            order::identification::{client_order_id::ClientOrderId, OrderId},
+            trade::ClientTradeId,
+        },
+        test_utils::create_test_instrument,
+        Exchange,
+    };
+
+    fn fill(cid: &str, side: Side, price: f64, size: f64, fees: f64) -> ClientTrade
+    {
+        ClientTrade { exchange: Exchange::Hourglass,
+                      timestamp: 0,
+                      trade_id: ClientTradeId(0),
+                      order_id: Some(OrderId(1)),
+                      cid: Some(ClientOrderId(cid.to_string())),
+                      instrument: create_test_instrument(InstrumentKind::Perpetual),
+                      side,
+                      price,
+                      size,
+                      fees }
+    }
+
+    fn liquidation(side: Side, price: f64, size: f64) -> ClientTrade
+    {
+        ClientTrade { order_id: None,
+                      cid: None,
+                      ..fill("", side, price, size, 0.0) }
+    }
+
+    #[test]
+    fn attribution_should_split_pnl_by_source_side_and_strategy()
+    {
+        let instrument = create_test_instrument(InstrumentKind::Perpetual);
+        let mut attribution = PnLAttribution::default();
+
+        // mm 策略做多 2 张，以 110 卖出 3 张，平多 2 张后反手做空 1 张
+        attribution.record_fill(&fill("mm-000001", Side::Buy, 100.0, 2.0, 0.3), &PositionDirectionMode::Net);
+        attribution.record_fill(&fill("mm-000002", Side::Sell, 110.0, 3.0, 0.6), &PositionDirectionMode::Net);
+        // 未标记的订单做多 1 张，随后被爆仓
+        attribution.record_fill(&fill("plain0001", Side::Buy, 100.0, 1.0, 0.1), &PositionDirectionMode::Net);
+        attribution.record_fill(&liquidation(Side::Sell, 90.0, 1.0), &PositionDirectionMode::Net);
+        attribution.record_funding(&instrument, 0.001, 100.0);
+
+        let summary = attribution.summary(|_| Some(105.0));
+        let mm = summary.by_strategy["mm"];
+        assert_eq!(mm.price_pnl, 20.0);
+        assert!((mm.fees - 0.9).abs() < 1e-12);
+        assert!((mm.funding - 0.1).abs() < 1e-12); // 空头收取资金费用
+        assert_eq!(mm.unrealised_pnl, 5.0);
+
+        // 爆仓成交没有订单，也就没有策略标签
+        let untagged = summary.by_strategy[UNTAGGED];
+        assert_eq!(untagged.liquidation_loss, -10.0);
+        assert_eq!(untagged.price_pnl, 0.0);
+
+        // 反手成交的手续费按数量拆到多空两侧
+        assert!((summary.long.fees - 0.8).abs() < 1e-12);
+        assert!((summary.short.fees - 0.2).abs() < 1e-12);
+        assert_eq!(summary.short.unrealised_pnl, 5.0);
+
+        let total = summary.total;
+        assert!((total.net_pnl() - (20.0 - 1.0 + 0.1 - 10.0 + 5.0)).abs() < 1e-12);
+        assert_eq!(summary.by_instrument.len(), 1);
+        assert!((summary.by_instrument[&instrument.to_string()].net_pnl() - total.net_pnl()).abs() < 1e-12);
+        assert_eq!(summary.table().len(), 6);
+    }
+
+    #[test]
+    fn liquidation_should_close_tagged_lots()
+    {
+        let mut attribution = PnLAttribution::default();
+        attribution.record_fill(&fill("mm-000001", Side::Buy, 100.0, 2.0, 0.2), &PositionDirectionMode::Net);
+        attribution.record_fill(&fill("arb-000001", Side::Buy, 110.0, 1.0, 0.1), &PositionDirectionMode::Net);
+        // 爆仓成交没有标签，同时平掉两个策略的多头
+        attribution.record_fill(&liquidation(Side::Sell, 80.0, 3.0), &PositionDirectionMode::Net);
+
+        let summary = attribution.summary(|_| Some(70.0));
+        assert_eq!(summary.by_strategy["mm"].liquidation_loss, -40.0);
+        assert_eq!(summary.by_strategy["arb"].liquidation_loss, -30.0);
+        assert!(!summary.by_strategy.contains_key(UNTAGGED));
+        assert_eq!(summary.total.unrealised_pnl, 0.0);
+        assert_eq!(summary.short, PnLBreakdown::default());
+        assert_eq!(attribution.lots.len(), 0);
+    }
+
+    #[test]
+    fn hedge_mode_should_keep_long_and_short_lots_apart()
+    {
+        let instrument = create_test_instrument(InstrumentKind::Perpetual);
+        let mut attribution = PnLAttribution::default();
+        attribution.record_fill(&fill("mm-000001", Side::Buy, 100.0, 1.0, 0.0), &PositionDirectionMode::LongShort);
+        attribution.record_fill(&fill("mm-000002", Side::Sell, 110.0, 1.0, 0.0), &PositionDirectionMode::LongShort);
+        attribution.record_funding(&instrument, 0.001, 100.0);
+
+        // 多空仓位各自计算未实现盈亏，资金费用一付一收
+        let summary = attribution.summary(|_| Some(105.0));
+        assert_eq!(summary.total.price_pnl, 0.0);
+        assert_eq!(summary.long.unrealised_pnl, 5.0);
+        assert_eq!(summary.short.unrealised_pnl, 5.0);
+        assert!((summary.long.funding + 0.1).abs() < 1e-12);
+        assert!((summary.short.funding - 0.1).abs() < 1e-12);
+    }
+}
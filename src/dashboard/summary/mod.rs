@@ -1,9 +1,10 @@
+pub mod attribution;
 pub mod data;
 pub mod drawdown;
 pub mod pnl;
 pub mod trading;
 
-use crate::common::account_positions::Position;
+use crate::common::account_positions::{exited_position::PositionExit, Position};
 use prettytable::{Cell, Row, Table};
 
 /// 该模块定义了一些用于处理交易数据和生成摘要表格的通用工具和接口。
@@ -14,6 +15,7 @@ use prettytable::{Cell, Row, Table};
 /// 具体来说：
 /// - `Initialiser` 特性（trait）定义了一个初始化器接口，它要求实现者能够通过配置初始化自身。
 /// - `PositionSummariser` 特性定义了一个更新和生成交易仓位摘要的接口。通过实现这个接口，可以在处理一组交易仓位时快速生成统计数据。
+/// - `ExitedPositionSummariser` 特性与之相同，输入为已平仓的仓位。
 /// - `TableBuilder` 特性定义了一套接口，用于生成表格标题、行数据以及完整表格。它还支持将多个表格合并为一个表格。
 /// - `combine` 函数用于合并多个表格生成器，生成一个包含所有行数据的完整表格。
 pub trait Initialiser
@@ -37,6 +39,23 @@ pub trait PositionSummariser
     }
 }
 
+/// 用已平仓仓位生成摘要的接口，回测结束后通常用 [`AccountExitedPositions::all_exits`] 的结果生成。
+///
+/// [`AccountExitedPositions::all_exits`]: crate::common::account_positions::exited_positions::AccountExitedPositions::all_exits
+pub trait ExitedPositionSummariser
+{
+    /// 用一个已平仓仓位更新摘要。
+    fn update(&mut self, position: &PositionExit);
+
+    /// 按顺序用每个已平仓仓位更新摘要。
+    fn generate_summary(&mut self, positions: &[PositionExit])
+    {
+        for position in positions.iter() {
+            self.update(position)
+        }
+    }
+}
+
 /// 用于生成表格的接口，提供生成标题行、数据行和完整表格的功能。
 pub trait TableBuilder
 {
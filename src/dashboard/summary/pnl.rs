@@ -1,8 +1,12 @@
-use crate::dashboard::{
-    de_duration_from_secs, se_duration_as_secs,
-    summary::{data::DataSummary, Initialiser},
+use crate::{
+    common::{account_positions::exited_position::PositionExit, Side},
+    dashboard::{
+        de_duration_from_secs, se_duration_as_secs,
+        summary::{data::DataSummary, ExitedPositionSummariser, Initialiser, TableBuilder},
+    },
 };
 use chrono::{DateTime, Duration, Utc};
+use prettytable::{row, Row};
 use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Deserialize, Serialize)]
@@ -37,209 +41,260 @@ impl Default for PnLReturnSummary
                losses: DataSummary::default() }
     }
 }
-// impl PositionSummariser for PnLReturnSummary {
-//     fn update(&mut self, account_positions: &Position) {
-//         // Set start timestamp if it's the first trade of the session
-//         if self.total.count == 0 {
-//             self.time = account_positions.meta.enter_time;
-//         }
-//
-//         // Update duration of trading session & trades per day
-//         self.update_trading_session_duration(account_positions);
-//         self.update_trades_per_day();
-//
-//         // Calculate the Position PnL Return
-//         let pnl_return = account_positions.calculate_profit_loss_return();
-//
-//         // Update Total PnL Returns
-//         self.total.update(pnl_return);
-//
-//         // Update Loss PnL Returns if relevant
-//         if pnl_return.is_sign_negative() {
-//             self.losses.update(pnl_return);
-//         }
-//     }
-// }
-//
-// impl TableBuilder for PnLReturnSummary {
-//     fn titles(&self) -> Row {
-//         row![
-//             "Trades",
-//             "Wins",
-//             "Losses",
-//             "Trading Days",
-//             "Trades Per Day",
-//             "Mean Return",
-//             "Std. Dev. Return",
-//             "Loss Mean Return",
-//             "Biggest Win",
-//             "Biggest Loss",
-//         ]
-//     }
-//
-//     fn row(&self) -> Row {
-//         let wins = self.total.count - self.losses.count;
-//         row![
-//             self.total.count.to_string(),
-//             wins,
-//             self.losses.count,
-//             self.duration.num_days().to_string(),
-//             format!("{:.3}", self.trades_per_day),
-//             format!("{:.3}", self.total.mean),
-//             format!("{:.3}", self.total.dispersion.std_dev),
-//             format!("{:.3}", self.losses.mean),
-//             format!("{:.3}", self.total.dispersion.range.high),
-//             format!("{:.3}", self.total.dispersion.range.low),
-//         ]
-//     }
-// }
-
-// impl PnLReturnSummary {
-//     const SECONDS_IN_DAY: f64 = 86400.0;
-//
-//     pub fn new() -> Self {
-//         Self {
-//             time: Utc::now(),
-//             duration: Duration::zero(),
-//             trades_per_day: 0.0,
-//             total: Default::default(),
-//             losses: Default::default(),
-//         }
-//     }
-//
-//     pub fn update_trading_session_duration(&mut self, account_positions: &Position) {
-//         self.duration = match account_positions.meta.exit_balance {
-//             None => {
-//                 // Since Position is not exited, estimate duration w/ last_update_time
-//                 account_positions.meta.update_time.signed_duration_since(self.time)
-//             }
-//             Some(exit_balance) => exit_balance.time.signed_duration_since(self.time),
-//         }
-//     }
-//
-//     pub fn update_trades_per_day(&mut self) {
-//         self.trades_per_day = self.total.count as f64
-//             / (self.duration.num_seconds() as f64 / PnLReturnSummary::SECONDS_IN_DAY)
-//     }
-// }
-
-// #[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Default, Deserialize, Serialize)]
-// pub struct ProfitLossSummary {
-//     pub long_contracts: f64,
-//     pub long_pnl: f64,
-//     pub long_pnl_per_contract: f64,
-//     pub short_contracts: f64,
-//     pub short_pnl: f64,
-//     pub short_pnl_per_contract: f64,
-//     pub total_contracts: f64,
-//     pub total_pnl: f64,
-//     pub total_pnl_per_contract: f64,
-// }
-//
-// impl PositionSummariser for ProfitLossSummary {
-//     fn update(&mut self, account_positions: &Position) {
-//         self.total_contracts += account_positions.quantity.abs();
-//         self.total_pnl += account_positions.realised_profit_loss;
-//         self.total_pnl_per_contract = self.total_pnl / self.total_contracts;
-//
-//         match account_positions.side {
-//             Side::Buy => {
-//                 self.long_contracts += account_positions.quantity.abs();
-//                 self.long_pnl += account_positions.realised_profit_loss;
-//                 self.long_pnl_per_contract = self.long_pnl / self.long_contracts;
-//             }
-//             Side::Sell => {
-//                 self.short_contracts += account_positions.quantity.abs();
-//                 self.short_pnl += account_positions.realised_profit_loss;
-//                 self.short_pnl_per_contract = self.short_pnl / self.short_contracts;
-//             }
-//         }
-//     }
-// }
-//
-// impl TableBuilder for ProfitLossSummary {
-//     fn titles(&self) -> Row {
-//         row![
-//             "Long Contracts",
-//             "Long PnL",
-//             "Long PnL Per Contract",
-//             "Short Contracts",
-//             "Short PnL",
-//             "Short PnL Per Contract",
-//             "Total Contracts",
-//             "Total PnL",
-//             "Total PnL Per Contract",
-//         ]
-//     }
-//
-//     fn row(&self) -> Row {
-//         row![
-//             format!("{:.3}", self.long_contracts),
-//             format!("{:.3}", self.long_pnl),
-//             format!("{:.3}", self.long_pnl_per_contract),
-//             format!("{:.3}", self.short_contracts),
-//             format!("{:.3}", self.short_pnl),
-//             format!("{:.3}", self.short_pnl_per_contract),
-//             format!("{:.3}", self.total_contracts),
-//             format!("{:.3}", self.total_pnl),
-//             format!("{:.3}", self.total_pnl_per_contract),
-//         ]
-//     }
-// }
-//
-// impl ProfitLossSummary {
-//     pub fn new() -> Self {
-//         Self::default()
-//     }
-// }
-//
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     use crate::{portfolio::Balance, test_util::account_positions};
-//     use chrono::{Duration, Utc};
-//
-//     #[test]
-//     fn update_pnl_return_summary() {
-//         // Todo:
-//     }
-//
-//     #[test]
-//     fn update_trading_session_duration_with_non_exited_position() {
-//         let base_time = Utc::now();
-//
-//         let mut pnl_return_view = PnLReturnSummary::new();
-//         pnl_return_view.time = base_time;
-//
-//         let mut input_position = account_positions();
-//         input_position.meta.exit_balance = None;
-//         input_position.meta.update_time = base_time.checked_add_signed(Duration::days(10)).unwrap();
-//
-//         pnl_return_view.update_trading_session_duration(&input_position);
-//
-//         let expected = Duration::days(10);
-//
-//         assert_eq!(pnl_return_view.duration, expected);
-//     }
-//
-//     #[test]
-//     fn update_trading_session_duration_with_exited_position() {
-//         let base_time = Utc::now();
-//
-//         let mut pnl_return_view = PnLReturnSummary::new();
-//         pnl_return_view.time = base_time;
-//
-//         let mut input_position = account_positions();
-//         input_position.meta.exit_balance = Some(Balance {
-//             time: base_time.checked_add_signed(Duration::days(15)).unwrap(),
-//             total: 0.0,
-//             available: 0.0,
-//         });
-//
-//         pnl_return_view.update_trading_session_duration(&input_position);
-//
-//         let expected = Duration::days(15);
-//
-//         assert_eq!(pnl_return_view.duration, expected);
-//     }
-// }
+
+impl PnLReturnSummary
+{
+    const SECONDS_IN_DAY: f64 = 86400.0;
+
+    /// 记录 `time` 时的一次收益率，并按 `time` 到起始时间的跨度更新每日交易次数。
+    pub fn update_return(&mut self, time: DateTime<Utc>, pnl_return: f64)
+    {
+        self.total.update(pnl_return);
+        if pnl_return.is_sign_negative() {
+            self.losses.update(pnl_return);
+        }
+
+        self.duration = time.signed_duration_since(self.time);
+        if self.duration > Duration::zero() {
+            self.trades_per_day = self.total.count as f64 / (self.duration.num_seconds() as f64 / Self::SECONDS_IN_DAY);
+        }
+    }
+}
+
+/// 把毫秒时间戳转换为 [`DateTime<Utc>`]，超出范围时返回 Unix 纪元。
+pub(crate) fn datetime_from_millis(timestamp: i64) -> DateTime<Utc>
+{
+    DateTime::from_timestamp_millis(timestamp).unwrap_or_default()
+}
+
+impl ExitedPositionSummariser for PnLReturnSummary
+{
+    fn update(&mut self, position: &PositionExit)
+    {
+        // 第一笔交易的开仓时间作为交易时段的起点
+        if self.total.count == 0 {
+            self.time = datetime_from_millis(position.enter_ts);
+        }
+        self.update_return(datetime_from_millis(position.exit_ts), position.pnl_return());
+    }
+}
+
+impl TableBuilder for PnLReturnSummary
+{
+    fn titles(&self) -> Row
+    {
+        row!["Trades",
+             "Wins",
+             "Losses",
+             "Trading Days",
+             "Trades Per Day",
+             "Mean Return",
+             "Std. Dev. Return",
+             "Loss Mean Return",
+             "Biggest Win",
+             "Biggest Loss",]
+    }
+
+    fn row(&self) -> Row
+    {
+        let wins = self.total.count - self.losses.count;
+        row![self.total.count.to_string(),
+             wins,
+             self.losses.count,
+             self.duration.num_days().to_string(),
+             format!("{:.3}", self.trades_per_day),
+             format!("{:.3}", self.total.mean),
+             format!("{:.3}", self.total.dispersion.std_dev),
+             format!("{:.3}", self.losses.mean),
+             format!("{:.3}", self.total.dispersion.range.high),
+             format!("{:.3}", self.total.dispersion.range.low),]
+    }
+}
+
+/// 按多空方向汇总已平仓仓位的扣费后盈亏，并统计盈利与亏损交易，用于计算胜率、盈亏比与期望收益。
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Default, Deserialize, Serialize)]
+pub struct ProfitLossSummary
+{
+    pub long_contracts: f64,
+    pub long_pnl: f64,
+    pub long_pnl_per_contract: f64,
+    pub short_contracts: f64,
+    pub short_pnl: f64,
+    pub short_pnl_per_contract: f64,
+    pub total_contracts: f64,
+    pub total_pnl: f64,
+    pub total_pnl_per_contract: f64,
+    pub trades: u64,
+    pub wins: u64,
+    pub losses: u64,
+    pub gross_profit: f64, // 盈利交易的盈亏之和
+    pub gross_loss: f64,   // 亏损交易的盈亏之和，为负数
+}
+
+impl ExitedPositionSummariser for ProfitLossSummary
+{
+    fn update(&mut self, position: &PositionExit)
+    {
+        let pnl = position.net_pnl();
+        let contracts = position.quantity.abs();
+        self.total_contracts += contracts;
+        self.total_pnl += pnl;
+        self.total_pnl_per_contract = self.total_pnl / self.total_contracts;
+
+        match position.side {
+            | Side::Buy => {
+                self.long_contracts += contracts;
+                self.long_pnl += pnl;
+                self.long_pnl_per_contract = self.long_pnl / self.long_contracts;
+            }
+            | Side::Sell => {
+                self.short_contracts += contracts;
+                self.short_pnl += pnl;
+                self.short_pnl_per_contract = self.short_pnl / self.short_contracts;
+            }
+        }
+
+        self.trades += 1;
+        if pnl > 0.0 {
+            self.wins += 1;
+            self.gross_profit += pnl;
+        }
+        else if pnl < 0.0 {
+            self.losses += 1;
+            self.gross_loss += pnl;
+        }
+    }
+}
+
+impl TableBuilder for ProfitLossSummary
+{
+    fn titles(&self) -> Row
+    {
+        row!["Long Contracts",
+             "Long PnL",
+             "Long PnL Per Contract",
+             "Short Contracts",
+             "Short PnL",
+             "Short PnL Per Contract",
+             "Total Contracts",
+             "Total PnL",
+             "Total PnL Per Contract",]
+    }
+
+    fn row(&self) -> Row
+    {
+        row![format!("{:.3}", self.long_contracts),
+             format!("{:.3}", self.long_pnl),
+             format!("{:.3}", self.long_pnl_per_contract),
+             format!("{:.3}", self.short_contracts),
+             format!("{:.3}", self.short_pnl),
+             format!("{:.3}", self.short_pnl_per_contract),
+             format!("{:.3}", self.total_contracts),
+             format!("{:.3}", self.total_pnl),
+             format!("{:.3}", self.total_pnl_per_contract),]
+    }
+}
+
+impl ProfitLossSummary
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// 盈利交易占全部交易的比例。
+    pub fn win_rate(&self) -> f64
+    {
+        match self.trades {
+            | 0 => 0.0,
+            | trades => self.wins as f64 / trades as f64,
+        }
+    }
+
+    /// 盈利之和与亏损之和的比值，没有亏损时为 `f64::INFINITY`，没有交易时为 0。
+    pub fn profit_factor(&self) -> f64
+    {
+        match (self.gross_profit == 0.0, self.gross_loss == 0.0) {
+            | (true, true) => 0.0,
+            | (false, true) => f64::INFINITY,
+            | _ => self.gross_profit / self.gross_loss.abs(),
+        }
+    }
+
+    pub fn avg_win(&self) -> f64
+    {
+        match self.wins {
+            | 0 => 0.0,
+            | wins => self.gross_profit / wins as f64,
+        }
+    }
+
+    /// 亏损交易的平均盈亏，为负数。
+    pub fn avg_loss(&self) -> f64
+    {
+        match self.losses {
+            | 0 => 0.0,
+            | losses => self.gross_loss / losses as f64,
+        }
+    }
+
+    /// 每笔交易的期望盈亏：胜率乘以平均盈利，加上败率乘以平均亏损。
+    pub fn expectancy(&self) -> f64
+    {
+        match self.trades {
+            | 0 => 0.0,
+            | trades => {
+                let loss_rate = self.losses as f64 / trades as f64;
+                self.win_rate() * self.avg_win() + loss_rate * self.avg_loss()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_utils::create_test_exited_position;
+
+    #[test]
+    fn pnl_return_summary_should_track_session_duration_and_trades_per_day()
+    {
+        let day = 86_400_000;
+        let mut summary = PnLReturnSummary::default();
+        summary.generate_summary(&[create_test_exited_position(Side::Buy, 0, day, 100.0, 110.0),
+                                   create_test_exited_position(Side::Buy, day, 2 * day, 100.0, 95.0)]);
+
+        assert_eq!(summary.time.timestamp_millis(), 0);
+        assert_eq!(summary.duration, Duration::days(2));
+        assert_eq!(summary.trades_per_day, 1.0);
+        assert_eq!(summary.total.count, 2);
+        assert_eq!(summary.losses.count, 1);
+        assert!((summary.total.mean - 0.025).abs() < 1e-12);
+    }
+
+    #[test]
+    fn profit_loss_summary_should_split_long_short_and_wins_losses()
+    {
+        let mut summary = ProfitLossSummary::new();
+        summary.generate_summary(&[create_test_exited_position(Side::Buy, 0, 1, 100.0, 110.0),
+                                   create_test_exited_position(Side::Sell, 0, 1, 100.0, 80.0),
+                                   create_test_exited_position(Side::Buy, 0, 1, 100.0, 70.0)]);
+
+        assert_eq!(summary.trades, 3);
+        assert_eq!(summary.wins, 2);
+        assert_eq!(summary.losses, 1);
+        assert_eq!(summary.long_pnl, -20.0);
+        assert_eq!(summary.short_pnl, 20.0);
+        assert_eq!(summary.total_contracts, 3.0);
+        assert!((summary.win_rate() - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(summary.profit_factor(), 30.0 / 30.0);
+        assert_eq!(summary.avg_win(), 15.0);
+        assert_eq!(summary.avg_loss(), -30.0);
+        assert!(summary.expectancy().abs() < 1e-12);
+
+        // 没有交易时各项指标为 0
+        let empty = ProfitLossSummary::new();
+        assert_eq!((empty.win_rate(), empty.profit_factor(), empty.expectancy()), (0.0, 0.0, 0.0));
+    }
+}
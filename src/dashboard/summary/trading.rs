@@ -1,134 +1,246 @@
-// use crate::{
-//     portfolio::account_positions::Position,
-//     statistic::{
-//         metrics::ratio::{CalmarRatio, Ratio, SharpeRatio, SortinoRatio},
-//         summary::{
-//             drawdown::DrawdownSummary, pnl::PnLReturnSummary, Initialiser, PositionSummariser,
-//             TableBuilder,
-//         },
-//     },
-// };
-// use chrono::{DateTime, Duration, Utc};
-// use prettytable::{Cell, Row};
-// use serde::{Deserialize, Serialize};
-//
-// /// Configuration for initialising a [`TradingSummary`] via the init() constructor method.
-// #[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Deserialize, Serialize)]
-// pub struct Config {
-//     pub starting_equity: f64,
-//     pub trading_days_per_year: usize,
-//     pub risk_free_return: f64,
-// }
-//
-// #[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Deserialize, Serialize)]
-// pub struct TradingSummary {
-//     pub pnl_returns: PnLReturnSummary,
-//     pub drawdown: DrawdownSummary,
-//     pub tear_sheet: TearSheet,
-// }
-//
-// impl Initialiser for TradingSummary {
-//     type Config = Config;
-//
-//     fn init(config: Self::Config) -> Self {
-//         Self {
-//             pnl_returns: PnLReturnSummary::new(),
-//             drawdown: DrawdownSummary::new(config.starting_equity),
-//             tear_sheet: TearSheet::new(config.risk_free_return),
-//         }
-//     }
-// }
-//
-// impl PositionSummariser for TradingSummary {
-//     fn update(&mut self, account_positions: &Position) {
-//         self.pnl_returns.update(account_positions);
-//         self.drawdown.update(account_positions);
-//         self.tear_sheet.update(&self.pnl_returns, &self.drawdown);
-//     }
-// }
-//
-// impl TableBuilder for TradingSummary {
-//     fn titles(&self) -> Row {
-//         let mut titles = Vec::<Cell>::new();
-//
-//         for title in &self.pnl_returns.titles() {
-//             titles.push(title.clone())
-//         }
-//
-//         for title in &self.tear_sheet.titles() {
-//             titles.push(title.clone())
-//         }
-//
-//         for title in &self.drawdown.titles() {
-//             titles.push(title.clone())
-//         }
-//
-//         Row::new(titles)
-//     }
-//
-//     fn row(&self) -> Row {
-//         let mut cells = Vec::<Cell>::new();
-//
-//         for cell in &self.pnl_returns.row() {
-//             cells.push(cell.clone())
-//         }
-//
-//         for cell in &self.tear_sheet.row() {
-//             cells.push(cell.clone())
-//         }
-//
-//         for cell in &self.drawdown.row() {
-//             cells.push(cell.clone())
-//         }
-//
-//         Row::new(cells)
-//     }
-// }
-//
-// #[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Deserialize, Serialize)]
-// pub struct TearSheet {
-//     pub sharpe_ratio: SharpeRatio,
-//     pub sortino_ratio: SortinoRatio,
-//     pub calmar_ratio: CalmarRatio,
-// }
-//
-// impl TearSheet {
-//     pub fn new(risk_free_return: f64) -> Self {
-//         Self {
-//             sharpe_ratio: SharpeRatio::init(risk_free_return),
-//             sortino_ratio: SortinoRatio::init(risk_free_return),
-//             calmar_ratio: CalmarRatio::init(risk_free_return),
-//         }
-//     }
-//
-//     pub fn update(&mut self, pnl_returns: &PnLReturnSummary, drawdown: &DrawdownSummary) {
-//         self.sharpe_ratio.update(pnl_returns);
-//         self.sortino_ratio.update(pnl_returns);
-//         self.calmar_ratio
-//             .update(pnl_returns, drawdown.max_drawdown.drawdown.drawdown);
-//     }
-// }
-//
-// impl TableBuilder for TearSheet {
-//     fn titles(&self) -> Row {
-//         row!["Sharpe Ratio", "Sortino Ratio", "Calmar Ratio"]
-//     }
-//
-//     fn row(&self) -> Row {
-//         row![
-//             format!("{:.3}", self.sharpe_ratio.daily()),
-//             format!("{:.3}", self.sortino_ratio.daily()),
-//             format!("{:.3}", self.calmar_ratio.daily()),
-//         ]
-//     }
-// }
-//
-// pub fn calculate_trading_duration(start_time: &DateTime<Utc>, account_positions: &Position) -> Duration {
-//     match account_positions.meta.exit_balance {
-//         None => {
-//             // Since Position is not exited, estimate duration w/ last_update_time
-//             account_positions.meta.update_time.signed_duration_since(*start_time)
-//         }
-//         Some(exit_balance) => exit_balance.time.signed_duration_since(*start_time),
-//     }
-// }
+use crate::{
+    common::account_positions::exited_position::PositionExit,
+    dashboard::{
+        de_duration_from_secs, de_f64_or_sentinel,
+        metrics::ratio::{CalmarRatio, Ratio, SharpeRatio, SortinoRatio},
+        se_duration_as_secs, se_f64_or_sentinel,
+        summary::{
+            drawdown::DrawdownSummary,
+            pnl::{PnLReturnSummary, ProfitLossSummary},
+            ExitedPositionSummariser, Initialiser, TableBuilder,
+        },
+    },
+};
+use chrono::Duration;
+use prettytable::{row, Cell, Row};
+use serde::{Deserialize, Serialize};
+
+/// 通过 [`Initialiser::init`] 创建 [`TradingSummary`] 时使用的配置。
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Deserialize, Serialize)]
+pub struct Config
+{
+    pub starting_equity: f64,
+    pub trading_days_per_year: u32,
+    pub risk_free_return: f64,
+}
+
+/// 回测结束后的交易汇总：每笔收益率、多空盈亏、回撤与 [`TearSheet`]。
+///
+/// 通过 [`TableBuilder`] 输出为一行表格，多个账户的汇总可以用 [`combine`](crate::dashboard::summary::combine) 合并。
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Deserialize, Serialize)]
+pub struct TradingSummary
+{
+    pub pnl_returns: PnLReturnSummary,
+    pub profit_loss: ProfitLossSummary,
+    pub drawdown: DrawdownSummary,
+    pub tear_sheet: TearSheet,
+}
+
+impl Initialiser for TradingSummary
+{
+    type Config = Config;
+
+    fn init(config: Self::Config) -> Self
+    {
+        Self { pnl_returns: PnLReturnSummary::default(),
+               profit_loss: ProfitLossSummary::new(),
+               drawdown: DrawdownSummary::new(config.starting_equity),
+               tear_sheet: TearSheet::new(config.risk_free_return, config.trading_days_per_year) }
+    }
+}
+
+impl ExitedPositionSummariser for TradingSummary
+{
+    fn update(&mut self, position: &PositionExit)
+    {
+        self.pnl_returns.update(position);
+        self.profit_loss.update(position);
+        self.drawdown.update(position);
+        self.tear_sheet.exposure += position.duration();
+        self.tear_sheet.update(&self.pnl_returns, &self.profit_loss, &self.drawdown);
+    }
+}
+
+impl TradingSummary
+{
+    /// 用初始配置与按平仓时间排序的已平仓仓位生成汇总。
+    pub fn generate(config: Config, positions: &[PositionExit]) -> Self
+    {
+        let mut summary = Self::init(config);
+        summary.generate_summary(positions);
+        summary
+    }
+}
+
+impl TableBuilder for TradingSummary
+{
+    fn titles(&self) -> Row
+    {
+        let mut titles = Vec::<Cell>::new();
+
+        for title in &self.pnl_returns.titles() {
+            titles.push(title.clone())
+        }
+
+        for title in &self.tear_sheet.titles() {
+            titles.push(title.clone())
+        }
+
+        for title in &self.drawdown.titles() {
+            titles.push(title.clone())
+        }
+
+        Row::new(titles)
+    }
+
+    fn row(&self) -> Row
+    {
+        let mut cells = Vec::<Cell>::new();
+
+        for cell in &self.pnl_returns.row() {
+            cells.push(cell.clone())
+        }
+
+        for cell in &self.tear_sheet.row() {
+            cells.push(cell.clone())
+        }
+
+        for cell in &self.drawdown.row() {
+            cells.push(cell.clone())
+        }
+
+        Row::new(cells)
+    }
+}
+
+/// 交易策略的绩效概览：胜率、盈亏比、平均盈亏、期望收益、交易频率、持仓时间、风险调整收益与最大回撤。
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Deserialize, Serialize)]
+pub struct TearSheet
+{
+    pub trading_days_per_year: u32, // 年化比率时使用的每年交易天数
+    pub win_rate: f64,
+    #[serde(deserialize_with = "de_f64_or_sentinel", serialize_with = "se_f64_or_sentinel")]
+    pub profit_factor: f64, // 没有亏损时为无穷大
+    pub avg_win: f64,
+    pub avg_loss: f64,
+    pub expectancy: f64, // 每笔交易的期望盈亏
+    pub trades_per_day: f64,
+    #[serde(deserialize_with = "de_duration_from_secs", serialize_with = "se_duration_as_secs")]
+    pub exposure: Duration, // 各仓位持仓时长之和
+    pub exposure_ratio: f64, // 持仓时长之和占交易时段的比例，同时持有多个仓位时可能大于 1
+    pub sharpe_ratio: SharpeRatio,
+    pub sortino_ratio: SortinoRatio,
+    pub calmar_ratio: CalmarRatio,
+    pub max_drawdown: f64,
+}
+
+impl TearSheet
+{
+    pub fn new(risk_free_return: f64, trading_days_per_year: u32) -> Self
+    {
+        Self { trading_days_per_year,
+               win_rate: 0.0,
+               profit_factor: 0.0,
+               avg_win: 0.0,
+               avg_loss: 0.0,
+               expectancy: 0.0,
+               trades_per_day: 0.0,
+               exposure: Duration::zero(),
+               exposure_ratio: 0.0,
+               sharpe_ratio: SharpeRatio::init(risk_free_return),
+               sortino_ratio: SortinoRatio::init(risk_free_return),
+               calmar_ratio: CalmarRatio::init(risk_free_return),
+               max_drawdown: 0.0 }
+    }
+
+    pub fn update(&mut self, pnl_returns: &PnLReturnSummary, profit_loss: &ProfitLossSummary, drawdown: &DrawdownSummary)
+    {
+        self.win_rate = profit_loss.win_rate();
+        self.profit_factor = profit_loss.profit_factor();
+        self.avg_win = profit_loss.avg_win();
+        self.avg_loss = profit_loss.avg_loss();
+        self.expectancy = profit_loss.expectancy();
+        self.trades_per_day = pnl_returns.trades_per_day;
+        self.exposure_ratio = match pnl_returns.duration.num_milliseconds() {
+            | 0 => 0.0,
+            | session => self.exposure.num_milliseconds() as f64 / session as f64,
+        };
+        self.max_drawdown = drawdown.max_drawdown.drawdown.drawdown;
+        self.sharpe_ratio.update(pnl_returns);
+        self.sortino_ratio.update(pnl_returns);
+        self.calmar_ratio.update(pnl_returns, self.max_drawdown);
+    }
+}
+
+impl TableBuilder for TearSheet
+{
+    fn titles(&self) -> Row
+    {
+        row!["Win Rate",
+             "Profit Factor",
+             "Avg. Win",
+             "Avg. Loss",
+             "Expectancy",
+             "Exposure Hours",
+             "Exposure Ratio",
+             "Sharpe Ratio",
+             "Sortino Ratio",
+             "Calmar Ratio",
+             "Annual Sharpe Ratio",]
+    }
+
+    fn row(&self) -> Row
+    {
+        row![format!("{:.3}", self.win_rate),
+             format!("{:.3}", self.profit_factor),
+             format!("{:.3}", self.avg_win),
+             format!("{:.3}", self.avg_loss),
+             format!("{:.3}", self.expectancy),
+             self.exposure.num_hours().to_string(),
+             format!("{:.3}", self.exposure_ratio),
+             format!("{:.3}", self.sharpe_ratio.daily()),
+             format!("{:.3}", self.sortino_ratio.daily()),
+             format!("{:.3}", self.calmar_ratio.daily()),
+             format!("{:.3}", self.sharpe_ratio.annual(self.trading_days_per_year)),]
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{common::Side, dashboard::summary::combine, test_utils::create_test_exited_position};
+
+    #[test]
+    fn tear_sheet_should_summarise_exited_positions()
+    {
+        let hour = 3_600_000;
+        let config = Config { starting_equity: 1_000.0,
+                              trading_days_per_year: 365,
+                              risk_free_return: 0.0 };
+        let summary = TradingSummary::generate(config, &[create_test_exited_position(Side::Buy, 0, 6 * hour, 100.0, 110.0),
+                                                         create_test_exited_position(Side::Sell, 12 * hour, 18 * hour, 100.0, 105.0),
+                                                         create_test_exited_position(Side::Buy, 24 * hour, 48 * hour, 100.0, 120.0)]);
+        let tear_sheet = summary.tear_sheet;
+
+        assert!((tear_sheet.win_rate - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(tear_sheet.profit_factor, 30.0 / 5.0);
+        assert_eq!(tear_sheet.avg_win, 15.0);
+        assert_eq!(tear_sheet.avg_loss, -5.0);
+        assert!((tear_sheet.expectancy - 25.0 / 3.0).abs() < 1e-12);
+        assert!((tear_sheet.trades_per_day - 1.5).abs() < 1e-12);
+        assert_eq!(tear_sheet.exposure, Duration::hours(36));
+        assert!((tear_sheet.exposure_ratio - 0.75).abs() < 1e-12);
+        assert!((tear_sheet.max_drawdown + 5.0 / 1_010.0).abs() < 1e-12);
+        assert!(tear_sheet.sharpe_ratio.ratio() > 0.0);
+        // 只有一笔亏损时下行波动为 0，Sortino Ratio 记为 0
+        assert_eq!(tear_sheet.sortino_ratio.ratio(), 0.0);
+        assert!(tear_sheet.calmar_ratio.ratio() > 0.0);
+
+        // 每个账户的汇总占一行，标题与每行的列数一致
+        let table = combine(vec![("alice".to_string(), summary), ("bob".to_string(), TradingSummary::init(config))]);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get_row(0).unwrap().len(), summary.titles().len() + 1);
+        assert!(table.to_string().contains("Profit Factor"));
+    }
+}
@@ -57,6 +57,23 @@ pub fn update_variance_accumulator(prev_m: f64, prev_mean: f64, new_value: f64,
     prev_m + ((new_value - prev_mean) * (new_value - new_mean))
 }
 
+/// 移除一个数据点后的均值，`count` 为移除后的数量，数量为 0 时均值为 0。
+pub fn downdate_mean(prev_mean: f64, removed_value: f64, count: f64) -> f64
+{
+    match count > 0.0 {
+        | true => prev_mean - (removed_value - prev_mean) / count,
+        | false => 0.0,
+    }
+}
+
+/// 移除一个数据点后的递推关系 M，与 [`update_variance_accumulator`] 互逆。
+///
+/// 反复移除会累积浮点误差，结果甚至可能为负，调用方需要定期用剩余的数据点重新计算。
+pub fn downdate_variance_accumulator(prev_m: f64, prev_mean: f64, removed_value: f64, new_mean: f64) -> f64
+{
+    prev_m - ((removed_value - new_mean) * (removed_value - prev_mean))
+}
+
 /// 使用贝塞尔校正（count - 1）和 Welford 在线递推关系 M 计算下一个无偏的“样本”方差。
 pub fn compute_sample_variance(recurrence_relation_m: f64, count: u64) -> f64
 {
@@ -204,4 +221,25 @@ mod tests
             assert_eq!(actual_variance, expected);
         }
     }
+
+    #[test]
+    fn downdate_should_reverse_update()
+    {
+        let values = [0.1, -0.2, -0.05, 0.2, 0.15];
+        let (mut mean, mut m) = (0.0, 0.0);
+        for (index, value) in values.iter().enumerate() {
+            let new_mean = update_mean(mean, *value, (index + 1) as f64);
+            m = update_variance_accumulator(m, mean, *value, new_mean);
+            mean = new_mean;
+        }
+
+        // 移除第一个数据点后与只用其余数据点计算的结果一致
+        let new_mean = downdate_mean(mean, values[0], 4.0);
+        let new_m = downdate_variance_accumulator(m, mean, values[0], new_mean);
+        let rest_mean = values[1..].iter().sum::<f64>() / 4.0;
+        let rest_m = values[1..].iter().map(|value| (value - rest_mean).powi(2)).sum::<f64>();
+        assert!((new_mean - rest_mean).abs() < 1e-12);
+        assert!((new_m - rest_m).abs() < 1e-12);
+        assert_eq!(downdate_mean(0.1, 0.1, 0.0), 0.0);
+    }
 }
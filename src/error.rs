@@ -89,9 +89,16 @@ pub enum ExchangeError
     #[error("Invalid dates: {0}")]
     InvalidDates(String),
 
-    /// API 限制，达到调用限制。
-    #[error("API limit reached, unable to proceed")]
-    ApiLimitReached,
+    /// API 限制，达到调用限制，`retry_after_ms` 为建议的重试等待时间。
+    #[error("API limit reached, retry after {retry_after_ms} ms")]
+    ApiLimitReached
+    {
+        retry_after_ms: u64
+    },
+
+    /// 请求的权重超过限流额度的容量，无论等待多久都无法通过。
+    #[error("Request exceeds the rate limit capacity: {0}")]
+    RequestTooHeavy(String),
 
     /// 权限不足，无法执行操作。
     #[error("Insufficient permissions to perform operation")]
@@ -187,4 +194,29 @@ pub enum ExchangeError
 
     #[error("PasswordHashError.")]
     PasswordHashError,
+
+    #[error("User already exists: {0}")]
+    UserAlreadyExists(String),
+
+    #[error("SessionExpired.")]
+    SessionExpired,
+
+    #[error("Account not found: {0}")]
+    AccountNotFound(String),
+
+    /// 回测数据未通过质量审计。
+    #[error("Data quality check failed: {0}")]
+    DataQuality(String),
+
+    /// 检查点无法保存、读取或恢复。
+    #[error("Checkpoint error: {0}")]
+    Checkpoint(String),
+
+    /// 审计日志无法写入或读取。
+    #[error("Journal error: {0}")]
+    Journal(String),
+
+    /// 回测结果无法导出。
+    #[error("Export error: {0}")]
+    Export(String),
 }
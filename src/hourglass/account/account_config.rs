@@ -5,6 +5,7 @@ use crate::{
     },
     error::ExchangeError,
     hourglass::utils::config_parser::read_config_file,
+    vault::VaultConfig,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -23,6 +24,10 @@ pub struct AccountConfig
     pub max_price_deviation: f64,                              // 最大价格偏差，用于限制订单价格与市场价格的偏离范围
     pub lazy_account_positions: bool,                          // 是否惰性更新以节约性能
     pub liquidation_threshold: f64,                            // 平仓的门槛，通常为一个0.9~1的系数
+    #[serde(default)]
+    pub vault: VaultConfig,                                    // 仓位、余额与统计数据的存储方式，缺省为内存
+    #[serde(default)]
+    pub equity_sample_interval: Option<i64>,                   // 权益曲线的采样间隔（毫秒，交易所时间），缺省不采样
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -157,6 +162,8 @@ pub struct AccountConfigBuilder
     max_price_deviation: Option<f64>,
     lazy_account_positions: Option<bool>,
     liquidation_threshold: Option<f64>,
+    vault: Option<VaultConfig>,
+    equity_sample_interval: Option<i64>,
 }
 
 impl Default for AccountConfigBuilder
@@ -180,7 +187,9 @@ impl AccountConfigBuilder
                execution_mode: None,
                max_price_deviation: None,
                lazy_account_positions: None,
-               liquidation_threshold: None }
+               liquidation_threshold: None,
+               vault: None,
+               equity_sample_interval: None }
     }
 
     pub fn margin_mode(mut self, margin_mode: MarginMode) -> Self
@@ -260,6 +269,19 @@ impl AccountConfigBuilder
         }
     }
 
+    pub fn vault(mut self, vault: VaultConfig) -> Self
+    {
+        self.vault = Some(vault);
+        self
+    }
+
+    /// 每隔 `interval_millis` 毫秒交易所时间对账户总权益采样一次，见 [`crate::dashboard::equity::EquityTracker`]。
+    pub fn equity_sample_interval(mut self, interval_millis: i64) -> Self
+    {
+        self.equity_sample_interval = Some(interval_millis);
+        self
+    }
+
     pub fn initiate(self) -> Result<AccountConfig, &'static str>
     {
         Ok(AccountConfig { margin_mode: self.margin_mode.ok_or("margin_mode is required")?,
@@ -272,6 +294,8 @@ impl AccountConfigBuilder
                            execution_mode: HourglassMode::Backtest,
                            max_price_deviation: self.max_price_deviation.ok_or("max price deviation is required")?,
                            lazy_account_positions: self.lazy_account_positions.ok_or("lazy_account_positions switch is required")?,
-                           liquidation_threshold: self.liquidation_threshold.ok_or("liquidation threshold is required")? })
+                           liquidation_threshold: self.liquidation_threshold.ok_or("liquidation threshold is required")?,
+                           vault: self.vault.unwrap_or_default(),
+                           equity_sample_interval: self.equity_sample_interval })
     }
 }
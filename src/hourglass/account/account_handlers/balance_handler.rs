@@ -116,6 +116,7 @@ impl BalanceHandler for HourglassAccount
     /// [`Balance`]的变化取决于[`Order<Open>`]是[`Side::Buy`]还是[`Side::Sell`]。
     fn apply_cancel_order_changes(&mut self, cancelled: &Order<Open>) -> Result<AccountEvent, ExchangeError>
     {
+        let now = self.clock.now();
         let updated_balance = match cancelled.side {
             | Side::Buy => {
                 info!("[apply_cancel_order_changes] : applying cancelled balance");
@@ -123,12 +124,14 @@ impl BalanceHandler for HourglassAccount
                 info!("[apply_cancel_order_changes] : balance before application of change: {:?}", *balance);
                 info!("[apply_cancel_order_changes] : cancelled order's price is : {:?}", cancelled.state.price);
                 balance.available += cancelled.state.price * cancelled.state.remaining_quantity();
+                balance.time = now;
                 info!("[apply_cancel_order_changes] : balance after application of change: {:?}", *balance);
                 *balance
             }
             | Side::Sell => {
                 let mut balance = self.get_balance_mut(&cancelled.instrument.base).expect("Balance existence checked when opening Order");
                 balance.available += cancelled.state.price * cancelled.state.remaining_quantity();
+                balance.time = now;
                 *balance
             }
         };
@@ -223,9 +226,10 @@ impl BalanceHandler for HourglassAccount
     /// 将 [`BalanceDelta`] 应用于指定 [`Token`] 的 [`Balance`]，并返回更新后的 [`Balance`] 。
     fn apply_balance_delta(&mut self, token: &Token, delta: BalanceDelta) -> Balance
     {
+        let now = self.clock.now();
         let mut base_balance = self.get_balance_mut(token).unwrap();
 
-        let _ = base_balance.apply(delta);
+        let _ = base_balance.apply_at(delta, now);
 
         *base_balance
     }
@@ -94,6 +94,7 @@ pub trait PositionHandler
 
     async fn remove_option_position(&self, instrument: Instrument, side: Side) -> Option<OptionPosition>;
 
+    /// 把平仓记入已退出的仓位，`side` 为仓位的方向。
     async fn register_exit_position(&self, meta: &PositionMeta, side: Side, exit_margin: Option<f64>) -> Result<(), ExchangeError>;
 
     async fn get_position_long_config(&self, instrument: &Instrument) -> Result<Option<PerpetualPositionConfig>, ExchangeError>;
@@ -102,7 +103,7 @@ pub trait PositionHandler
     // 更新已有仓位
     async fn update_existing_position(&mut self, trade: ClientTrade) -> Result<(), ExchangeError>;
     // 关闭仓位
-    async fn close_position(&mut self, instrument: Instrument, side: Side) -> Result<(), ExchangeError>;
+    async fn close_position(&mut self, trade: &ClientTrade) -> Result<(), ExchangeError>;
     // 关闭并反向开仓
 
     async fn check_and_handle_liquidation(&mut self, trade: &MarketTrade) -> Result<(), ExchangeError>;
@@ -652,6 +653,7 @@ impl PositionHandler for HourglassAccount
     {
         // 通过调用 determine_handling_type 确定该交易的处理方式
         let handling_type = self.determine_handling_type(trade.clone()).await?;
+        let instrument = trade.instrument.clone();
 
         // 根据处理类型调用不同的处理逻辑
         match handling_type {
@@ -665,7 +667,7 @@ impl PositionHandler for HourglassAccount
             }
             | PositionHandling::CloseComplete => {
                 info!("executing PositionHandling::CloseComplete");
-                self.close_position(trade.instrument.clone(), trade.side).await?;
+                self.close_position(&trade).await?;
             }
             | PositionHandling::CloseCompleteAndReverse { remaining_size: reverse_size } => {
                 info!("executing PositionHandling::CloseCompleteAndReverse");
@@ -676,19 +678,25 @@ impl PositionHandler for HourglassAccount
                 self.partial_close_position(trade).await?;
             }
         }
+        // 仓位变化后写入仓库
+        self.persist_positions(&instrument).await;
 
         Ok(())
     }
 
     async fn remove_position(&self, instrument: Instrument, side: Side) -> Option<Position>
     {
-        match instrument.kind {
+        let removed = match instrument.kind {
             | InstrumentKind::Perpetual => self.remove_perpetual_position(instrument, side).await.map(Position::Perpetual),
             | InstrumentKind::Future => self.remove_future_position(instrument, side).await.map(Position::Future),
             | InstrumentKind::CryptoLeveragedToken => self.remove_leveraged_token_position(instrument, side).await.map(Position::LeveragedToken),
             | InstrumentKind::CryptoOption => self.remove_option_position(instrument, side).await.map(Position::Option),
             | _ => None,
+        };
+        if let Some(position) = &removed {
+            self.persist_exited_position(position);
         }
+        removed
     }
 
     async fn remove_perpetual_position(&self, instrument: Instrument, side: Side) -> Option<PerpetualPosition>
@@ -862,59 +870,40 @@ impl PositionHandler for HourglassAccount
     }
 
     /// FIXME 支持的金融工具太少了。
-    /// 关闭仓位
-    async fn close_position(&mut self, instrument: Instrument, side: Side) -> Result<(), ExchangeError>
+    /// 用 `trade` 完全平掉方向相反的仓位，平仓价格与时间取自该成交。
+    async fn close_position(&mut self, trade: &ClientTrade) -> Result<(), ExchangeError>
     {
-        match side {
-            | Side::Buy => {
-                // 处理空头仓位关闭
-                let position = self.get_position_short(&instrument).await?;
-                if let Some(Position::Perpetual(position)) = position {
-                    match position.pos_config.pos_margin_mode {
-                        | PositionMarginMode::Cross => {
-                            // 减去对应的保证金
-                            let margin_to_subtract = position.meta.current_size / position.pos_config.leverage;
-                            self.account_margin.fetch_sub(margin_to_subtract, Ordering::SeqCst);
-                            self.register_exit_position(&position.meta, side, None).await?;
-                        }
-                        | PositionMarginMode::Isolated => {
-                            // 并不清空 isolated 保证金，只需要 dump
-                            self.register_exit_position(&position.meta, side, position.isolated_margin).await?;
-                        }
-                    };
-                }
-                else {
-                    // 返回不支持的仓位类型错误
-                    return Err(ExchangeError::UnsupportedInstrumentKind);
-                }
-
-                // 使用 `ok_or` 将 `Option` 转换为 `Result`
-                self.remove_position(instrument, Side::Sell).await.ok_or(ExchangeError::AttemptToRemoveNonExistingPosition)?;
+        // 买入平空，卖出平多
+        let position_side = trade.side.toggle();
+        let position = match position_side {
+            | Side::Buy => self.get_position_long(&trade.instrument).await?,
+            | Side::Sell => self.get_position_short(&trade.instrument).await?,
+        };
+        let Some(Position::Perpetual(mut position)) = position
+        else {
+            // 返回不支持的仓位类型错误
+            return Err(ExchangeError::UnsupportedInstrumentKind);
+        };
+        let exit_margin = match position.pos_config.pos_margin_mode {
+            | PositionMarginMode::Cross => {
+                // 减去对应的保证金
+                let margin_to_subtract = position.meta.current_size / position.pos_config.leverage;
+                self.account_margin.fetch_sub(margin_to_subtract, Ordering::SeqCst);
+                None
             }
-            | Side::Sell => {
-                // 处理多头仓位关闭
-                let position = self.get_position_long(&instrument).await?;
-                if let Some(Position::Perpetual(mut position)) = position {
-                    match position.pos_config.pos_margin_mode {
-                        | PositionMarginMode::Cross => {
-                            // 减去对应的保证金
-                            let margin_to_subtract = position.meta.current_size / position.pos_config.leverage;
-                            self.account_margin.fetch_sub(margin_to_subtract, Ordering::SeqCst);
-                        }
-                        | PositionMarginMode::Isolated => {
-                            // 清空 isolated 保证金
-                            position.isolated_margin = Some(0.0);
-                        }
-                    }
-                }
-                else {
-                    // 返回不支持的仓位类型错误
-                    return Err(ExchangeError::UnsupportedInstrumentKind);
-                }
+            // 并不清空 isolated 保证金，只需要 dump
+            | PositionMarginMode::Isolated => position.isolated_margin,
+        };
 
-                self.remove_position(instrument, Side::Buy).await.ok_or(ExchangeError::AttemptToRemoveNonExistingPosition)?;
-            }
-        }
+        // 反手成交只有平仓部分的手续费计入该仓位
+        let closing = position.meta.current_size.min(trade.size);
+        position.meta.update_ts = trade.timestamp;
+        position.meta.current_symbol_price = trade.price;
+        position.meta.current_fees_total += trade.fees * closing / trade.size;
+        self.register_exit_position(&position.meta, position_side, exit_margin).await?;
+
+        // 使用 `ok_or` 将 `Option` 转换为 `Result`
+        self.remove_position(trade.instrument.clone(), position_side).await.ok_or(ExchangeError::AttemptToRemoveNonExistingPosition)?;
         Ok(())
     }
 
@@ -981,7 +970,7 @@ impl PositionHandler for HourglassAccount
     // 关闭并反向开仓
     async fn close_and_reverse_position(&mut self, trade: ClientTrade, remaining: f64) -> Result<(), ExchangeError>
     {
-        self.close_position(trade.instrument.clone(), trade.side).await?;
+        self.close_position(&trade).await?;
         // Ignore the returned `PerpetualPosition`
         let _ = self.create_perpetual_position(trade.clone(), CloseCompleteAndReverse { remaining_size: remaining }).await?;
         Ok(())
@@ -1048,6 +1037,8 @@ impl PositionHandler for HourglassAccount
                     if trade.size > position.meta.current_size {
                         return Err(ExchangeError::InvalidTradeSize);
                     }
+                    // 平掉的部分按平仓前的均价记入已退出的仓位
+                    let exited = PositionExit::from_partial_close(&position.meta, &trade, None);
                     position.meta.update_from_trade(&trade); // 更新 PositionMeta
                                                              // 根据保证金模式调整保证金
                     match position.pos_config.pos_margin_mode {
@@ -1066,6 +1057,7 @@ impl PositionHandler for HourglassAccount
                             }
                         }
                     }
+                    self.exited_positions.insert_perpetual_pos_long(exited).await;
                 }
                 else {
                     return Err(ExchangeError::AttemptToRemoveNonExistingPosition);
@@ -1080,6 +1072,8 @@ impl PositionHandler for HourglassAccount
                     if trade.size > position.meta.current_size {
                         return Err(ExchangeError::InvalidTradeSize);
                     }
+                    // 平掉的部分按平仓前的均价记入已退出的仓位
+                    let exited = PositionExit::from_partial_close(&position.meta, &trade, None);
                     position.meta.update_from_trade(&trade); // 更新 PositionMeta
 
                     // 根据保证金模式调整保证金
@@ -1097,6 +1091,7 @@ impl PositionHandler for HourglassAccount
                             }
                         }
                     }
+                    self.exited_positions.insert_perpetual_pos_short(exited).await;
                 }
                 else {
                     return Err(ExchangeError::AttemptToRemoveNonExistingPosition);
@@ -1127,6 +1122,7 @@ mod tests
     use crate::{
         common::{order::identification::OrderId, token::Token, trade::ClientTradeId},
         test_utils::create_test_account,
+        vault::{BalanceProcessor, PositionProcessor},
         Exchange,
     };
     // #[tokio::test]
@@ -1583,6 +1579,97 @@ mod tests
         assert_eq!(pos.meta.current_size, 5.0); // 剩余仓位为5
     }
 
+    #[tokio::test]
+    async fn positions_and_balance_should_be_persisted_to_vault()
+    {
+        let mut account = create_test_account().await;
+        let instrument = Instrument { base: Token("BTC".to_string()),
+                                      quote: Token("USDT".to_string()),
+                                      kind: InstrumentKind::Perpetual };
+        let trade = |side: Side| ClientTrade { exchange: Exchange::Hourglass,
+                                               timestamp: 1690000000,
+                                               trade_id: ClientTradeId(5),
+                                               order_id: Some(OrderId(5)),
+                                               cid: None,
+                                               instrument: instrument.clone(),
+                                               side,
+                                               price: 100.0,
+                                               size: 10.0,
+                                               fees: 0.1 };
+        let preconfig = PerpetualPositionConfig { pos_margin_mode: PositionMarginMode::Isolated,
+                                                  leverage: 1.0,
+                                                  position_direction_mode: PositionDirectionMode::Net };
+        account.positions.perpetual_pos_long_config.write().await.insert(instrument.clone(), preconfig);
+        let session = account.current_session;
+
+        // 开仓后仓库中有对应的持仓
+        account.update_position_from_client_trade(trade(Side::Buy)).await.unwrap();
+        let long = account.get_position_long(&instrument).await.unwrap().unwrap();
+        let stored = account.vault.lock().get_open_positions(session, Exchange::Hourglass, instrument.clone()).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].meta(), long.meta());
+
+        // 平仓后持仓移入已退出的仓位
+        account.update_position_from_client_trade(trade(Side::Sell)).await.unwrap();
+        let mut vault = account.vault.lock();
+        assert!(vault.get_open_positions(session, Exchange::Hourglass, instrument.clone()).unwrap().is_empty());
+        assert_eq!(vault.get_exited_positions(session).unwrap().len(), 1);
+        drop(vault);
+
+        // 充值后仓库中每个币种的余额都跟随账户余额
+        account.deposit_usdt(500.0).unwrap();
+        let mut vault = account.vault.lock();
+        for token in [Token::usdt(), Token::from("ETH")] {
+            assert_eq!(vault.get_balance(session, &token).unwrap().total, account.balances.get(&token).unwrap().total);
+        }
+    }
+
+    #[tokio::test]
+    async fn closes_should_register_side_aware_exits()
+    {
+        let mut account = create_test_account().await;
+        let instrument = Instrument { base: Token("BTC".to_string()),
+                                      quote: Token("USDT".to_string()),
+                                      kind: InstrumentKind::Perpetual };
+        let trade = |id: i64, side: Side, price: f64, size: f64| ClientTrade { exchange: Exchange::Hourglass,
+                                                                               timestamp: 1690000000 + id,
+                                                                               trade_id: ClientTradeId(id),
+                                                                               order_id: Some(OrderId(id as u64)),
+                                                                               cid: None,
+                                                                               instrument: instrument.clone(),
+                                                                               side,
+                                                                               price,
+                                                                               size,
+                                                                               fees: 0.0 };
+        let preconfig = PerpetualPositionConfig { pos_margin_mode: PositionMarginMode::Cross,
+                                                  leverage: 1.0,
+                                                  position_direction_mode: PositionDirectionMode::Net };
+        account.positions.perpetual_pos_short_config.write().await.insert(instrument.clone(), preconfig.clone());
+        account.positions.perpetual_pos_long_config.write().await.insert(instrument.clone(), preconfig);
+
+        // 空头 100 开 2 张，90 平 1 张，80 平剩下的 1 张；多头 100 开 1 张，95 平仓
+        for trade in [trade(1, Side::Sell, 100.0, 2.0),
+                      trade(2, Side::Buy, 90.0, 1.0),
+                      trade(3, Side::Buy, 80.0, 1.0),
+                      trade(4, Side::Buy, 100.0, 1.0),
+                      trade(5, Side::Sell, 95.0, 1.0)]
+        {
+            account.update_position_from_client_trade(trade).await.unwrap();
+        }
+
+        let exits = account.exited_positions.all_exits().await;
+        let pnls = exits.iter().map(|exit| (exit.side, exit.quantity, exit.realised_pnl)).collect::<Vec<_>>();
+        assert_eq!(pnls, vec![(Side::Sell, 1.0, 10.0), (Side::Sell, 1.0, 20.0), (Side::Buy, 1.0, -5.0)]);
+        assert_eq!(account.exited_positions.perpetual_pos_short.read().await.len(), 2);
+
+        let summary = account.trading_summary(crate::dashboard::summary::trading::Config { starting_equity: 10_000.0,
+                                                                                           trading_days_per_year: 365,
+                                                                                           risk_free_return: 0.0 })
+                             .await;
+        assert!((summary.tear_sheet.win_rate - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(summary.tear_sheet.profit_factor, 6.0);
+    }
+
     #[tokio::test]
     async fn test_close_long_position_completely()
     {
@@ -1,8 +1,9 @@
 use crate::{
     common::{
+        account_positions::AccountPositions,
         event::{AccountEvent, AccountEventKind},
         instrument::{kind::InstrumentKind, Instrument},
-        order::OrderRole,
+        order::{states::open::Open, Order, OrderRole},
         token::Token,
         trade::ClientTrade,
         Side,
@@ -36,6 +37,9 @@ pub trait TradeHandler
 
     async fn match_orders(&mut self, market_trade: &MarketTrade) -> Result<Vec<ClientTrade>, ExchangeError>;
 
+    /// 本账户的挂单与其他账户的订单成交，`order` 为成交前的订单，`role` 决定手续费比例。
+    async fn fill_agent_order(&mut self, order: &Order<Open>, price: f64, size: f64, role: OrderRole) -> Result<ClientTrade, ExchangeError>;
+
     async fn fees_percent(&self, instrument_kind: &InstrumentKind, role: OrderRole) -> Result<f64, ExchangeError>;
     /// 处理客户端交易列表并更新账户余额及交易事件。
     ///
@@ -100,6 +104,8 @@ impl TradeHandler for HourglassAccount
         // 用交易所记录的用户的挂单去匹配 market_rade 以实现模拟的目的
         self.check_and_handle_liquidation(trade).await?;
         self.match_orders(&trade).await?;
+        // 成交处理完后按交易所时间采样权益
+        self.sample_equity().await;
         Ok(())
     }
 
@@ -182,6 +188,44 @@ impl TradeHandler for HourglassAccount
         Ok(trades)
     }
 
+    /// 账户之间的成交不经过 `MarketTrade`，直接按成交数量更新挂单，再复用 [`TradeHandler::process_trade`] 更新余额并发送事件。
+    async fn fill_agent_order(&mut self, order: &Order<Open>, price: f64, size: f64, role: OrderRole) -> Result<ClientTrade, ExchangeError>
+    {
+        let fees_percent = self.fees_percent(&order.instrument.kind, role).await?;
+
+        // 完全成交的订单从挂单中移除，部分成交的订单累加成交数量
+        {
+            let orders_guard = self.account_open_book.read().await;
+            let mut instrument_orders = orders_guard.get_ins_orders_mut(&order.instrument)?;
+            let orders = match order.side {
+                | Side::Buy => &mut instrument_orders.bids,
+                | Side::Sell => &mut instrument_orders.asks,
+            };
+            let index = orders.iter()
+                              .position(|open| open.state.id == order.state.id)
+                              .ok_or_else(|| ExchangeError::OrderNotFound { client_order_id: order.cid.clone(),
+                                                                            order_id: Some(order.state.id.clone()) })?;
+            orders[index].state.filled_quantity += size;
+            if orders[index].state.remaining_quantity() <= 0.0 {
+                orders.remove(index);
+            }
+        }
+
+        let trade_id = self.client_trade_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let trade = ClientTrade { exchange: Exchange::Hourglass,
+                                  timestamp: self.exchange_timestamp.load(Ordering::SeqCst),
+                                  trade_id: trade_id.into(),
+                                  order_id: Some(order.state.id.clone()),
+                                  cid: order.cid.clone(),
+                                  instrument: order.instrument.clone(),
+                                  side: order.side,
+                                  price,
+                                  size,
+                                  fees: size * price * fees_percent };
+        self.process_trade(trade.clone()).await?;
+        Ok(trade)
+    }
+
     /// 根据金融工具类型和订单角色返回相应的手续费百分比。 NOTE 需要扩展并支持现货和期货。
     ///
     /// # 参数
@@ -221,6 +265,7 @@ impl TradeHandler for HourglassAccount
     /// 1. 更新账户的相关余额信息。
     /// 2. 发送交易事件 `AccountEventKind::Trade`。
     /// 3. 发送余额更新事件 `AccountEventKind::Balance`。
+    /// 4. `publish_positions` 为真时发送仓位快照 `AccountEventKind::Positions`。
     ///
     /// # 参数
     ///
@@ -246,6 +291,14 @@ impl TradeHandler for HourglassAccount
                 return Err(err);
             }
         };
+        if trade.order_id.is_some() && trade.instrument.kind == InstrumentKind::Perpetual {
+            if let Err(err) = self.update_position_from_client_trade(trade.clone()).await {
+                warn!("Failed to update position: {:?}", err);
+            }
+        }
+        let mode = self.position_direction_mode(&trade.instrument).await;
+        self.pnl_attribution.record_fill(&trade, &mode);
+        self.persist_balance();
 
         // 发送交易事件
         if let Err(err) = self.account_event_tx.send(AccountEvent { exchange_timestamp,
@@ -260,6 +313,17 @@ impl TradeHandler for HourglassAccount
             warn!("Client offline - Failed to send AccountEvent::Balance: {:?}", err);
         }
 
+        // 仓位快照取自处理这笔成交时的状态，不会混入之后的成交
+        if self.publish_positions {
+            let positions = AccountPositions::from_positions(self.positions.all_positions().await).await;
+            if let Err(err) = self.account_event_tx.send(AccountEvent { exchange_timestamp,
+                                                                        exchange: Exchange::Hourglass,
+                                                                        kind: AccountEventKind::Positions(positions) })
+            {
+                warn!("Client offline - Failed to send AccountEvent::Positions: {:?}", err);
+            }
+        }
+
         Ok(())
     }
 
@@ -290,11 +354,14 @@ mod tests
 {
     use super::*;
     use crate::{
-        common::order::{
-            identification::{client_order_id::ClientOrderId, OrderId},
-            order_instructions::OrderInstruction,
-            states::{open::Open, request_cancel::RequestCancel, request_open::RequestOpen},
-            Order,
+        common::{
+            account_positions::{perpetual::PerpetualPositionConfig, PositionDirectionMode, PositionMarginMode},
+            order::{
+                identification::{client_order_id::ClientOrderId, OrderId},
+                order_instructions::OrderInstruction,
+                states::{open::Open, request_cancel::RequestCancel, request_open::RequestOpen},
+                Order,
+            },
         },
         hourglass::account::account_handlers::trade_handler::TradeHandler,
         test_utils::create_test_account,
@@ -507,4 +574,39 @@ mod tests
         // 验证时间戳是否已更新
         assert_eq!(account.get_exchange_ts().unwrap(), 1625247600000);
     }
+
+    #[tokio::test]
+    async fn process_trade_should_publish_positions_of_the_same_trade()
+    {
+        let mut account = create_test_account().await;
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel();
+        account.account_event_tx = event_tx;
+        account.publish_positions = true;
+
+        let instrument = Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual));
+        let config = PerpetualPositionConfig { pos_margin_mode: PositionMarginMode::Cross,
+                                               leverage: 1.0,
+                                               position_direction_mode: PositionDirectionMode::LongShort };
+        account.positions.perpetual_pos_long_config.write().await.insert(instrument.clone(), config);
+        let trade = ClientTrade { exchange: Exchange::Hourglass,
+                                  timestamp: 1,
+                                  trade_id: 1.into(),
+                                  order_id: Some(OrderId(1)),
+                                  cid: None,
+                                  instrument: instrument.clone(),
+                                  side: Side::Buy,
+                                  price: 100.0,
+                                  size: 1.0,
+                                  fees: 0.0 };
+        account.process_trade(trade).await.unwrap();
+
+        let kinds: Vec<_> = std::iter::from_fn(|| event_rx.try_recv().ok()).map(|event| event.kind).collect();
+        assert!(matches!(kinds[0], AccountEventKind::Trade(_)));
+        assert!(matches!(kinds[1], AccountEventKind::Balances(_)));
+        let AccountEventKind::Positions(positions) = &kinds[2]
+        else {
+            panic!("expected a positions snapshot after the trade, got {:?}", kinds[2]);
+        };
+        assert_eq!(positions.perpetual_pos_long.read().await[&instrument].meta.current_size, 1.0);
+    }
 }
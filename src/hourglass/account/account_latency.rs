@@ -1,11 +1,10 @@
-use rand::Rng;
-// 引入随机数生成器
-use rand_distr::{Distribution, Normal};
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::hourglass::simulation::SimRng;
+use rand_distr::Normal;
+use serde::{Deserialize, Serialize};
 
 // 引入随机分布库，包括常态分布
 
-#[derive(Clone, Debug)] // 派生Clone和Debug特性
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)] // 派生Clone和Debug特性
 pub struct AccountLatency
 {
     pub fluctuation_mode: FluctuationMode,
@@ -14,7 +13,7 @@ pub struct AccountLatency
     pub current_value: i64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum FluctuationMode
 {
     Sine,
@@ -34,10 +33,11 @@ impl AccountLatency
     }
 }
 
-pub fn fluctuate_latency(latency: &mut AccountLatency, seed: i64)
+/// 按 `fluctuation_mode` 更新 `current_value`，所有随机性都取自 `rng`，种子相同时结果相同。
+pub fn fluctuate_latency(latency: &mut AccountLatency, seed: i64, rng: &SimRng)
 {
     let range = (latency.maximum - latency.minimum) as f64;
-    let dynamic_seed = seed + (SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64 % 1000);
+    let dynamic_seed = seed.wrapping_add(rng.gen_range(0..1000));
 
     match latency.fluctuation_mode {
         | FluctuationMode::Sine => {
@@ -50,11 +50,11 @@ pub fn fluctuate_latency(latency: &mut AccountLatency, seed: i64)
         }
         | FluctuationMode::NormalDistribution => {
             let normal = Normal::new((latency.maximum + latency.minimum) as f64 / 2.0, range / 4.0).unwrap();
-            let value = normal.sample(&mut rand::thread_rng()) as i64;
+            let value = rng.sample(&normal) as i64;
             latency.current_value = value.clamp(latency.minimum, latency.maximum);
         }
         | FluctuationMode::Uniform => {
-            latency.current_value = rand::thread_rng().gen_range(latency.minimum..=latency.maximum);
+            latency.current_value = rng.gen_range(latency.minimum..=latency.maximum);
         }
     }
 }
@@ -70,7 +70,7 @@ mod tests
     {
         let machine_id = generate_machine_id().unwrap();
         let mut latency = AccountLatency::new(FluctuationMode::Sine, 100, 0);
-        fluctuate_latency(&mut latency, machine_id as i64);
+        fluctuate_latency(&mut latency, machine_id as i64, &SimRng::from_entropy());
         assert!(latency.current_value >= latency.minimum && latency.current_value <= latency.maximum);
     }
 
@@ -79,7 +79,7 @@ mod tests
     {
         let machine_id = generate_machine_id().unwrap();
         let mut latency = AccountLatency::new(FluctuationMode::Cosine, 100, 0);
-        fluctuate_latency(&mut latency, machine_id as i64);
+        fluctuate_latency(&mut latency, machine_id as i64, &SimRng::from_entropy());
         assert!(latency.current_value >= latency.minimum && latency.current_value <= latency.maximum);
     }
 
@@ -88,7 +88,7 @@ mod tests
     {
         let machine_id = generate_machine_id().unwrap();
         let mut latency = AccountLatency::new(FluctuationMode::NormalDistribution, 100, 0);
-        fluctuate_latency(&mut latency, machine_id as i64);
+        fluctuate_latency(&mut latency, machine_id as i64, &SimRng::from_entropy());
         assert!(latency.current_value >= latency.minimum && latency.current_value <= latency.maximum);
     }
 
@@ -97,7 +97,7 @@ mod tests
     {
         let machine_id = generate_machine_id().unwrap();
         let mut latency = AccountLatency::new(FluctuationMode::Uniform, 100, 0);
-        fluctuate_latency(&mut latency, machine_id as i64);
+        fluctuate_latency(&mut latency, machine_id as i64, &SimRng::from_entropy());
         assert!(latency.current_value >= latency.minimum && latency.current_value <= latency.maximum);
     }
 }
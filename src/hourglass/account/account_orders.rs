@@ -2,7 +2,7 @@ use crate::{
     common::{
         instrument::Instrument,
         order::{
-            identification::{machine_id::generate_machine_id, OrderId},
+            identification::OrderId,
             order_instructions::OrderInstruction,
             states::{open::Open, request_open::RequestOpen},
             Order, OrderRole,
@@ -14,15 +14,13 @@ use crate::{
         account::account_latency::{fluctuate_latency, AccountLatency},
         clickhouse_api::datatype::single_level_order_book::SingleLevelOrderBook,
         open_orders_book::OpenOrdersBook,
+        simulation::{SimClock, SimRng},
     },
 };
 use async_trait::async_trait;
 use dashmap::{mapref::one::RefMut, DashMap};
-use rand::Rng;
-use std::{
-    sync::atomic::{AtomicU64, Ordering},
-    time::{SystemTime, UNIX_EPOCH},
-};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 #[derive(Debug)]
 pub struct AccountOrders
@@ -33,6 +31,20 @@ pub struct AccountOrders
     pub request_counter: AtomicU64,
     pub order_counter: AtomicU64,
     pub instrument_orders_map: DashMap<Instrument, OpenOrdersBook>,
+    pub rng: SimRng,     // 模拟延迟与订单 ID 的随机组件
+    pub clock: SimClock, // 订单 ID 中的时间
+}
+
+/// [`AccountOrders`] 的可序列化快照，包含计数器与各交易工具的挂单，见 [`AccountOrders::snapshot`]。
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AccountOrdersSnapshot
+{
+    pub machine_id: u64,
+    pub latency_generator: AccountLatency,
+    pub selectable_latencies: [i64; 20],
+    pub request_counter: u64,
+    pub order_counter: u64,
+    pub instrument_orders: Vec<(Instrument, OpenOrdersBook)>,
 }
 
 impl AccountOrders
@@ -73,16 +85,61 @@ impl AccountOrders
     ///     println!("新建的 AccountOrders 实例: {:?}", account_orders);
     /// }
     /// ```
-    pub async fn new(machine_id: u64, instruments: Vec<Instrument>, mut account_latency: AccountLatency) -> Self
+    pub async fn new(machine_id: u64, instruments: Vec<Instrument>, account_latency: AccountLatency) -> Self
     {
-        let selectable_latencies = Self::generate_latencies(&mut account_latency).await;
+        Self::with_simulation(machine_id, instruments, account_latency, SimRng::from_entropy(), SimClock::System).await
+    }
+
+    /// 与 [`AccountOrders::new`] 相同，随机数与时间分别取自 `rng` 与 `clock`。
+    pub async fn with_simulation(machine_id: u64, instruments: Vec<Instrument>, mut account_latency: AccountLatency, rng: SimRng, clock: SimClock) -> Self
+    {
+        let selectable_latencies = Self::generate_latencies(&mut account_latency, &rng).await;
 
         Self { machine_id,
                order_counter: AtomicU64::new(0),
                request_counter: AtomicU64::new(0),
                instrument_orders_map: instruments.into_iter().map(|instrument| (instrument, OpenOrdersBook::default())).collect(),
                latency_generator: account_latency,
-               selectable_latencies }
+               selectable_latencies,
+               rng,
+               clock }
+    }
+
+    /// 换用新的随机数发生器与时钟，并用新的发生器重新生成候选延迟。
+    pub async fn reseed(&mut self, machine_id: u64, rng: SimRng, clock: SimClock)
+    {
+        self.machine_id = machine_id;
+        self.selectable_latencies = Self::generate_latencies(&mut self.latency_generator, &rng).await;
+        self.rng = rng;
+        self.clock = clock;
+    }
+
+    /// 当前计数器与全部挂单的快照，按 [`Instrument`] 排序。
+    pub fn snapshot(&self) -> AccountOrdersSnapshot
+    {
+        let mut instrument_orders = self.instrument_orders_map.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect::<Vec<_>>();
+        instrument_orders.sort_by(|a, b| a.0.cmp(&b.0));
+        AccountOrdersSnapshot { machine_id: self.machine_id,
+                                latency_generator: self.latency_generator.clone(),
+                                selectable_latencies: self.selectable_latencies,
+                                request_counter: self.request_counter.load(Ordering::SeqCst),
+                                order_counter: self.order_counter.load(Ordering::SeqCst),
+                                instrument_orders }
+    }
+
+    /// 由快照重建 [`AccountOrders`]，恢复后生成的订单 ID 与请求 ID 接着快照中的计数器继续递增。
+    ///
+    /// 随机数发生器的状态不在快照中，由调用方提供。
+    pub fn from_snapshot(snapshot: AccountOrdersSnapshot, rng: SimRng, clock: SimClock) -> Self
+    {
+        Self { machine_id: snapshot.machine_id,
+               latency_generator: snapshot.latency_generator,
+               selectable_latencies: snapshot.selectable_latencies,
+               request_counter: AtomicU64::new(snapshot.request_counter),
+               order_counter: AtomicU64::new(snapshot.order_counter),
+               instrument_orders_map: snapshot.instrument_orders.into_iter().collect(),
+               rng,
+               clock }
     }
 
     /// 返回指定 [`Instrument`] 的 [`OpenOrdersBook`] 的可变引用。
@@ -155,22 +212,18 @@ impl AccountOrders
 
     /// 生成一个新的 [OrderId]。
     ///
-    /// 该函数根据当前的系统时间戳和订单计数器生成一个唯一的 [OrderId]。
-    /// 系统时间戳以毫秒为单位计算，并结合机器 ID 和计数器来确保生成的 ID 唯一。
+    /// 该函数根据 [`SimClock`] 的时间戳和订单计数器生成一个唯一的 [OrderId]。
+    /// 时间戳以毫秒为单位计算，并结合机器 ID、计数器与 `rng` 产生的随机组件来确保生成的 ID 唯一。
     /// 由于计数器使用 [Ordering::SeqCst] 进行递增，确保了在多线程环境下的顺序一致性和原子性。
     ///
     /// # 返回值
     ///
     /// 返回一个唯一的 [OrderId]，该 ID 是基于当前的时间戳、机器 ID 和计数器生成的。
-    ///
-    /// # 错误处理
-    ///
-    /// 如果系统时间出现倒退，将导致程序崩溃并输出错误信息 "时间出现倒退"。
     pub fn order_id(&self) -> OrderId
     {
-        let now_ts = SystemTime::now().duration_since(UNIX_EPOCH).expect("时间出现倒退").as_millis() as u64;
+        let now_ts = self.clock.now_millis() as u64;
         let counter = self.order_counter.fetch_add(1, Ordering::SeqCst);
-        OrderId::new(now_ts, self.machine_id, counter)
+        OrderId::with_random_component(now_ts, self.machine_id, counter, self.rng.gen_range(0..8192))
     }
 }
 #[async_trait]
@@ -404,7 +457,7 @@ pub trait OrderRoleClassifier
 #[async_trait]
 pub trait LatencySimulator
 {
-    async fn generate_latencies(latency_generator: &mut AccountLatency) -> [i64; 20];
+    async fn generate_latencies(latency_generator: &mut AccountLatency, rng: &SimRng) -> [i64; 20];
     fn get_random_latency(&self) -> i64;
     async fn process_backtest_requestopen_with_a_simulated_latency(&mut self, order: Order<RequestOpen>) -> Order<RequestOpen>;
     fn update_latency(&mut self, current_time: i64);
@@ -439,20 +492,20 @@ impl LatencySimulator for AccountOrders
     /// # 参数
     ///
     /// * `latency_generator` - 一个可变引用，指向 `AccountLatency` 实例，用于生成和调整延迟值。
+    /// * `rng` - 初始种子与每次的变化量都取自这里。
     ///
     /// # 返回值
     ///
     /// 返回一个包含 20 个延迟值的数组 `[i64; 20]`，每个延迟值是通过 `AccountLatency` 计算得到的。
-    async fn generate_latencies(latency_generator: &mut AccountLatency) -> [i64; 20]
+    async fn generate_latencies(latency_generator: &mut AccountLatency, rng: &SimRng) -> [i64; 20]
     {
-        let mut seed = generate_machine_id().unwrap();
-        let mut rng = rand::thread_rng();
+        let mut seed = rng.next_u64();
         let mut latencies = [0; 20];
 
         for (i, latency) in latencies.iter_mut().enumerate() {
             // 增加种子的变化范围，确保不同种子之间有足够大的差异
-            seed += rng.gen_range(1..1000000) as u64 + (i as u64 * 9999);
-            fluctuate_latency(latency_generator, seed as i64);
+            seed = seed.wrapping_add(rng.gen_range(1..1000000) as u64 + (i as u64 * 9999));
+            fluctuate_latency(latency_generator, seed as i64, rng);
             *latency = latency_generator.current_value;
             // 调试信息，输出种子和当前值
             // println!("Iteration: {}, Seed: {}, Current Value: {}", i, seed, latency_generator.current_value);
@@ -470,8 +523,7 @@ impl LatencySimulator for AccountOrders
     /// 返回一个随机选择的延迟值 `i64`。
     fn get_random_latency(&self) -> i64
     {
-        let mut rng = rand::thread_rng();
-        let idx = rng.gen_range(0..self.selectable_latencies.len());
+        let idx = self.rng.gen_range(0..self.selectable_latencies.len());
         self.selectable_latencies[idx]
     }
 
@@ -511,7 +563,7 @@ impl LatencySimulator for AccountOrders
     /// - current_time: 当前时间，以微秒为单位，作为调整延迟值的参考点。
     fn update_latency(&mut self, current_time: i64)
     {
-        fluctuate_latency(&mut self.latency_generator, current_time);
+        fluctuate_latency(&mut self.latency_generator, current_time, &self.rng);
     }
 }
 
@@ -546,7 +598,7 @@ mod tests
         let mut latency_generator = latency_generator.write().await;
 
         // 传递给 generate_latencies 函数
-        let latencies = AccountOrders::generate_latencies(&mut latency_generator).await;
+        let latencies = AccountOrders::generate_latencies(&mut latency_generator, &SimRng::from_entropy()).await;
 
         // println!("{:?}", latencies);
         assert_eq!(latencies.len(), 20);
@@ -555,6 +607,23 @@ mod tests
         }
     }
 
+    #[tokio::test]
+    async fn seeded_account_orders_should_repeat_latencies_and_order_ids()
+    {
+        let build = || async {
+            let instruments = vec![Instrument::new("BTC", "USD", InstrumentKind::Spot)];
+            let account_latency = AccountLatency::new(FluctuationMode::NormalDistribution, 100, 10);
+            let clock = SimClock::Exchange(Arc::new(1_700_000_000_000.into()));
+            AccountOrders::with_simulation(123, instruments, account_latency, SimRng::seeded(42), clock).await
+        };
+        let (a, b) = (build().await, build().await);
+        assert_eq!(a.selectable_latencies, b.selectable_latencies);
+        for _ in 0..10 {
+            assert_eq!(a.get_random_latency(), b.get_random_latency());
+            assert_eq!(a.order_id(), b.order_id());
+        }
+    }
+
     #[tokio::test]
     async fn test_get_random_latency()
     {
@@ -0,0 +1,105 @@
+use crate::{dashboard::metrics::EquitySnapshot, hourglass_log::warn, vault::Vault};
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+};
+
+/// 提交给后台线程的写操作，失败由写操作自己记录。
+type VaultWrite = Box<dyn FnOnce(&mut Vault<EquitySnapshot>) + Send>;
+
+enum VaultTask
+{
+    Write(VaultWrite),
+    Flush(Sender<()>), // 之前提交的写操作都执行完后回复
+}
+
+/// 账户的仓库，统计量为权益快照。
+///
+/// 仓库的读写都是同步的，可能等待磁盘或网络。撮合路径上的写操作通过 [`AccountVault::write`] 交给后台线程，
+/// 按提交顺序执行，不阻塞事件循环；克隆共享同一个仓库与后台线程。
+#[derive(Clone)]
+pub struct AccountVault
+{
+    vault: Arc<Mutex<Vault<EquitySnapshot>>>,
+    task_tx: Sender<VaultTask>,
+}
+
+impl AccountVault
+{
+    /// 启动后台写线程，所有克隆都被丢弃后线程退出。
+    pub fn new(vault: Vault<EquitySnapshot>) -> Self
+    {
+        let vault = Arc::new(Mutex::new(vault));
+        let (task_tx, task_rx) = mpsc::channel();
+        let shared = Arc::clone(&vault);
+        thread::Builder::new().name("account-vault".to_string())
+                              .spawn(move || {
+                                  for task in task_rx {
+                                      match task {
+                                          | VaultTask::Write(write) => write(&mut shared.lock().unwrap_or_else(PoisonError::into_inner)),
+                                          | VaultTask::Flush(done_tx) => {
+                                              let _ = done_tx.send(());
+                                          }
+                                      }
+                                  }
+                              })
+                              .expect("failed to spawn the account vault writer");
+        Self { vault, task_tx }
+    }
+
+    /// 提交一个写操作，立即返回。
+    pub fn write(&self, write: impl FnOnce(&mut Vault<EquitySnapshot>) + Send + 'static)
+    {
+        if self.task_tx.send(VaultTask::Write(Box::new(write))).is_err() {
+            warn!("Account vault writer has stopped, dropping a write");
+        }
+    }
+
+    /// 等待已提交的写操作全部执行完，再锁定仓库直接读写。
+    ///
+    /// 会阻塞当前线程，只用于测试与检查等不在撮合路径上的场合。
+    pub fn lock(&self) -> MutexGuard<'_, Vault<EquitySnapshot>>
+    {
+        let (done_tx, done_rx) = mpsc::channel();
+        if self.task_tx.send(VaultTask::Flush(done_tx)).is_ok() {
+            let _ = done_rx.recv();
+        }
+        self.vault.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Debug for AccountVault
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        f.debug_struct("AccountVault").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::{balance::Balance, token::Token},
+        vault::{in_memory::InMemoryVault, BalanceProcessor},
+    };
+    use uuid::Uuid;
+
+    #[test]
+    fn writes_should_apply_in_order_before_lock()
+    {
+        let vault = AccountVault::new(Vault::InMemory(InMemoryVault::new()));
+        let session_id = Uuid::new_v4();
+        let usdt = Token::usdt();
+        for total in 1..=100 {
+            let usdt = usdt.clone();
+            vault.write(move |vault| vault.set_balance(session_id, &usdt, Balance::new(total as f64, total as f64)).unwrap());
+        }
+        assert_eq!(vault.lock().get_balance(session_id, &usdt).unwrap().total, 100.0);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     common::{
-        account_positions::{exited_positions::AccountExitedPositions, AccountPositions, PositionDirectionMode},
+        account_positions::{exited_positions::AccountExitedPositions, AccountPositions, Position, PositionDirectionMode},
         balance::{Balance, BalanceDelta, TokenBalance},
         event::{AccountEvent, AccountEventKind},
         instrument::Instrument,
@@ -13,22 +13,34 @@ use crate::{
         token::Token,
         Side,
     },
+    dashboard::{
+        equity::EquityTracker,
+        export::BacktestReport,
+        metrics::EquitySnapshot,
+        summary::{
+            attribution::{AttributionSummary, PnLAttribution},
+            trading::{self, TradingSummary},
+        },
+    },
     error::ExchangeError,
     hourglass::{
         account::{
             account_config::{ConfigLoader, FeesQuerier, HourglassMode},
             account_handlers::{balance_handler::BalanceHandler, position_handler::PositionHandler, trade_handler::TradeHandler},
             account_orders::{LatencySimulator, OrderRoleClassifier},
+            account_vault::AccountVault,
         },
         clickhouse_api::datatype::single_level_order_book::{OrderBookUpdater, SingleLevelOrderBook},
+        simulation::{SimClock, SimRng},
     },
-    hourglass_log::info,
+    hourglass_log::{info, warn},
+    vault::{BalanceProcessor, PositionProcessor},
     Exchange,
 };
 use account_config::AccountConfig;
 use account_orders::AccountOrders;
 use atomic_float::AtomicF64;
-use chrono::Utc;
+use chrono::DateTime;
 use dashmap::{mapref::one::RefMut as DashMapRefMut, DashMap};
 use mpsc::UnboundedSender;
 use oneshot::Sender;
@@ -50,6 +62,7 @@ pub mod account_handlers;
 pub mod account_latency;
 pub mod account_market_feed;
 pub mod account_orders;
+pub mod account_vault;
 
 #[derive(Debug)]
 pub struct HourglassAccount
@@ -61,15 +74,21 @@ pub struct HourglassAccount
     pub current_session: Uuid,
     pub machine_id: u64,
     pub client_trade_counter: AtomicI64,
-    pub exchange_timestamp: AtomicI64,                                                  // 交易所时间戳
+    pub exchange_timestamp: Arc<AtomicI64>,                                             // 交易所时间戳
     pub account_event_tx: UnboundedSender<AccountEvent>,                                // 帐户事件发送器
     pub config: AccountConfig,                                                          // 帐户配置
     pub account_open_book: Arc<RwLock<AccountOrders>>,                                  // 帐户订单集合
     pub single_level_order_book: Arc<Mutex<HashMap<Instrument, SingleLevelOrderBook>>>, // 将最新的价格存到订单簿里面去
     pub balances: DashMap<Token, Balance>,                                              // 每个币种的细分余额
     pub positions: AccountPositions,                                                    // 帐户持仓
-    pub exited_positions: AccountExitedPositions,                                       // pub vault: Vault,
+    pub exited_positions: AccountExitedPositions,                                       // 已退出的仓位
+    pub vault: AccountVault,                                                            // 按 `config.vault` 构造的仓库，持久化仓位与余额
     pub account_margin: Arc<AtomicF64>,
+    pub rng: SimRng,                           // 账户的随机数来源，与挂单共享同一个状态
+    pub clock: SimClock,                       // 余额更新时间
+    pub equity_tracker: Option<EquityTracker>, // 按 `config.equity_sample_interval` 采样的权益曲线与绩效指标
+    pub pnl_attribution: PnLAttribution,       // 按交易工具、方向与策略拆分的盈亏
+    pub publish_positions: bool,               // 每笔成交后是否附带发送仓位快照，在线模式的 WebSocket 推送需要
 }
 
 // 手动实现 Clone trait
@@ -80,7 +99,7 @@ impl Clone for HourglassAccount
         HourglassAccount { current_session: Uuid::new_v4(),
                            machine_id: self.machine_id,
                            client_trade_counter: 0.into(),
-                           exchange_timestamp: AtomicI64::new(self.exchange_timestamp.load(Ordering::SeqCst)),
+                           exchange_timestamp: Arc::new(AtomicI64::new(self.exchange_timestamp.load(Ordering::SeqCst))),
                            account_event_tx: self.account_event_tx.clone(),
                            config: self.config.clone(),
                            account_open_book: Arc::clone(&self.account_open_book),
@@ -88,7 +107,13 @@ impl Clone for HourglassAccount
                            balances: self.balances.clone(),
                            positions: self.positions.clone(),
                            exited_positions: self.exited_positions.clone(),
-                           account_margin: self.account_margin.clone() }
+                           vault: self.vault.clone(),
+                           account_margin: self.account_margin.clone(),
+                           rng: self.rng.clone(),
+                           clock: self.clock.clone(),
+                           equity_tracker: self.equity_tracker.clone(),
+                           pnl_attribution: self.pnl_attribution.clone(),
+                           publish_positions: self.publish_positions }
     }
 }
 #[derive(Debug)]
@@ -152,20 +177,37 @@ impl AccountBuilder
         self
     }
 
+    pub fn closed_positions(mut self, value: AccountExitedPositions) -> Self
+    {
+        self.closed_positions = Some(value);
+        self
+    }
+
     pub fn build(self) -> Result<HourglassAccount, String>
     {
-        Ok(HourglassAccount { current_session: Uuid::new_v4(),
+        let current_session = Uuid::new_v4();
+        let config = self.config.ok_or("config is required")?;
+        let vault = config.vault
+                          .build(config.execution_mode.clone(), current_session)
+                          .map_err(|error| format!("failed to build vault: {}", error))?;
+        Ok(HourglassAccount { current_session,
                               machine_id: generate_machine_id()?,
                               client_trade_counter: 0.into(),
-                              exchange_timestamp: 0.into(),
+                              exchange_timestamp: Arc::new(0.into()),
                               account_event_tx: self.account_event_tx.ok_or("account_event_tx is required")?,
-                              config: self.config.ok_or("config is required")?,
+                              config,
                               account_open_book: self.orders.ok_or("orders are required")?,
                               balances: self.balances.ok_or("balances are required")?,
                               positions: self.positions.ok_or("positions are required")?,
                               single_level_order_book: Arc::new(Mutex::new(HashMap::new())),
                               exited_positions: self.closed_positions.ok_or("closed_positions sink are required")?,
-                              account_margin: Arc::new(0.0.into()) })
+                              vault: AccountVault::new(vault),
+                              account_margin: Arc::new(0.0.into()),
+                              rng: SimRng::from_entropy(),
+                              clock: SimClock::System,
+                              equity_tracker: None,
+                              pnl_attribution: PnLAttribution::default(),
+                              publish_positions: false })
     }
 }
 
@@ -177,6 +219,157 @@ impl HourglassAccount
         AccountBuilder::new()
     }
 
+    /// 让账户的运行结果可以重复：`rng` 成为账户与挂单唯一的随机数来源，时钟改为跟随交易所时间戳，
+    /// 机器 ID、会话 ID 与候选延迟都由 `rng` 重新生成。
+    pub async fn seed(&mut self, rng: SimRng)
+    {
+        self.machine_id = rng.next_u64();
+        self.current_session = Uuid::from_u64_pair(rng.next_u64(), rng.next_u64());
+        self.clock = SimClock::Exchange(Arc::clone(&self.exchange_timestamp));
+        // 构建账户时余额带有系统时间，改由模拟时钟重新标记
+        let now = self.clock.now();
+        self.balances.iter_mut().for_each(|mut balance| balance.time = now);
+        self.account_open_book.write().await.reseed(self.machine_id, rng.clone(), self.clock.clone()).await;
+        self.rng = rng;
+    }
+
+    /// 把 `instrument` 当前的多空仓位交给仓库的后台线程写入，写入失败只记录警告，不影响撮合。
+    pub async fn persist_positions(&self, instrument: &Instrument)
+    {
+        let Ok((long, short)) = self.get_position_both_ways(instrument).await
+        else {
+            return;
+        };
+        let session_id = self.current_session;
+        let instrument = instrument.clone();
+        self.vault.write(move |vault| {
+                      for position in [long, short].into_iter().flatten() {
+                          if let Err(error) = vault.add_open_position(session_id, position) {
+                              warn!("Failed to persist position of {}: {:?}", instrument, error);
+                          }
+                      }
+                  });
+    }
+
+    /// 仓位被移除后从仓库的持仓中删除，并记入已退出的仓位。
+    pub fn persist_exited_position(&self, position: &Position)
+    {
+        let session_id = self.current_session;
+        let position = position.clone();
+        self.vault.write(move |vault| {
+                      let result = vault.remove_position(&position.meta().position_id).and_then(|_| vault.set_exited_position(session_id, position.clone()));
+                      if let Err(error) = result {
+                          warn!("Failed to persist exited position of {}: {:?}", position.meta().instrument, error);
+                      }
+                  });
+    }
+
+    /// 把每个币种的余额写入仓库。
+    pub fn persist_balance(&self)
+    {
+        let session_id = self.current_session;
+        let balances: Vec<(Token, Balance)> = self.balances.iter().map(|entry| (entry.key().clone(), *entry.value())).collect();
+        self.vault.write(move |vault| {
+                      for (token, balance) in balances {
+                          if let Err(error) = vault.set_balance(session_id, &token, balance) {
+                              warn!("Failed to persist balance of {}: {:?}", token, error);
+                          }
+                      }
+                  });
+    }
+
+    /// 以 USDT 计价的账户总权益：各币种余额按最新成交价折算，加上按最新成交价重新计算的持仓未实现盈亏。
+    ///
+    /// 没有行情的币种不计入，没有行情的仓位沿用仓位中记录的未实现盈亏。
+    pub async fn total_equity(&self) -> f64
+    {
+        let usdt = Token::usdt();
+        let order_books = self.single_level_order_book.lock().await;
+        let latest_price = |instrument: &Instrument| order_books.get(instrument).map(|book| book.latest_price).filter(|price| *price > 0.0);
+
+        // 遍历顺序不固定，排序后再求和，保证相同的状态得到逐位相同的结果
+        let mut balances = self.balances.iter().map(|entry| (entry.key().clone(), entry.value().total)).collect::<Vec<_>>();
+        balances.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut equity = 0.0;
+        for (token, total) in balances {
+            if token == usdt {
+                equity += total;
+            }
+            else if let Some(price) = order_books.keys().filter(|instrument| instrument.base == token && instrument.quote == usdt).min().and_then(&latest_price) {
+                equity += total * price;
+            }
+        }
+
+        let mut positions = self.positions.all_positions().await;
+        positions.sort_by(|a, b| a.meta().instrument.cmp(&b.meta().instrument));
+        for position in positions {
+            let meta = position.meta();
+            equity += match latest_price(&meta.instrument) {
+                | Some(price) => match meta.side {
+                    | Side::Buy => (price - meta.current_avg_price) * meta.current_size.abs(),
+                    | Side::Sell => (meta.current_avg_price - price) * meta.current_size.abs(),
+                },
+                | None => meta.unrealised_pnl,
+            };
+        }
+        equity
+    }
+
+    /// 配置了 [`AccountConfig::equity_sample_interval`] 时，按交易所时间对总权益采样并更新 [`EquityTracker`]。
+    pub async fn sample_equity(&mut self)
+    {
+        let Some(interval) = self.config.equity_sample_interval
+        else {
+            return;
+        };
+        let timestamp = self.exchange_timestamp.load(Ordering::SeqCst);
+        if self.equity_tracker.as_ref().is_some_and(|tracker| !tracker.is_due(timestamp)) {
+            return;
+        }
+        let Some(time) = DateTime::from_timestamp_millis(timestamp)
+        else {
+            return;
+        };
+        let total = self.total_equity().await;
+        self.equity_tracker.get_or_insert_with(|| EquityTracker::new(interval)).record(EquitySnapshot { time, total });
+    }
+
+    /// `instrument` 的持仓模式：优先取该交易工具的仓位配置，没有配置时使用账户的全局设置。
+    pub async fn position_direction_mode(&self, instrument: &Instrument) -> PositionDirectionMode
+    {
+        let long = self.positions.perpetual_pos_long_config.read().await.get(instrument).map(|config| config.position_direction_mode.clone());
+        let config = match long {
+            | Some(mode) => Some(mode),
+            | None => self.positions.perpetual_pos_short_config.read().await.get(instrument).map(|config| config.position_direction_mode.clone()),
+        };
+        config.unwrap_or_else(|| self.config.global_position_direction_mode.clone())
+    }
+
+    /// 用全部已平仓仓位生成交易汇总与 [`TearSheet`](crate::dashboard::summary::trading::TearSheet)，通常在回测结束时调用。
+    pub async fn trading_summary(&self, config: trading::Config) -> TradingSummary
+    {
+        TradingSummary::generate(config, &self.exited_positions.all_exits().await)
+    }
+
+    /// 按交易工具、多空方向与策略标签拆分的盈亏，持仓按各交易工具的最新成交价计算未实现盈亏。
+    pub async fn pnl_attribution(&self) -> AttributionSummary
+    {
+        let order_books = self.single_level_order_book.lock().await;
+        self.pnl_attribution
+            .summary(|instrument| order_books.get(instrument).map(|book| book.latest_price).filter(|price| *price > 0.0))
+    }
+
+    /// 用已平仓仓位、权益曲线以及 `events` 中的成交和订单生成可导出的回测报告。
+    ///
+    /// `events` 通常是客户端在回测过程中收到的账户事件，也可以取自审计日志。
+    pub async fn backtest_report<'a>(&self, config: trading::Config, events: impl IntoIterator<Item = &'a AccountEvent>) -> BacktestReport
+    {
+        let equity_curve = self.equity_tracker.as_ref().map(|tracker| tracker.curve.clone()).unwrap_or_default();
+        let mut report = BacktestReport::new(config, self.exited_positions.all_exits().await, equity_curve);
+        events.into_iter().for_each(|event| report.record(event));
+        report
+    }
+
     /// 初始化账户中要使用的币种，初始余额设为 0。
     ///
     /// # 参数
@@ -186,7 +379,7 @@ impl HourglassAccount
     {
         for token_str in tokens {
             let token = Token(token_str);
-            self.balances.entry(token.clone()).or_insert_with(|| Balance { time: Utc::now(),
+            self.balances.entry(token.clone()).or_insert_with(|| Balance { time: self.clock.now(),
                                                                            // current_price: Some(1.0), // 假设初始价格为 1.0，具体根据实际情况调整
                                                                            total: 0.0,
                                                                            available: 0.0 });
@@ -208,8 +401,9 @@ impl HourglassAccount
     /// 返回更新后的 `TokenBalance`。
     fn deposit_coin(&mut self, token: Token, amount: f64) -> Result<TokenBalance, ExchangeError>
     {
+        let now = self.clock.now();
         let mut balance = self.balances.entry(token.clone()).or_insert_with(|| {
-                                                                Balance { time: Utc::now(),
+                                                                Balance { time: now,
                                                                           // current_price: Some(1.0), // 假设稳定币价格为1.0
                                                                           total: 0.0,
                                                                           available: 0.0 }
@@ -217,8 +411,13 @@ impl HourglassAccount
 
         balance.total += amount;
         balance.available += amount;
+        balance.time = now;
+        let updated = *balance;
+        // 先释放余额表的锁再写入仓库
+        drop(balance);
+        self.persist_balance();
 
-        Ok(TokenBalance::new(token, *balance))
+        Ok(TokenBalance::new(token, updated))
     }
 
     /// 为多个指定的 `Token` 充值指定数量的稳定币。
@@ -331,7 +530,6 @@ impl HourglassAccount
     /// # 参数
     ///
     /// * `open_requests` - 一个包含多个 `Order<RequestOpen>` 的向量，表示待处理的开仓请求。
-    /// * `response_tx` - 一个 `oneshot::Sender`，用于异步发送订单处理结果。
     ///
     /// # 逻辑
     ///
@@ -339,13 +537,13 @@ impl HourglassAccount
     ///    - 如果是 `reduce only`，则跳过方向冲突检查，但如果订单方向与当前持仓方向相同，则拒绝该订单。
     /// 2. 如果是 `NetMode` 且订单不是 `reduce only`，则调用 `check_position_direction_conflict` 检查当前持仓方向是否与订单冲突。
     /// 3. 计算订单的当前价格，并尝试原子性开仓操作。
-    /// 4. 将每个订单的处理结果发送到 `response_tx`。
+    /// 4. 按请求顺序返回每个订单的处理结果。
     ///
     /// # 错误处理
     ///
     /// - 如果 `reduce only` 订单的方向与现有持仓方向相同，则拒绝该订单，并继续处理下一个订单。
     /// - 如果在 `NetMode` 下存在方向冲突，则跳过该订单并继续处理下一个订单。
-    pub async fn open_orders(&mut self, open_requests: Vec<Order<RequestOpen>>, response_tx: Sender<Vec<Result<Order<Open>, ExchangeError>>>) -> Result<(), ExchangeError>
+    pub async fn open_orders(&mut self, open_requests: Vec<Order<RequestOpen>>) -> Vec<Result<Order<Open>, ExchangeError>>
     {
         let mut open_results = Vec::new();
 
@@ -372,12 +570,14 @@ impl HourglassAccount
             open_results.push(open_result);
         }
 
-        // 发送处理结果
-        if let Err(e) = response_tx.send(open_results) {
-            return Err(ExchangeError::Hourglass(format!("Failed to send open order results: {:?}", e)));
-        }
+        open_results
+    }
 
-        Ok(())
+    /// 见 [`HourglassAccount::open_orders`]，处理结果发送到 `response_tx`。
+    pub async fn open_orders_and_respond(&mut self, open_requests: Vec<Order<RequestOpen>>, response_tx: Sender<Vec<Result<Order<Open>, ExchangeError>>>)
+    {
+        let open_results = self.open_orders(open_requests).await;
+        respond(response_tx, open_results);
     }
 
     // 辅助函数，用于检查仓位方向冲突
@@ -759,6 +959,7 @@ mod tests
             instrument::kind::InstrumentKind,
             order::{identification::OrderId, states::request_open::RequestOpen},
         },
+        hourglass::clickhouse_api::datatype::clickhouse_trade_data::MarketTrade,
         test_utils::create_test_account,
     };
 
@@ -898,4 +1099,29 @@ mod tests
         assert_eq!(usdt_balance.total, 10_000.0);
         assert_eq!(btc_balance.total, usdt_amount / btc_price);
     }
+
+    #[tokio::test]
+    async fn equity_should_be_sampled_on_exchange_time_and_marked_to_market()
+    {
+        let mut account = create_test_account().await;
+        account.config.equity_sample_interval = Some(60_000);
+        let trade = |timestamp: i64, price: f64| MarketTrade { exchange: "binance-futures".to_string(),
+                                                               symbol: "ETHUSDT".to_string(),
+                                                               timestamp,
+                                                               price,
+                                                               side: Side::Buy.to_string(),
+                                                               amount: 1.0 };
+        for (timestamp, price) in [(0, 16_000.0), (30_000, 20_000.0), (60_000, 15_000.0), (120_000, 17_000.0)] {
+            account.handle_trade_data(&trade(timestamp, price)).await.unwrap();
+        }
+
+        // 10 ETH 按最新成交价折算，加上 10000 USDT；30 秒时的成交不到采样间隔
+        let tracker = account.equity_tracker.as_ref().unwrap();
+        let totals = tracker.curve.iter().map(|snapshot| snapshot.total).collect::<Vec<_>>();
+        assert_eq!(totals, vec![170_000.0, 160_000.0, 180_000.0]);
+        assert_eq!(tracker.curve[2].time.timestamp_millis(), 120_000);
+        assert!((tracker.max_drawdown.drawdown.drawdown + 10_000.0 / 170_000.0).abs() < 1e-12);
+        assert_eq!(tracker.returns.total.count, 2);
+        assert_eq!(account.total_equity().await, 180_000.0);
+    }
 }
@@ -0,0 +1,307 @@
+use crate::{
+    common::{
+        account_positions::{exited_positions::AccountExitedPositionsSnapshot, AccountPositionsSnapshot},
+        balance::TokenBalance,
+    },
+    dashboard::{equity::EquityTracker, summary::attribution::PnLAttribution},
+    error::ExchangeError,
+    hourglass::{
+        account::{
+            account_config::AccountConfig,
+            account_handlers::balance_handler::BalanceHandler,
+            account_orders::{AccountOrders, AccountOrdersSnapshot},
+            HourglassAccount,
+        },
+        simulation::{SimClock, SimClockKind, SimRng, SimRngState},
+    },
+    hourglass_log::warn,
+    vault::PositionProcessor,
+    Exchange,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
+use uuid::Uuid;
+
+/// 检查点文件格式的版本，格式不兼容时递增。
+pub const CHECKPOINT_VERSION: u16 = 3;
+
+/// 定期写入检查点：每处理 `every` 个行情条目写入一次 `path`，关闭时再写入一次。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointPolicy
+{
+    pub path: PathBuf,
+    pub every: usize,
+}
+
+impl CheckpointPolicy
+{
+    pub fn new(path: impl Into<PathBuf>, every: usize) -> Self
+    {
+        Self { path: path.into(), every }
+    }
+}
+
+/// 回测数据的读取位置。
+///
+/// ClickHouse 游标无法序列化，恢复时需要用同样的查询重新创建 [`DataSource`](crate::hourglass::DataSource)，
+/// 交易所会先跳过 `processed_count` 个已经处理过的条目。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataCursor
+{
+    pub processed_count: usize,  // 已处理的行情数据条目数
+    pub exchange_timestamp: i64, // 默认账户最后的交易所时间戳，可用于按时间重新查询
+}
+
+/// 单个账户的检查点，默认账户的 `username` 为 `None`。
+///
+/// 最新价格不在检查点中，恢复后由下一条行情重新填充。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountCheckpoint
+{
+    pub username: Option<String>,
+    pub current_session: Uuid,
+    pub machine_id: u64,
+    pub client_trade_counter: i64,
+    pub exchange_timestamp: i64,
+    pub account_margin: f64,
+    pub config: AccountConfig,
+    pub balances: Vec<TokenBalance>,
+    pub orders: AccountOrdersSnapshot,
+    pub positions: AccountPositionsSnapshot,
+    pub exited_positions: AccountExitedPositionsSnapshot,
+    pub equity_tracker: Option<EquityTracker>,
+    pub pnl_attribution: PnLAttribution,
+    pub rng: SimRngState, // 随机数发生器的当前状态，恢复后继续原来的序列
+    pub clock: SimClockKind,
+}
+
+/// 交易所的检查点，包含全部账户与回测数据的读取位置。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeCheckpoint
+{
+    pub version: u16,
+    pub created_at: DateTime<Utc>,
+    pub data_cursor: DataCursor,
+    pub accounts: Vec<AccountCheckpoint>,
+}
+
+impl ExchangeCheckpoint
+{
+    /// 以 JSON 写入 `path`，先写临时文件并落盘再重命名，写入中途崩溃或断电不会损坏已有的检查点。
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ExchangeError>
+    {
+        let path = path.as_ref();
+        let directory = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        std::fs::create_dir_all(directory).map_err(|e| ExchangeError::Checkpoint(format!("{}: {}", directory.display(), e)))?;
+        let content = serde_json::to_vec(self).map_err(|e| ExchangeError::Checkpoint(e.to_string()))?;
+        let temp_path = path.with_extension("tmp");
+        File::create(&temp_path).and_then(|mut file| {
+                                    file.write_all(&content)?;
+                                    file.sync_all()
+                                })
+                                .map_err(|e| ExchangeError::Checkpoint(format!("{}: {}", temp_path.display(), e)))?;
+        std::fs::rename(&temp_path, path).map_err(|e| ExchangeError::Checkpoint(format!("{}: {}", path.display(), e)))?;
+        // 重命名只修改了目录项，目录同样需要落盘
+        File::open(directory).and_then(|directory| directory.sync_all())
+                             .map_err(|e| ExchangeError::Checkpoint(format!("{}: {}", directory.display(), e)))
+    }
+
+    /// 读取 [`ExchangeCheckpoint::save`] 写入的检查点，版本不一致时返回错误。
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ExchangeError>
+    {
+        let path = path.as_ref();
+        let content = std::fs::read(path).map_err(|e| ExchangeError::Checkpoint(format!("{}: {}", path.display(), e)))?;
+        let checkpoint: Self = serde_json::from_slice(&content).map_err(|e| ExchangeError::Checkpoint(e.to_string()))?;
+        if checkpoint.version != CHECKPOINT_VERSION {
+            return Err(ExchangeError::Checkpoint(format!("unsupported checkpoint version {}, expected {}", checkpoint.version, CHECKPOINT_VERSION)));
+        }
+        Ok(checkpoint)
+    }
+}
+
+impl HourglassAccount
+{
+    /// 当前账户状态的检查点，`username` 由交易所填写。
+    pub async fn checkpoint(&self) -> AccountCheckpoint
+    {
+        let orders = self.account_open_book.read().await.snapshot();
+        // 余额表无序，按币种排序使同样的状态得到同样的检查点
+        let mut balances = self.get_balances().await;
+        balances.sort_by(|a, b| a.token.cmp(&b.token));
+        AccountCheckpoint { username: None,
+                            current_session: self.current_session,
+                            machine_id: self.machine_id,
+                            client_trade_counter: self.client_trade_counter.load(Ordering::SeqCst),
+                            exchange_timestamp: self.exchange_timestamp.load(Ordering::SeqCst),
+                            account_margin: self.account_margin.load(Ordering::SeqCst),
+                            config: self.config.clone(),
+                            balances,
+                            orders,
+                            positions: self.positions.snapshot().await,
+                            exited_positions: self.exited_positions.snapshot().await,
+                            equity_tracker: self.equity_tracker.clone(),
+                            pnl_attribution: self.pnl_attribution.clone(),
+                            rng: self.rng.state(),
+                            clock: self.clock.kind() }
+    }
+
+    /// 用检查点替换账户状态，事件通道保持不变。
+    ///
+    /// 挂单、仓位与余额写回原有的共享容器，持有这些 `Arc` 的其他组件同样能看到恢复后的状态。
+    pub async fn restore(&mut self, checkpoint: AccountCheckpoint)
+    {
+        self.current_session = checkpoint.current_session;
+        self.machine_id = checkpoint.machine_id;
+        self.client_trade_counter.store(checkpoint.client_trade_counter, Ordering::SeqCst);
+        self.exchange_timestamp.store(checkpoint.exchange_timestamp, Ordering::SeqCst);
+        self.account_margin.store(checkpoint.account_margin, Ordering::SeqCst);
+        self.config = checkpoint.config;
+        self.rng = SimRng::from_state(checkpoint.rng);
+        self.clock = match checkpoint.clock {
+            | SimClockKind::System => SimClock::System,
+            | SimClockKind::Exchange => SimClock::Exchange(Arc::clone(&self.exchange_timestamp)),
+        };
+
+        self.balances.clear();
+        for token_balance in checkpoint.balances {
+            self.balances.insert(token_balance.token, token_balance.balance);
+        }
+        {
+            // 挂单与账户共享恢复后的随机数发生器与时钟
+            let mut orders = self.account_open_book.write().await;
+            *orders = AccountOrders::from_snapshot(checkpoint.orders, self.rng.clone(), self.clock.clone());
+        }
+        self.positions.restore(checkpoint.positions).await;
+        self.exited_positions.restore(checkpoint.exited_positions).await;
+        self.equity_tracker = checkpoint.equity_tracker;
+        self.pnl_attribution = checkpoint.pnl_attribution;
+        self.rebuild_vault().await;
+    }
+
+    /// 按恢复后的 `config.vault` 与会话重建仓库，再写入恢复后的持仓与余额，检查点之后才写入的持仓从仓库中删除。
+    ///
+    /// 重建与写入都交给仓库的后台线程按顺序执行；构建失败时只记录警告，继续使用原有的仓库。
+    async fn rebuild_vault(&self)
+    {
+        let config = self.config.vault.clone();
+        let execution_mode = self.config.execution_mode.clone();
+        let session_id = self.current_session;
+        let restored: Vec<_> = self.positions.all_positions().await.iter().map(|position| position.meta().position_id.clone()).collect();
+        let instruments: Vec<_> = self.account_open_book.read().await.instrument_orders_map.iter().map(|entry| entry.key().clone()).collect();
+        let stale_instruments = instruments.clone();
+        self.vault.write(move |vault| {
+                      match config.build(execution_mode, session_id) {
+                          | Ok(rebuilt) => *vault = rebuilt,
+                          | Err(error) => {
+                              warn!("Failed to rebuild vault from checkpoint: {:?}", error);
+                              return;
+                          }
+                      }
+                      for instrument in stale_instruments {
+                          let stale = vault.get_open_positions(session_id, Exchange::Hourglass, instrument.clone()).unwrap_or_default();
+                          for position in stale.into_iter().filter(|position| !restored.contains(&position.meta().position_id)) {
+                              if let Err(error) = vault.remove_position(&position.meta().position_id) {
+                                  warn!("Failed to remove stale position of {}: {:?}", instrument, error);
+                              }
+                          }
+                      }
+                  });
+        for instrument in instruments {
+            self.persist_positions(&instrument).await;
+        }
+        self.persist_balance();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::{
+            account_positions::{exited_position::PositionExit, position_id::PositionId, PositionDirectionMode},
+            balance::Balance,
+            instrument::{kind::InstrumentKind, Instrument},
+            order::identification::{client_order_id::ClientOrderId, OrderId},
+            token::Token,
+            trade::{ClientTrade, ClientTradeId},
+            Side,
+        },
+        dashboard::metrics::EquitySnapshot,
+        test_utils::{create_test_account, create_test_order_open, create_test_perpetual_position},
+        vault::BalanceProcessor,
+    };
+
+    #[tokio::test]
+    async fn account_should_round_trip_through_checkpoint()
+    {
+        let instrument = Instrument::new("ETH", "USDT", InstrumentKind::Perpetual);
+        let mut account = create_test_account().await;
+        account.seed(SimRng::seeded(11)).await;
+        account.rng.next_u64();
+        account.balances.insert(Token::from("USDT"), Balance::new(9_000.0, 8_500.0));
+        account.client_trade_counter.store(42, Ordering::SeqCst);
+        account.exchange_timestamp.store(1_700_000_000_000, Ordering::SeqCst);
+        account.account_margin.store(125.0, Ordering::SeqCst);
+        {
+            let orders = account.account_open_book.read().await;
+            orders.order_counter.store(7, Ordering::SeqCst);
+            orders.get_ins_orders_mut(&instrument).unwrap().bids.push(create_test_order_open(Side::Buy, 16_000.0, 1.0));
+        }
+        let position = create_test_perpetual_position(instrument.clone());
+        account.positions.perpetual_pos_long.write().await.insert(instrument.clone(), position.clone());
+        let mut closed = position.meta.clone();
+        closed.position_id = PositionId(9);
+        let exit = PositionExit::from_position_meta(&closed, None);
+        account.exited_positions.perpetual_pos_long.write().await.insert(PositionId(9), exit.clone());
+        let mut tracker = EquityTracker::new(60_000);
+        tracker.record(EquitySnapshot { time: DateTime::from_timestamp_millis(1_700_000_000_000).unwrap(),
+                                        total: 10_000.0 });
+        account.equity_tracker = Some(tracker);
+        account.pnl_attribution.record_fill(&ClientTrade { exchange: Exchange::Hourglass,
+                                                           timestamp: 1_700_000_000_000,
+                                                           trade_id: ClientTradeId(1),
+                                                           order_id: Some(OrderId(1)),
+                                                           cid: Some(ClientOrderId("mm-000001".to_string())),
+                                                           instrument: instrument.clone(),
+                                                           side: Side::Buy,
+                                                           price: 16_000.0,
+                                                           size: 1.0,
+                                                           fees: 3.2 },
+                                            &PositionDirectionMode::Net);
+
+        // 经过 JSON 往返后恢复到另一个账户
+        let checkpoint = account.checkpoint().await;
+        let checkpoint: AccountCheckpoint = serde_json::from_str(&serde_json::to_string(&checkpoint).unwrap()).unwrap();
+        let mut restored = create_test_account().await;
+        restored.restore(checkpoint.clone()).await;
+
+        assert_eq!(restored.checkpoint().await, checkpoint);
+        assert_eq!(restored.current_session, account.current_session);
+        assert_eq!(restored.client_trade_counter.load(Ordering::SeqCst), 42);
+        assert_eq!(restored.balances.get(&Token::from("USDT")).unwrap().available, 8_500.0);
+        let orders = restored.account_open_book.read().await;
+        assert_eq!(orders.order_counter.load(Ordering::SeqCst), 7);
+        assert_eq!(orders.fetch_all().len(), 1);
+        assert_eq!(restored.positions.perpetual_pos_long.read().await.get(&instrument).unwrap().meta, position.meta);
+        assert_eq!(restored.exited_positions.perpetual_pos_long.read().await.get(&PositionId(9)), Some(&exit));
+        assert_eq!(restored.equity_tracker, account.equity_tracker);
+        assert_eq!(restored.pnl_attribution, account.pnl_attribution);
+        assert_eq!(restored.pnl_attribution.summary(|_| Some(16_100.0)).by_strategy["mm"].unrealised_pnl, 100.0);
+        drop(orders);
+        // 恢复后的随机数序列与时钟和未中断的账户一致
+        assert_eq!(restored.clock.kind(), SimClockKind::Exchange);
+        assert_eq!(restored.clock.now(), account.clock.now());
+        assert_eq!((0..4).map(|_| restored.rng.next_u64()).collect::<Vec<_>>(), (0..4).map(|_| account.rng.next_u64()).collect::<Vec<_>>());
+        // 仓库按恢复后的会话重建，持仓与余额都已写入
+        let mut vault = restored.vault.lock();
+        assert_eq!(vault.get_open_positions(restored.current_session, Exchange::Hourglass, instrument).unwrap().len(), 1);
+        assert_eq!(vault.get_balance(restored.current_session, &Token::usdt()).unwrap().available, 8_500.0);
+    }
+}
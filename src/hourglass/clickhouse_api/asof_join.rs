@@ -0,0 +1,196 @@
+use crate::{
+    hourglass::clickhouse_api::{
+        datatype::{clickhouse_trade_data::MarketTrade, order_book_25::OrderBook25},
+        queries_operations::{ClickHouseClient, Result, Row},
+        query_builder::quote_table_path,
+    },
+    hourglass_log::info,
+};
+use clickhouse::query::RowCursor;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, iter::Peekable};
+
+/// 一笔成交及其发生时（含同一时刻）最近的一张25档快照。
+///
+/// 快照只会取时间戳不晚于成交时间戳的那一张，因此不存在前视偏差。成交之前尚无快照时 `book` 为 `None`。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradeBookPair
+{
+    pub trade: MarketTrade,
+    pub book: Option<OrderBook25>,
+}
+
+/// `ASOF LEFT JOIN` 的查询结果行，按位置依次反序列化成交与快照的各列。
+#[derive(Debug, Deserialize, Row)]
+pub struct TradeBookRow
+{
+    pub trade: MarketTrade,
+    pub book: OrderBook25,
+}
+
+impl From<TradeBookRow> for TradeBookPair
+{
+    fn from(row: TradeBookRow) -> Self
+    {
+        // `ASOF LEFT JOIN` 未匹配时快照列为默认值，时间戳为 0
+        let book = if row.book.timestamp == 0 { None } else { Some(row.book) };
+        TradeBookPair { trade: row.trade, book }
+    }
+}
+
+/// 按时间顺序逐条返回 [`TradeBookPair`] 的游标。
+pub struct TradeBookCursor
+{
+    cursor: RowCursor<TradeBookRow>,
+}
+
+impl TradeBookCursor
+{
+    pub async fn next(&mut self) -> Result<Option<TradeBookPair>>
+    {
+        Ok(self.cursor.next().await?.map(TradeBookPair::from))
+    }
+}
+
+impl ClickHouseClient
+{
+    /// 成交表与快照表按交易对做 `ASOF LEFT JOIN` 的查询语句。
+    pub fn construct_trade_book_asof_query(&self, trades_database: &str, trades_table: &str, books_database: &str, books_table: &str) -> String
+    {
+        let trade_columns = ["exchange", "symbol", "side", "price", "timestamp", "amount"].iter()
+                                                                                          .map(|field| format!("t.{}", field))
+                                                                                          .collect::<Vec<_>>()
+                                                                                          .join(", ");
+        let book_columns = OrderBook25::field_names().iter().map(|field| format!("b.{}", field)).collect::<Vec<_>>().join(", ");
+
+        format!("SELECT {}, {} FROM (SELECT {} FROM {}) AS t ASOF LEFT JOIN (SELECT {} FROM {}) AS b ON t.symbol = b.symbol AND t.timestamp >= b.timestamp ORDER BY \
+                 t.timestamp ASC",
+                trade_columns,
+                book_columns,
+                self.config.columns.trade_select_list(),
+                quote_table_path(trades_database, trades_table),
+                OrderBook25::select_list(),
+                quote_table_path(books_database, books_table))
+    }
+
+    /// 返回某交易对某日的成交与其对应最新快照的游标。
+    pub async fn cursor_trades_with_book_snapshot(&self, exchange: &str, instrument: &str, date: &str, base: &str, quote: &str) -> Result<TradeBookCursor>
+    {
+        let trades_database = self.construct_database_name(exchange, instrument, "trades");
+        let trades_table = self.construct_table_name(exchange, instrument, "trades", date, base, quote);
+        let books_database = self.construct_database_name(exchange, instrument, "book_snapshot_25");
+        let books_table = self.construct_table_name(exchange, instrument, "book_snapshot_25", date, base, quote);
+
+        let query = self.construct_trade_book_asof_query(&trades_database, &trades_table, &books_database, &books_table);
+        info!("Constructed query {}", query);
+
+        let client = self.client.read().await;
+        let cursor = client.query(&query).fetch::<TradeBookRow>()?;
+        Ok(TradeBookCursor { cursor })
+    }
+}
+
+/// 进程内的 as-of 合并，用于文件等非 ClickHouse 数据源。
+///
+/// 两个输入都必须按时间戳升序排列。每输出一笔成交前，只推进时间戳不晚于该成交的快照，
+/// 并按交易对记录最新快照，因此结果与 ClickHouse 的 `ASOF LEFT JOIN` 一致。
+pub struct AsOfMerge<T, B>
+    where T: Iterator<Item = MarketTrade>,
+          B: Iterator<Item = OrderBook25>
+{
+    trades: T,
+    books: Peekable<B>,
+    latest: HashMap<String, OrderBook25>,
+}
+
+impl<T, B> AsOfMerge<T, B>
+    where T: Iterator<Item = MarketTrade>,
+          B: Iterator<Item = OrderBook25>
+{
+    pub fn new(trades: T, books: B) -> Self
+    {
+        Self { trades,
+               books: books.peekable(),
+               latest: HashMap::new() }
+    }
+}
+
+impl<T, B> Iterator for AsOfMerge<T, B>
+    where T: Iterator<Item = MarketTrade>,
+          B: Iterator<Item = OrderBook25>
+{
+    type Item = TradeBookPair;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        let trade = self.trades.next()?;
+
+        while let Some(book) = self.books.next_if(|book| book.timestamp <= trade.timestamp) {
+            self.latest.insert(book.symbol.clone(), book);
+        }
+
+        let book = self.latest.get(&trade.symbol).cloned();
+        Some(TradeBookPair { trade, book })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn trade(timestamp: i64) -> MarketTrade
+    {
+        MarketTrade { exchange: "binance-futures".to_string(),
+                      symbol: "XRPUSDT".to_string(),
+                      side: "buy".to_string(),
+                      price: 0.5,
+                      timestamp,
+                      amount: 10.0 }
+    }
+
+    fn book(symbol: &str, timestamp: i64, best_bid: f64) -> OrderBook25
+    {
+        OrderBook25 { exchange: "binance-futures".to_string(),
+                      symbol: symbol.to_string(),
+                      timestamp,
+                      local_timestamp: timestamp,
+                      bids_0_price: best_bid,
+                      ..Default::default() }
+    }
+
+    #[test]
+    fn asof_merge_should_never_look_ahead()
+    {
+        let trades = vec![trade(5), trade(10), trade(15), trade(30)];
+        let books = vec![book("XRPUSDT", 10, 0.49), book("ETHUSDT", 12, 3000.0), book("XRPUSDT", 20, 0.51)];
+
+        let pairs: Vec<_> = AsOfMerge::new(trades.into_iter(), books.into_iter()).collect();
+        let best_bids: Vec<Option<f64>> = pairs.iter().map(|pair| pair.book.as_ref().map(|book| book.bids_0_price)).collect();
+
+        assert_eq!(best_bids, vec![None, Some(0.49), Some(0.49), Some(0.51)]);
+        assert!(pairs.iter().all(|pair| pair.book.as_ref().is_none_or(|book| book.timestamp <= pair.trade.timestamp)));
+    }
+
+    #[test]
+    fn asof_query_should_join_on_symbol_and_past_timestamp()
+    {
+        let client = ClickHouseClient::new();
+        let query = client.construct_trade_book_asof_query("binance_futures_trades",
+                                                           "binance_futures_trades_2020_12_19_XRPUSDT",
+                                                           "binance_futures_book_snapshot_25",
+                                                           "binance_futures_book_snapshot_25_2020_12_19_XRPUSDT");
+        assert!(query.contains("ASOF LEFT JOIN"));
+        assert!(query.contains("ON t.symbol = b.symbol AND t.timestamp >= b.timestamp"));
+        assert!(query.contains("`asks[24].amount` AS asks_24_amount"));
+        assert!(query.contains("b.bids_24_amount"));
+    }
+
+    #[test]
+    fn unmatched_row_should_have_no_book()
+    {
+        let row = TradeBookRow { trade: trade(1),
+                                 book: OrderBook25::default() };
+        assert_eq!(TradeBookPair::from(row).book, None);
+    }
+}
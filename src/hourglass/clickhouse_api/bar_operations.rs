@@ -0,0 +1,113 @@
+use crate::{
+    hourglass::clickhouse_api::{
+        clickhouse_config::render_template,
+        datatype::bar::{Bar, BarInterval},
+        queries_operations::{ClickHouseClient, Error, Result},
+        query_builder::{quote_identifier, quote_table_path, ClickHouseQueryBuilder},
+    },
+    hourglass_log::info,
+};
+use clickhouse::query::RowCursor;
+
+impl ClickHouseClient
+{
+    /// 按配置的模板生成K线表名，默认模板下例如 `binance_futures_bars_1m_2024_05_05`。
+    pub fn construct_bar_table_name(&self, exchange: &str, instrument: &str, interval: &BarInterval, date: &str) -> String
+    {
+        render_template(&self.config.bar_table_template, exchange, instrument, &format!("bars_{}", interval), date, "", "")
+    }
+
+    /// 从指定成交表聚合K线的查询语句。
+    pub fn construct_bar_aggregation_query(&self, database: &str, table_name: &str, interval: &BarInterval) -> String
+    {
+        let source = format!("SELECT {} FROM {}", self.config.columns.trade_select_list(), quote_table_path(database, table_name));
+        let select = format!("any(exchange) AS exchange, symbol, toInt64(intDiv(timestamp, {micros}) * {micros}) AS open_time, toInt64(max(timestamp)) AS close_time, \
+                              argMin(price, timestamp) AS open, max(price) AS high, min(price) AS low, argMax(price, timestamp) AS close, sum(amount) AS volume, \
+                              if(sum(amount) = 0, argMax(price, timestamp), sum(price * amount) / sum(amount)) AS vwap, count() AS trade_count, \
+                              sumIf(amount, lower(side) IN ('buy', 'b')) AS buy_volume, sumIf(amount, lower(side) IN ('sell', 's')) AS sell_volume",
+                             micros = interval.micros);
+
+        format!("SELECT {} FROM ({}) GROUP BY symbol, open_time ORDER BY open_time ASC, symbol ASC", select, source)
+    }
+
+    /// 基于某日的合并成交表创建K线表。
+    pub async fn create_bar_table(&self, exchange: &str, instrument: &str, interval: &BarInterval, date: &str) -> Result<(), Error>
+    {
+        let trades_database = self.construct_database_name(exchange, instrument, "trades");
+        let trades_table = self.construct_union_table_name(exchange, instrument, "trades", date);
+        let bars_database = self.construct_database_name(exchange, instrument, "bars");
+        let bars_table = self.construct_bar_table_name(exchange, instrument, interval, date);
+
+        self.create_database_if_not_exists(&bars_database).await?;
+
+        let create_query = format!("CREATE TABLE IF NOT EXISTS {} ENGINE = ReplacingMergeTree() ORDER BY (open_time, symbol) AS {}",
+                                   quote_table_path(&bars_database, &bars_table),
+                                   self.construct_bar_aggregation_query(&trades_database, &trades_table, interval));
+        info!("Creating bar table {}.{}", quote_identifier(&bars_database), quote_identifier(&bars_table));
+        self.client.read().await.query(&create_query).execute().await?;
+
+        Ok(())
+    }
+
+    /// 读取已创建的K线表。
+    pub async fn query_bars(&self, exchange: &str, instrument: &str, interval: &BarInterval, date: &str) -> Result<Vec<Bar>, Error>
+    {
+        let database = self.construct_database_name(exchange, instrument, "bars");
+        let table_name = self.construct_bar_table_name(exchange, instrument, interval, date);
+        let query = ClickHouseQueryBuilder::new().select("*").from(&database, &table_name).order("open_time", Some("ASC"));
+
+        let client = self.client.read().await;
+        query.query(&client).fetch_all::<Bar>().await
+    }
+
+    /// 以游标方式读取K线表，供 [`DataSource::Bars`](crate::hourglass::DataSource::Bars) 回测使用。
+    pub async fn cursor_bars(&self, exchange: &str, instrument: &str, interval: &BarInterval, date: &str) -> Result<RowCursor<Bar>>
+    {
+        let database = self.construct_database_name(exchange, instrument, "bars");
+        let table_name = self.construct_bar_table_name(exchange, instrument, interval, date);
+        let query = ClickHouseQueryBuilder::new().select("?fields").from(&database, &table_name).order("open_time", Some("ASC"));
+
+        let client = self.client.read().await;
+        query.query(&client).fetch::<Bar>()
+    }
+
+    /// 不落表，直接从合并成交表实时聚合K线并返回游标。
+    pub async fn cursor_aggregated_bars(&self, exchange: &str, instrument: &str, interval: &BarInterval, date: &str) -> Result<RowCursor<Bar>>
+    {
+        let database = self.construct_database_name(exchange, instrument, "trades");
+        let table_name = self.construct_union_table_name(exchange, instrument, "trades", date);
+        let query = self.construct_bar_aggregation_query(&database, &table_name, interval);
+
+        let client = self.client.read().await;
+        client.query(&query).fetch::<Bar>()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::hourglass::clickhouse_api::clickhouse_config::ClickHouseConfig;
+
+    #[test]
+    fn bar_table_name_should_include_interval()
+    {
+        let client = ClickHouseClient::new();
+        assert_eq!(client.construct_bar_table_name("binance", "futures", &BarInterval::minutes(1), "2024-05-05"),
+                   "binance_futures_bars_1m_2024_05_05");
+
+        let client = ClickHouseClient::from_config(ClickHouseConfig { bar_table_template: "{exchange}_{channel}_{date}".to_string(),
+                                                                      ..Default::default() });
+        assert_eq!(client.construct_bar_table_name("binance", "futures", &BarInterval::minutes(1), "2024-05-05"), "binance_bars_1m_2024_05_05");
+    }
+
+    #[test]
+    fn aggregation_query_should_bucket_by_interval()
+    {
+        let client = ClickHouseClient::new();
+        let query = client.construct_bar_aggregation_query("binance_futures_trades", "binance_futures_trades_union_2024_05_05", &BarInterval::seconds(1));
+        assert!(query.contains("intDiv(timestamp, 1000000) * 1000000"));
+        assert!(query.contains("FROM (SELECT exchange, symbol, side, price, timestamp, amount FROM binance_futures_trades.binance_futures_trades_union_2024_05_05)"));
+        assert!(query.ends_with("GROUP BY symbol, open_time ORDER BY open_time ASC, symbol ASC"));
+    }
+}
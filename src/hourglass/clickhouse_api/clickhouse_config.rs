@@ -0,0 +1,203 @@
+use crate::{error::ExchangeError, hourglass::utils::config_parser::read_clickhouse_config};
+use clickhouse::Compression;
+use serde::{Deserialize, Serialize};
+
+/// ClickHouse 连接与库表布局配置。
+///
+/// 可以写在 `config.toml` 的 `[clickhouse]` 段落中，也可以直接传给 [`ExchangeBuilder`](crate::hourglass::ExchangeBuilder)。
+/// 未填写的字段会回落到默认值，即 `http://localhost:8123`、用户 `default`、空密码以及 `Tardis` 风格的库表命名。
+///
+/// 表名模板支持以下占位符：`{exchange}`、`{instrument}`、`{channel}`、`{date}`、`{base}`、`{quote}`。
+/// 其中 `{date}` 会被替换为以下划线分隔的日期（例如 `2024_05_05`），`{base}` 与 `{quote}` 为大写。
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ClickHouseConfig
+{
+    pub url: String,                        // ClickHouse HTTP 接口地址
+    pub user: String,                       // 用户名
+    pub password: String,                   // 密码
+    pub database_prefix: String,            // 数据库名前缀，例如 `warehouse_`
+    pub compression: ClickHouseCompression, // 传输压缩方式
+    pub database_template: String,          // 数据库名模板
+    pub table_template: Option<String>,     // 单日单交易对表名模板，为 `None` 时沿用按交易所区分的默认命名
+    pub union_table_template: String,       // 合并表名模板
+    pub bar_table_template: String,         // K线表名模板，`{channel}` 为 `bars_` 加K线周期，例如 `bars_1m`
+    pub columns: ClickHouseColumnMapping,   // 交易表的列名映射
+}
+
+impl Default for ClickHouseConfig
+{
+    fn default() -> Self
+    {
+        Self { url: "http://localhost:8123".to_string(),
+               user: "default".to_string(),
+               password: String::new(),
+               database_prefix: String::new(),
+               compression: ClickHouseCompression::default(),
+               database_template: "{exchange}_{instrument}_{channel}".to_string(),
+               table_template: None,
+               union_table_template: "{exchange}_{instrument}_{channel}_union_{date}".to_string(),
+               bar_table_template: "{exchange}_{instrument}_{channel}_{date}".to_string(),
+               columns: ClickHouseColumnMapping::default() }
+    }
+}
+
+impl ClickHouseConfig
+{
+    /// 从 `config.toml` 的 `[clickhouse]` 段落读取配置，段落缺失时返回默认配置。
+    pub fn load() -> Result<Self, ExchangeError>
+    {
+        read_clickhouse_config()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ClickHouseCompression
+{
+    None,
+    #[default]
+    Lz4,
+}
+
+impl From<ClickHouseCompression> for Compression
+{
+    fn from(compression: ClickHouseCompression) -> Self
+    {
+        match compression {
+            | ClickHouseCompression::None => Compression::None,
+            | ClickHouseCompression::Lz4 => Compression::Lz4,
+        }
+    }
+}
+
+/// 交易表的列名映射，键为 [`MarketTrade`](crate::hourglass::clickhouse_api::datatype::clickhouse_trade_data::MarketTrade) 的字段名，值为仓库中的实际列名。
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ClickHouseColumnMapping
+{
+    pub exchange: String,
+    pub symbol: String,
+    pub id: String,
+    pub side: String,
+    pub price: String,
+    pub timestamp: String,
+    pub amount: String,
+}
+
+impl Default for ClickHouseColumnMapping
+{
+    fn default() -> Self
+    {
+        Self { exchange: "exchange".to_string(),
+               symbol: "symbol".to_string(),
+               id: "id".to_string(),
+               side: "side".to_string(),
+               price: "price".to_string(),
+               timestamp: "timestamp".to_string(),
+               amount: "amount".to_string() }
+    }
+}
+
+impl ClickHouseColumnMapping
+{
+    /// 返回字段对应的实际列名，未知字段原样返回。
+    pub fn column<'a>(&'a self, field: &'a str) -> &'a str
+    {
+        match field {
+            | "exchange" => &self.exchange,
+            | "symbol" => &self.symbol,
+            | "id" => &self.id,
+            | "side" => &self.side,
+            | "price" => &self.price,
+            | "timestamp" => &self.timestamp,
+            | "amount" => &self.amount,
+            | _ => field,
+        }
+    }
+
+    /// 构建 SELECT 列表，列名与字段名不一致时使用 `AS` 取回字段名，保证 `RowBinary` 反序列化对齐。
+    pub fn select_list(&self, fields: &[&str]) -> String
+    {
+        fields.iter()
+              .map(|field| {
+                  let column = self.column(field);
+                  if column == *field {
+                      column.to_string()
+                  }
+                  else {
+                      format!("{} AS {}", column, field)
+                  }
+              })
+              .collect::<Vec<_>>()
+              .join(", ")
+    }
+
+    /// `MarketTrade` 所需的全部列。
+    pub fn trade_select_list(&self) -> String
+    {
+        self.select_list(&["exchange", "symbol", "side", "price", "timestamp", "amount"])
+    }
+}
+
+/// 用占位符替换模板中的字段。
+pub(crate) fn render_template(template: &str, exchange: &str, instrument: &str, channel: &str, date: &str, base: &str, quote: &str) -> String
+{
+    template.replace("{exchange}", exchange)
+            .replace("{instrument}", instrument)
+            .replace("{channel}", channel)
+            .replace("{date}", &date.replace('-', "_"))
+            .replace("{base}", &base.to_uppercase())
+            .replace("{quote}", &quote.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn default_column_mapping_should_not_alias()
+    {
+        let mapping = ClickHouseColumnMapping::default();
+        assert_eq!(mapping.trade_select_list(), "exchange, symbol, side, price, timestamp, amount");
+    }
+
+    #[test]
+    fn custom_column_mapping_should_alias_to_field_names()
+    {
+        let mapping = ClickHouseColumnMapping { timestamp: "ts".to_string(),
+                                                amount: "qty".to_string(),
+                                                ..Default::default() };
+        assert_eq!(mapping.trade_select_list(), "exchange, symbol, side, price, ts AS timestamp, qty AS amount");
+    }
+
+    #[test]
+    fn partial_toml_should_fall_back_to_defaults()
+    {
+        let config: ClickHouseConfig = toml::from_str(
+                                                      r#"
+            url = "http://warehouse:8123"
+            database_prefix = "tardis_"
+            compression = "None"
+
+            [columns]
+            timestamp = "ts"
+            "#,
+        ).unwrap();
+
+        assert_eq!(config.url, "http://warehouse:8123");
+        assert_eq!(config.user, "default");
+        assert_eq!(config.compression, ClickHouseCompression::None);
+        assert_eq!(config.columns.timestamp, "ts");
+        assert_eq!(config.columns.price, "price");
+        assert_eq!(config.union_table_template, ClickHouseConfig::default().union_table_template);
+        assert_eq!(config.bar_table_template, ClickHouseConfig::default().bar_table_template);
+    }
+
+    #[test]
+    fn render_template_should_fill_placeholders()
+    {
+        let rendered = render_template("{exchange}_{channel}_{date}_{base}{quote}", "binance", "futures", "trades", "2024-05-05", "btc", "usdt");
+        assert_eq!(rendered, "binance_trades_2024_05_05_BTCUSDT");
+    }
+}
@@ -0,0 +1,613 @@
+use crate::{
+    common::Side,
+    dashboard::summary::{combine, TableBuilder},
+    error::ExchangeError,
+    hourglass::clickhouse_api::{
+        datatype::clickhouse_trade_data::MarketTrade,
+        queries_operations::{ClickHouseClient, Row},
+        query_builder::ClickHouseQueryBuilder,
+    },
+    hourglass_log::{info, warn},
+};
+use chrono::NaiveDate;
+use clickhouse::{error::Error, query::RowCursor};
+use prettytable::{row, Table};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    str::FromStr,
+};
+
+/// 数据质量审计所需的交易行，相比 `MarketTrade` 额外携带 `id` 用于重复检测。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Row)]
+pub struct AuditTrade
+{
+    pub id: String,
+    pub exchange: String,
+    pub symbol: String,
+    pub side: String,
+    pub price: f64,
+    pub timestamp: i64,
+    pub amount: f64,
+}
+
+impl From<AuditTrade> for MarketTrade
+{
+    fn from(trade: AuditTrade) -> Self
+    {
+        MarketTrade { exchange: trade.exchange,
+                      symbol: trade.symbol,
+                      side: trade.side,
+                      price: trade.price,
+                      timestamp: trade.timestamp,
+                      amount: trade.amount }
+    }
+}
+
+/// 审计阈值配置。时间戳单位与数据表一致（`Tardis` 数据为微秒）。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataAuditConfig
+{
+    pub max_gap: i64,               // 相邻两笔成交允许的最大时间间隔
+    pub outlier_threshold: f64,     // 相对滚动均价的最大偏离比例，超过即视为异常成交
+    pub outlier_window: usize,      // 计算滚动均价所用的成交笔数
+    pub max_issues_per_kind: usize, // 每类问题最多保留的明细条数，计数不受影响
+}
+
+impl Default for DataAuditConfig
+{
+    fn default() -> Self
+    {
+        Self { max_gap: 60_000_000,
+               outlier_threshold: 0.1,
+               outlier_window: 100,
+               max_issues_per_kind: 100 }
+    }
+}
+
+/// 单条数据质量问题。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DataIssue
+{
+    Gap
+    {
+        from: i64, to: i64
+    },
+    DuplicateId
+    {
+        id: String, occurrences: usize
+    },
+    NonMonotonicTimestamp
+    {
+        id: String, previous: i64, current: i64
+    },
+    NonPositivePrice
+    {
+        id: String, price: f64
+    },
+    NonPositiveAmount
+    {
+        id: String, amount: f64
+    },
+    UnparseableSide
+    {
+        id: String, side: String
+    },
+    Outlier
+    {
+        id: String, price: f64, reference: f64
+    },
+}
+
+/// 各类问题的计数。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DataIssueCounts
+{
+    pub gaps: usize,
+    pub duplicate_ids: usize,
+    pub non_monotonic_timestamps: usize,
+    pub non_positive_prices: usize,
+    pub non_positive_amounts: usize,
+    pub unparseable_sides: usize,
+    pub outliers: usize,
+}
+
+impl DataIssueCounts
+{
+    pub fn total(&self) -> usize
+    {
+        self.gaps + self.duplicate_ids + self.non_monotonic_timestamps + self.non_positive_prices + self.non_positive_amounts + self.unparseable_sides + self.outliers
+    }
+}
+
+/// 单日（单张表）的审计结果。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DailyAuditReport
+{
+    pub date: String,
+    pub table: String,
+    pub trade_count: usize,
+    pub counts: DataIssueCounts,
+    pub issues: Vec<DataIssue>,
+}
+
+impl DailyAuditReport
+{
+    pub fn is_clean(&self) -> bool
+    {
+        self.counts.total() == 0
+    }
+
+    /// 按照给定策略处理脏数据：忽略、告警或拒绝回测。
+    pub fn enforce(&self, policy: DataQualityPolicy) -> Result<(), ExchangeError>
+    {
+        if self.is_clean() {
+            return Ok(());
+        }
+
+        match policy {
+            | DataQualityPolicy::Ignore => Ok(()),
+            | DataQualityPolicy::Warn => {
+                warn!("Data quality issues found in {} ({}): {:?}", self.table, self.date, self.counts);
+                Ok(())
+            }
+            | DataQualityPolicy::Refuse => Err(ExchangeError::DataQuality(format!("{} ({}) has {} issues", self.table, self.date, self.counts.total()))),
+        }
+    }
+}
+
+impl TableBuilder for DailyAuditReport
+{
+    fn titles(&self) -> prettytable::Row
+    {
+        row!["Trades", "Gaps", "Duplicate IDs", "Non-monotonic", "Bad Price", "Bad Amount", "Bad Side", "Outliers",]
+    }
+
+    fn row(&self) -> prettytable::Row
+    {
+        row![self.trade_count,
+             self.counts.gaps,
+             self.counts.duplicate_ids,
+             self.counts.non_monotonic_timestamps,
+             self.counts.non_positive_prices,
+             self.counts.non_positive_amounts,
+             self.counts.unparseable_sides,
+             self.counts.outliers,]
+    }
+}
+
+/// 一段日期范围内的审计结果。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DataAuditReport
+{
+    pub exchange: String,
+    pub instrument: String,
+    pub missing_dates: Vec<String>,
+    pub days: Vec<DailyAuditReport>,
+}
+
+impl DataAuditReport
+{
+    pub fn dirty_days(&self) -> impl Iterator<Item = &DailyAuditReport>
+    {
+        self.days.iter().filter(|day| !day.is_clean())
+    }
+
+    pub fn to_json(&self) -> Result<String, ExchangeError>
+    {
+        serde_json::to_string_pretty(self).map_err(|_| ExchangeError::JsonSerDeError)
+    }
+
+    /// 每日一行的汇总表格。
+    pub fn summary_table(&self) -> Table
+    {
+        combine(self.days.iter().map(|day| (day.date.clone(), day.clone())))
+    }
+}
+
+/// 逐笔审计成交，不需要把整张表读入内存，但要记住已出现的每个 `id`，内存随成交笔数增长。
+///
+/// 每天（每张表）各用一个审计器，重复 `id` 只在当天内检测。
+pub struct TradeAuditor
+{
+    config: DataAuditConfig,
+    trade_count: usize,
+    counts: DataIssueCounts,
+    issues: Vec<DataIssue>,
+    kept: HashMap<&'static str, usize>, // 每类问题已保留的明细条数
+    id_occurrences: HashMap<String, usize>,
+    window: VecDeque<f64>,
+    window_sum: f64,
+    previous_timestamp: Option<i64>,
+}
+
+impl TradeAuditor
+{
+    pub fn new(config: DataAuditConfig) -> Self
+    {
+        Self { window: VecDeque::with_capacity(config.outlier_window),
+               config,
+               trade_count: 0,
+               counts: DataIssueCounts::default(),
+               issues: Vec::new(),
+               kept: HashMap::new(),
+               id_occurrences: HashMap::new(),
+               window_sum: 0.0,
+               previous_timestamp: None }
+    }
+
+    fn record(&mut self, kind: &'static str, issue: DataIssue)
+    {
+        let entry = self.kept.entry(kind).or_insert(0);
+        if *entry < self.config.max_issues_per_kind {
+            *entry += 1;
+            self.issues.push(issue);
+        }
+    }
+
+    /// 目前为止各类问题的计数。
+    pub fn counts(&self) -> &DataIssueCounts
+    {
+        &self.counts
+    }
+
+    /// 按读取顺序审计下一笔成交。
+    pub fn push(&mut self, trade: &AuditTrade)
+    {
+        self.trade_count += 1;
+        let occurrences = self.id_occurrences.entry(trade.id.clone()).or_insert(0);
+        *occurrences += 1;
+        if *occurrences == 2 {
+            self.counts.duplicate_ids += 1;
+        }
+
+        if let Some(previous) = self.previous_timestamp {
+            if trade.timestamp < previous {
+                self.counts.non_monotonic_timestamps += 1;
+                self.record("non_monotonic", DataIssue::NonMonotonicTimestamp { id: trade.id.clone(),
+                                                                                previous,
+                                                                                current: trade.timestamp });
+            }
+            else if trade.timestamp - previous > self.config.max_gap {
+                self.counts.gaps += 1;
+                self.record("gap", DataIssue::Gap { from: previous, to: trade.timestamp });
+            }
+        }
+        self.previous_timestamp = Some(trade.timestamp);
+
+        if Side::from_str(&trade.side).is_err() {
+            self.counts.unparseable_sides += 1;
+            self.record("side", DataIssue::UnparseableSide { id: trade.id.clone(),
+                                                             side: trade.side.clone() });
+        }
+
+        if trade.amount <= 0.0 {
+            self.counts.non_positive_amounts += 1;
+            self.record("amount", DataIssue::NonPositiveAmount { id: trade.id.clone(),
+                                                                 amount: trade.amount });
+        }
+
+        // 非正价格不参与滚动均价的计算
+        if trade.price <= 0.0 {
+            self.counts.non_positive_prices += 1;
+            self.record("price", DataIssue::NonPositivePrice { id: trade.id.clone(),
+                                                               price: trade.price });
+            return;
+        }
+
+        let outlier_window = self.config.outlier_window;
+        if outlier_window == 0 {
+            return;
+        }
+        if self.window.len() == outlier_window {
+            let reference = self.window_sum / self.window.len() as f64;
+            if ((trade.price - reference) / reference).abs() > self.config.outlier_threshold {
+                self.counts.outliers += 1;
+                self.record("outlier", DataIssue::Outlier { id: trade.id.clone(),
+                                                            price: trade.price,
+                                                            reference });
+                // 异常价格不进入滚动窗口，避免污染后续基准
+                return;
+            }
+            self.window_sum -= self.window.pop_front().unwrap_or_default();
+        }
+        self.window.push_back(trade.price);
+        self.window_sum += trade.price;
+    }
+
+    /// 结束审计，补充重复 `id` 的明细，返回成交笔数、各类问题计数与明细。
+    pub fn finish(mut self) -> (usize, DataIssueCounts, Vec<DataIssue>)
+    {
+        let mut duplicates: Vec<(String, usize)> = std::mem::take(&mut self.id_occurrences).into_iter().filter(|(_, occurrences)| *occurrences > 1).collect();
+        duplicates.sort();
+        for (id, occurrences) in duplicates {
+            self.record("duplicate", DataIssue::DuplicateId { id, occurrences });
+        }
+
+        (self.trade_count, self.counts, self.issues)
+    }
+
+    /// 结束审计，生成某一天某张表的报告。
+    pub fn into_report(self, date: &str, table: String) -> DailyAuditReport
+    {
+        let (trade_count, counts, issues) = self.finish();
+        DailyAuditReport { date: date.replace('-', "_"),
+                           table,
+                           trade_count,
+                           counts,
+                           issues }
+    }
+}
+
+/// 对一组按读取顺序排列的成交逐笔审计。
+pub fn audit_trades(trades: &[AuditTrade], config: &DataAuditConfig) -> (DataIssueCounts, Vec<DataIssue>)
+{
+    let mut auditor = TradeAuditor::new(config.clone());
+    trades.iter().for_each(|trade| auditor.push(trade));
+    let (_, counts, issues) = auditor.finish();
+    (counts, issues)
+}
+
+impl ClickHouseClient
+{
+    /// 按时间戳顺序（同一时间戳再按 `id`）以游标读取某张成交表，用于审计。顺序与回测回放成交的顺序一致。
+    pub async fn cursor_audit_trades(&self, database: &str, table_name: &str) -> Result<RowCursor<AuditTrade>, Error>
+    {
+        let columns = &self.config.columns;
+        let select = format!("toString({}) AS id, {}", columns.id, columns.trade_select_list());
+        let order = format!("{}, {}", columns.timestamp, columns.id);
+        let query = ClickHouseQueryBuilder::new().select(&select).from(database, table_name).order(&order, Some("ASC"));
+        let client = self.client.read().await;
+        query.query(&client).fetch::<AuditTrade>()
+    }
+
+    /// 审计某一天的合并成交表。
+    pub async fn audit_unioned_trade_table(&self, exchange: &str, instrument: &str, date: &str, config: &DataAuditConfig) -> Result<DailyAuditReport, Error>
+    {
+        let database = self.construct_database_name(exchange, instrument, "trades");
+        let table_name = self.construct_union_table_name(exchange, instrument, "trades", date);
+        let mut cursor = self.cursor_audit_trades(&database, &table_name).await?;
+        let mut auditor = TradeAuditor::new(config.clone());
+        while let Some(trade) = cursor.next().await? {
+            auditor.push(&trade);
+        }
+        Ok(auditor.into_report(date, format!("{}.{}", database, table_name)))
+    }
+
+    /// 审计一段日期范围内的合并成交表，缺失的日期单独列出。
+    pub async fn audit_trade_tables_between_dates(&self, exchange: &str, instrument: &str, mut start_date: NaiveDate, end_date: NaiveDate, config: &DataAuditConfig) -> Result<DataAuditReport, Error>
+    {
+        let database = self.construct_database_name(exchange, instrument, "trades");
+        let existing_tables: HashSet<String> = self.get_union_table_names(&database).await.into_iter().collect();
+        let mut report = DataAuditReport { exchange: exchange.to_string(),
+                                           instrument: instrument.to_string(),
+                                           ..Default::default() };
+
+        while start_date <= end_date {
+            let date = start_date.format("%Y_%m_%d").to_string();
+            let table_name = self.construct_union_table_name(exchange, instrument, "trades", &date);
+
+            if existing_tables.contains(&table_name) {
+                let day = self.audit_unioned_trade_table(exchange, instrument, &date, config).await?;
+                info!("Audited {}: {} trades, {} issues", day.table, day.trade_count, day.counts.total());
+                report.days.push(day);
+            }
+            else {
+                report.missing_dates.push(date);
+            }
+
+            start_date += chrono::Duration::days(1);
+        }
+
+        Ok(report)
+    }
+}
+
+/// 回测遇到脏数据时的处理策略。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataQualityPolicy
+{
+    Ignore,
+    #[default]
+    Warn,
+    Refuse,
+}
+
+/// 正在回放的一天，边回放边审计。
+struct CheckedDay
+{
+    date: String,
+    table: String,
+    cursor: RowCursor<AuditTrade>,
+    auditor: Option<TradeAuditor>, // 策略为 `Ignore` 时不审计
+}
+
+/// 逐日回放合并成交表，回放的同时按 [`DataQualityPolicy`] 审计，每张表只读一遍，供 [`DataSource::CheckedDays`](crate::hourglass::DataSource::CheckedDays) 使用。
+///
+/// 策略为 [`DataQualityPolicy::Warn`] 时在每天结束后告警；策略为 [`DataQualityPolicy::Refuse`] 时，发现第一笔有问题的成交即停止回放，
+/// 该笔成交不会交给交易所，但当天之前的成交已经回放。重复 `id` 在第二次出现时发现。
+pub struct CheckedTradeDays
+{
+    pub exchange: String,
+    pub instrument: String,
+    pub audit_config: DataAuditConfig,
+    pub policy: DataQualityPolicy,
+    dates: VecDeque<String>,
+    day: Option<Box<CheckedDay>>,
+}
+
+impl CheckedTradeDays
+{
+    /// 回放 `start_date` 到 `end_date`（含）之间的每一天。
+    pub fn new(exchange: &str, instrument: &str, mut start_date: NaiveDate, end_date: NaiveDate, audit_config: DataAuditConfig, policy: DataQualityPolicy) -> Self
+    {
+        let mut dates = VecDeque::new();
+        while start_date <= end_date {
+            dates.push_back(start_date.format("%Y_%m_%d").to_string());
+            start_date += chrono::Duration::days(1);
+        }
+        Self { exchange: exchange.to_string(),
+               instrument: instrument.to_string(),
+               audit_config,
+               policy,
+               dates,
+               day: None }
+    }
+
+    /// 下一笔成交，当天的数据读完后按策略处理审计结果并打开下一天。全部日期读完时返回 `Ok(None)`，
+    /// 策略为 [`DataQualityPolicy::Refuse`] 且发现问题时返回 [`ExchangeError::DataQuality`]，之后不再回放。
+    pub async fn next(&mut self, client: &ClickHouseClient) -> Result<Option<MarketTrade>, ExchangeError>
+    {
+        loop {
+            let Some(day) = &mut self.day
+            else {
+                let Some(date) = self.dates.pop_front()
+                else {
+                    return Ok(None);
+                };
+                self.day = Some(Box::new(self.open_day(client, date).await?));
+                continue;
+            };
+
+            match day.cursor.next().await {
+                | Ok(Some(trade)) => {
+                    let dirty = day.auditor.as_mut().is_some_and(|auditor| {
+                                                        auditor.push(&trade);
+                                                        auditor.counts().total() > 0
+                                                    });
+                    if !dirty || self.policy != DataQualityPolicy::Refuse {
+                        return Ok(Some(trade.into()));
+                    }
+                }
+                | Ok(None) => {}
+                | Err(e) => return Err(ExchangeError::InternalError(format!("Failed to read trades of {}: {}", self.instrument, e))),
+            }
+
+            // 当天读完，或者发现了需要拒绝的问题
+            let Some(CheckedDay { date, table, auditor, .. }) = self.day.take().map(|day| *day)
+            else {
+                continue;
+            };
+            if let Some(auditor) = auditor {
+                if let Err(e) = auditor.into_report(&date, table).enforce(self.policy) {
+                    self.dates.clear();
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// 打开某一天的游标，策略不是 `Ignore` 时同时创建审计器。
+    async fn open_day(&mut self, client: &ClickHouseClient, date: String) -> Result<CheckedDay, ExchangeError>
+    {
+        info!("Loading {} {} trades of {}", self.exchange, self.instrument, date);
+        let database = client.construct_database_name(&self.exchange, &self.instrument, "trades");
+        let table_name = client.construct_union_table_name(&self.exchange, &self.instrument, "trades", &date);
+        let cursor = client.cursor_audit_trades(&database, &table_name)
+                           .await
+                           .map_err(|e| ExchangeError::InternalError(format!("Failed to open cursor for {}: {}", date, e)))?;
+        let auditor = (self.policy != DataQualityPolicy::Ignore).then(|| TradeAuditor::new(self.audit_config.clone()));
+        Ok(CheckedDay { table: format!("{}.{}", database, table_name),
+                        date,
+                        cursor,
+                        auditor })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn trade(id: &str, side: &str, price: f64, timestamp: i64, amount: f64) -> AuditTrade
+    {
+        AuditTrade { id: id.to_string(),
+                     exchange: "binance-futures".to_string(),
+                     symbol: "BTCUSDT".to_string(),
+                     side: side.to_string(),
+                     price,
+                     timestamp,
+                     amount }
+    }
+
+    #[test]
+    fn clean_trades_should_report_no_issues()
+    {
+        let trades = vec![trade("1", "buy", 100.0, 1, 1.0), trade("2", "sell", 100.5, 2, 2.0), trade("3", "buy", 100.2, 3, 0.5)];
+        let (counts, issues) = audit_trades(&trades, &DataAuditConfig::default());
+        assert_eq!(counts.total(), 0);
+        assert!(issues.is_empty());
+    }
+
+    #[test]
+    fn dirty_trades_should_report_each_issue_kind()
+    {
+        let config = DataAuditConfig { max_gap: 10,
+                                       outlier_threshold: 0.5,
+                                       outlier_window: 2,
+                                       max_issues_per_kind: 10 };
+        let trades = vec![trade("1", "buy", 100.0, 1, 1.0),
+                          trade("2", "sell", 101.0, 2, 1.0),
+                          trade("2", "sell", 101.0, 2, 1.0),  // 重复 id
+                          trade("3", "buy", 300.0, 3, 1.0),   // 异常价格
+                          trade("4", "hold", 100.0, 30, 1.0), // 时间间隔过大 + 无法解析方向
+                          trade("5", "buy", 0.0, 25, -1.0)    /* 时间戳倒退 + 非正价格 + 非正数量 */];
+
+        let (counts, issues) = audit_trades(&trades, &config);
+        assert_eq!(counts, DataIssueCounts { gaps: 1,
+                                             duplicate_ids: 1,
+                                             non_monotonic_timestamps: 1,
+                                             non_positive_prices: 1,
+                                             non_positive_amounts: 1,
+                                             unparseable_sides: 1,
+                                             outliers: 1 });
+        assert!(issues.contains(&DataIssue::DuplicateId { id: "2".to_string(), occurrences: 2 }));
+        assert!(issues.contains(&DataIssue::Gap { from: 3, to: 30 }));
+    }
+
+    #[test]
+    fn issue_details_should_be_capped_per_kind()
+    {
+        let config = DataAuditConfig { max_issues_per_kind: 2,
+                                       ..Default::default() };
+        let trades: Vec<_> = (0..5).map(|i| trade(&i.to_string(), "buy", 100.0, i, 0.0)).collect();
+        let (counts, issues) = audit_trades(&trades, &config);
+        assert_eq!(counts.non_positive_amounts, 5);
+        assert_eq!(issues.len(), 2);
+    }
+
+    #[test]
+    fn duplicate_ids_should_be_counted_when_they_reappear()
+    {
+        let mut auditor = TradeAuditor::new(DataAuditConfig::default());
+        auditor.push(&trade("1", "buy", 100.0, 1, 1.0));
+        auditor.push(&trade("1", "buy", 100.0, 2, 1.0));
+        assert_eq!(auditor.counts().duplicate_ids, 1);
+        auditor.push(&trade("1", "buy", 100.0, 3, 1.0));
+        let (_, counts, issues) = auditor.finish();
+        assert_eq!(counts.duplicate_ids, 1);
+        assert_eq!(issues, vec![DataIssue::DuplicateId { id: "1".to_string(), occurrences: 3 }]);
+    }
+
+    #[test]
+    fn checked_days_should_cover_inclusive_date_range()
+    {
+        let days = CheckedTradeDays::new("binance",
+                                         "futures",
+                                         NaiveDate::from_ymd_opt(2024, 4, 30).unwrap(),
+                                         NaiveDate::from_ymd_opt(2024, 5, 2).unwrap(),
+                                         DataAuditConfig::default(),
+                                         DataQualityPolicy::Refuse);
+        assert_eq!(days.dates, ["2024_04_30", "2024_05_01", "2024_05_02"]);
+    }
+
+    #[test]
+    fn refuse_policy_should_reject_dirty_day()
+    {
+        let day = DailyAuditReport { counts: DataIssueCounts { gaps: 1, ..Default::default() },
+                                     ..Default::default() };
+        assert!(day.enforce(DataQualityPolicy::Warn).is_ok());
+        assert!(matches!(day.enforce(DataQualityPolicy::Refuse), Err(ExchangeError::DataQuality(_))));
+        assert!(DailyAuditReport::default().enforce(DataQualityPolicy::Refuse).is_ok());
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{
+    common::{datafeed::market_event::MarketEvent, instrument::Instrument, Side},
+    error::ExchangeError,
+    hourglass::clickhouse_api::{datatype::clickhouse_trade_data::MarketTrade, queries_operations::Row},
+    Exchange,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+/// K线的时间粒度，内部以微秒保存，与 `Tardis` 成交数据的时间戳单位一致。
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BarInterval
+{
+    pub micros: i64,
+}
+
+impl BarInterval
+{
+    pub fn seconds(seconds: i64) -> Self
+    {
+        Self { micros: seconds * 1_000_000 }
+    }
+
+    pub fn minutes(minutes: i64) -> Self
+    {
+        Self::seconds(minutes * 60)
+    }
+
+    pub fn hours(hours: i64) -> Self
+    {
+        Self::seconds(hours * 3_600)
+    }
+
+    /// 时间戳所属K线的起始时间。
+    pub fn bucket_start(&self, timestamp: i64) -> i64
+    {
+        timestamp.div_euclid(self.micros) * self.micros
+    }
+}
+
+impl Display for BarInterval
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let seconds = self.micros / 1_000_000;
+        match seconds {
+            | s if s % 86_400 == 0 => write!(f, "{}d", s / 86_400),
+            | s if s % 3_600 == 0 => write!(f, "{}h", s / 3_600),
+            | s if s % 60 == 0 => write!(f, "{}m", s / 60),
+            | s => write!(f, "{}s", s),
+        }
+    }
+}
+
+impl FromStr for BarInterval
+{
+    type Err = ExchangeError;
+
+    /// 解析 `1s`、`15m`、`1h`、`1d` 形式的时间粒度。
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let invalid = || ExchangeError::InvalidDates(format!("Invalid bar interval: {}", s));
+        let (value, unit) = s.split_at(s.len().checked_sub(1).ok_or_else(invalid)?);
+        let value: i64 = value.parse().map_err(|_| invalid())?;
+        if value <= 0 {
+            return Err(invalid());
+        }
+
+        match unit {
+            | "s" => Ok(Self::seconds(value)),
+            | "m" => Ok(Self::minutes(value)),
+            | "h" => Ok(Self::hours(value)),
+            | "d" => Ok(Self::hours(value * 24)),
+            | _ => Err(invalid()),
+        }
+    }
+}
+
+/// OHLCV K线，包含成交量加权均价、成交笔数以及主动买卖成交量。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Row)]
+pub struct Bar
+{
+    pub exchange: String,
+    pub symbol: String,
+    pub open_time: i64,  // K线起始时间
+    pub close_time: i64, // K线内最后一笔成交的时间
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub vwap: f64,
+    pub trade_count: u64,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+}
+
+impl Bar
+{
+    fn from_first_trade(trade: &MarketTrade, interval: &BarInterval) -> Self
+    {
+        let mut bar = Bar { exchange: trade.exchange.clone(),
+                            symbol: trade.symbol.clone(),
+                            open_time: interval.bucket_start(trade.timestamp),
+                            close_time: trade.timestamp,
+                            open: trade.price,
+                            high: trade.price,
+                            low: trade.price,
+                            close: trade.price,
+                            volume: 0.0,
+                            vwap: 0.0,
+                            trade_count: 0,
+                            buy_volume: 0.0,
+                            sell_volume: 0.0 };
+        bar.update(trade);
+        bar
+    }
+
+    fn update(&mut self, trade: &MarketTrade)
+    {
+        if trade.timestamp >= self.close_time {
+            self.close = trade.price;
+            self.close_time = trade.timestamp;
+        }
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        let volume = self.volume + trade.amount;
+        // 成交量为 0 时无法加权，沿用之前的 VWAP，尚无成交量时取收盘价
+        self.vwap = match (volume == 0.0, self.volume == 0.0) {
+            | (false, _) => (self.vwap * self.volume + trade.price * trade.amount) / volume,
+            | (true, true) => self.close,
+            | (true, false) => self.vwap,
+        };
+        self.volume = volume;
+        self.trade_count += 1;
+        match Side::from_str(&trade.side) {
+            | Ok(Side::Buy) => self.buy_volume += trade.amount,
+            | Ok(Side::Sell) => self.sell_volume += trade.amount,
+            | Err(_) => {}
+        }
+    }
+
+    /// 将一根K线展开为四笔合成成交，供回测撮合使用。
+    ///
+    /// 收阳线按 `O -> L -> H -> C` 的路径展开，收阴线按 `O -> H -> L -> C` 展开，成交量平均分配。
+    /// 上行的价格视为主动买，下行的价格视为主动卖，这样挂单能在K线的高低点被触发。
+    pub fn to_synthetic_trades(&self) -> Vec<MarketTrade>
+    {
+        let path = if self.close >= self.open {
+            [self.open, self.low, self.high, self.close]
+        }
+        else {
+            [self.open, self.high, self.low, self.close]
+        };
+        let span = self.close_time.max(self.open_time) - self.open_time;
+        let amount = self.volume / path.len() as f64;
+
+        let mut previous_price = if self.close >= self.open { f64::MIN } else { f64::MAX };
+        path.iter()
+            .enumerate()
+            .map(|(i, price)| {
+                let side = if *price >= previous_price { Side::Buy } else { Side::Sell };
+                previous_price = *price;
+                MarketTrade { exchange: self.exchange.clone(),
+                              symbol: self.symbol.clone(),
+                              side: side.to_string(),
+                              price: *price,
+                              timestamp: self.open_time + span * i as i64 / (path.len() as i64 - 1),
+                              amount }
+            })
+            .collect()
+    }
+
+    /// 借助合成成交解析K线对应的金融工具。
+    pub fn parse_instrument(&self) -> Option<Instrument>
+    {
+        self.to_synthetic_trades().first().and_then(MarketTrade::parse_instrument)
+    }
+}
+
+// NOTE 以K线为单位的回测数据，时间戳取K线内最后一笔成交的时间。
+impl MarketEvent<Bar>
+{
+    pub fn from_bar(bar: Bar, instrument: Instrument) -> Self
+    {
+        MarketEvent { exchange_ts: bar.close_time,
+                      received_ts: bar.close_time,
+                      exchange: Exchange::Hourglass,
+                      instrument,
+                      kind: bar }
+    }
+}
+
+/// 在进程内把逐笔成交聚合成K线，适用于文件数据源或实时数据源。
+#[derive(Debug, Clone)]
+pub struct BarAggregator
+{
+    pub interval: BarInterval,
+    current: HashMap<String, Bar>,
+}
+
+impl BarAggregator
+{
+    pub fn new(interval: BarInterval) -> Self
+    {
+        Self { interval, current: HashMap::new() }
+    }
+
+    /// 输入一笔成交；若该成交开启了新的K线，则返回同一交易对上一根已完成的K线。
+    pub fn update(&mut self, trade: &MarketTrade) -> Option<Bar>
+    {
+        let bucket = self.interval.bucket_start(trade.timestamp);
+        match self.current.get_mut(&trade.symbol) {
+            | Some(bar) if bar.open_time == bucket => {
+                bar.update(trade);
+                None
+            }
+            | Some(_) => self.current.insert(trade.symbol.clone(), Bar::from_first_trade(trade, &self.interval)),
+            | None => {
+                self.current.insert(trade.symbol.clone(), Bar::from_first_trade(trade, &self.interval));
+                None
+            }
+        }
+    }
+
+    /// 取出所有尚未完成的K线。
+    pub fn flush(&mut self) -> Vec<Bar>
+    {
+        let mut bars: Vec<Bar> = self.current.drain().map(|(_, bar)| bar).collect();
+        bars.sort_by(|a, b| a.open_time.cmp(&b.open_time).then_with(|| a.symbol.cmp(&b.symbol)));
+        bars
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn trade(side: &str, price: f64, timestamp: i64, amount: f64) -> MarketTrade
+    {
+        MarketTrade { exchange: "binance-futures".to_string(),
+                      symbol: "BTCUSDT".to_string(),
+                      side: side.to_string(),
+                      price,
+                      timestamp,
+                      amount }
+    }
+
+    #[test]
+    fn interval_should_parse_and_display()
+    {
+        assert_eq!("1s".parse::<BarInterval>().unwrap(), BarInterval::seconds(1));
+        assert_eq!("15m".parse::<BarInterval>().unwrap(), BarInterval::minutes(15));
+        assert_eq!("1h".parse::<BarInterval>().unwrap().to_string(), "1h");
+        assert_eq!(BarInterval::seconds(90).to_string(), "90s");
+        assert!("0m".parse::<BarInterval>().is_err());
+        assert!("m".parse::<BarInterval>().is_err());
+    }
+
+    #[test]
+    fn aggregator_should_emit_completed_bar()
+    {
+        let mut aggregator = BarAggregator::new(BarInterval::seconds(1));
+        assert!(aggregator.update(&trade("buy", 100.0, 1_000_000, 1.0)).is_none());
+        assert!(aggregator.update(&trade("sell", 102.0, 1_200_000, 3.0)).is_none());
+        assert!(aggregator.update(&trade("sell", 99.0, 1_500_000, 1.0)).is_none());
+
+        let bar = aggregator.update(&trade("buy", 101.0, 2_100_000, 1.0)).unwrap();
+        assert_eq!(bar.open_time, 1_000_000);
+        assert_eq!(bar.close_time, 1_500_000);
+        assert_eq!((bar.open, bar.high, bar.low, bar.close), (100.0, 102.0, 99.0, 99.0));
+        assert_eq!(bar.volume, 5.0);
+        assert_eq!(bar.trade_count, 3);
+        assert_eq!((bar.buy_volume, bar.sell_volume), (1.0, 4.0));
+        assert!((bar.vwap - (100.0 + 306.0 + 99.0) / 5.0).abs() < 1e-9);
+
+        let remaining = aggregator.flush();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].open_time, 2_000_000);
+    }
+
+    #[test]
+    fn zero_volume_should_not_produce_nan_vwap()
+    {
+        let mut aggregator = BarAggregator::new(BarInterval::seconds(1));
+        aggregator.update(&trade("buy", 100.0, 0, 0.0));
+        aggregator.update(&trade("sell", 101.0, 100_000, 0.0));
+        assert_eq!(aggregator.flush()[0].vwap, 101.0);
+
+        aggregator.update(&trade("buy", 100.0, 0, 2.0));
+        aggregator.update(&trade("sell", 110.0, 100_000, 0.0));
+        let bar = aggregator.flush().remove(0);
+        assert_eq!(bar.vwap, 100.0);
+        assert_eq!(bar.close, 110.0);
+    }
+
+    #[test]
+    fn synthetic_trades_should_visit_extremes_in_order()
+    {
+        let mut aggregator = BarAggregator::new(BarInterval::minutes(1));
+        aggregator.update(&trade("buy", 100.0, 0, 1.0));
+        aggregator.update(&trade("sell", 95.0, 10_000_000, 1.0));
+        aggregator.update(&trade("buy", 110.0, 20_000_000, 1.0));
+        aggregator.update(&trade("buy", 105.0, 30_000_000, 1.0));
+        let bar = aggregator.flush().remove(0);
+
+        let trades = bar.to_synthetic_trades();
+        let prices: Vec<f64> = trades.iter().map(|t| t.price).collect();
+        let sides: Vec<&str> = trades.iter().map(|t| t.side.as_str()).collect();
+        assert_eq!(prices, vec![100.0, 95.0, 110.0, 105.0]);
+        assert_eq!(sides, vec!["buy", "sell", "buy", "sell"]);
+        assert!(trades.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        assert_eq!(trades.last().unwrap().timestamp, 30_000_000);
+        assert_eq!(trades.iter().map(|t| t.amount).sum::<f64>(), bar.volume);
+    }
+}
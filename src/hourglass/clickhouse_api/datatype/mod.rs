@@ -1,3 +1,4 @@
+pub mod bar;
 pub mod clickhouse_trade_data;
 pub mod order_book_25;
 pub mod single_level_order_book;
@@ -2,7 +2,7 @@ use crate::hourglass::clickhouse_api::queries_operations::Row;
 use serde::{Deserialize, Serialize};
 
 #[allow(dead_code)]
-#[derive(Debug, Serialize, Deserialize, Row)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, Row)]
 pub struct OrderBook25
 {
     pub exchange: String,
@@ -110,3 +110,32 @@ pub struct OrderBook25
     pub bids_24_price: f64,
     pub bids_24_amount: f64,
 }
+
+impl OrderBook25
+{
+    /// 结构体字段名，顺序与字段声明一致。
+    pub fn field_names() -> Vec<String>
+    {
+        let mut fields = vec!["exchange".to_string(), "symbol".to_string(), "timestamp".to_string(), "local_timestamp".to_string()];
+        for level in 0..25 {
+            for side in ["asks", "bids"] {
+                fields.push(format!("{}_{}_price", side, level));
+                fields.push(format!("{}_{}_amount", side, level));
+            }
+        }
+        fields
+    }
+
+    /// 从 `Tardis` 快照表（列名形如 `asks[0].price`）读取时使用的 SELECT 列表。
+    pub fn select_list() -> String
+    {
+        let mut columns = vec!["exchange".to_string(), "symbol".to_string(), "timestamp".to_string(), "local_timestamp".to_string()];
+        for level in 0..25 {
+            for side in ["asks", "bids"] {
+                columns.push(format!("`{side}[{level}].price` AS {side}_{level}_price"));
+                columns.push(format!("`{side}[{level}].amount` AS {side}_{level}_amount"));
+            }
+        }
+        columns.join(", ")
+    }
+}
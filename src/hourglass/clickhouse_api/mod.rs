@@ -1,3 +1,7 @@
+pub mod asof_join;
+pub mod bar_operations;
+pub mod clickhouse_config;
+pub mod data_audit;
 pub mod datatype;
 pub mod queries_operations;
 pub mod query_builder;
@@ -14,14 +14,20 @@ use tokio::sync::RwLock;
 use crate::{
     common::Side,
     hourglass::{
-        clickhouse_api::{datatype::clickhouse_trade_data::MarketTrade, query_builder::ClickHouseQueryBuilder},
+        clickhouse_api::{
+            clickhouse_config::{render_template, ClickHouseConfig},
+            datatype::clickhouse_trade_data::MarketTrade,
+            query_builder::{quote_identifier, quote_table_path, ClickHouseQueryBuilder},
+        },
         utils::chrono_operations::extract_date,
     },
 };
 
+#[derive(Clone)]
 pub struct ClickHouseClient
 {
     pub client: Arc<RwLock<Client>>,
+    pub config: ClickHouseConfig,
 }
 
 impl Default for ClickHouseClient
@@ -36,9 +42,19 @@ impl ClickHouseClient
 {
     pub fn new() -> Self
     {
-        let client = Client::default().with_url("http://localhost:8123").with_user("default").with_password("");
-        info!("Successfully connected to the ClickHouse server.");
-        Self { client: Arc::new(RwLock::new(client)) }
+        Self::from_config(ClickHouseConfig::default())
+    }
+
+    /// 使用给定的 [`ClickHouseConfig`] 创建客户端。
+    pub fn from_config(config: ClickHouseConfig) -> Self
+    {
+        let client = Client::default().with_url(&config.url)
+                                      .with_user(&config.user)
+                                      .with_password(&config.password)
+                                      .with_compression(config.compression.into());
+        info!("Successfully connected to the ClickHouse server at {}.", config.url);
+        Self { client: Arc::new(RwLock::new(client)),
+               config }
     }
 }
 
@@ -86,6 +102,10 @@ impl ClickHouseClient
 {
     pub fn construct_table_name(&self, exchange: &str, instrument: &str, channel: &str, date: &str, base: &str, quote: &str) -> String
     {
+        if let Some(template) = &self.config.table_template {
+            return render_template(template, exchange, instrument, channel, date, base, quote);
+        }
+
         match exchange {
             | "binance" => format!("{}_{}_{}_{}_{}", exchange, instrument, channel, date.replace("-", "_"), base.to_uppercase() + &*quote.to_uppercase()),
             | "okex" => format!("{}_{}_{}_{}_{}_{}_{}",
@@ -102,21 +122,25 @@ impl ClickHouseClient
 
     pub fn construct_union_table_name(&self, exchange: &str, instrument: &str, channel: &str, date: &str) -> String
     {
-        format!("{}_{}_{}_union_{}",
-                exchange,
-                instrument,
-                channel,
-                date.replace("-", "_") /* Replace dashes with underscores for valid table names */)
+        render_template(&self.config.union_table_template, exchange, instrument, channel, date, "", "")
     }
 
     pub fn construct_database_name(&self, exchange: &str, instrument: &str, channel: &str) -> String
     {
-        format!("{}_{}_{}", exchange, instrument, channel)
+        format!("{}{}",
+                self.config.database_prefix,
+                render_template(&self.config.database_template, exchange, instrument, channel, "", "", ""))
+    }
+
+    /// 用户表所在的数据库，同样加上 `database_prefix`。
+    pub fn construct_accounts_database_name(&self) -> String
+    {
+        format!("{}accounts", self.config.database_prefix)
     }
 
     pub async fn get_table_names(&self, database: &str) -> Vec<String>
     {
-        let table_names_query = format!("SHOW TABLES FROM {database}",);
+        let table_names_query = format!("SHOW TABLES FROM {}", quote_identifier(database));
         info!("Trying to retrieve table names within database : {:}", database);
         self.client.read().await.query(&table_names_query).fetch_all::<String>().await.unwrap_or_else(|e| {
                                                                                           warn!("Error loading table names: {:?}", e);
@@ -127,13 +151,13 @@ impl ClickHouseClient
 
     pub async fn get_union_table_names(&self, database: &str) -> Vec<String>
     {
-        let table_names_query = format!("SHOW TABLES FROM {database} LIKE '%union%'",);
+        let table_names_query = format!("SHOW TABLES FROM {} LIKE ?", quote_identifier(database));
         info!("Trying to retrieve table names within the database that contain 'union': {:?}", table_names_query);
-        self.client.read().await.query(&table_names_query).fetch_all::<String>().await.unwrap_or_else(|e| {
-                                                                                          warn!("Error loading table names: {:?}", e);
+        self.client.read().await.query(&table_names_query).bind("%union%").fetch_all::<String>().await.unwrap_or_else(|e| {
+                                                                                                          warn!("Error loading table names: {:?}", e);
 
-                                                                                          vec![]
-                                                                                      })
+                                                                                                          vec![]
+                                                                                                      })
     }
 
     pub async fn get_tables_for_date(&self, table_names: &[String], date: &str) -> Vec<String>
@@ -163,9 +187,10 @@ impl ClickHouseClient
         // 构建UNION ALL查询
         let queries = Arc::new(Mutex::new(Vec::new()));
         let total_tables = table_names.len();
+        let columns = self.config.columns.select_list(&["exchange", "symbol", "id", "side", "price", "timestamp", "amount"]);
 
         table_names.par_iter().enumerate().for_each(|(i, table_name)| {
-                                              let select_query = ClickHouseQueryBuilder::new().select("exchange, symbol, id, side, price, timestamp, amount") // Select required fields
+                                              let select_query = ClickHouseQueryBuilder::new().select(&columns) // Select required fields
                                                                                               .from(database, table_name) // Format the table name with database
                                                                                               .build(); // Build the individual query
 
@@ -184,10 +209,11 @@ impl ClickHouseClient
 
         // 假设你要创建的表使用MergeTree引擎并按timestamp排序 NOTE this ought to be replaced with ReplacingMergeTree Engine in due course.
         let final_query = format!(
-                                  "CREATE TABLE {}.{} ENGINE = ReplacingMergeTree() \
+                                  "CREATE TABLE {} ENGINE = ReplacingMergeTree() \
         PARTITION BY toYYYYMMDD(toDate(timestamp)) \
         ORDER BY  (timestamp,id) AS {}",
-                                  database, new_table_name, union_all_query
+                                  quote_table_path(database, new_table_name),
+                                  union_all_query
         );
 
         if report_progress {
@@ -209,7 +235,7 @@ impl ClickHouseClient
         let database_name = self.construct_database_name(exchange, instrument, "trades");
         // let table_name = self.construct_table_name(exchange, instrument, "trades", date, base, quote);
         let table_name = self.construct_table_name(exchange, instrument, "trades", date, base, quote);
-        let query = ClickHouseQueryBuilder::new().select("symbol, side, price, timestamp, amount")
+        let query = ClickHouseQueryBuilder::new().select(&self.config.columns.trade_select_list())
                                                  .from(&database_name, &table_name)
                                                  .order("timestamp", Some("DESC"))
                                                  .build();
@@ -224,7 +250,7 @@ impl ClickHouseClient
         let database_name = self.construct_database_name(exchange, instrument, "trades");
         let table_name = self.construct_table_name(exchange, instrument, "trades", date, base, quote);
         // let full_table_path = format!("{}.{}", database_name, table_name);
-        let query = ClickHouseQueryBuilder::new().select("symbol, side, price, timestamp, amount")
+        let query = ClickHouseQueryBuilder::new().select(&self.config.columns.trade_select_list())
                                                  .from(&database_name, &table_name)
                                                  .order("timestamp", Some("DESC"))
                                                  .limit(1)
@@ -238,7 +264,10 @@ impl ClickHouseClient
     {
         let table_name = self.construct_union_table_name(exchange, instrument, channel, date);
         let database = self.construct_database_name(exchange, instrument, "trades");
-        let query = format!("SELECT exchange, symbol, side, price, timestamp, amount FROM {}.{} ORDER BY timestamp", database, table_name);
+        let query = ClickHouseQueryBuilder::new().select(&self.config.columns.trade_select_list())
+                                                 .from(&database, &table_name)
+                                                 .order("timestamp", Some("ASC"))
+                                                 .build();
         info!("Executing query: {}", query);
         let trade_datas = self.client.read().await.query(&query).fetch_all::<MarketTrade>().await?;
         Ok(trade_datas)
@@ -251,7 +280,7 @@ impl ClickHouseClient
         let table_name = self.construct_table_name(exchange, instrument, "trades", date, base, quote);
 
         // 使用 ClickHouseQueryBuilder 构造查询语句
-        let query = ClickHouseQueryBuilder::new().select("exchange, symbol, side, price, timestamp, amount")
+        let query = ClickHouseQueryBuilder::new().select(&self.config.columns.trade_select_list())
                                                  .from(&database_name, &table_name)
                                                  .order("timestamp", Some("DESC"))
                                                  .build();
@@ -272,7 +301,7 @@ impl ClickHouseClient
         let table_name = self.construct_union_table_name(exchange, instrument, "trades", date);
 
         // 使用 ClickHouseQueryBuilder 构造查询语句
-        let query = ClickHouseQueryBuilder::new().select("exchange, symbol, side, price, timestamp, amount")
+        let query = ClickHouseQueryBuilder::new().select(&self.config.columns.trade_select_list())
                                                  .from(&database_name, &table_name)
                                                  .order("timestamp", Some("ASC"))
                                                  .build();
@@ -293,7 +322,7 @@ impl ClickHouseClient
         let table_name = self.construct_union_table_name(exchange, instrument, "trades", date);
 
         // 使用 ClickHouseQueryBuilder 构造查询语句
-        let query = ClickHouseQueryBuilder::new().select("exchange, symbol, side, price, timestamp, amount")
+        let query = ClickHouseQueryBuilder::new().select(&self.config.columns.trade_select_list())
                                                  .from(&database_name, &table_name)
                                                  .order("timestamp", Some("ASC"))
                                                  .limit(8)
@@ -321,7 +350,7 @@ impl ClickHouseClient
     // Method to optimize only "union" tables within a date range
     pub async fn optimize_union_tables_in_date_range(&self, exchange: &str, instrument: &str, channel: &str, mut start_date: NaiveDate, end_date: NaiveDate) -> Result<(), Error>
     {
-        let database = format!("{}_{}_{}", exchange, instrument, channel);
+        let database = self.construct_database_name(exchange, instrument, channel);
 
         let total_days = (end_date - start_date).num_days() + 1;
         let mut processed_days = 0;
@@ -336,7 +365,7 @@ impl ClickHouseClient
             // Iterate over table names and filter for "union" tables
             for table_name in &table_names {
                 if table_name.contains("union") {
-                    let table_path = format!("{}.{}", database, table_name);
+                    let table_path = quote_table_path(&database, table_name);
                     info!("Optimizing table: {}", table_path);
 
                     if let Err(e) = self.optimize_table(&table_path).await {
@@ -362,9 +391,10 @@ impl ClickHouseClient
         // 构建UNION ALL查询
         let queries = Arc::new(Mutex::new(Vec::new()));
         let total_tables = additional_table_names.len();
+        let columns = self.config.columns.select_list(&["symbol", "side", "price", "timestamp", "amount"]);
 
         additional_table_names.par_iter().enumerate().for_each(|(i, table_name)| {
-                                                         let select_query = ClickHouseQueryBuilder::new().select("symbol, side, price, timestamp, amount") // Select required fields
+                                                         let select_query = ClickHouseQueryBuilder::new().select(&columns) // Select required fields
                                                                                                          .from(database, table_name) // Format the table name with database
                                                                                                          .build(); // Build the individual query
 
@@ -381,8 +411,9 @@ impl ClickHouseClient
         let queries = Arc::try_unwrap(queries).expect("Failed to unwrap Arc").into_inner().unwrap();
         let union_all_query = queries.join(" UNION DISTINCT ");
 
-        let final_query = format!("INSERT INTO {}.{} SELECT DISTINCT symbol, side, price, timestamp, amount FROM ({})",
-                                  database, target_table_name, union_all_query);
+        let final_query = format!("INSERT INTO {} SELECT DISTINCT symbol, side, price, timestamp, amount FROM ({})",
+                                  quote_table_path(database, target_table_name),
+                                  union_all_query);
 
         info!("The Final Query is : {}", final_query);
         if report_progress {
@@ -402,7 +433,7 @@ impl ClickHouseClient
     pub async fn create_database_if_not_exists(&self, database: &str) -> Result<(), Error>
     {
         // 创建数据库的SQL查询
-        let create_db_query = format!("CREATE DATABASE IF NOT EXISTS {}", database);
+        let create_db_query = format!("CREATE DATABASE IF NOT EXISTS {}", quote_identifier(database));
 
         // 执行创建数据库的SQL查询
         self.client.read().await.query(&create_db_query).execute().await?;
@@ -418,7 +449,7 @@ impl ClickHouseClient
 
         // 创建用户表的SQL查询
         let create_table_query = format!(
-                                         "CREATE TABLE IF NOT EXISTS {}.user_info ( \
+                                         "CREATE TABLE IF NOT EXISTS {} ( \
         id UUID, \
         username String, \
         email String, \
@@ -428,7 +459,7 @@ impl ClickHouseClient
     )   ENGINE = ReplacingMergeTree() \
         PARTITION BY toYYYYMMDD(created_at) \
         ORDER BY (created_at, id)",
-                                         database
+                                         quote_table_path(database, "user_info")
         );
 
         // 执行创建表的SQL查询
@@ -458,4 +489,17 @@ mod tests
         let table_name = client.construct_table_name("binance", "futures", "trades", "2024_08_24", "BTC", "USDT");
         assert_eq!(table_name, "binance_futures_trades_2024_08_24_BTCUSDT");
     }
+
+    #[tokio::test]
+    async fn test_construct_names_from_config()
+    {
+        let config = ClickHouseConfig { database_prefix: "tardis_".to_string(),
+                                        table_template: Some("{channel}_{base}_{quote}_{date}".to_string()),
+                                        union_table_template: "{exchange}_{channel}_all_{date}".to_string(),
+                                        ..Default::default() };
+        let client = ClickHouseClient::from_config(config);
+        assert_eq!(client.construct_database_name("binance", "futures", "trades"), "tardis_binance_futures_trades");
+        assert_eq!(client.construct_table_name("binance", "futures", "trades", "2024-08-24", "btc", "usdt"), "trades_BTC_USDT_2024_08_24");
+        assert_eq!(client.construct_union_table_name("binance", "futures", "trades", "2024-08-24"), "binance_trades_all_2024_08_24");
+    }
 }
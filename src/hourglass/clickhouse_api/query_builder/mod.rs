@@ -1,13 +1,174 @@
+use clickhouse::{query::Query, Client};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use uuid::Uuid;
+
+/// 绑定到查询中的参数。
+///
+/// 查询语句中以 `?` 作为占位符，参数按出现顺序依次绑定。执行时通过 `clickhouse` 客户端的 `bind` 完成转义，
+/// 因此用户输入不会被拼接进 SQL 文本。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum QueryParam
+{
+    String(String),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl QueryParam
+{
+    /// 将参数渲染为转义后的 SQL 字面量，仅用于 [`ClickHouseQueryBuilder::build`] 的可读输出。
+    pub fn to_sql_literal(&self) -> String
+    {
+        match self {
+            | QueryParam::String(value) => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")),
+            | QueryParam::Int(value) => value.to_string(),
+            | QueryParam::UInt(value) => value.to_string(),
+            | QueryParam::Float(value) => value.to_string(),
+            | QueryParam::Bool(value) => value.to_string(),
+        }
+    }
+}
+
+impl From<&str> for QueryParam
+{
+    fn from(value: &str) -> Self
+    {
+        QueryParam::String(value.to_string())
+    }
+}
+
+impl From<String> for QueryParam
+{
+    fn from(value: String) -> Self
+    {
+        QueryParam::String(value)
+    }
+}
+
+impl From<&String> for QueryParam
+{
+    fn from(value: &String) -> Self
+    {
+        QueryParam::String(value.clone())
+    }
+}
+
+impl From<i64> for QueryParam
+{
+    fn from(value: i64) -> Self
+    {
+        QueryParam::Int(value)
+    }
+}
+
+impl From<u64> for QueryParam
+{
+    fn from(value: u64) -> Self
+    {
+        QueryParam::UInt(value)
+    }
+}
+
+impl From<f64> for QueryParam
+{
+    fn from(value: f64) -> Self
+    {
+        QueryParam::Float(value)
+    }
+}
+
+impl From<bool> for QueryParam
+{
+    fn from(value: bool) -> Self
+    {
+        QueryParam::Bool(value)
+    }
+}
+
+impl From<Uuid> for QueryParam
+{
+    fn from(value: Uuid) -> Self
+    {
+        QueryParam::String(value.to_string())
+    }
+}
+
+/// 为标识符（库名、表名、列名）加引号。
+///
+/// 只由字母、数字和下划线组成且不以数字开头的标识符原样返回，其余一律用反引号包裹并转义。
+pub fn quote_identifier(identifier: &str) -> String
+{
+    let mut chars = identifier.chars();
+    let is_plain = match chars.next() {
+        | Some(first) => (first.is_ascii_alphabetic() || first == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        | None => false,
+    };
+
+    if is_plain {
+        identifier.to_string()
+    }
+    else {
+        format!("`{}`", identifier.replace('\\', "\\\\").replace('`', "\\`"))
+    }
+}
+
+/// 为列名加引号，`alias.column` 形式的限定列名会逐段处理。
+pub fn quote_field(field: &str) -> String
+{
+    field.split('.').map(quote_identifier).collect::<Vec<_>>().join(".")
+}
+
+/// 为 `database.table` 形式的完整表路径加引号。
+pub fn quote_table_path(database_name: &str, table_name: &str) -> String
+{
+    format!("{}.{}", quote_identifier(database_name), quote_identifier(table_name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind
+{
+    Inner,
+    Left,
+    Right,
+    Full,
+    Asof,
+    LeftAsof,
+}
+
+impl Display for JoinKind
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let keyword = match self {
+            | JoinKind::Inner => "INNER JOIN",
+            | JoinKind::Left => "LEFT JOIN",
+            | JoinKind::Right => "RIGHT JOIN",
+            | JoinKind::Full => "FULL OUTER JOIN",
+            | JoinKind::Asof => "ASOF JOIN",
+            | JoinKind::LeftAsof => "ASOF LEFT JOIN",
+        };
+        write!(f, "{}", keyword)
+    }
+}
+
 #[allow(dead_code)]
 pub struct ClickHouseQueryBuilder
 {
+    insert_clause: Option<String>,
     select_clause: String,
     from_clause: String,
+    join_clauses: Vec<String>,
     where_clause: Option<String>,
+    group_by_clause: Option<String>,
     order_by_clause: Option<String>,
     order_direction: Option<String>, // 存储排序方向（ASC 或 DESC）
     limit_clause: Option<String>,
     offset_clause: Option<String>, // Add this line
+    params: Vec<QueryParam>,       // 按占位符顺序存放的绑定参数
 }
 
 impl Default for ClickHouseQueryBuilder
@@ -24,13 +185,34 @@ impl ClickHouseQueryBuilder
     // 初始化构造器
     pub fn new() -> Self
     {
-        Self { select_clause: String::new(),
+        Self { insert_clause: None,
+               select_clause: String::new(),
                from_clause: String::new(),
+               join_clauses: Vec::new(),
                where_clause: None,
+               group_by_clause: None,
                order_by_clause: None,
                order_direction: None,
                limit_clause: None,
-               offset_clause: None }
+               offset_clause: None,
+               params: Vec::new() }
+    }
+
+    // 设置INSERT INTO子句，配合 `values` 使用
+    pub fn insert_into(mut self, database_name: &str, table_name: &str, columns: &[&str]) -> Self
+    {
+        let columns = columns.iter().map(|column| quote_identifier(column)).collect::<Vec<_>>().join(", ");
+        self.insert_clause = Some(format!("INSERT INTO {} ({})", quote_table_path(database_name, table_name), columns));
+        self
+    }
+
+    // 设置INSERT的VALUES，所有值均以参数形式绑定
+    pub fn values(mut self, values: Vec<QueryParam>) -> Self
+    {
+        let placeholders = vec!["?"; values.len()].join(", ");
+        self.select_clause = format!("VALUES ({})", placeholders);
+        self.params.extend(values);
+        self
     }
 
     // 设置SELECT子句
@@ -43,30 +225,93 @@ impl ClickHouseQueryBuilder
     // 设置FROM子句
     pub fn from(mut self, database_name: &str, table_name: &str) -> Self
     {
-        self.from_clause = format!("FROM {}.{}", database_name, table_name);
+        self.from_clause = format!("FROM {}", quote_table_path(database_name, table_name));
         self
     }
 
-    // 添加WHERE条件
-    pub fn where_clause(mut self, condition: &str) -> Self
+    // 设置带别名的FROM子句，便于在JOIN中引用
+    pub fn from_as(mut self, database_name: &str, table_name: &str, alias: &str) -> Self
     {
-        self.where_clause = Some(format!("WHERE {}", condition));
+        self.from_clause = format!("FROM {} AS {}", quote_table_path(database_name, table_name), quote_identifier(alias));
         self
     }
 
-    // 添加LIKE条件
-    pub fn like_clause(mut self, field: &str, pattern: &str) -> Self
+    // 添加JOIN子句，`on` 为连接条件，可包含 `?` 占位符
+    pub fn join(mut self, kind: JoinKind, database_name: &str, table_name: &str, alias: &str, on: &str) -> Self
+    {
+        self.join_clauses
+            .push(format!("{} {} AS {} ON {}", kind, quote_table_path(database_name, table_name), quote_identifier(alias), on));
+        self
+    }
+
+    // 添加WHERE条件，已有条件时以AND追加，保证与已绑定参数的顺序一致
+    pub fn where_clause(self, condition: &str) -> Self
+    {
+        self.and_where(condition)
+    }
+
+    // 以AND追加WHERE条件，条件中的 `?` 需配合 `bind` 使用
+    pub fn and_where(mut self, condition: &str) -> Self
+    {
+        self.where_clause = Some(self.where_clause
+                                     .map_or_else(|| format!("WHERE {}", condition), |existing_clause| format!("{} AND {}", existing_clause, condition)));
+        self
+    }
+
+    // 按顺序绑定一个参数
+    pub fn bind(mut self, value: impl Into<QueryParam>) -> Self
+    {
+        self.params.push(value.into());
+        self
+    }
+
+    // 添加 `field = ?` 条件
+    pub fn where_eq(self, field: &str, value: impl Into<QueryParam>) -> Self
+    {
+        let condition = format!("{} = ?", quote_field(field));
+        self.and_where(&condition).bind(value)
+    }
+
+    // 添加左闭右开的时间戳区间条件 `start <= field < end`
+    pub fn where_timestamp_range(self, field: &str, start: i64, end: i64) -> Self
+    {
+        let field = quote_field(field);
+        let condition = format!("{} >= ? AND {} < ?", field, field);
+        self.and_where(&condition).bind(start).bind(end)
+    }
+
+    // 添加 `field IN (?, ?, ...)` 条件，空列表会生成恒假条件
+    pub fn where_in<T: Into<QueryParam>>(mut self, field: &str, values: impl IntoIterator<Item = T>) -> Self
     {
-        self.where_clause = Some(self.where_clause.map_or_else(|| format!("WHERE {} LIKE '{}'", field, pattern),
-                                                               |existing_clause| format!("{} AND {} LIKE '{}'", existing_clause, field, pattern)));
+        let values = values.into_iter().map(Into::into).collect::<Vec<QueryParam>>();
+        if values.is_empty() {
+            return self.and_where("1 = 0");
+        }
+
+        let condition = format!("{} IN ({})", quote_field(field), vec!["?"; values.len()].join(", "));
+        self = self.and_where(&condition);
+        self.params.extend(values);
         self
     }
 
+    // 添加LIKE条件
+    pub fn like_clause(self, field: &str, pattern: &str) -> Self
+    {
+        let condition = format!("{} LIKE ?", quote_field(field));
+        self.and_where(&condition).bind(pattern)
+    }
+
     // 添加NOT LIKE条件
-    pub fn not_like_clause(mut self, field: &str, pattern: &str) -> Self
+    pub fn not_like_clause(self, field: &str, pattern: &str) -> Self
+    {
+        let condition = format!("{} NOT LIKE ?", quote_field(field));
+        self.and_where(&condition).bind(pattern)
+    }
+
+    // 添加GROUP BY子句
+    pub fn group_by(mut self, fields: &str) -> Self
     {
-        self.where_clause = Some(self.where_clause.map_or_else(|| format!("WHERE {} NOT LIKE '{}'", field, pattern),
-                                                               |existing_clause| format!("{} AND {} NOT LIKE '{}'", existing_clause, field, pattern)));
+        self.group_by_clause = Some(format!("GROUP BY {}", fields));
         self
     }
 
@@ -91,15 +336,26 @@ impl ClickHouseQueryBuilder
         self
     }
 
-    // 构建最终的查询
-    pub fn build(self) -> String
+    // 构建带 `?` 占位符的查询模板及其参数
+    pub fn build_with_params(self) -> (String, Vec<QueryParam>)
     {
-        let mut query = format!("{} {}", self.select_clause, self.from_clause);
+        let mut query = match &self.insert_clause {
+            | Some(insert_clause) => format!("{} {}", insert_clause, self.select_clause),
+            | None => format!("{} {}", self.select_clause, self.from_clause),
+        };
+
+        for join_clause in &self.join_clauses {
+            query.push_str(&format!(" {}", join_clause));
+        }
 
         if let Some(where_clause) = self.where_clause {
             query.push_str(&format!(" {}", where_clause));
         }
 
+        if let Some(group_by_clause) = self.group_by_clause {
+            query.push_str(&format!(" {}", group_by_clause));
+        }
+
         if let Some(order_by_clause) = self.order_by_clause {
             query.push_str(&format!(" {}", order_by_clause));
         }
@@ -108,8 +364,105 @@ impl ClickHouseQueryBuilder
             query.push_str(&format!(" {}", limit_clause));
         }
 
+        if let Some(offset_clause) = self.offset_clause {
+            query.push_str(&format!(" {}", offset_clause));
+        }
+
+        (query, self.params)
+    }
+
+    // 构建最终的查询，参数以转义后的字面量内联，适合日志输出
+    pub fn build(self) -> String
+    {
+        let (template, params) = self.build_with_params();
+        let mut params = params.iter();
+        let mut query = String::with_capacity(template.len());
+
+        for token in scan_template(&template) {
+            match token {
+                | TemplateToken::Text(text) | TemplateToken::Quoted(text) => query.push_str(text),
+                | TemplateToken::Placeholder => match params.next() {
+                    | Some(param) => query.push_str(&param.to_sql_literal()),
+                    | None => query.push('?'),
+                },
+            }
+        }
+
         query
     }
+
+    // 构建可执行的 `Query`，参数通过客户端绑定
+    pub fn query(self, client: &Client) -> Query
+    {
+        let (template, params) = self.build_with_params();
+        params.into_iter().fold(client.query(&escape_quoted_question_marks(&template)), |query, param| query.bind(param))
+    }
+}
+
+/// 查询模板按占位符切分后的片段。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TemplateToken<'a>
+{
+    Text(&'a str),
+    Quoted(&'a str), // 字符串字面量或带引号的标识符，包括两侧的引号
+    Placeholder,
+}
+
+/// 找出模板中的 `?` 占位符，跳过引号内的 `?` 与 `clickhouse` 客户端保留的 `?fields`。
+fn scan_template(template: &str) -> Vec<TemplateToken<'_>>
+{
+    let bytes = template.as_bytes();
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            | quote @ (b'\'' | b'"' | b'`') => {
+                if start < index {
+                    tokens.push(TemplateToken::Text(&template[start..index]));
+                }
+                let mut end = index + 1;
+                while end < bytes.len() && bytes[end] != quote {
+                    // 反斜杠转义的字符不会结束字面量
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                let end = (end + 1).min(bytes.len());
+                tokens.push(TemplateToken::Quoted(&template[index..end]));
+                start = end;
+                index = end;
+            }
+            | b'?' if !template[index + 1..].starts_with("fields") => {
+                if start < index {
+                    tokens.push(TemplateToken::Text(&template[start..index]));
+                }
+                tokens.push(TemplateToken::Placeholder);
+                start = index + 1;
+                index += 1;
+            }
+            | _ => index += 1,
+        }
+    }
+
+    if start < bytes.len() {
+        tokens.push(TemplateToken::Text(&template[start..]));
+    }
+    tokens
+}
+
+/// `clickhouse` 客户端会把模板中所有的 `?` 视为占位符，引号内的 `?` 改写为等价的 `\x3F` 转义。
+///
+/// 反引号内是标识符而非字面量，原样保留。
+fn escape_quoted_question_marks(template: &str) -> String
+{
+    scan_template(template).into_iter()
+                           .map(|token| match token {
+                               | TemplateToken::Text(text) => text.to_string(),
+                               | TemplateToken::Quoted(text) if text.starts_with('`') => text.to_string(),
+                               | TemplateToken::Quoted(text) => text.replace('?', "\\x3F"),
+                               | TemplateToken::Placeholder => "?".to_string(),
+                           })
+                           .collect()
 }
 
 #[cfg(test)]
@@ -163,4 +516,109 @@ mod tests
                                                  .build();
         assert_eq!(query, "SELECT * FROM default_db.users WHERE identification = 1 AND username LIKE '%user%' AND password NOT LIKE '%weak%'");
     }
+
+    #[test]
+    fn test_bound_params_stay_out_of_template()
+    {
+        let (template, params) = ClickHouseQueryBuilder::new().select("password_hash")
+                                                              .from("accounts", "user_info")
+                                                              .where_eq("username", "alice' OR '1'='1")
+                                                              .build_with_params();
+        assert_eq!(template, "SELECT password_hash FROM accounts.user_info WHERE username = ?");
+        assert_eq!(params, vec![QueryParam::String("alice' OR '1'='1".to_string())]);
+    }
+
+    #[test]
+    fn test_build_escapes_inlined_literals()
+    {
+        let query = ClickHouseQueryBuilder::new().select("*").from("accounts", "user_info").where_eq("username", "o'brien\\").build();
+        assert_eq!(query, "SELECT * FROM accounts.user_info WHERE username = 'o\\'brien\\\\'");
+    }
+
+    #[test]
+    fn test_identifier_quoting()
+    {
+        assert_eq!(quote_identifier("binance_futures_trades"), "binance_futures_trades");
+        assert_eq!(quote_identifier("2024_table"), "`2024_table`");
+        assert_eq!(quote_identifier("users; DROP TABLE x"), "`users; DROP TABLE x`");
+        assert_eq!(quote_identifier("a`b"), "`a\\`b`");
+        assert_eq!(quote_field("t.price"), "t.price");
+    }
+
+    #[test]
+    fn test_timestamp_range_and_in_list()
+    {
+        let query = ClickHouseQueryBuilder::new().select("symbol, count() AS trades")
+                                                 .from("binance_futures_trades", "binance_futures_trades_union_2024_05_05")
+                                                 .where_timestamp_range("timestamp", 1714867200000, 1714953600000)
+                                                 .where_in("symbol", ["BTCUSDT", "ETHUSDT"])
+                                                 .group_by("symbol")
+                                                 .build();
+        assert_eq!(query,
+                   "SELECT symbol, count() AS trades FROM binance_futures_trades.binance_futures_trades_union_2024_05_05 WHERE timestamp >= 1714867200000 AND timestamp < \
+                    1714953600000 AND symbol IN ('BTCUSDT', 'ETHUSDT') GROUP BY symbol");
+    }
+
+    #[test]
+    fn test_empty_in_list_matches_nothing()
+    {
+        let query = ClickHouseQueryBuilder::new().select("*").from("db", "t").where_in::<&str>("symbol", []).build();
+        assert_eq!(query, "SELECT * FROM db.t WHERE 1 = 0");
+    }
+
+    #[test]
+    fn test_join_query()
+    {
+        let query = ClickHouseQueryBuilder::new().select("t.price, s.bids")
+                                                 .from_as("trades_db", "trades", "t")
+                                                 .join(JoinKind::Asof, "books_db", "snapshots", "s", "t.symbol = s.symbol AND t.timestamp >= s.timestamp")
+                                                 .where_eq("t.symbol", "BTCUSDT")
+                                                 .build();
+        assert_eq!(query,
+                   "SELECT t.price, s.bids FROM trades_db.trades AS t ASOF JOIN books_db.snapshots AS s ON t.symbol = s.symbol AND t.timestamp >= s.timestamp WHERE t.symbol = \
+                    'BTCUSDT'");
+    }
+
+    #[test]
+    fn test_question_marks_inside_literals_are_not_placeholders()
+    {
+        let query = ClickHouseQueryBuilder::new().select("*")
+                                                 .from("db", "t")
+                                                 .where_clause("note != 'why?' AND `odd?col` = 1")
+                                                 .where_eq("username", "bob?")
+                                                 .where_clause("email LIKE ?")
+                                                 .bind("%@mail.com")
+                                                 .build();
+        assert_eq!(query, "SELECT * FROM db.t WHERE note != 'why?' AND `odd?col` = 1 AND username = 'bob?' AND email LIKE '%@mail.com'");
+
+        let template = "SELECT ?fields FROM t WHERE a = 'it\\'s?' AND b = ?";
+        assert_eq!(escape_quoted_question_marks(template), "SELECT ?fields FROM t WHERE a = 'it\\'s\\x3F' AND b = ?");
+
+        let template = "SELECT `odd?col` FROM t WHERE a = 'why?' AND b = ?";
+        assert_eq!(escape_quoted_question_marks(template), "SELECT `odd?col` FROM t WHERE a = 'why\\x3F' AND b = ?");
+    }
+
+    #[test]
+    fn test_where_clause_keeps_bound_conditions()
+    {
+        let (template, params) = ClickHouseQueryBuilder::new().select("*")
+                                                              .from("db", "t")
+                                                              .where_eq("symbol", "BTCUSDT")
+                                                              .where_clause("price > ?")
+                                                              .bind(100.0)
+                                                              .where_eq("side", "buy")
+                                                              .build_with_params();
+        assert_eq!(template, "SELECT * FROM db.t WHERE symbol = ? AND price > ? AND side = ?");
+        assert_eq!(params, vec!["BTCUSDT".into(), QueryParam::Float(100.0), "buy".into()]);
+    }
+
+    #[test]
+    fn test_insert_query()
+    {
+        let (template, params) = ClickHouseQueryBuilder::new().insert_into("accounts", "user_info", &["id", "username"])
+                                                              .values(vec!["42".into(), "bob".into()])
+                                                              .build_with_params();
+        assert_eq!(template, "INSERT INTO accounts.user_info (id, username) VALUES (?, ?)");
+        assert_eq!(params.len(), 2);
+    }
 }
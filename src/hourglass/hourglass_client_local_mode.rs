@@ -2,6 +2,7 @@ use crate::hourglass_log::{info, warn};
 use async_trait::async_trait;
 use mpsc::UnboundedSender;
 use oneshot::Sender;
+use std::net::IpAddr;
 use tokio::sync::{mpsc, mpsc::UnboundedReceiver, oneshot};
 use HourglassClientEvent::{CancelOrders, CancelOrdersAll, FetchOrdersOpen, FetchTokenBalances, OpenOrders};
 
@@ -16,8 +17,12 @@ use crate::{
         },
         token::Token,
     },
+    dashboard::summary::attribution::AttributionSummary,
     hourglass::{clickhouse_api::datatype::clickhouse_trade_data::MarketTrade, config_request::ConfigurationRequest},
-    network::login::{LoginRequest, LogoutRequest, RegisterRequest},
+    network::{
+        api_key::ApiCaller,
+        login::{LoginRequest, LoginResponse, LogoutRequest, RegisterRequest},
+    },
     AccountEvent, ClientExecution, Exchange, ExchangeError, RequestOpen,
 };
 
@@ -27,6 +32,7 @@ pub struct HourglassClient
     pub client_event_tx: UnboundedSender<HourglassClientEvent>,
     pub market_event_rx: UnboundedReceiver<MarketTrade>,
     // pub account_event_rx: UnboundedReceiver<AccountEvent>,
+    pub session_token: Option<String>, // 登录后由 `login` 设置，之后的请求都会携带
 }
 
 // NOTE 模拟交易所客户端可向模拟交易所发送的命令
@@ -50,6 +56,7 @@ pub enum HourglassClientEvent
     FetchLongPosition(Instrument, Sender<Result<Option<Position>, ExchangeError>>),
     FetchShortPosition(Instrument, Sender<Result<Option<Position>, ExchangeError>>),
     FetchAllPositions(Sender<Result<AccountPositions, ExchangeError>>),
+    FetchPnLAttribution(Sender<Result<AttributionSummary, ExchangeError>>),
     OpenOrders(RequestOpenOrders),
     CancelOrders(RequestCancelOrders),
     CancelOrdersAll(Sender<Result<Vec<Order<Cancelled>>, ExchangeError>>),
@@ -58,15 +65,117 @@ pub enum HourglassClientEvent
     Register(RegisterRequest),
     Login(LoginRequest),
     Logout(LogoutRequest),
+    Authenticated(String, Box<HourglassClientEvent>), // 携带 session 令牌的请求
+    Signed(ApiCaller, Box<HourglassClientEvent>),     // 已通过 API key 签名校验的请求
+    Remote(IpAddr, Box<HourglassClientEvent>),        // 经 REST 接口转发的请求，携带对端地址，位于最外层
+}
+
+impl HourglassClientEvent
+{
+    /// 需要登录后才能发起的请求，即订单、余额、仓位与配置相关的请求。
+    pub fn requires_session(&self) -> bool
+    {
+        !matches!(self,
+                  HourglassClientEvent::LetItRoll | HourglassClientEvent::Register(_) | HourglassClientEvent::Login(_) | HourglassClientEvent::Logout(_))
+    }
+
+    /// 有 session 令牌时，把需要登录的请求包装为 [`HourglassClientEvent::Authenticated`]。
+    pub fn with_session(self, session_token: Option<&String>) -> Self
+    {
+        match session_token {
+            | Some(session_token) if self.requires_session() => HourglassClientEvent::Authenticated(session_token.clone(), Box::new(self)),
+            | _ => self,
+        }
+    }
+
+    /// 通过 API key 签名校验后，把需要登录的请求包装为 [`HourglassClientEvent::Signed`]。
+    pub fn signed_by(self, caller: &ApiCaller) -> Self
+    {
+        if self.requires_session() {
+            HourglassClientEvent::Signed(caller.clone(), Box::new(self))
+        }
+        else {
+            self
+        }
+    }
+
+    /// 记录请求的对端地址，未登录的请求按地址限流。
+    pub fn from_peer(self, peer: Option<IpAddr>) -> Self
+    {
+        match peer {
+            | Some(peer) => HourglassClientEvent::Remote(peer, Box::new(self)),
+            | None => self,
+        }
+    }
+
+    /// 拆开最外层的 [`HourglassClientEvent::Remote`]。
+    pub fn split_peer(self) -> (Option<IpAddr>, Self)
+    {
+        match self {
+            | HourglassClientEvent::Remote(peer, event) => (Some(peer), *event),
+            | event => (None, event),
+        }
+    }
+
+    /// 以同一个错误响应该请求，批量请求中的每一项都返回该错误。
+    pub fn reject(self, error: ExchangeError)
+    {
+        match self {
+            | HourglassClientEvent::DepositTokens((_, response_tx)) => {
+                let _ = response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::FetchOrdersOpen(response_tx) => {
+                let _ = response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::FetchTokenBalances(response_tx) => {
+                let _ = response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::FetchTokenBalance(_, response_tx) => {
+                let _ = response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::FetchLongPosition(_, response_tx) | HourglassClientEvent::FetchShortPosition(_, response_tx) => {
+                let _ = response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::FetchAllPositions(response_tx) => {
+                let _ = response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::FetchPnLAttribution(response_tx) => {
+                let _ = response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::OpenOrders((requests, response_tx)) => {
+                let _ = response_tx.send(requests.iter().map(|_| Err(error.clone())).collect());
+            }
+            | HourglassClientEvent::CancelOrders((requests, response_tx)) => {
+                let _ = response_tx.send(requests.iter().map(|_| Err(error.clone())).collect());
+            }
+            | HourglassClientEvent::CancelOrdersAll(response_tx) => {
+                let _ = response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::ConfigureInstruments(requests, response_tx) => {
+                let _ = response_tx.send(requests.iter().map(|_| Err(error.clone())).collect());
+            }
+            | HourglassClientEvent::LetItRoll => warn!("LetItRoll rejected: {:?}", error),
+            | HourglassClientEvent::Register(request) => {
+                let _ = request.response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::Login(request) => {
+                let _ = request.response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::Logout(request) => {
+                let _ = request.response_tx.send(Err(error));
+            }
+            | HourglassClientEvent::Authenticated(_, event) | HourglassClientEvent::Signed(_, event) | HourglassClientEvent::Remote(_, event) => event.reject(error),
+        }
+    }
 }
 
 #[async_trait]
 impl ClientExecution for HourglassClient
 {
-    const CLIENT_KIND: Exchange = Exchange::Hourglass;
-
     type Config = (UnboundedSender<HourglassClientEvent>, UnboundedReceiver<MarketTrade>);
 
+    const CLIENT_KIND: Exchange = Exchange::Hourglass;
+
     async fn init(config: Self::Config, _: UnboundedSender<AccountEvent>) -> Self
     {
         // 从 config 元组中解构出 request_tx 和 market_event_rx
@@ -74,7 +183,8 @@ impl ClientExecution for HourglassClient
 
         // 使用 request_tx 和 market_event_rx 初始化 HourglassClient
         Self { client_event_tx: request_tx,
-               market_event_rx }
+               market_event_rx,
+               session_token: None }
     }
 
     async fn fetch_orders_open(&self) -> Result<Vec<Order<Open>>, ExchangeError>
@@ -82,7 +192,7 @@ impl ClientExecution for HourglassClient
         let (response_tx, response_rx) = oneshot::channel();
         // 向模拟交易所发送获取开放订单的请求。
         self.client_event_tx
-            .send(FetchOrdersOpen(response_tx))
+            .send(self.tag(FetchOrdersOpen(response_tx)))
             .expect("Hourglass exchange is currently offline - Failed to send FetchOrdersOpen request");
         // 从模拟交易所接收开放订单的响应。
         response_rx.await.expect("Hourglass exchange is currently offline - Failed to receive FetchOrdersOpen response")
@@ -93,7 +203,7 @@ impl ClientExecution for HourglassClient
         let (response_tx, response_rx) = oneshot::channel();
         // 向模拟交易所发送获取账户余额的请求。
         self.client_event_tx
-            .send(FetchTokenBalances(response_tx))
+            .send(self.tag(FetchTokenBalances(response_tx)))
             .expect("Hourglass exchange is currently offline - Failed to send FetchBalances request");
         // 从模拟交易所接收账户余额的响应。
         response_rx.await.expect("Hourglass exchange is currently offline - Failed to receive FetchBalances response")
@@ -104,17 +214,26 @@ impl ClientExecution for HourglassClient
     {
         let (response_tx, response_rx) = oneshot::channel();
         self.client_event_tx
-            .send(HourglassClientEvent::FetchAllPositions(response_tx))
+            .send(self.tag(HourglassClientEvent::FetchAllPositions(response_tx)))
             .expect("[HourglassClient] : Failed to send FetchAllPositions request");
         response_rx.await.expect("[HourglassClient] : Failed to receive FetchAllPositions response")
     }
 
+    async fn fetch_pnl_attribution(&self) -> Result<AttributionSummary, ExchangeError>
+    {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.client_event_tx
+            .send(self.tag(HourglassClientEvent::FetchPnLAttribution(response_tx)))
+            .expect("[HourglassClient] : Failed to send FetchPnLAttribution request");
+        response_rx.await.expect("[HourglassClient] : Failed to receive FetchPnLAttribution response")
+    }
+
     //  FetchLongPosition 的实现
     async fn fetch_long_position(&self, instrument: Instrument) -> Result<Option<Position>, ExchangeError>
     {
         let (response_tx, response_rx) = oneshot::channel();
         self.client_event_tx
-            .send(HourglassClientEvent::FetchLongPosition(instrument, response_tx))
+            .send(self.tag(HourglassClientEvent::FetchLongPosition(instrument, response_tx)))
             .expect("[HourglassClient] : Failed to send FetchLongPosition request");
         response_rx.await.expect("[HourglassClient] : Failed to receive FetchLongPosition response")
     }
@@ -124,7 +243,7 @@ impl ClientExecution for HourglassClient
     {
         let (response_tx, response_rx) = oneshot::channel();
         self.client_event_tx
-            .send(HourglassClientEvent::FetchShortPosition(instrument, response_tx))
+            .send(self.tag(HourglassClientEvent::FetchShortPosition(instrument, response_tx)))
             .expect("[HourglassClient] : Failed to send FetchShortPosition request");
         response_rx.await.expect("[HourglassClient] : Failed to receive FetchShortPosition response")
     }
@@ -134,7 +253,7 @@ impl ClientExecution for HourglassClient
         let (response_tx, response_rx) = oneshot::channel();
         // 向模拟交易所发送开启订单的请求。
         self.client_event_tx
-            .send(OpenOrders((open_requests, response_tx)))
+            .send(self.tag(OpenOrders((open_requests, response_tx))))
             .expect("Hourglass exchange is currently offline - Failed to send OpenOrders request");
         // 从模拟交易所接收开启订单的响应。
         response_rx.await.expect("Hourglass exchange is currently offline - Failed to receive OpenOrders response")
@@ -145,7 +264,7 @@ impl ClientExecution for HourglassClient
         let (response_tx, response_rx) = oneshot::channel();
         // 向模拟交易所发送取消订单的请求。
         self.client_event_tx
-            .send(CancelOrders((cancel_requests, response_tx)))
+            .send(self.tag(CancelOrders((cancel_requests, response_tx))))
             .expect("Hourglass exchange is currently offline - Failed to send CancelOrders request");
         // 从模拟交易所接收取消订单的响应。
         response_rx.await.expect("Hourglass exchange is currently offline - Failed to receive CancelOrders response")
@@ -157,7 +276,7 @@ impl ClientExecution for HourglassClient
         let (response_tx, response_rx) = oneshot::channel();
         // 向模拟交易所发送取消所有订单的请求。
         self.client_event_tx
-            .send(CancelOrdersAll(response_tx))
+            .send(self.tag(CancelOrdersAll(response_tx)))
             .expect("Hourglass exchange is currently offline - Failed to send CancelOrdersAll request");
         // 从模拟交易所接收取消所有订单的响应。
         response_rx.await.expect("Hourglass exchange is currently offline - Failed to receive CancelOrdersAll response")
@@ -169,7 +288,7 @@ impl ClientExecution for HourglassClient
         info!("begin to deposit tokens: {:?}", deposits);
         let (response_tx, response_rx) = oneshot::channel();
         self.client_event_tx
-            .send(HourglassClientEvent::DepositTokens((deposits, response_tx)))
+            .send(self.tag(HourglassClientEvent::DepositTokens((deposits, response_tx))))
             .expect("Failed to send DepositTokens request");
         response_rx.await.expect("Failed to receive DepositTokens response")
     }
@@ -200,6 +319,44 @@ impl HourglassClient
         }
         None // Return None if there are no events
     }
+
+    /// 注册新用户。
+    pub async fn register(&self, username: String, email: String, password: String) -> Result<(), ExchangeError>
+    {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.client_event_tx
+            .send(HourglassClientEvent::Register(RegisterRequest { username, email, password, response_tx }))
+            .map_err(|_| ExchangeError::ReponseSenderError)?;
+        response_rx.await.map_err(|_| ExchangeError::ReponseSenderError)?
+    }
+
+    /// 登录成功后保存 session 令牌，之后的请求都会携带该令牌。
+    pub async fn login(&mut self, username: String, password: String) -> Result<LoginResponse, ExchangeError>
+    {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.client_event_tx
+            .send(HourglassClientEvent::Login(LoginRequest { username, password, response_tx }))
+            .map_err(|_| ExchangeError::ReponseSenderError)?;
+        let response = response_rx.await.map_err(|_| ExchangeError::ReponseSenderError)??;
+        self.session_token = Some(response.session_token.clone());
+        Ok(response)
+    }
+
+    /// 注销当前会话。
+    pub async fn logout(&mut self) -> Result<(), ExchangeError>
+    {
+        let session_token = self.session_token.take().ok_or(ExchangeError::InvalidSession)?;
+        let (response_tx, response_rx) = oneshot::channel();
+        self.client_event_tx
+            .send(HourglassClientEvent::Logout(LogoutRequest { session_token, response_tx }))
+            .map_err(|_| ExchangeError::ReponseSenderError)?;
+        response_rx.await.map_err(|_| ExchangeError::ReponseSenderError)?
+    }
+
+    fn tag(&self, event: HourglassClientEvent) -> HourglassClientEvent
+    {
+        event.with_session(self.session_token.as_ref())
+    }
 }
 
 #[cfg(test)]
@@ -216,7 +373,8 @@ mod tests
         let (_market_tx, market_rx) = mpsc::unbounded_channel();
 
         let client = HourglassClient { client_event_tx: request_tx.clone(),
-                                       market_event_rx: market_rx };
+                                       market_event_rx: market_rx,
+                                       session_token: None };
 
         // 启动一个异步任务来调用客户端的 fetch_orders_open 方法
         let client_task = tokio::spawn(async move {
@@ -256,7 +414,8 @@ mod tests
 
         // 初始化 HourglassClient
         let client = HourglassClient { client_event_tx: request_tx.clone(),
-                                       market_event_rx: market_rx };
+                                       market_event_rx: market_rx,
+                                       session_token: None };
 
         // 启动一个异步任务来调用客户端的 cancel_orders_all 方法
         let client_task = tokio::spawn(async move {
@@ -289,4 +448,36 @@ mod tests
         // 确保客户端任务完成
         client_task.await.expect("Client task should complete successfully");
     }
+
+    #[tokio::test]
+    async fn logged_in_client_should_tag_requests_with_session()
+    {
+        let (request_tx, mut request_rx) = mpsc::unbounded_channel();
+        let (_market_tx, market_rx) = mpsc::unbounded_channel();
+        let mut client = HourglassClient { client_event_tx: request_tx,
+                                           market_event_rx: market_rx,
+                                           session_token: None };
+
+        let exchange = tokio::spawn(async move {
+            match request_rx.recv().await {
+                | Some(HourglassClientEvent::Login(request)) => {
+                    let _ = request.response_tx.send(Ok(LoginResponse { session_token: "token".to_string(),
+                                                                        expires_at: 0 }));
+                }
+                | other => panic!("Unexpected event: {:?}", other),
+            }
+            // 登录后的请求携带令牌，会话无效时以错误响应
+            match request_rx.recv().await {
+                | Some(HourglassClientEvent::Authenticated(session_token, event)) => {
+                    assert_eq!(session_token, "token");
+                    event.reject(ExchangeError::SessionExpired);
+                }
+                | other => panic!("Unexpected event: {:?}", other),
+            }
+        });
+
+        client.login("alice".to_string(), "secret".to_string()).await.unwrap();
+        assert_eq!(client.fetch_balances().await, Err(ExchangeError::SessionExpired));
+        exchange.await.unwrap();
+    }
 }
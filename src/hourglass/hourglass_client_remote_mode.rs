@@ -0,0 +1,536 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicI64, Ordering},
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::Bytes,
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Method, Request, StatusCode,
+};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    net::TcpStream,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+    time::{self, Duration},
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{
+    common::{
+        account_positions::{AccountPositions, Position},
+        balance::TokenBalance,
+        event::{AccountEvent, AccountEventKind},
+        instrument::Instrument,
+        order::{
+            states::{cancelled::Cancelled, open::Open, request_cancel::RequestCancel},
+            Order,
+        },
+        token::Token,
+    },
+    dashboard::summary::attribution::AttributionSummary,
+    hourglass::clickhouse_api::datatype::clickhouse_trade_data::MarketTrade,
+    hourglass_log::{info, warn},
+    network::{
+        api_key::{self, API_KEY_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+        login::{LoginForm, LoginResponse, RegisterForm},
+        rest_api::ApiError,
+        ws_stream::{StreamChannel, StreamMessage, StreamPayload, StreamRequest},
+    },
+    ClientExecution, Exchange, ExchangeError, RequestOpen,
+};
+
+/// 等待 WebSocket 订阅确认的最长时间
+const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 远程客户端的配置。
+#[derive(Debug, Clone)]
+pub struct RemoteClientConfig
+{
+    /// 在线模式服务器的地址，例如 `http://127.0.0.1:8888`
+    pub base_url: String,
+    /// 需要订阅市场成交的交易对，例如 `ETHUSDT`
+    pub market_symbols: Vec<String>,
+}
+
+/// 通过 REST 与 WebSocket 连接 `run_online` 服务器的客户端，接口与 [`HourglassClient`](super::hourglass_client_local_mode::HourglassClient) 一致。
+///
+/// 账户事件推送到 `init` 传入的 `event_tx`，市场成交推送到 `market_event_rx`。
+/// 服务器只向登录后的连接推送账户事件，未登录时只能收到市场成交。
+#[derive(Debug)]
+pub struct HourglassRemoteClient
+{
+    pub base_url: String,
+    pub market_event_rx: UnboundedReceiver<MarketTrade>,
+    pub session_token: Option<String>, // 登录后由 `login` 设置，之后的请求都会携带
+    api_key: Option<(String, String)>, // API key 与签名密钥，设置后每个请求都会签名
+    last_signed: AtomicI64,            // 最近一次签名的时间戳
+    http: Client<HttpConnector, Full<Bytes>>,
+    market_symbols: Vec<String>,
+    account_tx: UnboundedSender<AccountEvent>,
+    market_tx: UnboundedSender<MarketTrade>,
+    stream_task: Option<JoinHandle<()>>, // 当前推送连接的转发任务，重新连接时终止
+}
+
+#[async_trait]
+impl ClientExecution for HourglassRemoteClient
+{
+    type Config = RemoteClientConfig;
+
+    const CLIENT_KIND: Exchange = Exchange::Hourglass;
+
+    async fn init(config: Self::Config, event_tx: UnboundedSender<AccountEvent>) -> Self
+    {
+        let (market_tx, market_event_rx) = mpsc::unbounded_channel();
+        let mut client = Self { base_url: config.base_url.trim_end_matches('/').to_string(),
+                                market_event_rx,
+                                session_token: None,
+                                api_key: None,
+                                last_signed: AtomicI64::new(0),
+                                http: Client::builder(TokioExecutor::new()).build_http(),
+                                market_symbols: config.market_symbols,
+                                account_tx: event_tx,
+                                market_tx,
+                                stream_task: None };
+
+        // 推送连接失败时仍可以使用 REST 接口
+        if let Err(e) = client.connect_stream().await {
+            warn!("Failed to subscribe to stream of {}: {}", client.base_url, e);
+        }
+        client
+    }
+
+    async fn fetch_orders_open(&self) -> Result<Vec<Order<Open>>, ExchangeError>
+    {
+        self.call(Method::GET, "/orders", None).await
+    }
+
+    async fn fetch_balances(&self) -> Result<Vec<TokenBalance>, ExchangeError>
+    {
+        self.call(Method::GET, "/balances", None).await
+    }
+
+    async fn fetch_all_positions(&self) -> Result<AccountPositions, ExchangeError>
+    {
+        let positions: Vec<Position> = self.call(Method::GET, "/positions", None).await?;
+        Ok(AccountPositions::from_positions(positions).await)
+    }
+
+    async fn fetch_pnl_attribution(&self) -> Result<AttributionSummary, ExchangeError>
+    {
+        self.call(Method::GET, "/attribution", None).await
+    }
+
+    async fn fetch_long_position(&self, instrument: Instrument) -> Result<Option<Position>, ExchangeError>
+    {
+        let path = format!("/positions/long?{}", instrument_query(&instrument)?);
+        self.call(Method::GET, &path, None).await
+    }
+
+    async fn fetch_short_position(&self, instrument: Instrument) -> Result<Option<Position>, ExchangeError>
+    {
+        let path = format!("/positions/short?{}", instrument_query(&instrument)?);
+        self.call(Method::GET, &path, None).await
+    }
+
+    async fn open_orders(&self, open_requests: Vec<Order<RequestOpen>>) -> Vec<Result<Order<Open>, ExchangeError>>
+    {
+        self.call_batch("/orders", open_requests).await
+    }
+
+    async fn cancel_orders(&self, cancel_requests: Vec<Order<RequestCancel>>) -> Vec<Result<Order<Cancelled>, ExchangeError>>
+    {
+        self.call_batch("/orders/cancel", cancel_requests).await
+    }
+
+    async fn cancel_orders_all(&self) -> Result<Vec<Order<Cancelled>>, ExchangeError>
+    {
+        self.call(Method::DELETE, "/orders", None).await
+    }
+
+    async fn deposit_tokens(&self, deposits: Vec<(Token, f64)>) -> Result<Vec<TokenBalance>, ExchangeError>
+    {
+        info!("begin to deposit tokens: {:?}", deposits);
+        let body = serde_json::to_string(&deposits).map_err(|_| ExchangeError::JsonSerDeError)?;
+        self.call(Method::POST, "/deposits", Some(body)).await
+    }
+
+    async fn let_it_roll(&self) -> Result<(), ExchangeError>
+    {
+        self.call(Method::POST, "/let_it_roll", None).await
+    }
+}
+
+impl HourglassRemoteClient
+{
+    pub async fn listen_for_market_data(&mut self) -> Option<MarketTrade>
+    {
+        let market_event = self.market_event_rx.recv().await?;
+        info!("Received market event: {:?}", market_event);
+        Some(market_event)
+    }
+
+    /// 注册新用户。
+    pub async fn register(&self, username: String, email: String, password: String) -> Result<(), ExchangeError>
+    {
+        let body = serde_json::to_string(&RegisterForm { username, email, password }).map_err(|_| ExchangeError::JsonSerDeError)?;
+        self.call(Method::POST, "/register", Some(body)).await
+    }
+
+    /// 登录成功后保存 session 令牌，之后的请求都会携带该令牌。
+    pub async fn login(&mut self, username: String, password: String) -> Result<LoginResponse, ExchangeError>
+    {
+        let body = serde_json::to_string(&LoginForm { username, password }).map_err(|_| ExchangeError::JsonSerDeError)?;
+        let response: LoginResponse = self.call(Method::POST, "/login", Some(body)).await?;
+        self.session_token = Some(response.session_token.clone());
+        // 带上令牌重新连接，才能收到账户事件
+        if let Err(e) = self.connect_stream().await {
+            warn!("Failed to subscribe to stream of {}: {}", self.base_url, e);
+        }
+        Ok(response)
+    }
+
+    /// 注销当前会话。
+    pub async fn logout(&mut self) -> Result<(), ExchangeError>
+    {
+        self.call::<()>(Method::POST, "/logout", None).await?;
+        self.session_token = None;
+        if let Err(e) = self.connect_stream().await {
+            warn!("Failed to subscribe to stream of {}: {}", self.base_url, e);
+        }
+        Ok(())
+    }
+
+    /// 以当前的 session 令牌与 API key 重新连接 `GET /ws`，并断开之前的推送连接。`login` 与 `logout` 会自动调用。
+    pub async fn connect_stream(&mut self) -> Result<(), ExchangeError>
+    {
+        if let Some(stream_task) = self.stream_task.take() {
+            stream_task.abort();
+        }
+        let api_key = self.api_key.as_ref().map(|api_key| (api_key, self.sign_timestamp()));
+        let socket = subscribe_stream(&self.base_url, self.session_token.as_deref(), api_key, &self.market_symbols).await?;
+        self.stream_task = Some(tokio::spawn(forward_stream(socket, self.account_tx.clone(), self.market_tx.clone())));
+        Ok(())
+    }
+
+    /// 之后的请求都用该 API key 签名，服务器配置了 [`ApiKeyStore`](crate::network::api_key::ApiKeyStore) 时必须设置。
+    /// 推送连接同样需要签名，设置后调用 [`connect_stream`](Self::connect_stream) 重新连接。
+    pub fn set_api_key(&mut self, key: String, secret: String)
+    {
+        self.api_key = Some((key, secret));
+    }
+
+    /// 签名用的时间戳严格递增，同一毫秒内的相同请求不会被服务器当作重放。
+    fn sign_timestamp(&self) -> i64
+    {
+        let now = Utc::now().timestamp_millis();
+        let next = |last: i64| last.max(now - 1) + 1;
+        next(self.last_signed.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(next(last))).unwrap_or_default())
+    }
+
+    async fn send(&self, method: Method, path: &str, body: Option<String>) -> Result<(StatusCode, Bytes), ExchangeError>
+    {
+        let body = body.unwrap_or_default();
+        let mut request = Request::builder().method(method.clone()).uri(format!("{}{}", self.base_url, path)).header(CONTENT_TYPE, "application/json");
+        if let Some(session_token) = &self.session_token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", session_token));
+        }
+        if let Some((key, secret)) = &self.api_key {
+            let timestamp = self.sign_timestamp();
+            request = request.header(API_KEY_HEADER, key)
+                             .header(TIMESTAMP_HEADER, timestamp)
+                             .header(SIGNATURE_HEADER, api_key::sign(secret, timestamp, method.as_str(), path, body.as_bytes()));
+        }
+        let request = request.body(Full::new(Bytes::from(body))).map_err(|e| ExchangeError::NetworkError(e.to_string()))?;
+        let response = self.http.request(request).await.map_err(|e| ExchangeError::NetworkError(e.to_string()))?;
+        let status = response.status();
+        let body = response.into_body().collect().await.map_err(|e| ExchangeError::NetworkError(e.to_string()))?.to_bytes();
+        Ok((status, body))
+    }
+
+    async fn call<T>(&self, method: Method, path: &str, body: Option<String>) -> Result<T, ExchangeError>
+        where T: DeserializeOwned
+    {
+        let (status, body) = self.send(method, path, body).await?;
+        if !status.is_success() {
+            return Err(api_error(status, &body));
+        }
+        serde_json::from_slice(&body).map_err(|e| ExchangeError::ResponseParseError(e.to_string()))
+    }
+
+    /// 批量接口全部失败时返回错误状态码，但响应体仍是逐个订单的结果，因此先按结果列表解析。
+    async fn call_batch<R, T>(&self, path: &str, requests: Vec<R>) -> Vec<Result<T, ExchangeError>>
+        where R: Serialize,
+              T: DeserializeOwned
+    {
+        let count = requests.len();
+        let results = async {
+            let body = serde_json::to_string(&requests).map_err(|_| ExchangeError::JsonSerDeError)?;
+            let (status, body) = self.send(Method::POST, path, Some(body)).await?;
+            serde_json::from_slice::<Vec<Result<T, ExchangeError>>>(&body).map_err(|_| api_error(status, &body))
+        };
+        results.await.unwrap_or_else(|error| (0..count).map(|_| Err(error.clone())).collect())
+    }
+}
+
+/// 把错误响应还原为 [`ExchangeError`]。
+fn api_error(status: StatusCode, body: &[u8]) -> ExchangeError
+{
+    match serde_json::from_slice::<ApiError>(body) {
+        | Ok(ApiError { error: Some(error), .. }) => error,
+        | Ok(ApiError { message, error: None }) => ExchangeError::Hourglass(message),
+        | Err(_) => ExchangeError::NetworkError(format!("Unexpected response status {}", status)),
+    }
+}
+
+/// `GET /positions/long` 与 `GET /positions/short` 的查询参数。
+fn instrument_query(instrument: &Instrument) -> Result<String, ExchangeError>
+{
+    let kind = serde_json::to_value(instrument.kind).map_err(|_| ExchangeError::JsonSerDeError)?;
+    Ok(url::form_urlencoded::Serializer::new(String::new()).append_pair("base", instrument.base.as_ref())
+                                                           .append_pair("quote", instrument.quote.as_ref())
+                                                           .append_pair("instrument_kind", kind.as_str().unwrap_or_default())
+                                                           .finish())
+}
+
+type StreamSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// 连接 `GET /ws`，订阅指定交易对的市场成交，登录或设置 API key 后还会订阅账户频道，收到确认后返回连接。
+async fn subscribe_stream(base_url: &str, session_token: Option<&str>, api_key: Option<(&(String, String), i64)>, market_symbols: &[String]) -> Result<StreamSocket, ExchangeError>
+{
+    let mut request = format!("{}/ws", base_url.replacen("http", "ws", 1)).into_client_request()
+                                                                          .map_err(|e| ExchangeError::NetworkError(e.to_string()))?;
+    let headers = request.headers_mut();
+    if let Some(session_token) = session_token {
+        headers.insert("authorization", format!("Bearer {}", session_token).parse().map_err(|_| ExchangeError::InvalidSession)?);
+    }
+    if let Some(((key, secret), timestamp)) = api_key {
+        headers.insert(API_KEY_HEADER, key.parse().map_err(|_| ExchangeError::AuthenticationFailed)?);
+        headers.insert(TIMESTAMP_HEADER, timestamp.into());
+        headers.insert(SIGNATURE_HEADER, api_key::sign(secret, timestamp, "GET", "/ws", &[]).parse().map_err(|_| ExchangeError::InvalidSignature)?);
+    }
+    let (mut socket, _) = connect_async(request).await.map_err(|e| ExchangeError::NetworkError(e.to_string()))?;
+
+    let mut channels = if session_token.is_some() || api_key.is_some() {
+        vec![StreamChannel::Orders, StreamChannel::Trades, StreamChannel::Balances, StreamChannel::Positions]
+    }
+    else {
+        vec![]
+    };
+    channels.extend(market_symbols.iter().cloned().map(StreamChannel::MarketTrades));
+    let request = serde_json::to_string(&StreamRequest::Subscribe { channels }).map_err(|_| ExchangeError::JsonSerDeError)?;
+    socket.send(Message::Text(request)).await.map_err(|e| ExchangeError::NetworkError(e.to_string()))?;
+
+    // 确认之前的心跳直接忽略
+    let acknowledged = async {
+        while let Some(message) = socket.next().await {
+            match message {
+                | Ok(Message::Text(text)) => match serde_json::from_str::<StreamMessage>(&text) {
+                    | Ok(StreamMessage::Subscribed { .. }) => return Ok(()),
+                    | Ok(StreamMessage::Error { message }) => return Err(ExchangeError::Hourglass(message)),
+                    | _ => continue,
+                },
+                | Ok(_) => continue,
+                | Err(e) => return Err(ExchangeError::NetworkError(e.to_string())),
+            }
+        }
+        Err(ExchangeError::NetworkError("Stream closed before subscription was acknowledged".to_string()))
+    };
+    time::timeout(SUBSCRIBE_TIMEOUT, acknowledged).await
+                                                  .map_err(|_| ExchangeError::Timeout("Stream subscription was not acknowledged".to_string()))??;
+    Ok(socket)
+}
+
+async fn forward_stream(mut socket: StreamSocket, account_tx: UnboundedSender<AccountEvent>, market_tx: UnboundedSender<MarketTrade>)
+{
+    let mut sequences: HashMap<StreamChannel, u64> = HashMap::new();
+    // 仓位快照紧跟在成交之后推送，沿用最近一条账户事件的时间戳
+    let mut exchange_timestamp = 0;
+
+    while let Some(message) = socket.next().await {
+        let text = match message {
+            | Ok(Message::Text(text)) => text,
+            | Ok(Message::Close(_)) | Err(_) => break,
+            | Ok(_) => continue,
+        };
+        let Ok(StreamMessage::Event { channel, sequence, payload }) = serde_json::from_str::<StreamMessage>(&text)
+        else {
+            continue;
+        };
+
+        // 重新连接后的第一条推送作为新的起点
+        if let Some(previous) = sequences.insert(channel.clone(), sequence) {
+            if sequence != previous + 1 {
+                warn!("Stream channel {:?} jumped from sequence {} to {}", channel, previous, sequence);
+            }
+        }
+
+        match payload {
+            | StreamPayload::Account(event) => {
+                exchange_timestamp = event.exchange_timestamp;
+                let _ = account_tx.send(event);
+            }
+            | StreamPayload::Positions(positions) => {
+                let _ = account_tx.send(AccountEvent { exchange_timestamp,
+                                                       exchange: Exchange::Hourglass,
+                                                       kind: AccountEventKind::Positions(AccountPositions::from_positions(positions).await) });
+            }
+            | StreamPayload::Market(trade) => {
+                let _ = market_tx.send(trade);
+            }
+        }
+    }
+    info!("Stream connection closed");
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::balance::Balance,
+        network::{
+            api_key::{ApiKeyStore, ApiPermission},
+            login::{Authentication, Authenticator, InMemoryUserStore, DEFAULT_SESSION_TTL, TEST_PASSWORD_COST},
+            rest_api, ws_stream,
+        },
+    };
+    use std::sync::Arc;
+    use warp::Filter;
+
+    fn market_trade(symbol: &str) -> MarketTrade
+    {
+        MarketTrade { exchange: "binance-futures".to_string(),
+                      symbol: symbol.to_string(),
+                      side: "buy".to_string(),
+                      price: 100.0,
+                      timestamp: 1,
+                      amount: 1.0 }
+    }
+
+    /// 在回环地址上启动只有推送路由和 REST 路由的服务器，REST 请求由测试自行应答。
+    async fn spawn_server(api_keys: Option<Arc<ApiKeyStore>>) -> (String, Arc<ws_stream::StreamHub>, Authenticator, UnboundedReceiver<crate::hourglass::hourglass_client_local_mode::HourglassClientEvent>)
+    {
+        let hub = Arc::new(ws_stream::StreamHub::new(16, Duration::from_secs(60)));
+        let authenticator = Authenticator::new(Arc::new(InMemoryUserStore::new()), DEFAULT_SESSION_TTL, TEST_PASSWORD_COST, false);
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let routes = ws_stream::route(Arc::clone(&hub), authenticator.clone(), api_keys.clone()).or(rest_api::routes(event_tx, api_keys, true));
+        let (address, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}", address), hub, authenticator, event_rx)
+    }
+
+    #[tokio::test]
+    async fn remote_client_should_forward_stream_and_rest_replies()
+    {
+        use crate::hourglass::hourglass_client_local_mode::HourglassClientEvent;
+
+        let (base_url, hub, authenticator, mut event_rx) = spawn_server(None).await;
+        let (account_tx, mut account_rx) = mpsc::unbounded_channel();
+        let mut client = HourglassRemoteClient::init(RemoteClientConfig { base_url,
+                                                                          market_symbols: vec!["ETHUSDT".to_string()] },
+                                                     account_tx).await;
+
+        // 订阅确认之后才返回，因此此时发布的推送不会丢失。未登录时收不到账户事件
+        hub.publish(StreamChannel::Positions, StreamPayload::Positions(vec![]));
+        hub.publish(StreamChannel::MarketTrades("BTCUSDT".to_string()), StreamPayload::Market(market_trade("BTCUSDT")));
+        hub.publish(StreamChannel::MarketTrades("ETHUSDT".to_string()), StreamPayload::Market(market_trade("ETHUSDT")));
+        assert_eq!(client.listen_for_market_data().await.unwrap().symbol, "ETHUSDT");
+        assert!(account_rx.try_recv().is_err());
+
+        // 登录后重新连接
+        authenticator.handle_register("alice".to_string(), String::new(), "secret".to_string()).await.unwrap();
+        client.session_token = Some(authenticator.handle_login("alice".to_string(), "secret".to_string()).await.unwrap().session_token);
+        client.connect_stream().await.unwrap();
+        hub.publish(StreamChannel::Positions, StreamPayload::Positions(vec![]));
+        let event = account_rx.recv().await.unwrap();
+        assert!(matches!(event.kind, AccountEventKind::Positions(_)));
+
+        // 登录后的请求都带有 session 令牌
+        let responder = tokio::spawn(async move {
+            // REST 接口转发的请求外层带有对端地址
+            match event_rx.recv().await.map(|event| event.split_peer().1) {
+                | Some(HourglassClientEvent::Authenticated(_, event)) => match *event {
+                    | HourglassClientEvent::FetchTokenBalances(response_tx) => {
+                        let _ = response_tx.send(Ok(vec![TokenBalance::new("USDT", Balance::new(10.0, 10.0))]));
+                    }
+                    | other => panic!("Unexpected event: {:?}", other),
+                },
+                | other => panic!("Unexpected event: {:?}", other),
+            }
+            match event_rx.recv().await.map(|event| event.split_peer().1) {
+                | Some(HourglassClientEvent::Authenticated(_, event)) => match *event {
+                    | HourglassClientEvent::CancelOrdersAll(response_tx) => {
+                        let _ = response_tx.send(Err(ExchangeError::OrderNotFound { client_order_id: None, order_id: None }));
+                    }
+                    | other => panic!("Unexpected event: {:?}", other),
+                },
+                | other => panic!("Unexpected event: {:?}", other),
+            }
+        });
+
+        let balances = client.fetch_balances().await.unwrap();
+        assert_eq!(balances[0].balance.total, 10.0);
+        // 服务端的错误原样还原
+        assert_eq!(client.cancel_orders_all().await, Err(ExchangeError::OrderNotFound { client_order_id: None, order_id: None }));
+        responder.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_should_be_signed_with_api_key()
+    {
+        use crate::hourglass::hourglass_client_local_mode::HourglassClientEvent;
+
+        let api_keys = Arc::new(ApiKeyStore::default());
+        let api_key = api_keys.create_key("alice", vec![ApiPermission::ReadOnly]);
+        let (base_url, _hub, _authenticator, mut event_rx) = spawn_server(Some(api_keys)).await;
+        let (account_tx, _account_rx) = mpsc::unbounded_channel();
+        let mut client = HourglassRemoteClient::init(RemoteClientConfig { base_url, market_symbols: vec![] }, account_tx).await;
+
+        // 未签名的请求不会到达事件循环，推送连接只能订阅市场成交
+        assert_eq!(client.fetch_balances().await, Err(ExchangeError::AuthenticationFailed));
+        client.connect_stream().await.unwrap();
+
+        client.set_api_key(api_key.key, api_key.secret);
+        client.connect_stream().await.unwrap();
+        let responder = tokio::spawn(async move {
+            match event_rx.recv().await.map(|event| event.split_peer().1) {
+                | Some(HourglassClientEvent::Signed(caller, event)) => match *event {
+                    | HourglassClientEvent::FetchTokenBalances(response_tx) => {
+                        assert_eq!(caller.username, "alice");
+                        let _ = response_tx.send(Ok(vec![]));
+                    }
+                    | other => panic!("Unexpected event: {:?}", other),
+                },
+                | other => panic!("Unexpected event: {:?}", other),
+            }
+        });
+        assert_eq!(client.fetch_balances().await, Ok(vec![]));
+        assert_eq!(client.cancel_orders_all().await, Err(ExchangeError::InsufficientPermissions));
+        responder.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unreachable_server_should_return_network_error()
+    {
+        let (account_tx, _account_rx) = mpsc::unbounded_channel();
+        let client = HourglassRemoteClient::init(RemoteClientConfig { base_url: "http://127.0.0.1:1".to_string(),
+                                                                      market_symbols: vec![] },
+                                                 account_tx).await;
+        assert!(matches!(client.fetch_orders_open().await, Err(ExchangeError::NetworkError(_))));
+        assert!(client.open_orders(vec![]).await.is_empty());
+    }
+}
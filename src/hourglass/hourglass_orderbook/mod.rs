@@ -1,7 +1,7 @@
 use crate::common::order::{identification::OrderId, states::open::Open, Order};
 /// NOTE MODULE CODE BELOW IS UNDER CONSTRUCTION
 ///
-/// ### 1. **高级撮合逻辑**
+/// ### 1. **高级撮合逻辑** [DONE]
 ///    - **部分成交 (Partial Fill)**: 目前的代码已经考虑了部分成交的情况，但你可以进一步优化部分成交的逻辑。例如，当一个订单被部分成交后，其剩余部分是否应该立即与下一个层级的订单继续撮合，或者应该优先处理其他等待中的订单。
 ///    - **优先级撮合**: 当有多个订单在同一价格层级时，可以实现基于时间戳的优先级撮合（即更早提交的订单优先成交），以更接近真实市场的逻辑。
 ///
@@ -36,11 +36,66 @@ use rayon::{iter::IntoParallelRefIterator, prelude::IndexedParallelIterator};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 
+/// 共享订单簿中的一张客户订单，`owner` 为下单的用户名，`None` 表示默认账户。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookOrder
+{
+    pub owner: Option<String>,
+    pub order: Order<Open>,
+}
+
+/// 两个不同账户的订单之间的一次成交，成交价为先挂单一方（Maker）的价格。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentFill
+{
+    pub maker: BookOrder, // 成交前的挂单
+    pub taker: BookOrder, // 成交前的主动单
+    pub price: f64,
+    pub size: f64,
+}
+
+/// 交易所为每张挂单分配的到达序号，撮合不同账户的挂单时以序号决定时间优先，不采信客户端提供的时间戳。
+#[derive(Debug, Clone, Default)]
+pub struct ArrivalSequence
+{
+    next: u64,
+    arrivals: HashMap<(Option<String>, OrderId), u64>,
+}
+
+impl ArrivalSequence
+{
+    /// 为首次出现的挂单分配序号，忘掉已不在 `resting` 中的订单，并按到达顺序返回全部挂单。
+    ///
+    /// 同一批新出现的挂单按订单 ID 依次编号。
+    pub fn arrange(&mut self, mut resting: Vec<BookOrder>) -> Vec<BookOrder>
+    {
+        resting.sort_by(|a, b| a.order.state.id.cmp(&b.order.state.id));
+        let mut arrivals = HashMap::with_capacity(resting.len());
+        let mut sequenced: Vec<(u64, BookOrder)> = resting.into_iter()
+                                                          .map(|entry| {
+                                                              let key = (entry.owner.clone(), entry.order.state.id.clone());
+                                                              let sequence = match self.arrivals.get(&key) {
+                                                                  | Some(&sequence) => sequence,
+                                                                  | None => {
+                                                                      self.next += 1;
+                                                                      self.next
+                                                                  }
+                                                              };
+                                                              arrivals.insert(key, sequence);
+                                                              (sequence, entry)
+                                                          })
+                                                          .collect();
+        self.arrivals = arrivals;
+        sequenced.sort_by_key(|(sequence, _)| *sequence);
+        sequenced.into_iter().map(|(_, entry)| entry).collect()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceLevel
 {
-    pub price: f64,                    // 价格层级
-    pub orders: VecDeque<Order<Open>>, // 使用VecDeque保证FIFO顺序的订单队列
+    pub price: f64,                  // 价格层级
+    pub orders: VecDeque<BookOrder>, // 使用VecDeque保证FIFO顺序的订单队列
 }
 
 impl PriceLevel
@@ -50,20 +105,15 @@ impl PriceLevel
         PriceLevel { price, orders: VecDeque::new() }
     }
 
-    fn add_order(&mut self, order: Order<Open>)
+    fn add_order(&mut self, order: BookOrder)
     {
         self.orders.push_back(order); // 先进先出，插入到队列尾部
     }
 
-    fn remove_order(&mut self) -> Option<Order<Open>>
-    {
-        self.orders.pop_front() // 从队列头部移除并返回最早的订单
-    }
-
     fn remove_expired_orders(&mut self, expiration_times: &HashMap<OrderId, i64>, current_time: i64)
     {
-        self.orders.retain(|order| {
-                       if let Some(&expire_time) = expiration_times.get(&order.state.id) {
+        self.orders.retain(|entry| {
+                       if let Some(&expire_time) = expiration_times.get(&entry.order.state.id) {
                            expire_time > current_time // 保留未过期的订单
                        }
                        else {
@@ -76,8 +126,8 @@ impl PriceLevel
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HourglassOrderBook
 {
-    pub bid_levels: Vec<PriceLevel>,                // 买单簿
-    pub ask_levels: Vec<PriceLevel>,                // 卖单簿
+    pub bid_levels: Vec<PriceLevel>,                // 买单簿，最优（最高）价格在前
+    pub ask_levels: Vec<PriceLevel>,                // 卖单簿，最优（最低）价格在前
     pub max_levels: usize,                          // 允许的最大层级数量
     pub expiration_registry: HashMap<OrderId, i64>, // 订单ID与过期时间的映射
 }
@@ -97,93 +147,97 @@ impl HourglassOrderBook
         self.expiration_registry.insert(order_id, expire_ts); // 设置订单的过期时间
     }
 
-    pub fn insert_order(&mut self, order: Order<Open>)
+    pub fn insert_order(&mut self, entry: BookOrder)
     {
         // 根据订单的买卖方向，选择合适的价格层级列表（买单簿或卖单簿）
-        let levels = match order.side {
+        let side = entry.order.side;
+        let levels = match side {
             | Side::Buy => &mut self.bid_levels,  // 买单簿
             | Side::Sell => &mut self.ask_levels, // 卖单簿
         };
 
         // 尝试在现有的价格层级中找到与订单价格匹配的层级
-        match levels.iter_mut().find(|level| level.price == order.state.price) {
+        match levels.iter_mut().find(|level| level.price == entry.order.state.price) {
             // 如果找到相同价格的层级，直接将订单添加到该层级
-            | Some(level) => level.add_order(order),
+            | Some(level) => level.add_order(entry),
             // 如果没有找到相同价格的层级，创建一个新的价格层级
             | None => {
-                let mut new_level = PriceLevel::new(order.state.price); // 创建新价格层级
-                new_level.add_order(order); // 将订单添加到新层级
+                let mut new_level = PriceLevel::new(entry.order.state.price); // 创建新价格层级
+                new_level.add_order(entry); // 将订单添加到新层级
+                levels.push(new_level);
 
-                // 检查是否超过最大层级数量限制
-                if levels.len() >= self.max_levels {
-                    // 根据需要处理：例如，可以选择删除最低优先级的层级
-                    levels.pop(); // 这里我们简单地删除最后一个层级，假设它是优先级最低的
+                // 最优价格排在最前面，超过最大层级数量时丢弃最差的层级
+                match side {
+                    | Side::Buy => levels.sort_by(|a, b| b.price.partial_cmp(&a.price).unwrap()),
+                    | Side::Sell => levels.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap()),
                 }
-
-                levels.push(new_level); // 将新层级添加到价格层级列表中
-                levels.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap()); // 将价格层级列表按照价格排序
+                levels.truncate(self.max_levels);
             }
         }
     }
 
-    // NOTE 注意和Account模块的兼容性
-    pub fn process_trades(&mut self, current_time: i64)
+    /// 以价格-时间优先撮合一张新到的订单，未成交的部分挂入订单簿。
+    ///
+    /// 新订单依次与对手方最优价格层级中最早的挂单成交，成交价为挂单价格。同一账户的订单不会互相成交。
+    pub fn match_order(&mut self, mut incoming: BookOrder) -> Vec<AgentFill>
     {
-        // 清理过期的订单
-        for level in &mut self.bid_levels {
-            level.remove_expired_orders(&self.expiration_registry, current_time);
-        }
-        for level in &mut self.ask_levels {
-            level.remove_expired_orders(&self.expiration_registry, current_time);
-        }
+        let mut fills = Vec::new();
+        let mut remaining = incoming.order.state.remaining_quantity();
+        let opposite_levels = match incoming.order.side {
+            | Side::Buy => &mut self.ask_levels,
+            | Side::Sell => &mut self.bid_levels,
+        };
 
-        // 如果买单簿或卖单簿为空，则无法进行撮合
-        if self.bid_levels.is_empty() || self.ask_levels.is_empty() {
-            return;
-        }
+        for level in opposite_levels.iter_mut() {
+            let crosses = match incoming.order.side {
+                | Side::Buy => level.price <= incoming.order.state.price,
+                | Side::Sell => level.price >= incoming.order.state.price,
+            };
+            if !crosses || remaining <= 0.0 {
+                break;
+            }
+
+            let mut index = 0;
+            while index < level.orders.len() && remaining > 0.0 {
+                let resting = &mut level.orders[index];
+                if resting.owner == incoming.owner {
+                    index += 1;
+                    continue;
+                }
+
+                let size = remaining.min(resting.order.state.remaining_quantity());
+                fills.push(AgentFill { maker: resting.clone(),
+                                       taker: incoming.clone(),
+                                       price: level.price,
+                                       size });
+                resting.order.state.filled_quantity += size;
+                incoming.order.state.filled_quantity += size;
+                remaining -= size;
 
-        // 当买单簿和卖单簿都不为空时，进行订单撮合
-        while !self.bid_levels.is_empty() && !self.ask_levels.is_empty() {
-            let buy_level = &mut self.bid_levels[0]; // 获取买单簿的最优价格层级（价格最高）
-            let sell_level = &mut self.ask_levels[0]; // 获取卖单簿的最优价格层级（价格最低）
-
-            // 循环尝试撮合买单和卖单，直到其中一个层级的订单无法继续撮合
-            while let (Some(mut buy_order), Some(mut sell_order)) = (buy_level.remove_order(), sell_level.remove_order()) {
-                // 如果买单价格大于或等于卖单价格，则可以成交
-                if buy_order.state.price >= sell_order.state.price {
-                    // 计算成交量，取买单和卖单的最小剩余数量
-                    let executed_quantity = if buy_order.state.size < sell_order.state.size { buy_order.state.size } else { sell_order.state.size };
-
-                    // 更新订单的剩余数量
-                    buy_order.state.size -= executed_quantity;
-                    sell_order.state.size -= executed_quantity;
-
-                    // 如果买单还有剩余数量，将其重新添加到买单簿的相应层级
-                    if buy_order.state.size > 0.0 {
-                        buy_level.add_order(buy_order);
-                    }
-                    // 如果卖单还有剩余数量，将其重新添加到卖单簿的相应层级
-                    if sell_order.state.size > 0.0 {
-                        sell_level.add_order(sell_order);
-                    }
+                if resting.order.state.remaining_quantity() <= 0.0 {
+                    level.orders.remove(index);
                 }
                 else {
-                    // 如果买单价格小于卖单价格，则无法成交，将订单重新放回原层级
-                    buy_level.add_order(buy_order);
-                    sell_level.add_order(sell_order);
-                    break; // 跳出循环，尝试下一个价格层级
+                    index += 1;
                 }
             }
+        }
+        opposite_levels.retain(|level| !level.orders.is_empty());
 
-            // 如果买单层级的订单全部成交完毕，则移除该价格层级
-            if buy_level.orders.is_empty() {
-                self.bid_levels.remove(0);
-            }
-            // 如果卖单层级的订单全部成交完毕，则移除该价格层级
-            if sell_level.orders.is_empty() {
-                self.ask_levels.remove(0);
-            }
+        if remaining > 0.0 {
+            self.insert_order(incoming);
+        }
+        fills
+    }
+
+    /// 清理过期的订单
+    pub fn remove_expired_orders(&mut self, current_time: i64)
+    {
+        for level in self.bid_levels.iter_mut().chain(self.ask_levels.iter_mut()) {
+            level.remove_expired_orders(&self.expiration_registry, current_time);
         }
+        self.bid_levels.retain(|level| !level.orders.is_empty());
+        self.ask_levels.retain(|level| !level.orders.is_empty());
     }
 
     // 获取订单簿快照 NOTE 注意和Account模块的兼容性
@@ -197,11 +251,94 @@ impl HourglassOrderBook
     {
         for levels in [&mut self.bid_levels, &mut self.ask_levels].iter_mut() {
             for level in levels.iter_mut() {
-                if let Some(pos) = level.orders.par_iter().position_any(|order| order.state.id == order_id) {
-                    return level.orders.remove(pos);
+                if let Some(pos) = level.orders.par_iter().position_any(|entry| entry.order.state.id == order_id) {
+                    return level.orders.remove(pos).map(|entry| entry.order);
                 }
             }
         }
         None
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::{
+            instrument::{kind::InstrumentKind, Instrument},
+            order::{order_instructions::OrderInstruction, OrderRole},
+        },
+        Exchange,
+    };
+
+    fn book_order(owner: &str, id: u64, side: Side, price: f64, size: f64) -> BookOrder
+    {
+        BookOrder { owner: Some(owner.to_string()),
+                    order: Order { instruction: OrderInstruction::Limit,
+                                   exchange: Exchange::Hourglass,
+                                   instrument: Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual)),
+                                   timestamp: id as i64,
+                                   cid: None,
+                                   side,
+                                   state: Open { id: OrderId::new(0, 0, id),
+                                                 price,
+                                                 size,
+                                                 filled_quantity: 0.0,
+                                                 order_role: OrderRole::Maker } } }
+    }
+
+    #[test]
+    fn incoming_order_should_match_by_price_then_time()
+    {
+        let mut book = HourglassOrderBook::new(usize::MAX);
+        assert!(book.match_order(book_order("alice", 1, Side::Sell, 101.0, 1.0)).is_empty());
+        assert!(book.match_order(book_order("bob", 2, Side::Sell, 100.0, 1.0)).is_empty());
+        assert!(book.match_order(book_order("carol", 3, Side::Sell, 100.0, 1.0)).is_empty());
+
+        let fills = book.match_order(book_order("dave", 4, Side::Buy, 101.0, 2.5));
+        let makers: Vec<_> = fills.iter().map(|fill| (fill.maker.owner.clone().unwrap(), fill.price, fill.size)).collect();
+        assert_eq!(makers, vec![("bob".to_string(), 100.0, 1.0), ("carol".to_string(), 100.0, 1.0), ("alice".to_string(), 101.0, 0.5)]);
+        assert!(fills.iter().all(|fill| fill.taker.owner.as_deref() == Some("dave")));
+
+        // alice 剩余的 0.5 仍在卖单簿中，买单已全部成交
+        assert!(book.bid_levels.is_empty());
+        assert_eq!(book.ask_levels.len(), 1);
+        assert_eq!(book.ask_levels[0].orders[0].order.state.remaining_quantity(), 0.5);
+    }
+
+    #[test]
+    fn orders_from_the_same_owner_should_not_match()
+    {
+        let mut book = HourglassOrderBook::new(usize::MAX);
+        book.match_order(book_order("alice", 1, Side::Buy, 100.0, 1.0));
+        book.match_order(book_order("bob", 2, Side::Buy, 99.0, 1.0));
+
+        let fills = book.match_order(book_order("alice", 3, Side::Sell, 99.0, 1.0));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker.owner.as_deref(), Some("bob"));
+        assert_eq!(fills[0].price, 99.0);
+        assert_eq!(book.bid_levels.len(), 1);
+        assert_eq!(book.bid_levels[0].price, 100.0);
+        assert!(book.ask_levels.is_empty());
+    }
+
+    #[test]
+    fn arrival_sequence_should_ignore_client_timestamps()
+    {
+        let mut arrivals = ArrivalSequence::default();
+        let first = book_order("alice", 5, Side::Sell, 100.0, 1.0);
+        arrivals.arrange(vec![first.clone()]);
+
+        // bob 把时间戳填得比 alice 更早，也仍然排在 alice 之后
+        let mut backdated = book_order("bob", 4, Side::Sell, 100.0, 1.0);
+        backdated.order.timestamp = 0;
+        let owners: Vec<_> = arrivals.arrange(vec![backdated.clone(), first.clone()]).into_iter().map(|entry| entry.owner.unwrap()).collect();
+        assert_eq!(owners, vec!["alice".to_string(), "bob".to_string()]);
+
+        // alice 的订单离开订单簿后重新挂出，视为新到达的订单
+        arrivals.arrange(vec![backdated.clone()]);
+        let owners: Vec<_> = arrivals.arrange(vec![first, backdated]).into_iter().map(|entry| entry.owner.unwrap()).collect();
+        assert_eq!(owners, vec!["bob".to_string(), "alice".to_string()]);
+    }
+}
@@ -0,0 +1,692 @@
+use crate::{
+    common::{
+        account_positions::{exited_positions::AccountExitedPositions, AccountPositions, AccountPositionsSnapshot},
+        balance::{Balance, TokenBalance},
+        event::{AccountEvent, AccountEventKind},
+        instrument::kind::InstrumentKind,
+        order::{identification::OrderId, states::open::Open, Order},
+        token::Token,
+        trade::ClientTrade,
+        Side,
+    },
+    error::ExchangeError,
+    hourglass::{
+        account::{
+            account_config::AccountConfig,
+            account_handlers::{balance_handler::BalanceHandler, position_handler::PositionHandler},
+            account_latency::{AccountLatency, FluctuationMode},
+            account_orders::AccountOrders,
+            HourglassAccount,
+        },
+        config_request::ConfigurationRequest,
+        hourglass_client_local_mode::HourglassClientEvent,
+    },
+    hourglass_log::{error, warn},
+    network::{
+        event::NetworkRequest,
+        login::{LoginForm, RegisterForm},
+    },
+    vault::VaultConfig,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    Mutex,
+};
+
+/// 写入日志时替换密码与会话令牌。
+const REDACTED: &str = "<redacted>";
+
+/// 累积到这么多条未同步的记录后同步到磁盘。
+const SYNC_EVERY: u64 = 256;
+
+/// 未同步的记录最多保留这么久。
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 审计日志中的一条记录，每行一个 JSON 对象。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry
+{
+    pub sequence: u64, // 从 1 开始递增，同一个文件的多次运行连续编号
+    pub exchange_timestamp: i64,
+    pub recorded_at: DateTime<Utc>,
+    pub username: Option<String>, // 记录所属的账户，默认账户为 `None`
+    pub record: JournalRecord,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum JournalRecord
+{
+    /// 开始记录时的账户状态，重放从最近的一条开始。
+    Genesis
+    {
+        balances: Vec<TokenBalance>,
+        open_orders: Vec<Order<Open>>,
+        #[serde(default)]
+        positions: Box<AccountPositionsSnapshot>,
+    },
+    /// 收到的客户端请求，在鉴权与限流之前记录，密码与会话令牌已隐去，处理结果见 [`JournalRecord::Outcome`]。
+    Request(NetworkRequest),
+    /// 序号为 `request` 的请求是否通过了鉴权、限流与路由，通过时记录在处理它的账户下。
+    Outcome
+    {
+        request: u64, outcome: RequestOutcome
+    },
+    /// 账户发出的事件。
+    Event(AccountEvent),
+}
+
+/// 请求的处理结果。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "reason", rename_all = "snake_case")]
+pub enum RequestOutcome
+{
+    Accepted,
+    Rejected(String),
+}
+
+/// 截获账户的事件通道，记录后再转发给原来的接收方。
+struct EventTap
+{
+    username: Option<String>,
+    event_rx: UnboundedReceiver<AccountEvent>,
+    downstream_tx: UnboundedSender<AccountEvent>,
+}
+
+/// 追加写入的审计日志，记录每个客户端请求、它的处理结果及其引起的全部账户事件。
+///
+/// 事件循环每处理完一个请求就调用 [`Journal::drain`]，日志中请求之后紧跟着它引起的事件。
+/// 记录每累积 [`SYNC_EVERY`] 条或每隔 [`SYNC_INTERVAL`] 同步一次磁盘，关闭或丢弃日志时同步剩下的记录。
+pub struct Journal
+{
+    path: PathBuf,
+    writer: BufWriter<File>,
+    sequence: u64,
+    unsynced: u64, // 上次同步之后写入的记录数
+    last_sync: Instant,
+    taps: Vec<EventTap>,
+}
+
+impl Journal
+{
+    /// 打开或创建 `path` 处的日志，已有记录保留，序号接着最后一条递增。
+    ///
+    /// 上次写入中途退出留下的不完整的最后一行会被截掉。
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ExchangeError>
+    {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| ExchangeError::Journal(format!("{}: {}", parent.display(), e)))?;
+        }
+        let mut sequence = 0;
+        if path.exists() {
+            let (entries, valid_len) = Self::scan(&path)?;
+            sequence = entries.last().map(|entry| entry.sequence).unwrap_or_default();
+            let file = OpenOptions::new().write(true).open(&path).map_err(|e| ExchangeError::Journal(format!("{}: {}", path.display(), e)))?;
+            let len = file.metadata().map_err(|e| ExchangeError::Journal(format!("{}: {}", path.display(), e)))?.len();
+            if valid_len < len {
+                warn!("Journal drops a torn entry at the end of {}", path.display());
+                file.set_len(valid_len).map_err(|e| ExchangeError::Journal(format!("{}: {}", path.display(), e)))?;
+            }
+        }
+        let file = OpenOptions::new().create(true)
+                                     .append(true)
+                                     .open(&path)
+                                     .map_err(|e| ExchangeError::Journal(format!("{}: {}", path.display(), e)))?;
+        Ok(Self { path,
+                  writer: BufWriter::new(file),
+                  sequence,
+                  unsynced: 0,
+                  last_sync: Instant::now(),
+                  taps: Vec::new() })
+    }
+
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    /// 按顺序读取日志中的全部记录，忽略不完整的最后一行。
+    pub fn read_entries(path: impl AsRef<Path>) -> Result<Vec<JournalEntry>, ExchangeError>
+    {
+        Self::scan(path.as_ref()).map(|(entries, _)| entries)
+    }
+
+    /// 读取全部完整的记录，并返回这些记录占用的字节数。
+    ///
+    /// 只有以换行结尾的行才算写完，最后一行没有换行时不计入；中间的记录损坏仍然报错。
+    fn scan(path: &Path) -> Result<(Vec<JournalEntry>, u64), ExchangeError>
+    {
+        let content = std::fs::read(path).map_err(|e| ExchangeError::Journal(format!("{}: {}", path.display(), e)))?;
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while let Some(index) = content[offset..].iter().position(|byte| *byte == b'\n') {
+            let line = &content[offset..offset + index];
+            if !line.trim_ascii().is_empty() {
+                entries.push(serde_json::from_slice(line).map_err(|e| ExchangeError::Journal(e.to_string()))?);
+            }
+            offset += index + 1;
+        }
+        Ok((entries, offset as u64))
+    }
+
+    /// 截获各账户的事件通道，并记录各账户当前的状态作为重放的起点。
+    pub async fn attach(&mut self, accounts: &[(Option<String>, Arc<Mutex<HourglassAccount>>)])
+    {
+        for (username, account) in accounts {
+            let mut guard = account.lock().await;
+            let (event_tx, event_rx) = mpsc::unbounded_channel();
+            let downstream_tx = std::mem::replace(&mut guard.account_event_tx, event_tx);
+
+            let mut balances = guard.get_balances().await;
+            balances.sort_by(|a, b| a.token.cmp(&b.token));
+            let mut open_orders = guard.account_open_book.read().await.fetch_all();
+            open_orders.sort_by(|a, b| a.state.id.cmp(&b.state.id));
+            let positions = guard.positions.snapshot().await;
+            let exchange_timestamp = guard.exchange_timestamp.load(Ordering::SeqCst);
+            drop(guard);
+
+            self.taps.push(EventTap { username: username.clone(),
+                                      event_rx,
+                                      downstream_tx });
+            self.append(username.clone(), exchange_timestamp, JournalRecord::Genesis { balances,
+                                                                                       open_orders,
+                                                                                       positions: Box::new(positions) });
+        }
+        self.sync();
+    }
+
+    /// 在鉴权与限流之前记录收到的请求并返回它的序号，之后用 [`Journal::record_outcome`] 记录处理结果。
+    pub fn record_request(&mut self, exchange_timestamp: i64, event: &HourglassClientEvent) -> Option<u64>
+    {
+        let mut event = event;
+        while let HourglassClientEvent::Authenticated(_, inner) | HourglassClientEvent::Signed(_, inner) | HourglassClientEvent::Remote(_, inner) = event {
+            event = inner;
+        }
+        let request = NetworkRequest::from_client_event(event)?;
+        self.append(None, exchange_timestamp, JournalRecord::Request(redact(request)));
+        Some(self.sequence)
+    }
+
+    /// 记录序号为 `request` 的请求的处理结果，通过的请求记在处理它的 `username` 账户下。
+    pub fn record_outcome(&mut self, request: u64, username: Option<&str>, exchange_timestamp: i64, outcome: RequestOutcome)
+    {
+        self.append(username.map(str::to_string), exchange_timestamp, JournalRecord::Outcome { request, outcome });
+    }
+
+    /// 记录并转发各账户已经发出的事件，累积的记录足够多或距上次同步足够久时同步到磁盘。
+    pub fn drain(&mut self)
+    {
+        let mut drained = Vec::new();
+        for tap in &mut self.taps {
+            while let Ok(event) = tap.event_rx.try_recv() {
+                // `AccountPositions` 无法在这里序列化，重放时由成交推导仓位
+                if !matches!(event.kind, AccountEventKind::Positions(_)) {
+                    drained.push((tap.username.clone(), event.exchange_timestamp, JournalRecord::Event(event.clone())));
+                }
+                let _ = tap.downstream_tx.send(event);
+            }
+        }
+        for (username, exchange_timestamp, record) in drained {
+            self.append(username, exchange_timestamp, record);
+        }
+        if self.unsynced >= SYNC_EVERY || (self.unsynced > 0 && self.last_sync.elapsed() >= SYNC_INTERVAL) {
+            self.sync();
+        }
+    }
+
+    /// 写入缓冲区，失败时只记录警告，不影响交易所运行。
+    fn append(&mut self, username: Option<String>, exchange_timestamp: i64, record: JournalRecord)
+    {
+        self.sequence += 1;
+        let entry = JournalEntry { sequence: self.sequence,
+                                   exchange_timestamp,
+                                   recorded_at: Utc::now(),
+                                   username,
+                                   record };
+        let result = serde_json::to_string(&entry).map_err(|e| e.to_string())
+                                                  .and_then(|line| writeln!(self.writer, "{}", line).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Failed to append journal entry {}: {}", entry.sequence, e);
+        }
+        self.unsynced += 1;
+    }
+
+    /// 把缓冲区写入文件并同步到磁盘。
+    pub fn sync(&mut self)
+    {
+        if let Err(e) = self.writer.flush().and_then(|_| self.writer.get_ref().sync_data()) {
+            warn!("Failed to sync journal {}: {}", self.path.display(), e);
+        }
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+    }
+}
+
+impl Drop for Journal
+{
+    fn drop(&mut self)
+    {
+        if self.unsynced > 0 {
+            self.sync();
+        }
+    }
+}
+
+fn redact(request: NetworkRequest) -> NetworkRequest
+{
+    match request {
+        | NetworkRequest::Register(form) => NetworkRequest::Register(RegisterForm { password: REDACTED.to_string(), ..form }),
+        | NetworkRequest::Login(form) => NetworkRequest::Login(LoginForm { password: REDACTED.to_string(), ..form }),
+        | NetworkRequest::Logout { .. } => NetworkRequest::Logout { session_token: REDACTED.to_string() },
+        | request => request,
+    }
+}
+
+/// 由审计日志重建的账户状态。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayedAccount
+{
+    pub balances: HashMap<Token, Balance>,
+    pub open_orders: HashMap<OrderId, Order<Open>>,
+    pub positions: AccountPositionsSnapshot,   // 开始记录时的仓位
+    pub position_updates: Vec<PositionUpdate>, // 之后改变仓位的请求与成交，由 [`ReplayedAccount::derive_positions`] 重放
+}
+
+/// 会改变仓位的记录。
+#[derive(Debug, Clone, PartialEq)]
+pub enum PositionUpdate
+{
+    Configure(ConfigurationRequest),
+    Trade(ClientTrade),
+}
+
+impl ReplayedAccount
+{
+    /// 余额事件携带的是更新后的余额，直接覆盖；充值不发出事件，按请求中的数额累加。
+    ///
+    /// 这里的 [`JournalRecord::Request`] 指已经被账户接受的请求，请求与处理结果的配对由 [`replay`] 完成。
+    pub fn apply(&mut self, record: &JournalRecord)
+    {
+        match record {
+            | JournalRecord::Genesis { balances, open_orders, positions } => {
+                self.balances = balances.iter().map(|balance| (balance.token.clone(), balance.balance)).collect();
+                self.open_orders = open_orders.iter().map(|order| (order.state.id.clone(), order.clone())).collect();
+                self.positions = positions.as_ref().clone();
+                self.position_updates.clear();
+            }
+            | JournalRecord::Request(NetworkRequest::ConfigureInstruments(configs)) => {
+                self.position_updates.extend(configs.iter().cloned().map(PositionUpdate::Configure));
+            }
+            | JournalRecord::Request(NetworkRequest::DepositTokens(deposits)) => {
+                for (token, amount) in deposits {
+                    let balance = self.balances.entry(token.clone()).or_insert_with(|| Balance::new(0.0, 0.0));
+                    balance.total += amount;
+                    balance.available += amount;
+                }
+            }
+            | JournalRecord::Request(_) | JournalRecord::Outcome { .. } => {}
+            | JournalRecord::Event(event) => match &event.kind {
+                | AccountEventKind::Balance(balance) => {
+                    self.balances.insert(balance.token.clone(), balance.balance);
+                }
+                | AccountEventKind::Balances(balances) => {
+                    for balance in balances {
+                        self.balances.insert(balance.token.clone(), balance.balance);
+                    }
+                }
+                | AccountEventKind::OrdersOpen(orders) => {
+                    for order in orders {
+                        self.open_orders.insert(order.state.id.clone(), order.clone());
+                    }
+                }
+                | AccountEventKind::OrdersCancelled(orders) => {
+                    for order in orders {
+                        self.open_orders.remove(&order.state.id);
+                    }
+                }
+                // 与挂单簿一致：累加成交数量，完全成交后移除
+                | AccountEventKind::Trade(trade) => {
+                    self.position_updates.push(PositionUpdate::Trade(trade.clone()));
+                    if let Some(order_id) = &trade.order_id {
+                        if let Some(order) = self.open_orders.get_mut(order_id) {
+                            order.state.filled_quantity += trade.size;
+                            if order.state.remaining_quantity() <= 0.0 {
+                                self.open_orders.remove(order_id);
+                            }
+                        }
+                    }
+                }
+                | _ => {}
+            },
+        }
+    }
+
+    /// 从开始记录时的仓位出发，用账户的仓位处理逻辑依次重放仓位配置与成交，得到当前应有的仓位。
+    ///
+    /// 挂单的成交按成交更新仓位；没有订单号的成交来自强平，移除与它方向相反的仓位。
+    pub async fn derive_positions(&self, config: &AccountConfig) -> Result<AccountPositionsSnapshot, ExchangeError>
+    {
+        let config = AccountConfig { vault: VaultConfig::InMemory,
+                                     ..config.clone() };
+        let orders = AccountOrders::new(0, Vec::new(), AccountLatency::new(FluctuationMode::Sine, 0, 0)).await;
+        let mut account = HourglassAccount::initiate().config(config)
+                                                      .account_event_tx(mpsc::unbounded_channel().0)
+                                                      .orders(orders)
+                                                      .balances(Default::default())
+                                                      .positions(AccountPositions::init())
+                                                      .closed_positions(AccountExitedPositions::init())
+                                                      .build()
+                                                      .map_err(ExchangeError::Journal)?;
+        account.positions.restore(self.positions.clone()).await;
+        for update in &self.position_updates {
+            match update {
+                // 被拒绝的配置在实际账户中同样被拒绝，不影响仓位
+                | PositionUpdate::Configure(config) => {
+                    let _ = account.preconfigure_position(config.clone()).await;
+                }
+                | PositionUpdate::Trade(trade) if trade.instrument.kind != InstrumentKind::Perpetual => {}
+                | PositionUpdate::Trade(trade) if trade.order_id.is_some() => {
+                    if let Err(e) = account.update_position_from_client_trade(trade.clone()).await {
+                        warn!("Failed to replay trade {:?}: {:?}", trade.trade_id, e);
+                    }
+                }
+                | PositionUpdate::Trade(trade) => {
+                    let side = trade.side.toggle();
+                    let position = match side {
+                        | Side::Buy => account.get_position_long(&trade.instrument).await?,
+                        | Side::Sell => account.get_position_short(&trade.instrument).await?,
+                    };
+                    if let Some(mut position) = position {
+                        account.liquidate_position_by_trade(&mut position, side).await?;
+                    }
+                }
+            }
+        }
+        Ok(account.positions.snapshot().await)
+    }
+}
+
+/// 按顺序重放 `path` 处的审计日志，返回每个账户重建后的状态。
+pub fn replay(path: impl AsRef<Path>) -> Result<HashMap<Option<String>, ReplayedAccount>, ExchangeError>
+{
+    let mut accounts: HashMap<Option<String>, ReplayedAccount> = HashMap::new();
+    // 请求在鉴权之前记录，等到处理结果表明请求被接受后才计入处理它的账户
+    let mut pending = HashMap::new();
+    for entry in Journal::read_entries(path)? {
+        match entry.record {
+            | JournalRecord::Request(request) => {
+                pending.insert(entry.sequence, request);
+            }
+            | JournalRecord::Outcome { request, outcome } => {
+                if let (Some(request), RequestOutcome::Accepted) = (pending.remove(&request), outcome) {
+                    accounts.entry(entry.username).or_default().apply(&JournalRecord::Request(request));
+                }
+            }
+            | record => accounts.entry(entry.username).or_default().apply(&record),
+        }
+    }
+    Ok(accounts)
+}
+
+/// 重放结果与实际账户状态的差异，出现差异说明账户修改了状态却没有发出对应的事件。
+#[derive(Debug, Clone, PartialEq)]
+pub enum JournalDivergence
+{
+    MissingAccount
+    {
+        username: Option<String>
+    },
+    Balance
+    {
+        username: Option<String>,
+        token: Token,
+        journal: Option<Balance>,
+        live: Option<Balance>,
+    },
+    OpenOrder
+    {
+        username: Option<String>,
+        order_id: OrderId,
+        journal: Option<Order<Open>>,
+        live: Option<Order<Open>>,
+    },
+    Positions
+    {
+        username: Option<String>,
+        journal: Box<AccountPositionsSnapshot>,
+        live: Box<AccountPositionsSnapshot>,
+    },
+}
+
+/// 重放审计日志并与各账户的实际余额、挂单和仓位比较，每个差异都按错误记录日志。
+pub async fn verify(path: impl AsRef<Path>, accounts: &[(Option<String>, Arc<Mutex<HourglassAccount>>)]) -> Result<Vec<JournalDivergence>, ExchangeError>
+{
+    let mut replayed = replay(path)?;
+    let mut divergences = Vec::new();
+    for (username, account) in accounts {
+        let Some(expected) = replayed.remove(username)
+        else {
+            divergences.push(JournalDivergence::MissingAccount { username: username.clone() });
+            continue;
+        };
+        let account = account.lock().await;
+        let expected_positions = expected.derive_positions(&account.config).await?;
+        let balances: HashMap<Token, Balance> = account.get_balances().await.into_iter().map(|balance| (balance.token, balance.balance)).collect();
+        let open_orders: HashMap<OrderId, Order<Open>> = account.account_open_book.read().await.fetch_all().into_iter().map(|order| (order.state.id.clone(), order)).collect();
+        let positions = account.positions.snapshot().await;
+
+        // 余额的时间戳不参与比较
+        let tokens: BTreeSet<&Token> = expected.balances.keys().chain(balances.keys()).collect();
+        for token in tokens {
+            let (journal, live) = (expected.balances.get(token).copied(), balances.get(token).copied());
+            if journal.map(|balance| (balance.total, balance.available)) != live.map(|balance| (balance.total, balance.available)) {
+                divergences.push(JournalDivergence::Balance { username: username.clone(),
+                                                              token: token.clone(),
+                                                              journal,
+                                                              live });
+            }
+        }
+        let order_ids: BTreeSet<&OrderId> = expected.open_orders.keys().chain(open_orders.keys()).collect();
+        for order_id in order_ids {
+            let (journal, live) = (expected.open_orders.get(order_id), open_orders.get(order_id));
+            if journal != live {
+                divergences.push(JournalDivergence::OpenOrder { username: username.clone(),
+                                                                order_id: order_id.clone(),
+                                                                journal: journal.cloned(),
+                                                                live: live.cloned() });
+            }
+        }
+        if expected_positions != positions {
+            divergences.push(JournalDivergence::Positions { username: username.clone(),
+                                                            journal: Box::new(expected_positions),
+                                                            live: Box::new(positions) });
+        }
+    }
+    for divergence in &divergences {
+        error!("Journal replay diverged from live account state, this is a bug: {:?}", divergence);
+    }
+    Ok(divergences)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::{
+            instrument::{kind::InstrumentKind, Instrument},
+            order::states::{cancelled::Cancelled, request_open::RequestOpen},
+        },
+        hourglass::account::account_handlers::trade_handler::TradeHandler,
+        test_utils::{create_test_account, create_test_order_open, create_test_perpetual_position},
+        Exchange,
+    };
+
+    fn event(kind: AccountEventKind) -> JournalRecord
+    {
+        JournalRecord::Event(AccountEvent { exchange_timestamp: 0,
+                                            exchange: Exchange::Hourglass,
+                                            kind })
+    }
+
+    #[test]
+    fn replayed_account_should_fold_requests_and_events()
+    {
+        let usdt = Token::from("USDT");
+        let order = create_test_order_open(Side::Buy, 16_000.0, 1.0);
+        let mut account = ReplayedAccount::default();
+        account.apply(&JournalRecord::Genesis { balances: vec![TokenBalance::new(usdt.clone(), Balance::new(100.0, 100.0))],
+                                                open_orders: vec![],
+                                                positions: Box::default() });
+        account.apply(&JournalRecord::Request(NetworkRequest::DepositTokens(vec![(usdt.clone(), 50.0)])));
+        account.apply(&event(AccountEventKind::OrdersOpen(vec![order.clone()])));
+        account.apply(&event(AccountEventKind::Balance(TokenBalance::new(usdt.clone(), Balance::new(150.0, 120.0)))));
+
+        // 部分成交后挂单仍在，完全成交后移除
+        let trade = ClientTrade { exchange: Exchange::Hourglass,
+                                  timestamp: 0,
+                                  trade_id: 1.into(),
+                                  order_id: Some(order.state.id.clone()),
+                                  cid: order.cid.clone(),
+                                  instrument: order.instrument.clone(),
+                                  side: order.side,
+                                  price: 16_000.0,
+                                  size: 0.4,
+                                  fees: 0.0 };
+        account.apply(&event(AccountEventKind::Trade(trade.clone())));
+        assert_eq!(account.open_orders.get(&order.state.id).unwrap().state.filled_quantity, 0.4);
+        account.apply(&event(AccountEventKind::Trade(ClientTrade { size: 0.6, ..trade })));
+        assert!(account.open_orders.is_empty());
+
+        let balance = account.balances.get(&usdt).unwrap();
+        assert_eq!((balance.total, balance.available), (150.0, 120.0));
+
+        // 撤单同样移除挂单
+        account.apply(&event(AccountEventKind::OrdersOpen(vec![order.clone()])));
+        account.apply(&event(AccountEventKind::OrdersCancelled(vec![Order::<Cancelled>::from(order)])));
+        assert!(account.open_orders.is_empty());
+    }
+
+    #[test]
+    fn journal_should_redact_credentials_and_continue_sequence()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let (response_tx, _response_rx) = tokio::sync::oneshot::channel();
+        let login = HourglassClientEvent::Login(crate::network::login::LoginRequest { username: "alice".to_string(),
+                                                                                      password: "secret".to_string(),
+                                                                                      response_tx });
+        let mut journal = Journal::open(&path).unwrap();
+        journal.record_request(1, &login);
+        journal.record_request(2, &HourglassClientEvent::LetItRoll);
+        drop(journal);
+
+        // 重新打开后序号接着递增，请求在拆开会话之前记录，处理结果记在账户下
+        let mut journal = Journal::open(&path).unwrap();
+        let open_orders = HourglassClientEvent::OpenOrders((Vec::<Order<RequestOpen>>::new(), tokio::sync::oneshot::channel().0));
+        let request = journal.record_request(3, &HourglassClientEvent::Authenticated("session".to_string(), Box::new(open_orders))).unwrap();
+        journal.record_outcome(request, Some("alice"), 3, RequestOutcome::Accepted);
+        drop(journal);
+
+        let entries = Journal::read_entries(&path).unwrap();
+        assert_eq!(entries.iter().map(|entry| entry.sequence).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(entries[0].record,
+                   JournalRecord::Request(NetworkRequest::Login(LoginForm { username: "alice".to_string(),
+                                                                            password: REDACTED.to_string() })));
+        assert_eq!(entries[2].record, JournalRecord::Request(NetworkRequest::OpenOrders(vec![])));
+        assert_eq!(entries[3].record, JournalRecord::Outcome { request: 3,
+                                                               outcome: RequestOutcome::Accepted });
+        assert_eq!(entries[3].username.as_deref(), Some("alice"));
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("secret"));
+    }
+
+    #[test]
+    fn journal_should_drop_a_torn_final_entry()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let mut journal = Journal::open(&path).unwrap();
+        journal.record_request(1, &HourglassClientEvent::LetItRoll);
+        drop(journal);
+
+        // 模拟写入中途退出：最后一行只写了一半
+        let complete = std::fs::read(&path).unwrap();
+        let mut torn = complete.clone();
+        torn.extend_from_slice(br#"{"sequence":2,"exchange_timestamp""#);
+        std::fs::write(&path, torn).unwrap();
+        assert_eq!(Journal::read_entries(&path).unwrap().len(), 1);
+
+        let mut journal = Journal::open(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), complete);
+        journal.record_request(2, &HourglassClientEvent::LetItRoll);
+        drop(journal);
+        assert_eq!(Journal::read_entries(&path).unwrap().iter().map(|entry| entry.sequence).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn journal_should_derive_positions_from_trades()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let account = Arc::new(Mutex::new(create_test_account().await));
+        let accounts = vec![(None, Arc::clone(&account))];
+        let instrument = Instrument::new("ETH", "USDT", InstrumentKind::Perpetual);
+        // 开始记录前的仓位配置随起点一起记录
+        let config = ConfigurationRequest { exchange: Exchange::Hourglass,
+                                            instrument: instrument.clone(),
+                                            timestamp: 0,
+                                            cid: None,
+                                            leverage_rate: None,
+                                            side: Side::Buy,
+                                            position_margin_mode: None,
+                                            position_direction_mode: None };
+        account.lock().await.preconfigure_position(config).await.unwrap();
+        let mut journal = Journal::open(&path).unwrap();
+        journal.attach(&accounts).await;
+
+        let order = create_test_order_open(Side::Buy, 16_000.0, 1.0);
+        let trade = ClientTrade { exchange: Exchange::Hourglass,
+                                  timestamp: 0,
+                                  trade_id: 1.into(),
+                                  order_id: Some(order.state.id.clone()),
+                                  cid: order.cid.clone(),
+                                  instrument: instrument.clone(),
+                                  side: Side::Buy,
+                                  price: 16_000.0,
+                                  size: 0.1,
+                                  fees: 0.0 };
+        account.lock().await.process_trade(trade).await.unwrap();
+        journal.drain();
+        // 记录不多时不立即同步，同步后才出现在文件中
+        assert_eq!(Journal::read_entries(&path).unwrap().len(), 1);
+        journal.sync();
+        assert!(Journal::read_entries(&path).unwrap().len() > 1);
+
+        let live = account.lock().await.positions.snapshot().await;
+        assert_ne!(live, AccountPositionsSnapshot::default());
+        let config = account.lock().await.config.clone();
+        assert_eq!(replay(&path).unwrap()[&None].derive_positions(&config).await.unwrap(), live);
+        assert!(verify(&path, &accounts).await.unwrap().is_empty());
+
+        // 绕过成交直接修改仓位，核对时应当发现差异
+        account.lock()
+               .await
+               .positions
+               .perpetual_pos_short
+               .write()
+               .await
+               .insert(instrument.clone(), create_test_perpetual_position(instrument));
+        assert!(matches!(verify(&path, &accounts).await.unwrap().as_slice(), [JournalDivergence::Positions { username: None, .. }]));
+    }
+}
@@ -1,39 +1,73 @@
 use crate::{
-    common::datafeed::market_event::MarketEvent,
+    common::{datafeed::market_event::MarketEvent, instrument::Instrument, order::OrderRole},
     error::ExchangeError,
     hourglass::{
         account::account_handlers::{balance_handler::BalanceHandler, position_handler::PositionHandler, trade_handler::TradeHandler},
-        clickhouse_api::{datatype::clickhouse_trade_data::MarketTrade, queries_operations::ClickHouseClient},
-        hourglass_client_local_mode::HourglassClientEvent,
+        checkpoint::{CheckpointPolicy, DataCursor, ExchangeCheckpoint, CHECKPOINT_VERSION},
+        clickhouse_api::{
+            clickhouse_config::ClickHouseConfig,
+            data_audit::CheckedTradeDays,
+            datatype::{bar::Bar, clickhouse_trade_data::MarketTrade},
+            queries_operations::ClickHouseClient,
+        },
+        hourglass_client_local_mode::{HourglassClientEvent, RequestOpenOrders},
+        hourglass_orderbook::{AgentFill, ArrivalSequence, BookOrder, HourglassOrderBook},
+        journal::{Journal, RequestOutcome},
+        shutdown::{AccountSnapshot, IdlePolicy, OpenOrdersOnShutdown, ShutdownHandle, ShutdownReason, ShutdownReport},
+        simulation::SimRng,
+    },
+    hourglass_log::{info, warn},
+    network::{
+        api_key::ApiKeyStore,
+        is_port_in_use,
+        login::{Authentication, Authenticator, UserStore, DEFAULT_SESSION_TTL},
+        rate_limit::{RateLimitConfig, RateLimitKey, RateLimiter},
+        rest_api,
+        ws_stream::{self, StreamHub},
     },
-    hourglass_log::warn,
-    network::{event::NetworkEvent, is_port_in_use},
 };
 use account::HourglassAccount;
 use clickhouse::query::RowCursor;
 use mpsc::UnboundedReceiver;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
 use tokio::{
-    sync::{mpsc, mpsc::UnboundedSender, Mutex},
+    sync::{mpsc, mpsc::UnboundedSender, oneshot, Mutex},
     time::{self, Duration},
 };
-use uuid::Uuid;
 use warp::Filter;
 
 pub mod account;
+pub mod checkpoint;
 pub mod clickhouse_api;
 pub mod config_request;
 pub mod hourglass_client_local_mode;
+pub mod hourglass_client_remote_mode;
 pub mod hourglass_orderbook;
+pub mod journal;
 pub mod open_orders_book;
 pub mod risk_reserve;
+pub mod shutdown;
+pub mod simulation;
 pub mod utils;
 pub mod ws_trade;
 
+/// 每个 WebSocket 客户端最多积压的推送消息数。
+const STREAM_CAPACITY: usize = 1024;
+/// WebSocket 心跳间隔。
+const STREAM_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
 pub enum DataSource
 {
     RealTime(UnboundedReceiver<MarketEvent<MarketTrade>>),
     Backtest(RowCursor<MarketTrade>),
+    /// 以K线为单位回测，每根K线展开为若干合成成交后一次性撮合，速度远快于逐笔回测。
+    Bars(RowCursor<Bar>),
+    /// 逐日回放合并成交表，回放的同时审计数据，按 [`DataQualityPolicy`](clickhouse_api::data_audit::DataQualityPolicy) 告警或拒绝回测。
+    CheckedDays(CheckedTradeDays),
 }
 
 pub struct HourglassExchange
@@ -41,10 +75,25 @@ pub struct HourglassExchange
 {
     pub client_event_rx: UnboundedReceiver<HourglassClientEvent>,
     pub market_event_tx: UnboundedSender<MarketTrade>,
-    pub account: Arc<Mutex<HourglassAccount>>,
+    pub bar_event_tx: Option<UnboundedSender<MarketEvent<Bar>>>,
+    pub account: Arc<Mutex<HourglassAccount>>,                   // 默认账户，处理未登录的请求
+    pub tenants: BTreeMap<String, Arc<Mutex<HourglassAccount>>>, // 用户名到各自账户的映射，与默认账户共享同一份行情，按用户名排序保证遍历顺序固定
     pub data_source: DataSource,
     pub clickhouse_client: ClickHouseClient,
-    pub active_sessions: Mutex<HashMap<String, Uuid>>, // 存储 session_token 和 username 的映射
+    pub authenticator: Authenticator,
+    pub agent_matching: bool,               // 为 true 时，不同账户的挂单之间也会互相撮合
+    arrivals: ArrivalSequence,              // 账户间撮合时各挂单的到达顺序
+    pub api_keys: Option<Arc<ApiKeyStore>>, // 在线模式下要求 REST 请求带上 API key 签名
+    pub rate_limiter: Option<RateLimiter>,  // 按账户限制请求频率
+    pub idle_policy: Option<IdlePolicy>,    // 未设置时本地模式空闲后退出，在线模式一直运行
+    pub open_orders_on_shutdown: OpenOrdersOnShutdown,
+    pub shutdown: ShutdownHandle,
+    pub checkpoint_policy: Option<CheckpointPolicy>, // 定期写入检查点，默认不写入
+    pub processed_count: usize,                      // 已处理的行情数据条目数，从检查点恢复时接着计数
+    skip_entries: usize,                             // 从检查点恢复后，数据源中需要先跳过的条目数
+    restored: bool,                                  // 已从检查点恢复，账户的随机数与时钟沿用检查点中的状态
+    journal: Option<Journal>,                        // 审计日志，默认不记录
+    pub seed: Option<u64>,                           // 设定后各账户的随机数与时钟都可重复，默认使用系统熵与系统时间
 }
 
 impl HourglassExchange
@@ -59,96 +108,436 @@ impl HourglassExchange
         Arc::clone(&self.account)
     }
 
-    pub async fn start(mut self)
+    /// 按 [`ExchangeBuilder::idle_policy`] 运行事件循环，未设置时空闲 [`DEFAULT_IDLE_TIMEOUT`] 后退出。
+    ///
+    /// 与 [`run_online_at`](Self::run_online_at) 一样，收到 SIGINT 或 SIGTERM 后处理完队列中的请求再退出。
+    pub async fn start(self) -> ShutdownReport
+    {
+        let signal_listener = self.shutdown.shutdown_on_signal();
+        let idle_policy = self.idle_policy.unwrap_or_default();
+        let report = self.run_event_loop(idle_policy).await;
+        signal_listener.abort();
+        report
+    }
+
+    /// 用于从其他任务关闭交易所。
+    pub fn shutdown_handle(&self) -> ShutdownHandle
     {
-        let timeout = 1;
-        let mut processed_count = 0; // 记录已处理的数据条目数
+        self.shutdown.clone()
+    }
+
+    /// 事件循环，退出后按 [`OpenOrdersOnShutdown`] 处理挂单并返回运行报告。
+    pub(crate) async fn run_event_loop(mut self, idle_policy: IdlePolicy) -> ShutdownReport
+    {
+        let idle_timeout = idle_policy.timeout();
+        let shutdown = self.shutdown.clone().wait();
+        tokio::pin!(shutdown);
+        let accounts = self.all_accounts();
+        // 恢复后的账户已带有检查点中的随机数状态，重新派生会打乱后续的事件流
+        if let (Some(seed), false) = (self.seed, self.restored) {
+            Self::seed_accounts(&accounts, seed).await;
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.attach(&accounts).await;
+        }
 
-        loop {
+        let reason = loop {
             tokio::select! {
-                    // 监听客户端信号
-                     Some(event) = self.client_event_rx.recv() => {
-                match event {
-                    HourglassClientEvent::LetItRoll => {
-                        if let Some(row) = self.process_next_data().await {
-                            let mut account = self.account.lock().await;
-                            let _ = account.handle_trade_data(&row).await;
-                            processed_count += 1; // 每处理一个条目，计数器加1
-                        } else {
-                            // 如果没有更多数据
-                            if processed_count > 0 {
-                                warn!("No more data available. Processed {} entries", processed_count);
-                            } else {
-                                warn!("No data found.");
-                            }
-                            break; // 优雅退出循环
-                        }
-                    },
-                            // 其他客户端事件处理
-                            HourglassClientEvent::FetchOrdersOpen(response_tx) => {
-                                self.account.lock().await.fetch_orders_open_and_respond(response_tx).await;
-                            },
-                            HourglassClientEvent::FetchTokenBalance(token, response_tx) => {
-                                self.account.lock().await.fetch_token_balance_and_respond(&token, response_tx).await;
-                            },
-                            HourglassClientEvent::FetchTokenBalances(response_tx) => {
-                                self.account.lock().await.fetch_token_balances_and_respond(response_tx).await;
-                            },
-                            HourglassClientEvent::OpenOrders((open_requests, response_tx)) => {
-                                self.account.lock().await.open_orders(open_requests, response_tx).await.expect("Failed to open.");
-                            },
-                            HourglassClientEvent::CancelOrders((cancel_requests, response_tx)) => {
-                                self.account.lock().await.cancel_orders(cancel_requests, response_tx).await;
-                            },
-                            HourglassClientEvent::CancelOrdersAll(response_tx) => {
-                                self.account.lock().await.cancel_orders_all(response_tx).await;
-                            },
-                            HourglassClientEvent::FetchAllPositions(response_tx) => {
-                                self.account.lock().await.fetch_positions_and_respond(response_tx).await;
-                            },
-                            HourglassClientEvent::FetchLongPosition(instrument, response_tx) => {
-                                self.account.lock().await.fetch_long_position_and_respond(&instrument, response_tx).await;
-                            },
-                            HourglassClientEvent::FetchShortPosition(instrument, response_tx) => {
-                                self.account.lock().await.fetch_short_position_and_respond(&instrument, response_tx).await;
-
-                            },
-                            HourglassClientEvent::DepositTokens(deposit_request) => {
-                                self.account.lock().await.deposit_multiple_coins_and_respond(deposit_request.0, deposit_request.1).await;
-                            },
-                            HourglassClientEvent::ConfigureInstruments(position_configs, response_tx) => {
-                                let _ = self.account.lock().await.preconfigure_positions(position_configs, response_tx).await;
-                            }
+                // 监听客户端信号
+                Some(event) = self.client_event_rx.recv() => {
+                    let has_more_data = self.handle_client_event(event).await;
+                    self.drain_journal();
+                    if !has_more_data {
+                        break ShutdownReason::DataExhausted;
+                    }
+                }
+                _ = &mut shutdown => break ShutdownReason::Requested,
+                // 加入超时机制，防止一直挂起
+                _ = time::sleep(idle_timeout.unwrap_or_default()), if idle_timeout.is_some() => break ShutdownReason::Idle,
+                // 所有客户端都已断开
+                else => break ShutdownReason::ClientsDisconnected,
+            }
+        };
 
-                        HourglassClientEvent::Login(_) => {
-                            todo!()
-                        }
+        // 不再接收新的事件，已经进入队列的请求仍然处理完并给出响应
+        self.client_event_rx.close();
+        let mut drained_events = 0;
+        while let Ok(event) = self.client_event_rx.try_recv() {
+            self.handle_client_event(event).await;
+            self.drain_journal();
+            drained_events += 1;
+        }
 
-                        HourglassClientEvent::Register(_) => {
-                            todo!()
-                        }
+        // 在按策略撤销挂单之前写入最后一个检查点，恢复后的状态与关闭前一致
+        if let Some(policy) = &self.checkpoint_policy {
+            let path = policy.path.clone();
+            self.save_checkpoint(&path).await;
+        }
+
+        let accounts = self.all_accounts();
+        let report = ShutdownReport { reason,
+                                      processed_count: self.processed_count,
+                                      drained_events,
+                                      accounts: Self::snapshot_accounts(&accounts, self.open_orders_on_shutdown).await };
+        info!("{}", report);
+
+        // 关闭时撤单的事件同样写入审计日志，之后用日志重放的状态核对各账户
+        if let Some(journal) = &mut self.journal {
+            journal.drain();
+            journal.sync();
+            if let Err(e) = journal::verify(journal.path(), &accounts).await {
+                warn!("Failed to verify journal: {}", e);
+            }
+        }
+        report
+    }
+
+    /// 把账户在处理请求时发出的事件写入审计日志。
+    fn drain_journal(&mut self)
+    {
+        if let Some(journal) = &mut self.journal {
+            journal.drain();
+        }
+    }
 
-                        HourglassClientEvent::Logout(_) => {
-                todo!()
+    /// 记录 [`Journal::record_request`] 已经写入的请求的处理结果。
+    async fn record_outcome(&mut self, request: Option<u64>, username: Option<&str>, outcome: RequestOutcome)
+    {
+        let Some(request) = request
+        else {
+            return;
+        };
+        let exchange_timestamp = match username.and_then(|username| self.tenants.get(username)) {
+            | Some(account) => account.lock().await.exchange_timestamp.load(Ordering::SeqCst),
+            | None => self.account.lock().await.exchange_timestamp.load(Ordering::SeqCst),
+        };
+        if let Some(journal) = &mut self.journal {
+            journal.record_outcome(request, username, exchange_timestamp, outcome);
+        }
+    }
+
+    /// 处理一个客户端事件，回测数据已经全部处理完时返回 `false`。
+    async fn handle_client_event(&mut self, event: HourglassClientEvent) -> bool
+    {
+        // 请求在鉴权与限流之前写入审计日志，被拒绝的请求同样留下记录
+        let journaled = match &self.journal {
+            | Some(_) => {
+                let exchange_timestamp = self.account.lock().await.exchange_timestamp.load(Ordering::SeqCst);
+                self.journal.as_mut().and_then(|journal| journal.record_request(exchange_timestamp, &event))
+            }
+            | None => None,
+        };
+        let (peer, event) = event.split_peer();
+        let api_key = match &event {
+            | HourglassClientEvent::Signed(caller, _) => Some(caller.api_key.clone()),
+            | _ => None,
+        };
+        let (username, event) = match self.authenticator.authenticate(event).await {
+            | Ok(authorised) => authorised,
+            | Err((event, e)) => {
+                self.record_outcome(journaled, None, RequestOutcome::Rejected(e.to_string())).await;
+                event.reject(e);
+                return true;
+            }
+        };
+        if let Some(rate_limiter) = &self.rate_limiter {
+            // 额度按交易所时钟补充，回测中与行情时间一致
+            let now = self.account.lock().await.clock.now_millis();
+            if let Err(e) = rate_limiter.check(RateLimitKey::new(api_key, username.as_deref(), peer), &event, now) {
+                self.record_outcome(journaled, username.as_deref(), RequestOutcome::Rejected(e.to_string())).await;
+                event.reject(e);
+                return true;
+            }
+        }
+        let account = match self.route_account(username.as_deref()) {
+            | Ok(account) => account,
+            | Err(e) => {
+                self.record_outcome(journaled, username.as_deref(), RequestOutcome::Rejected(e.to_string())).await;
+                event.reject(e);
+                return true;
+            }
+        };
+        // 未配置租户时所有请求都记在默认账户下
+        let owner = username.as_deref().filter(|_| !self.tenants.is_empty());
+        self.record_outcome(journaled, owner, RequestOutcome::Accepted).await;
+        match event {
+            // 实时数据源没有可以推进的回放数据，拒绝请求但继续服务
+            | HourglassClientEvent::LetItRoll if matches!(self.data_source, DataSource::RealTime(_)) => {
+                event.reject(ExchangeError::NotImplemented("LetItRoll requires a replayed data source".to_string()));
+            }
+            | HourglassClientEvent::LetItRoll => {
+                if let Some(rows) = self.process_next_data().await {
+                    // 所有账户按同样的顺序处理同一批成交
+                    for account in std::iter::once(&self.account).chain(self.tenants.values()) {
+                        let mut account = account.lock().await;
+                        for row in &rows {
+                            let _ = account.handle_trade_data(row).await;
+                        }
+                    }
+                    self.processed_count += 1; // 每处理一个条目，计数器加1
+                    if let Some(policy) = &self.checkpoint_policy {
+                        if policy.every > 0 && self.processed_count.is_multiple_of(policy.every) {
+                            let path = policy.path.clone();
+                            self.save_checkpoint(&path).await;
                         }
                     }
                 }
-                    // 加入超时机制，防止一直挂起
-            _ = time::sleep(Duration::from_secs(timeout)) => {
-                if processed_count > 0 {
-                    println!("No more data available.");
-                } else {
-                    println!("No data found.");
+                else {
+                    // 如果没有更多数据
+                    if self.processed_count > 0 {
+                        warn!("No more data available. Processed {} entries", self.processed_count);
+                    }
+                    else {
+                        warn!("No data found.");
+                    }
+                    return false;
+                }
+            }
+            // 其他客户端事件处理
+            | HourglassClientEvent::FetchOrdersOpen(response_tx) => {
+                account.lock().await.fetch_orders_open_and_respond(response_tx).await;
+            }
+            | HourglassClientEvent::FetchTokenBalance(token, response_tx) => {
+                account.lock().await.fetch_token_balance_and_respond(&token, response_tx).await;
+            }
+            | HourglassClientEvent::FetchTokenBalances(response_tx) => {
+                account.lock().await.fetch_token_balances_and_respond(response_tx).await;
+            }
+            | HourglassClientEvent::OpenOrders((open_requests, response_tx)) => {
+                let mut instruments: Vec<Instrument> = open_requests.iter().map(|request| request.instrument.clone()).collect();
+                instruments.sort();
+                instruments.dedup();
+                if self.agent_matching {
+                    Self::open_agent_orders(&account, (open_requests, response_tx)).await;
+                    self.match_agent_orders(&instruments).await;
+                }
+                else {
+                    account.lock().await.open_orders_and_respond(open_requests, response_tx).await;
+                }
+            }
+            | HourglassClientEvent::CancelOrders((cancel_requests, response_tx)) => {
+                account.lock().await.cancel_orders(cancel_requests, response_tx).await;
+            }
+            | HourglassClientEvent::CancelOrdersAll(response_tx) => {
+                account.lock().await.cancel_orders_all(response_tx).await;
+            }
+            | HourglassClientEvent::FetchAllPositions(response_tx) => {
+                account.lock().await.fetch_positions_and_respond(response_tx).await;
+            }
+            | HourglassClientEvent::FetchPnLAttribution(response_tx) => {
+                let _ = response_tx.send(Ok(account.lock().await.pnl_attribution().await));
+            }
+            | HourglassClientEvent::FetchLongPosition(instrument, response_tx) => {
+                account.lock().await.fetch_long_position_and_respond(&instrument, response_tx).await;
+            }
+            | HourglassClientEvent::FetchShortPosition(instrument, response_tx) => {
+                account.lock().await.fetch_short_position_and_respond(&instrument, response_tx).await;
+            }
+            | HourglassClientEvent::DepositTokens(deposit_request) => {
+                account.lock().await.deposit_multiple_coins_and_respond(deposit_request.0, deposit_request.1).await;
+            }
+            | HourglassClientEvent::ConfigureInstruments(position_configs, response_tx) => {
+                let _ = account.lock().await.preconfigure_positions(position_configs, response_tx).await;
+            }
+            // 密码的加密与校验较慢，在事件循环之外完成，避免阻塞撮合
+            | HourglassClientEvent::Login(request) => {
+                let authenticator = self.authenticator.clone();
+                tokio::spawn(async move {
+                    let _ = request.response_tx.send(authenticator.handle_login(request.username, request.password).await);
+                });
+            }
+            | HourglassClientEvent::Register(request) => {
+                let authenticator = self.authenticator.clone();
+                tokio::spawn(async move {
+                    let _ = request.response_tx.send(authenticator.handle_register(request.username, request.email, request.password).await);
+                });
+            }
+            | HourglassClientEvent::Logout(request) => {
+                let _ = request.response_tx.send(self.authenticator.handle_logout(request.session_token).await);
+            }
+            // `authorise` 已经拆开了外层的会话，嵌套的会话视为无效
+            | HourglassClientEvent::Authenticated(_, event) | HourglassClientEvent::Signed(_, event) | HourglassClientEvent::Remote(_, event) => {
+                event.reject(ExchangeError::InvalidSession);
+            }
+        }
+        true
+    }
+
+    /// 当前全部账户与回测数据读取位置的检查点。
+    pub async fn checkpoint(&mut self) -> ExchangeCheckpoint
+    {
+        Self::build_checkpoint(&self.all_accounts(), self.processed_count).await
+    }
+
+    /// 从检查点恢复全部账户，之后的 [`HourglassClientEvent::LetItRoll`] 会先跳过数据源中已经处理过的条目。
+    ///
+    /// 检查点中的租户必须已经通过 [`ExchangeBuilder::tenant`] 注册，否则返回 [`ExchangeError::AccountNotFound`]，此时不会修改任何账户。
+    pub async fn restore(&mut self, checkpoint: ExchangeCheckpoint) -> Result<(), ExchangeError>
+    {
+        if checkpoint.version != CHECKPOINT_VERSION {
+            return Err(ExchangeError::Checkpoint(format!("unsupported checkpoint version {}, expected {}", checkpoint.version, CHECKPOINT_VERSION)));
+        }
+        let mut restores = Vec::with_capacity(checkpoint.accounts.len());
+        for account_checkpoint in checkpoint.accounts {
+            let account = match &account_checkpoint.username {
+                | None => Arc::clone(&self.account),
+                | Some(username) => self.tenants.get(username).cloned().ok_or_else(|| ExchangeError::AccountNotFound(username.clone()))?,
+            };
+            restores.push((account, account_checkpoint));
+        }
+        for (account, account_checkpoint) in restores {
+            account.lock().await.restore(account_checkpoint).await;
+        }
+        self.processed_count = checkpoint.data_cursor.processed_count;
+        self.skip_entries = checkpoint.data_cursor.processed_count;
+        self.restored = true;
+        Ok(())
+    }
+
+    async fn build_checkpoint(accounts: &[(Option<String>, Arc<Mutex<HourglassAccount>>)], processed_count: usize) -> ExchangeCheckpoint
+    {
+        let mut account_checkpoints = Vec::with_capacity(accounts.len());
+        for (username, account) in accounts {
+            let mut account_checkpoint = account.lock().await.checkpoint().await;
+            account_checkpoint.username = username.clone();
+            account_checkpoints.push(account_checkpoint);
+        }
+        let exchange_timestamp = account_checkpoints.first().map(|account| account.exchange_timestamp).unwrap_or_default();
+        ExchangeCheckpoint { version: CHECKPOINT_VERSION,
+                             created_at: chrono::Utc::now(),
+                             data_cursor: DataCursor { processed_count, exchange_timestamp },
+                             accounts: account_checkpoints }
+    }
+
+    /// 写入检查点，失败时只记录警告，不影响运行。
+    async fn save_checkpoint(&mut self, path: &Path)
+    {
+        let checkpoint = self.checkpoint().await;
+        match checkpoint.save(path) {
+            | Ok(()) => info!("Checkpoint saved to {} after {} entries", path.display(), checkpoint.data_cursor.processed_count),
+            | Err(e) => warn!("Failed to save checkpoint: {}", e),
+        }
+    }
+
+    /// 关闭时各账户的快照，`policy` 为 [`OpenOrdersOnShutdown::Cancel`] 时先撤销全部挂单。
+    async fn snapshot_accounts(accounts: &[(Option<String>, Arc<Mutex<HourglassAccount>>)], policy: OpenOrdersOnShutdown) -> Vec<AccountSnapshot>
+    {
+        let mut snapshots = Vec::with_capacity(accounts.len());
+        for (username, account) in accounts {
+            let mut account = account.lock().await;
+            let mut cancelled_orders = Vec::new();
+            if policy == OpenOrdersOnShutdown::Cancel {
+                let (response_tx, response_rx) = oneshot::channel();
+                account.cancel_orders_all(response_tx).await;
+                match response_rx.await {
+                    | Ok(Ok(cancelled)) => cancelled_orders = cancelled,
+                    | Ok(Err(e)) => warn!("Failed to cancel open orders on shutdown: {:?}", e),
+                    | Err(e) => warn!("Failed to cancel open orders on shutdown: {:?}", e),
                 }
-                break; // 超时后优雅退出循环
             }
+            let open_orders = account.account_open_book.read().await.fetch_all();
+            snapshots.push(AccountSnapshot { username: username.clone(),
+                                             balances: account.get_balances().await,
+                                             positions: account.positions.clone(),
+                                             open_orders,
+                                             cancelled_orders });
+        }
+        snapshots
+    }
+
+    /// 登录用户的请求交给其账户处理，未登录的请求交给默认账户。
+    ///
+    /// 未配置任何租户时所有请求共用默认账户；配置了租户后，没有账户的用户会收到 [`ExchangeError::AccountNotFound`]。
+    fn route_account(&self, username: Option<&str>) -> Result<Arc<Mutex<HourglassAccount>>, ExchangeError>
+    {
+        match username {
+            | Some(username) if !self.tenants.is_empty() => self.tenants.get(username).cloned().ok_or_else(|| ExchangeError::AccountNotFound(username.to_string())),
+            | _ => Ok(Arc::clone(&self.account)),
+        }
+    }
+
+    /// 用 `seed` 派生出每个账户各自的 [`SimRng`]，先默认账户，再按用户名顺序派生租户账户。
+    ///
+    /// 派生顺序固定，相同的种子与输入总是得到相同的事件流。
+    async fn seed_accounts(accounts: &[(Option<String>, Arc<Mutex<HourglassAccount>>)], seed: u64)
+    {
+        let root = SimRng::seeded(seed);
+        for (_, account) in accounts {
+            account.lock().await.seed(root.fork()).await;
+        }
+    }
+
+    /// 默认账户与全部租户账户，默认账户的用户名为 `None`。
+    fn all_accounts(&self) -> Vec<(Option<String>, Arc<Mutex<HourglassAccount>>)>
+    {
+        std::iter::once((None, Arc::clone(&self.account))).chain(self.tenants.iter().map(|(username, account)| (Some(username.clone()), Arc::clone(account))))
+                                                          .collect()
+    }
+
+    /// 账户间撮合时开仓，`reduce_only` 订单直接拒绝，其余订单交给账户处理，结果按请求顺序返回。
+    ///
+    /// 账户间的成交不经过仓位方向检查，无法保证只减仓。
+    async fn open_agent_orders(account: &Arc<Mutex<HourglassAccount>>, (open_requests, response_tx): RequestOpenOrders)
+    {
+        let reduce_only: Vec<bool> = open_requests.iter().map(|request| request.state.reduce_only).collect();
+        let accepted = open_requests.into_iter().filter(|request| !request.state.reduce_only).collect();
+        let mut opened = account.lock().await.open_orders(accepted).await.into_iter();
+        let results = reduce_only.into_iter()
+                                 .map(|reduce_only| match reduce_only {
+                                     | true => Err(ExchangeError::OrderRejected("reduce_only orders are not supported with agent matching".to_string())),
+                                     | false => opened.next().unwrap_or_else(|| Err(ExchangeError::Hourglass("missing open order result".to_string()))),
+                                 })
+                                 .collect();
+        if response_tx.send(results).is_err() {
+            warn!("Failed to send open order results");
+        }
+    }
+
+    /// 将各账户在 `instruments` 上的挂单按到达顺序放入同一个 [`HourglassOrderBook`]，撮合其中互相交叉的订单，并把成交记入双方账户。
+    ///
+    /// 时间优先以交易所接受订单的先后为准，见 [`ArrivalSequence`]。
+    async fn match_agent_orders(&mut self, instruments: &[Instrument])
+    {
+        let accounts = self.all_accounts();
+        let mut resting = Vec::new();
+        for (owner, account) in &accounts {
+            let account = account.lock().await;
+            let orders = account.account_open_book.read().await.fetch_all();
+            resting.extend(orders.into_iter().map(|order| BookOrder { owner: owner.clone(), order }));
+        }
+        let resting = self.arrivals.arrange(resting);
+
+        for instrument in instruments {
+            let mut book = HourglassOrderBook::new(usize::MAX);
+            let fills: Vec<AgentFill> = resting.iter()
+                                               .filter(|entry| entry.order.instrument == *instrument)
+                                               .flat_map(|entry| book.match_order(entry.clone()))
+                                               .collect();
+            for fill in fills {
+                for (entry, role) in [(&fill.maker, OrderRole::Maker), (&fill.taker, OrderRole::Taker)] {
+                    if let Some((_, account)) = accounts.iter().find(|(owner, _)| *owner == entry.owner) {
+                        if let Err(e) = account.lock().await.fill_agent_order(&entry.order, fill.price, fill.size, role).await {
+                            warn!("Failed to fill agent order: {:?}", e);
+                        }
+                    }
                 }
+            }
         }
     }
 
-    /// 处理下一条数据
-    async fn process_next_data(&mut self) -> Option<MarketTrade>
+    /// 处理下一条数据，K线模式下返回由一根K线展开的全部合成成交
+    async fn process_next_data(&mut self) -> Option<Vec<MarketTrade>>
     {
+        // 从检查点恢复后，先跳过已经处理过的条目，这些条目不再推送给客户端
+        while self.skip_entries > 0 {
+            let skipped = match &mut self.data_source {
+                | DataSource::Backtest(cursor) => matches!(cursor.next().await, Ok(Some(_))),
+                | DataSource::Bars(cursor) => matches!(cursor.next().await, Ok(Some(_))),
+                | DataSource::CheckedDays(days) => matches!(days.next(&self.clickhouse_client).await, Ok(Some(_))),
+                | DataSource::RealTime(_) => false,
+            };
+            self.skip_entries = if skipped { self.skip_entries - 1 } else { 0 };
+        }
         match &mut self.data_source {
             | DataSource::Backtest(cursor) => {
                 // 这里 cursor 需要是 mutable 的
@@ -157,12 +546,50 @@ impl HourglassExchange
                     if let Err(e) = self.market_event_tx.send(row.clone()) {
                         eprintln!("Failed to send market data to client: {:?}", e);
                     }
-                    Some(row)
+                    Some(vec![row])
+                }
+                else {
+                    None
+                }
+            }
+            | DataSource::Bars(cursor) => {
+                if let Ok(Some(bar)) = cursor.next().await {
+                    let rows = bar.to_synthetic_trades();
+                    for row in &rows {
+                        if let Err(e) = self.market_event_tx.send(row.clone()) {
+                            warn!("Failed to send market data to client: {:?}", e);
+                        }
+                    }
+                    // 如果客户端订阅了K线，额外发送 `MarketEvent<Bar>`
+                    if let Some(bar_event_tx) = &self.bar_event_tx {
+                        match bar.parse_instrument() {
+                            | Some(instrument) => {
+                                if let Err(e) = bar_event_tx.send(MarketEvent::from_bar(bar, instrument)) {
+                                    warn!("Failed to send bar to client: {:?}", e);
+                                }
+                            }
+                            | None => warn!("Unable to parse instrument from bar symbol: {}", bar.symbol),
+                        }
+                    }
+                    Some(rows)
                 }
                 else {
                     None
                 }
             }
+            | DataSource::CheckedDays(days) => match days.next(&self.clickhouse_client).await {
+                | Ok(Some(row)) => {
+                    if let Err(e) = self.market_event_tx.send(row.clone()) {
+                        warn!("Failed to send market data to client: {:?}", e);
+                    }
+                    Some(vec![row])
+                }
+                | Ok(None) => None,
+                | Err(e) => {
+                    warn!("Stopped loading backtest data: {}", e);
+                    None
+                }
+            },
             | _ => {
                 println!("Unhandled data source type");
                 None
@@ -170,47 +597,65 @@ impl HourglassExchange
         }
     }
 
-    /// 网络运行 [`HourglassExchange`]，并从网络接收事件
-    pub async fn run_online(self)
+    /// 网络运行 [`HourglassExchange`]，通过 [`rest_api::routes`] 接收 REST 请求。
+    pub async fn run_online(self) -> Option<ShutdownReport>
     {
-        let address = ([127, 0, 0, 1], 8888);
+        self.run_online_at(([127, 0, 0, 1], 8888)).await
+    }
 
+    /// 在指定地址上网络运行 [`HourglassExchange`]，端口已被占用时直接返回 `None`。
+    ///
+    /// 收到 SIGINT、SIGTERM 或通过 [`ShutdownHandle`] 请求关闭后，服务器停止接收新连接，事件循环处理完队列中的请求后退出。
+    pub async fn run_online_at(mut self, address: ([u8; 4], u16)) -> Option<ShutdownReport>
+    {
         // 检查端口是否已经被占用
         if is_port_in_use(address) {
-            return;
+            return None;
         }
 
-        // 创建一个通道，用于内部事件传递
-        let (event_tx, _event_rx) = mpsc::unbounded_channel();
-
-        // 创建 warp 路由
-        let route = warp::path("event").and(warp::body::json()).map(move |network_event: NetworkEvent| {
-                                                                   let event_tx_clone = event_tx.clone();
-
-                                                                   // 异步处理网络事件并发送到通道
-                                                                   tokio::spawn(async move {
-                                                                       match network_event.parse_payload() {
-                                                                           | Ok(event) => {
-                                                                               // 发送事件到通道
-                                                                               if let Err(e) = event_tx_clone.send(event) {
-                                                                                   eprintln!("Failed to send event: {:?}", e);
-                                                                               }
-                                                                           }
-                                                                           | Err(e) => eprintln!("Failed to parse event: {}", e),
-                                                                       }
-                                                                   });
-
-                                                                   warp::reply::reply()
-                                                               });
-
-        // 启动 warp 服务器
-        let warp_server = warp::serve(route).run(address);
-
-        // 同时运行 warp 服务器和事件处理逻辑
-        tokio::select! {
-            _ = warp_server => {},
-            _ = self.start() => {},
+        // REST 请求与本地客户端的事件汇入同一个通道，由同一个事件循环处理
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let mut local_event_rx = std::mem::replace(&mut self.client_event_rx, event_rx);
+        let local_event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            while let Some(event) = local_event_rx.recv().await {
+                if local_event_tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // 账户事件与市场成交同时推送给 WebSocket 客户端
+        let hub = Arc::new(StreamHub::new(STREAM_CAPACITY, STREAM_HEARTBEAT_INTERVAL));
+        self.market_event_tx = hub.tee_market_trades(self.market_event_tx.clone());
+        // 每个账户的事件以各自的名义推送，只有该账户登录后的连接能收到
+        for (owner, account) in self.all_accounts() {
+            let mut guard = account.lock().await;
+            guard.account_event_tx = hub.tee_account_events(guard.account_event_tx.clone(), owner);
+            guard.publish_positions = true;
         }
+
+        // 启动 warp 服务器，WebSocket 路由需放在 REST 路由之前，后者会兜底处理所有未匹配的请求
+        let shutdown = self.shutdown_handle();
+        let signal_listener = shutdown.shutdown_on_signal();
+        let replay = !matches!(self.data_source, DataSource::RealTime(_));
+        let routes = ws_stream::route(hub, self.authenticator.clone(), self.api_keys.clone()).or(rest_api::routes(event_tx, self.api_keys.clone(), replay));
+        let warp_server = match warp::serve(routes).try_bind_with_graceful_shutdown(address, shutdown.clone().wait()) {
+            | Ok((_, warp_server)) => tokio::spawn(warp_server),
+            | Err(e) => {
+                warn!("Failed to bind {:?}: {}", address, e);
+                signal_listener.abort();
+                return None;
+            }
+        };
+
+        // 事件循环退出后服务器也随之关闭
+        let idle_policy = self.idle_policy.unwrap_or(IdlePolicy::Never);
+        let report = self.run_event_loop(idle_policy).await;
+        shutdown.shutdown();
+        signal_listener.abort();
+        let _ = warp_server.await;
+        Some(report)
     }
 }
 
@@ -222,7 +667,22 @@ impl Default for ExchangeBuilder
         Self { event_hourglass_rx: Some(rx),
                account: None,
                market_event_tx: None,
-               data_source: None }
+               data_source: None,
+               bar_event_tx: None,
+               clickhouse_config: None,
+               user_store: None,
+               session_ttl: None,
+               password_cost: None,
+               require_session: false,
+               tenants: BTreeMap::new(),
+               agent_matching: false,
+               api_keys: None,
+               rate_limit: None,
+               idle_policy: None,
+               open_orders_on_shutdown: OpenOrdersOnShutdown::default(),
+               checkpoint_policy: None,
+               journal_path: None,
+               seed: None }
     }
 }
 pub struct ExchangeBuilder
@@ -231,6 +691,21 @@ pub struct ExchangeBuilder
     pub(crate) account: Option<Arc<Mutex<HourglassAccount>>>,
     pub(crate) market_event_tx: Option<UnboundedSender<MarketTrade>>,
     pub(crate) data_source: Option<DataSource>,
+    pub(crate) bar_event_tx: Option<UnboundedSender<MarketEvent<Bar>>>,
+    pub(crate) clickhouse_config: Option<ClickHouseConfig>,
+    pub(crate) user_store: Option<Arc<dyn UserStore>>,
+    pub(crate) session_ttl: Option<Duration>,
+    pub(crate) password_cost: Option<u32>,
+    pub(crate) require_session: bool,
+    pub(crate) tenants: BTreeMap<String, Arc<Mutex<HourglassAccount>>>,
+    pub(crate) agent_matching: bool,
+    pub(crate) api_keys: Option<Arc<ApiKeyStore>>,
+    pub(crate) rate_limit: Option<RateLimitConfig>,
+    pub(crate) idle_policy: Option<IdlePolicy>,
+    pub(crate) open_orders_on_shutdown: OpenOrdersOnShutdown,
+    pub(crate) checkpoint_policy: Option<CheckpointPolicy>,
+    pub(crate) journal_path: Option<PathBuf>,
+    pub(crate) seed: Option<u64>,
 }
 
 impl ExchangeBuilder
@@ -240,7 +715,22 @@ impl ExchangeBuilder
         Self { event_hourglass_rx: None,
                account: None,
                market_event_tx: None,
-               data_source: None }
+               data_source: None,
+               bar_event_tx: None,
+               clickhouse_config: None,
+               user_store: None,
+               session_ttl: None,
+               password_cost: None,
+               require_session: false,
+               tenants: BTreeMap::new(),
+               agent_matching: false,
+               api_keys: None,
+               rate_limit: None,
+               idle_policy: None,
+               open_orders_on_shutdown: OpenOrdersOnShutdown::default(),
+               checkpoint_policy: None,
+               journal_path: None,
+               seed: None }
     }
 
     pub fn event_hourglass_rx(self, value: UnboundedReceiver<HourglassClientEvent>) -> Self
@@ -259,20 +749,134 @@ impl ExchangeBuilder
         Self { market_event_tx: Some(value), ..self }
     }
 
+    /// 可选，在 [`DataSource::Bars`] 模式下接收 `MarketEvent<Bar>`。
+    pub fn bar_event_tx(self, value: UnboundedSender<MarketEvent<Bar>>) -> Self
+    {
+        Self { bar_event_tx: Some(value), ..self }
+    }
+
     pub fn account(self, value: Arc<Mutex<HourglassAccount>>) -> Self
     {
         Self { account: Some(value), ..self }
     }
 
+    /// 未设置时使用 [`ClickHouseConfig::default`]，可通过 [`ClickHouseConfig::load`] 从 `config.toml` 读取。
+    pub fn clickhouse_config(self, value: ClickHouseConfig) -> Self
+    {
+        Self { clickhouse_config: Some(value), ..self }
+    }
+
+    /// 为用户添加独立的账户，该用户登录后的请求都由此账户处理。账户自带的 `account_event_tx` 即为该用户的事件通道。
+    pub fn tenant(mut self, username: impl Into<String>, account: Arc<Mutex<HourglassAccount>>) -> Self
+    {
+        self.tenants.insert(username.into(), account);
+        self
+    }
+
+    /// 未设置时使用 ClickHouse 中的 `{database_prefix}accounts.user_info` 表。
+    pub fn user_store(self, value: Arc<dyn UserStore>) -> Self
+    {
+        Self { user_store: Some(value), ..self }
+    }
+
+    /// 未设置时为 [`DEFAULT_SESSION_TTL`]。
+    pub fn session_ttl(self, value: Duration) -> Self
+    {
+        Self { session_ttl: Some(value), ..self }
+    }
+
+    /// bcrypt 的计算强度，未设置时为 [`bcrypt::DEFAULT_COST`]。
+    pub fn password_cost(self, value: u32) -> Self
+    {
+        Self { password_cost: Some(value), ..self }
+    }
+
+    /// 要求订单、余额与仓位请求携带有效会话，默认不要求；注册了租户时总是要求。通过 API key 签名的请求视为已经登录。
+    pub fn require_session(self, value: bool) -> Self
+    {
+        Self { require_session: value, ..self }
+    }
+
+    /// 让不同账户的挂单按价格-时间优先互相撮合，与历史成交的撮合同时进行，默认关闭。
+    pub fn agent_matching(self, value: bool) -> Self
+    {
+        Self { agent_matching: value, ..self }
+    }
+
+    /// 在线模式下的 API key，设置后 REST 请求必须签名，见 [`rest_api::routes`]。
+    pub fn api_keys(self, value: Arc<ApiKeyStore>) -> Self
+    {
+        Self { api_keys: Some(value), ..self }
+    }
+
+    /// 按账户或 API key 所属用户限流，本地客户端与 REST 请求共用同一份额度，默认不限流。
+    pub fn rate_limit(self, value: RateLimitConfig) -> Self
+    {
+        Self { rate_limit: Some(value), ..self }
+    }
+
+    /// 没有客户端事件时是否退出，未设置时 [`HourglassExchange::start`] 空闲一秒后退出，在线模式一直运行。
+    pub fn idle_policy(self, value: IdlePolicy) -> Self
+    {
+        Self { idle_policy: Some(value), ..self }
+    }
+
+    /// 关闭时撤销还是保留挂单，默认保留。
+    pub fn open_orders_on_shutdown(self, value: OpenOrdersOnShutdown) -> Self
+    {
+        Self { open_orders_on_shutdown: value, ..self }
+    }
+
+    /// 定期写入检查点，配合 [`HourglassExchange::restore`] 从中断处继续回测，默认不写入。
+    pub fn checkpoint_policy(self, value: CheckpointPolicy) -> Self
+    {
+        Self { checkpoint_policy: Some(value), ..self }
+    }
+
+    /// 把每个客户端请求与账户事件追加写入 `path` 处的审计日志，关闭时用 [`journal::verify`] 核对账户状态，默认不记录。
+    pub fn journal(self, path: impl Into<PathBuf>) -> Self
+    {
+        Self { journal_path: Some(path.into()),
+               ..self }
+    }
+
+    /// 设定随机数种子，相同的种子与输入得到逐位相同的事件流，默认每次运行都不同。
+    pub fn seed(self, value: u64) -> Self
+    {
+        Self { seed: Some(value), ..self }
+    }
+
     pub fn initiate(self) -> Result<HourglassExchange, ExchangeError>
     {
+        let clickhouse_client = ClickHouseClient::from_config(self.clickhouse_config.unwrap_or_default());
+        let user_store = self.user_store.unwrap_or_else(|| Arc::new(clickhouse_client.clone()));
+        // 存在租户时，未登录的请求不能落到默认账户上
+        let require_session = self.require_session || !self.tenants.is_empty();
         Ok(HourglassExchange { client_event_rx: self.event_hourglass_rx.ok_or_else(|| ExchangeError::BuilderIncomplete("event_hourglass_rx".to_string()))?,
                                // market_event_tx: self.market_event_tx.ok_or_else(|| ExecutionError::BuilderIncomplete("market_event_tx".to_string()))?,
                                market_event_tx: self.market_event_tx.ok_or_else(|| ExchangeError::BuilderIncomplete("market_tx".to_string()))?,
+                               bar_event_tx: self.bar_event_tx,
                                account: self.account.ok_or_else(|| ExchangeError::BuilderIncomplete("account".to_string()))?,
+                               tenants: self.tenants,
                                data_source: self.data_source.ok_or_else(|| ExchangeError::BuilderIncomplete("data_source".to_string()))?,
-                               clickhouse_client: ClickHouseClient::new(),
-                               active_sessions: HashMap::new().into() })
+                               clickhouse_client,
+                               authenticator: Authenticator::new(user_store,
+                                                                 self.session_ttl.unwrap_or(DEFAULT_SESSION_TTL),
+                                                                 self.password_cost.unwrap_or(bcrypt::DEFAULT_COST),
+                                                                 require_session),
+                               agent_matching: self.agent_matching,
+                               arrivals: ArrivalSequence::default(),
+                               api_keys: self.api_keys,
+                               rate_limiter: self.rate_limit.map(RateLimiter::new),
+                               idle_policy: self.idle_policy,
+                               open_orders_on_shutdown: self.open_orders_on_shutdown,
+                               shutdown: ShutdownHandle::new(),
+                               checkpoint_policy: self.checkpoint_policy,
+                               processed_count: 0,
+                               skip_entries: 0,
+                               restored: false,
+                               journal: self.journal_path.map(Journal::open).transpose()?,
+                               seed: self.seed })
     }
 }
 
@@ -280,10 +884,52 @@ impl ExchangeBuilder
 mod tests
 {
     use super::*;
-    use crate::{hourglass::clickhouse_api::queries_operations::ClickHouseClient, test_utils::create_test_account};
+    use crate::{
+        common::{
+            event::AccountEventKind,
+            instrument::{kind::InstrumentKind, Instrument},
+            order::{identification::client_order_id::ClientOrderId, order_instructions::OrderInstruction, states::request_open::RequestOpen, Order},
+            token::Token,
+            Side,
+        },
+        dashboard::summary::attribution::PnLBreakdown,
+        hourglass::{
+            clickhouse_api::queries_operations::ClickHouseClient,
+            config_request::ConfigurationRequest,
+            hourglass_client_local_mode::HourglassClient,
+            hourglass_client_remote_mode::{HourglassRemoteClient, RemoteClientConfig},
+        },
+        network::{
+            event::NetworkRequest,
+            login::{InMemoryUserStore, LoginRequest, LogoutRequest, UserRecord, TEST_PASSWORD_COST},
+            rate_limit::{BucketConfig, RequestWeights},
+        },
+        test_utils::create_test_account,
+        AccountEvent, ClientExecution, Exchange,
+    };
     use std::net::TcpListener;
     use tokio::sync::mpsc;
 
+    /// 每个租户使用独立的账户与事件通道
+    async fn tenant_account() -> (Arc<Mutex<HourglassAccount>>, mpsc::UnboundedReceiver<AccountEvent>)
+    {
+        let (account_event_tx, account_event_rx) = mpsc::unbounded_channel();
+        let mut account = create_test_account().await;
+        account.account_event_tx = account_event_tx;
+        (Arc::new(Mutex::new(account)), account_event_rx)
+    }
+
+    async fn logged_in_client(event_tx: &UnboundedSender<HourglassClientEvent>, username: &str) -> HourglassClient
+    {
+        let (_market_tx, market_rx) = mpsc::unbounded_channel();
+        let mut client = HourglassClient { client_event_tx: event_tx.clone(),
+                                           market_event_rx: market_rx,
+                                           session_token: None };
+        client.register(username.to_string(), format!("{}@example.com", username), "secret".to_string()).await.unwrap();
+        client.login(username.to_string(), "secret".to_string()).await.unwrap();
+        client
+    }
+
     #[tokio::test]
     async fn builder_should_create_exchange_builder_with_default_values()
     {
@@ -309,6 +955,26 @@ mod tests
         assert!(builder.account.is_some());
     }
 
+    #[tokio::test]
+    async fn builder_should_pass_clickhouse_config_to_client()
+    {
+        let (_tx, rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let account = Arc::new(Mutex::new(create_test_account().await));
+        let config = ClickHouseConfig { database_prefix: "warehouse_".to_string(),
+                                        ..Default::default() };
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(rx)
+                                             .account(account)
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .clickhouse_config(config.clone())
+                                             .initiate()
+                                             .unwrap();
+        assert_eq!(exchange.clickhouse_client.config, config);
+        assert_eq!(exchange.clickhouse_client.construct_database_name("binance", "futures", "trades"), "warehouse_binance_futures_trades");
+    }
+
     #[tokio::test]
     async fn builder_should_return_error_if_event_hourglass_rx_is_missing()
     {
@@ -345,17 +1011,604 @@ mod tests
         let account = Arc::new(Mutex::new(account)); // Wrap `Account` in `Arc<Mutex<Account>>`
         let exchange = HourglassExchange { client_event_rx: rx,
                                            market_event_tx: market_tx,
+                                           bar_event_tx: None,
                                            account,
+                                           tenants: BTreeMap::new(),
                                            data_source: DataSource::Backtest(cursor),
                                            clickhouse_client: ClickHouseClient::new(),
-                                           active_sessions: HashMap::new().into() };
+                                           authenticator: Authenticator::new(Arc::new(InMemoryUserStore::new()), DEFAULT_SESSION_TTL, bcrypt::DEFAULT_COST, false),
+                                           agent_matching: false,
+                                           arrivals: ArrivalSequence::default(),
+                                           api_keys: None,
+                                           rate_limiter: None,
+                                           idle_policy: None,
+                                           open_orders_on_shutdown: OpenOrdersOnShutdown::Keep,
+                                           shutdown: ShutdownHandle::new(),
+                                           checkpoint_policy: None,
+                                           processed_count: 0,
+                                           skip_entries: 0,
+                                           restored: false,
+                                           journal: None,
+                                           seed: None };
         let address = "127.0.0.1:3030".parse().unwrap(); // Convert to a SocketAddr
         assert!(is_port_in_use(address));
-        exchange.run_online().await;
+        exchange.run_online_at(([127, 0, 0, 1], 3030)).await;
     }
     // Function to check if a port is in use
     fn is_port_in_use(address: std::net::SocketAddr) -> bool
     {
         TcpListener::bind(address).is_err()
     }
+
+    #[tokio::test]
+    async fn let_it_roll_should_be_rejected_online_with_real_time_data()
+    {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(Arc::new(Mutex::new(create_test_account().await)))
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .initiate()
+                                             .unwrap();
+        let shutdown = exchange.shutdown_handle();
+        let running = tokio::spawn(exchange.run_online_at(([127, 0, 0, 1], port)));
+
+        let (account_tx, _account_rx) = mpsc::unbounded_channel();
+        let config = RemoteClientConfig { base_url: format!("http://127.0.0.1:{}", port),
+                                          market_symbols: vec![] };
+        let client = HourglassRemoteClient::init(config, account_tx).await;
+        // 等待服务器开始监听
+        let mut result = client.let_it_roll().await;
+        while matches!(result, Err(ExchangeError::NetworkError(_))) {
+            time::sleep(Duration::from_millis(10)).await;
+            result = client.let_it_roll().await;
+        }
+        assert!(matches!(result, Err(ExchangeError::NotImplemented(_))));
+
+        // 绕过 REST 路由直接发给事件循环的请求同样被拒绝，交易所继续服务
+        event_tx.send(HourglassClientEvent::LetItRoll).unwrap();
+        assert!(client.fetch_balances().await.is_ok());
+
+        shutdown.shutdown();
+        let report = running.await.unwrap().unwrap();
+        assert_eq!(report.reason, ShutdownReason::Requested);
+    }
+
+    #[tokio::test]
+    async fn tenants_should_not_see_each_others_state()
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let (alice_account, mut alice_events) = tenant_account().await;
+        let (bob_account, mut bob_events) = tenant_account().await;
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(Arc::new(Mutex::new(create_test_account().await)))
+                                             .tenant("alice", alice_account)
+                                             .tenant("bob", bob_account)
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .user_store(Arc::new(InMemoryUserStore::new()))
+                                             .password_cost(TEST_PASSWORD_COST)
+                                             .require_session(true)
+                                             .initiate()
+                                             .unwrap();
+        tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+
+        let alice = logged_in_client(&event_tx, "alice").await;
+        let bob = logged_in_client(&event_tx, "bob").await;
+
+        alice.deposit_tokens(vec![(Token::from("USDT"), 500.0)]).await.unwrap();
+        let order = Order { instruction: OrderInstruction::Limit,
+                            exchange: Exchange::Hourglass,
+                            instrument: Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual)),
+                            timestamp: 1233312345124,
+                            cid: Some(ClientOrderId("alice_cid".to_string())),
+                            side: Side::Buy,
+                            state: RequestOpen { reduce_only: false,
+                                                 price: 16000.0,
+                                                 size: 0.1 } };
+        assert!(alice.open_orders(vec![order]).await[0].is_ok());
+
+        assert_eq!(alice.fetch_orders_open().await.unwrap().len(), 1);
+        assert!(bob.fetch_orders_open().await.unwrap().is_empty());
+        let bob_usdt = bob.fetch_balances().await.unwrap().into_iter().find(|balance| balance.token == Token::from("USDT")).unwrap();
+        assert_eq!(bob_usdt.balance.total, 10_000.0);
+
+        // 账户事件只发送到各自的通道
+        assert!(alice_events.try_recv().is_ok());
+        assert!(bob_events.try_recv().is_err());
+
+        // 没有账户的用户无法访问任何账户
+        let carol = logged_in_client(&event_tx, "carol").await;
+        assert_eq!(carol.fetch_balances().await, Err(ExchangeError::AccountNotFound("carol".to_string())));
+    }
+
+    #[tokio::test]
+    async fn agent_orders_should_cross_between_tenants()
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let (alice_account, mut alice_events) = tenant_account().await;
+        let (bob_account, mut bob_events) = tenant_account().await;
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(Arc::new(Mutex::new(create_test_account().await)))
+                                             .tenant("alice", alice_account)
+                                             .tenant("bob", bob_account)
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .user_store(Arc::new(InMemoryUserStore::new()))
+                                             .password_cost(TEST_PASSWORD_COST)
+                                             .agent_matching(true)
+                                             .initiate()
+                                             .unwrap();
+        tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+
+        let alice = logged_in_client(&event_tx, "alice").await;
+        let bob = logged_in_client(&event_tx, "bob").await;
+        let order = |cid: &str, side: Side, price: f64, timestamp: i64| Order { instruction: OrderInstruction::Limit,
+                                                                                exchange: Exchange::Hourglass,
+                                                                                instrument: Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual)),
+                                                                                timestamp,
+                                                                                cid: Some(ClientOrderId(cid.to_string())),
+                                                                                side,
+                                                                                state: RequestOpen { reduce_only: false, price, size: 0.1 } };
+
+        // 账户间撮合不支持 reduce_only 订单
+        let mut reduce_only = order("alice_reduce", Side::Sell, 16010.0, 1_000);
+        reduce_only.state.reduce_only = true;
+        assert!(matches!(alice.open_orders(vec![reduce_only]).await[0], Err(ExchangeError::OrderRejected(_))));
+
+        // bob 把时间戳填得比 alice 更早，时间优先仍以交易所接受订单的先后为准
+        assert!(alice.open_orders(vec![order("alice_cid", Side::Buy, 16000.0, 10_000)]).await[0].is_ok());
+        assert!(bob.open_orders(vec![order("bob_cid", Side::Sell, 15990.0, 1_000)]).await[0].is_ok());
+
+        // 两张订单都已成交，成交价为先挂单的 alice 的价格
+        assert!(alice.fetch_orders_open().await.unwrap().is_empty());
+        assert!(bob.fetch_orders_open().await.unwrap().is_empty());
+        for (events, side) in [(&mut alice_events, Side::Buy), (&mut bob_events, Side::Sell)] {
+            let trade = std::iter::from_fn(|| events.try_recv().ok()).find_map(|event| match event.kind {
+                                                                         | AccountEventKind::Trade(trade) => Some(trade),
+                                                                         | _ => None,
+                                                                     })
+                                                                     .expect("missing trade event");
+            assert_eq!((trade.side, trade.price, trade.size), (side, 16000.0, 0.1));
+        }
+    }
+
+    #[tokio::test]
+    async fn pnl_attribution_should_be_queried_by_strategy_tag()
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let (alice_account, _alice_events) = tenant_account().await;
+        let (bob_account, _bob_events) = tenant_account().await;
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(Arc::new(Mutex::new(create_test_account().await)))
+                                             .tenant("alice", alice_account)
+                                             .tenant("bob", bob_account)
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .user_store(Arc::new(InMemoryUserStore::new()))
+                                             .password_cost(TEST_PASSWORD_COST)
+                                             .agent_matching(true)
+                                             .initiate()
+                                             .unwrap();
+        tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+
+        let alice = logged_in_client(&event_tx, "alice").await;
+        let bob = logged_in_client(&event_tx, "bob").await;
+        let order = |cid: &str, side: Side, timestamp: i64| Order { instruction: OrderInstruction::Limit,
+                                                                    exchange: Exchange::Hourglass,
+                                                                    instrument: Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual)),
+                                                                    timestamp,
+                                                                    cid: Some(ClientOrderId(cid.to_string())),
+                                                                    side,
+                                                                    state: RequestOpen { reduce_only: false,
+                                                                                         price: 16000.0,
+                                                                                         size: 0.1 } };
+        assert!(alice.open_orders(vec![order("trend-0001", Side::Buy, 1_000)]).await[0].is_ok());
+        assert!(bob.open_orders(vec![order("mm-000001", Side::Sell, 10_000)]).await[0].is_ok());
+
+        // 成交按 `ClientOrderId` 的前缀归入策略，买方计入多头、卖方计入空头
+        let attribution = alice.fetch_pnl_attribution().await.unwrap();
+        assert_eq!(attribution.by_strategy.keys().collect::<Vec<_>>(), vec!["trend"]);
+        assert!((attribution.long.volume - 1_600.0).abs() < 1e-9);
+        assert_eq!(attribution.short, PnLBreakdown::default());
+        assert!((attribution.total.net_pnl() - (attribution.total.unrealised_pnl - attribution.total.fees)).abs() < 1e-9);
+
+        let attribution = bob.fetch_pnl_attribution().await.unwrap();
+        assert_eq!(attribution.by_strategy.keys().collect::<Vec<_>>(), vec!["mm"]);
+        assert!((attribution.short.volume - 1_600.0).abs() < 1e-9);
+    }
+
+    /// 查询密码时一直等到测试放行，模拟耗时的密码校验
+    struct SlowUserStore(Arc<tokio::sync::Notify>);
+
+    #[async_trait::async_trait]
+    impl UserStore for SlowUserStore
+    {
+        async fn insert_user(&self, _user: UserRecord) -> Result<(), ExchangeError>
+        {
+            Ok(())
+        }
+
+        async fn fetch_password_hash(&self, _username: &str) -> Result<Option<String>, ExchangeError>
+        {
+            self.0.notified().await;
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn login_should_not_block_event_loop()
+    {
+        let release = Arc::new(tokio::sync::Notify::new());
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(Arc::new(Mutex::new(create_test_account().await)))
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .user_store(Arc::new(SlowUserStore(Arc::clone(&release))))
+                                             .initiate()
+                                             .unwrap();
+        let shutdown = exchange.shutdown_handle();
+        let running = tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+
+        let (login_tx, login_rx) = oneshot::channel();
+        event_tx.send(HourglassClientEvent::Login(LoginRequest { username: "alice".to_string(),
+                                                                 password: "secret".to_string(),
+                                                                 response_tx: login_tx }))
+                .unwrap();
+        // 登录尚未完成时，之后的请求照常处理
+        let (balances_tx, balances_rx) = oneshot::channel();
+        event_tx.send(HourglassClientEvent::FetchTokenBalances(balances_tx)).unwrap();
+        assert!(time::timeout(Duration::from_secs(1), balances_rx).await.unwrap().unwrap().is_ok());
+
+        release.notify_one();
+        assert_eq!(login_rx.await.unwrap(), Err(ExchangeError::InvalidCredentials));
+        shutdown.shutdown();
+        assert_eq!(running.await.unwrap().reason, ShutdownReason::Requested);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_should_apply_per_account()
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let (alice_account, _alice_events) = tenant_account().await;
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(Arc::new(Mutex::new(create_test_account().await)))
+                                             .tenant("alice", alice_account)
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .user_store(Arc::new(InMemoryUserStore::new()))
+                                             .password_cost(TEST_PASSWORD_COST)
+                                             .rate_limit(RateLimitConfig { request_weight: BucketConfig::per(3.0, Duration::from_secs(60)),
+                                                                           order_count: None,
+                                                                           weights: RequestWeights::default() })
+                                             .initiate()
+                                             .unwrap();
+        tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+
+        // 注册与登录计入未登录请求的额度
+        let alice = logged_in_client(&event_tx, "alice").await;
+        for _ in 0..3 {
+            assert!(alice.fetch_balances().await.is_ok());
+        }
+        // 每 20 秒补充 1 个令牌
+        assert!(matches!(alice.fetch_balances().await, Err(ExchangeError::ApiLimitReached { retry_after_ms: 1..=20_000 })));
+
+        let (_market_tx, market_rx) = mpsc::unbounded_channel();
+        let anonymous = HourglassClient { client_event_tx: event_tx.clone(),
+                                          market_event_rx: market_rx,
+                                          session_token: None };
+        // 存在租户时未登录的请求不会落到默认账户上
+        assert!(matches!(anonymous.fetch_balances().await, Err(ExchangeError::InvalidSession)));
+
+        // 远程的未登录请求按对端地址分别计算额度
+        let logout_from = |peer: &str| {
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = LogoutRequest { session_token: "unknown".to_string(),
+                                          response_tx };
+            event_tx.send(HourglassClientEvent::Logout(request).from_peer(Some(peer.parse().unwrap()))).unwrap();
+            response_rx
+        };
+        for _ in 0..3 {
+            assert!(!matches!(logout_from("10.0.0.1").await.unwrap(), Err(ExchangeError::ApiLimitReached { .. })));
+        }
+        assert!(matches!(logout_from("10.0.0.1").await.unwrap(), Err(ExchangeError::ApiLimitReached { .. })));
+        assert!(!matches!(logout_from("10.0.0.2").await.unwrap(), Err(ExchangeError::ApiLimitReached { .. })));
+    }
+
+    #[tokio::test]
+    async fn shutdown_should_drain_requests_and_cancel_open_orders()
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let (account, _account_events) = tenant_account().await;
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(account)
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .open_orders_on_shutdown(OpenOrdersOnShutdown::Cancel)
+                                             .initiate()
+                                             .unwrap();
+        let shutdown = exchange.shutdown_handle();
+        let running = tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+
+        let (_market_tx, market_rx) = mpsc::unbounded_channel();
+        let client = HourglassClient { client_event_tx: event_tx.clone(),
+                                       market_event_rx: market_rx,
+                                       session_token: None };
+        let order = Order { instruction: OrderInstruction::Limit,
+                            exchange: Exchange::Hourglass,
+                            instrument: Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual)),
+                            timestamp: 1233312345124,
+                            cid: Some(ClientOrderId("shutdown_cid".to_string())),
+                            side: Side::Buy,
+                            state: RequestOpen { reduce_only: false,
+                                                 price: 16000.0,
+                                                 size: 0.1 } };
+        assert!(client.open_orders(vec![order]).await[0].is_ok());
+
+        // 请求关闭之前已经进入队列的请求仍会得到响应
+        let (response_tx, response_rx) = oneshot::channel();
+        event_tx.send(HourglassClientEvent::FetchTokenBalances(response_tx)).unwrap();
+        shutdown.shutdown();
+        assert!(response_rx.await.unwrap().is_ok());
+
+        let report = running.await.unwrap();
+        assert_eq!(report.reason, ShutdownReason::Requested);
+        assert_eq!(report.accounts.len(), 1);
+        assert!(report.accounts[0].open_orders.is_empty());
+        assert_eq!(report.accounts[0].cancelled_orders.len(), 1);
+        assert!(event_tx.send(HourglassClientEvent::LetItRoll).is_err());
+    }
+
+    #[tokio::test]
+    async fn start_should_follow_idle_policy()
+    {
+        let (_event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(Arc::new(Mutex::new(create_test_account().await)))
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .idle_policy(IdlePolicy::Exit(Duration::from_millis(10)))
+                                             .initiate()
+                                             .unwrap();
+
+        let report = exchange.start().await;
+        assert_eq!(report.reason, ShutdownReason::Idle);
+        assert_eq!((report.processed_count, report.drained_events), (0, 0));
+        // 默认保留挂单
+        assert!(report.accounts[0].cancelled_orders.is_empty());
+    }
+
+    fn checkpoint_exchange(account: Arc<Mutex<HourglassAccount>>) -> ExchangeBuilder
+    {
+        let (_event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                              .account(account)
+                              .market_event_tx(market_tx)
+                              .data_source(DataSource::RealTime(feed_rx))
+    }
+
+    #[tokio::test]
+    async fn restored_accounts_should_not_be_reseeded()
+    {
+        let mut exchange = checkpoint_exchange(Arc::new(Mutex::new(create_test_account().await))).seed(7).initiate().unwrap();
+        HourglassExchange::seed_accounts(&exchange.all_accounts(), 99).await;
+        let checkpoint = exchange.checkpoint().await;
+
+        let account = Arc::new(Mutex::new(create_test_account().await));
+        let mut resumed = checkpoint_exchange(Arc::clone(&account)).seed(7).initiate().unwrap();
+        resumed.restore(checkpoint.clone()).await.unwrap();
+        // 事件循环启动时就会派生随机数，随即关闭即可
+        resumed.shutdown_handle().shutdown();
+        resumed.run_event_loop(IdlePolicy::Never).await;
+        let account = account.lock().await;
+        assert_eq!((account.machine_id, account.current_session), (checkpoint.accounts[0].machine_id, checkpoint.accounts[0].current_session));
+        assert_eq!(account.rng.state(), checkpoint.accounts[0].rng);
+    }
+
+    #[tokio::test]
+    async fn checkpoint_should_restore_accounts_and_data_cursor()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        let usdt = Token::from("USDT");
+
+        let (alice, _alice_rx) = tenant_account().await;
+        alice.lock().await.balances.get_mut(&usdt).unwrap().available = 1_234.0;
+        let mut exchange = checkpoint_exchange(Arc::new(Mutex::new(create_test_account().await))).tenant("alice", alice).initiate().unwrap();
+        exchange.processed_count = 12;
+        let checkpoint = exchange.checkpoint().await;
+        assert_eq!(checkpoint.accounts.iter().map(|account| account.username.clone()).collect::<Vec<_>>(), vec![None, Some("alice".to_string())]);
+        assert_eq!(checkpoint.data_cursor.processed_count, 12);
+        checkpoint.save(&path).unwrap();
+
+        // 重新启动后从检查点继续
+        let (alice, _alice_rx) = tenant_account().await;
+        let mut resumed = checkpoint_exchange(Arc::new(Mutex::new(create_test_account().await))).tenant("alice", Arc::clone(&alice)).initiate().unwrap();
+        resumed.restore(ExchangeCheckpoint::load(&path).unwrap()).await.unwrap();
+        assert_eq!((resumed.processed_count, resumed.skip_entries), (12, 12));
+        assert_eq!(alice.lock().await.balances.get(&usdt).unwrap().available, 1_234.0);
+
+        // 缺少租户时不修改任何账户
+        let account = Arc::new(Mutex::new(create_test_account().await));
+        let session = account.lock().await.current_session;
+        let mut exchange = checkpoint_exchange(Arc::clone(&account)).initiate().unwrap();
+        assert_eq!(exchange.restore(checkpoint).await, Err(ExchangeError::AccountNotFound("alice".to_string())));
+        assert_eq!(account.lock().await.current_session, session);
+        assert_eq!(exchange.processed_count, 0);
+    }
+
+    #[tokio::test]
+    async fn checkpoint_policy_should_write_checkpoint_on_shutdown()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("runs").join("checkpoint.json");
+        let exchange = checkpoint_exchange(Arc::new(Mutex::new(create_test_account().await))).idle_policy(IdlePolicy::Exit(Duration::from_millis(10)))
+                                                                                             .checkpoint_policy(CheckpointPolicy::new(&path, 100))
+                                                                                             .initiate()
+                                                                                             .unwrap();
+        exchange.start().await;
+
+        let checkpoint = ExchangeCheckpoint::load(&path).unwrap();
+        assert_eq!(checkpoint.accounts.len(), 1);
+        assert_eq!(checkpoint.data_cursor.processed_count, 0);
+    }
+
+    #[tokio::test]
+    async fn journal_replay_should_match_live_accounts()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let (alice_account, mut alice_events) = tenant_account().await;
+        let (bob_account, _bob_events) = tenant_account().await;
+        let accounts = vec![(None, Arc::new(Mutex::new(create_test_account().await))),
+                            (Some("alice".to_string()), Arc::clone(&alice_account)),
+                            (Some("bob".to_string()), Arc::clone(&bob_account)),];
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(Arc::clone(&accounts[0].1))
+                                             .tenant("alice", alice_account)
+                                             .tenant("bob", bob_account)
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .user_store(Arc::new(InMemoryUserStore::new()))
+                                             .password_cost(TEST_PASSWORD_COST)
+                                             .agent_matching(true)
+                                             .journal(&path)
+                                             .initiate()
+                                             .unwrap();
+        let shutdown = exchange.shutdown_handle();
+        let running = tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+
+        let alice = logged_in_client(&event_tx, "alice").await;
+        let bob = logged_in_client(&event_tx, "bob").await;
+        let order = |cid: &str, side: Side, price: f64, timestamp: i64| Order { instruction: OrderInstruction::Limit,
+                                                                                exchange: Exchange::Hourglass,
+                                                                                instrument: Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual)),
+                                                                                timestamp,
+                                                                                cid: Some(ClientOrderId(cid.to_string())),
+                                                                                side,
+                                                                                state: RequestOpen { reduce_only: false, price, size: 0.1 } };
+        // 仓位配置同样经由请求写入日志，重放时先于成交生效
+        for (client, side) in [(&alice, Side::Buy), (&bob, Side::Sell)] {
+            let config = ConfigurationRequest { exchange: Exchange::Hourglass,
+                                                instrument: Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual)),
+                                                timestamp: 0,
+                                                cid: None,
+                                                leverage_rate: None,
+                                                side,
+                                                position_margin_mode: None,
+                                                position_direction_mode: None };
+            let (response_tx, response_rx) = oneshot::channel();
+            event_tx.send(HourglassClientEvent::ConfigureInstruments(vec![config], response_tx).with_session(client.session_token.as_ref()))
+                    .unwrap();
+            assert!(response_rx.await.unwrap()[0].is_ok());
+        }
+        alice.deposit_tokens(vec![(Token::from("USDT"), 500.0)]).await.unwrap();
+        assert!(alice.open_orders(vec![order("alice_cid", Side::Buy, 16000.0, 1_000)]).await[0].is_ok());
+        assert!(bob.open_orders(vec![order("bob_cid", Side::Sell, 15990.0, 10_000)]).await[0].is_ok());
+        assert!(alice.open_orders(vec![order("alice_rest", Side::Buy, 15990.0, 20_000)]).await[0].is_ok());
+        // 会话无效的请求被拒绝，日志中同样记录请求与拒绝结果，重放时不计入余额
+        let forged = HourglassClient { client_event_tx: event_tx.clone(),
+                                       market_event_rx: mpsc::unbounded_channel().1,
+                                       session_token: Some("forged".to_string()) };
+        assert!(forged.deposit_tokens(vec![(Token::from("USDT"), 1_000.0)]).await.is_err());
+        assert_eq!(alice.fetch_orders_open().await.unwrap().len(), 1);
+        // 关闭时剩下的记录同步到磁盘
+        shutdown.shutdown();
+        running.await.unwrap();
+
+        // 事件仍然转发给账户原来的通道，成交后的仓位由日志中的成交推导
+        assert!(std::iter::from_fn(|| alice_events.try_recv().ok()).any(|event| matches!(event.kind, AccountEventKind::Trade(_))));
+        assert!(!accounts[1].1.lock().await.positions.all_positions().await.is_empty());
+        assert!(journal::verify(&path, &accounts).await.unwrap().is_empty());
+        let entries = Journal::read_entries(&path).unwrap();
+        let rejected = entries.iter()
+                              .find_map(|entry| match &entry.record {
+                                  | journal::JournalRecord::Outcome { request,
+                                                                      outcome: RequestOutcome::Rejected(_), } => Some(*request),
+                                  | _ => None,
+                              })
+                              .unwrap();
+        let deposit = NetworkRequest::DepositTokens(vec![(Token::from("USDT"), 1_000.0)]);
+        assert!(entries.iter().any(|entry| entry.sequence == rejected && entry.record == journal::JournalRecord::Request(deposit.clone())));
+
+        // 绕过事件直接修改余额，核对时应当发现差异
+        let usdt = Token::from("USDT");
+        accounts[1].1.lock().await.balances.get_mut(&usdt).unwrap().available += 1.0;
+        let divergences = journal::verify(&path, &accounts).await.unwrap();
+        assert!(matches!(divergences.as_slice(), [journal::JournalDivergence::Balance { username: Some(username), token, .. }] if username == "alice" && *token == usdt));
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("secret"));
+    }
+
+    async fn seeded_event_stream(seed: u64) -> Vec<String>
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let (account, mut account_events) = tenant_account().await;
+        let exchange = ExchangeBuilder::new().event_hourglass_rx(event_rx)
+                                             .account(Arc::clone(&account))
+                                             .market_event_tx(market_tx)
+                                             .data_source(DataSource::RealTime(feed_rx))
+                                             .user_store(Arc::new(InMemoryUserStore::new()))
+                                             .password_cost(TEST_PASSWORD_COST)
+                                             .seed(seed)
+                                             .initiate()
+                                             .unwrap();
+        tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+
+        let client = logged_in_client(&event_tx, "alice").await;
+        let order = |cid: &str, price: f64| Order { instruction: OrderInstruction::Limit,
+                                                    exchange: Exchange::Hourglass,
+                                                    instrument: Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual)),
+                                                    timestamp: 1_000,
+                                                    cid: Some(ClientOrderId(cid.to_string())),
+                                                    side: Side::Buy,
+                                                    state: RequestOpen { reduce_only: false, price, size: 0.1 } };
+        client.deposit_tokens(vec![(Token::from("USDT"), 10_000.0)]).await.unwrap();
+        let opened = client.open_orders(vec![order("first_cid", 16000.0), order("second_cid", 15995.0), order("third_cid", 15990.0)]).await;
+        assert!(opened.iter().all(Result::is_ok));
+        // 回测模式下订单时间戳带有模拟延迟，行情按事件循环回放时的方式交给账户撮合
+        let market_trade = MarketTrade { exchange: "binance-futures".to_string(),
+                                         symbol: "ETHUSDT".to_string(),
+                                         side: Side::Sell.to_string(),
+                                         price: 15995.0,
+                                         timestamp: 5_000,
+                                         amount: 0.15 };
+        account.lock().await.handle_trade_data(&market_trade).await.unwrap();
+        assert!(!client.cancel_orders_all().await.unwrap().is_empty());
+
+        std::iter::from_fn(|| account_events.try_recv().ok()).map(|event| serde_json::to_string(&event).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn same_seed_should_produce_identical_event_streams()
+    {
+        let first = seeded_event_stream(42).await;
+        assert!(first.iter().any(|event| event.contains("\"Trade\"")));
+        assert_eq!(first, seeded_event_stream(42).await);
+        assert_ne!(first, seeded_event_stream(43).await);
+    }
 }
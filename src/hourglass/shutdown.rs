@@ -0,0 +1,215 @@
+use crate::common::{
+    account_positions::AccountPositions,
+    balance::TokenBalance,
+    order::{
+        states::{cancelled::Cancelled, open::Open},
+        Order,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
+use tokio::{sync::watch, task::JoinHandle, time::Duration};
+
+/// 本地回测默认的空闲等待时间。
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 事件循环在没有客户端事件时的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IdlePolicy
+{
+    /// 连续 `Duration` 没有客户端事件时关闭交易所。
+    Exit(Duration),
+    /// 一直等待，直到收到关闭信号或所有客户端断开，在线模式默认使用。
+    Never,
+}
+
+impl IdlePolicy
+{
+    pub fn timeout(&self) -> Option<Duration>
+    {
+        match self {
+            | IdlePolicy::Exit(timeout) => Some(*timeout),
+            | IdlePolicy::Never => None,
+        }
+    }
+}
+
+impl Default for IdlePolicy
+{
+    fn default() -> Self
+    {
+        IdlePolicy::Exit(DEFAULT_IDLE_TIMEOUT)
+    }
+}
+
+/// 关闭时如何处理仍在挂单簿中的订单。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OpenOrdersOnShutdown
+{
+    /// 撤销全部挂单并解冻对应的余额。
+    Cancel,
+    /// 保留挂单，记入关闭时的账户快照。
+    #[default]
+    Keep,
+}
+
+/// 事件循环退出的原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShutdownReason
+{
+    Requested,           // 通过 [`ShutdownHandle`] 或系统信号请求关闭
+    Idle,                // 按 [`IdlePolicy::Exit`] 空闲超时
+    DataExhausted,       // 回测数据已全部处理
+    ClientsDisconnected, // 所有客户端都已断开
+}
+
+/// 用于从其他任务关闭交易所，可以任意复制。
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle
+{
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Default for ShutdownHandle
+{
+    fn default() -> Self
+    {
+        Self { tx: Arc::new(watch::Sender::new(false)) }
+    }
+}
+
+impl ShutdownHandle
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// 请求关闭，重复调用没有副作用。
+    pub fn shutdown(&self)
+    {
+        self.tx.send_replace(true);
+    }
+
+    pub fn is_shutdown(&self) -> bool
+    {
+        *self.tx.borrow()
+    }
+
+    /// 等待关闭请求，已经请求过关闭时立即返回。
+    pub async fn wait(self)
+    {
+        let mut rx = self.tx.subscribe();
+        let _ = rx.wait_for(|stop| *stop).await;
+    }
+
+    /// 收到 SIGINT 或 SIGTERM 时请求关闭。
+    ///
+    /// 监听任务在请求关闭后自行结束，调用方在交易所退出后终止返回的任务，同一进程内多次运行不会留下监听任务。
+    pub fn shutdown_on_signal(&self) -> JoinHandle<()>
+    {
+        let handle = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = shutdown_signal() => handle.shutdown(),
+                _ = handle.clone().wait() => {}
+            }
+        })
+    }
+}
+
+/// 等待 SIGINT（Ctrl-C），Unix 下同时等待 SIGTERM。
+pub async fn shutdown_signal()
+{
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            | Ok(mut signal) => {
+                signal.recv().await;
+            }
+            | Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// 关闭时单个账户的快照，默认账户的 `username` 为 `None`。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountSnapshot
+{
+    pub username: Option<String>,
+    pub balances: Vec<TokenBalance>,
+    pub positions: AccountPositions,
+    pub open_orders: Vec<Order<Open>>,
+    pub cancelled_orders: Vec<Order<Cancelled>>, // 按 [`OpenOrdersOnShutdown::Cancel`] 撤销的挂单
+}
+
+/// 交易所关闭时输出的运行报告。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShutdownReport
+{
+    pub reason: ShutdownReason,
+    pub processed_count: usize, // 已处理的行情数据条目数
+    pub drained_events: usize,  // 关闭时仍在队列中、随后被处理完的客户端事件数
+    pub accounts: Vec<AccountSnapshot>,
+}
+
+impl fmt::Display for ShutdownReport
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f,
+               "HourglassExchange stopped ({:?}): processed {} entries, drained {} events",
+               self.reason, self.processed_count, self.drained_events)?;
+        for account in &self.accounts {
+            write!(f,
+                   "\n  [{}] open orders: {}, cancelled: {}",
+                   account.username.as_deref().unwrap_or("default"),
+                   account.open_orders.len(),
+                   account.cancelled_orders.len())?;
+            for balance in &account.balances {
+                write!(f, ", {}: {}", balance.token, balance.balance.total)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[tokio::test]
+    async fn handle_should_release_waiters_before_and_after_shutdown()
+    {
+        let handle = ShutdownHandle::new();
+        let waiter = tokio::spawn(handle.clone().wait());
+        assert!(!handle.is_shutdown());
+
+        handle.shutdown();
+        waiter.await.unwrap();
+        assert!(handle.is_shutdown());
+        // 已经关闭后再等待立即返回
+        handle.clone().wait().await;
+    }
+
+    #[tokio::test]
+    async fn signal_listener_should_finish_after_shutdown()
+    {
+        let handle = ShutdownHandle::new();
+        let listener = handle.shutdown_on_signal();
+        handle.shutdown();
+        listener.await.unwrap();
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{error::ExchangeError, hourglass::account::account_config::AccountConfig};
+use crate::{
+    error::ExchangeError,
+    hourglass::{account::account_config::AccountConfig, clickhouse_api::clickhouse_config::ClickHouseConfig},
+};
+use serde::Deserialize;
 use std::{fs, path::Path};
 
 /// 读取配置文件，并返回`AccountConfig`结构体实例。
@@ -29,6 +33,31 @@ pub fn read_config_file() -> Result<AccountConfig, ExchangeError>
     Ok(config)
 }
 
+/// `config.toml` 中与 ClickHouse 相关的段落。
+#[derive(Deserialize)]
+struct ClickHouseSection
+{
+    #[serde(default)]
+    clickhouse: ClickHouseConfig,
+}
+
+/// 读取配置文件中的 `[clickhouse]` 段落，并返回`ClickHouseConfig`结构体实例。
+///
+/// 段落缺失时返回默认配置，其余错误与 [`read_config_file`] 一致。
+pub fn read_clickhouse_config() -> Result<ClickHouseConfig, ExchangeError>
+{
+    let config_path = Path::new("config.toml");
+
+    if !config_path.exists() {
+        return Err(ExchangeError::ConfigMissing);
+    }
+
+    let config_content = fs::read_to_string(config_path).map_err(ExchangeError::from)?;
+    let section: ClickHouseSection = toml::from_str(&config_content).map_err(ExchangeError::from)?;
+
+    Ok(section.clickhouse)
+}
+
 // 将`std::io::Error`转换为自定义的`ExecutionError`
 impl From<std::io::Error> for ExchangeError
 {
@@ -10,6 +10,7 @@ use crate::{
         },
         token::Token,
     },
+    dashboard::summary::attribution::AttributionSummary,
     error::ExchangeError,
 };
 use async_trait::async_trait;
@@ -42,6 +43,7 @@ pub trait ClientExecution
     async fn fetch_all_positions(&self) -> Result<AccountPositions, ExchangeError>; // 补全 FetchLongPosition 的实现
     async fn fetch_long_position(&self, instrument: Instrument) -> Result<Option<Position>, ExchangeError>; // 补全 FetchShortPosition 的实现
     async fn fetch_short_position(&self, instrument: Instrument) -> Result<Option<Position>, ExchangeError>;
+    async fn fetch_pnl_attribution(&self) -> Result<AttributionSummary, ExchangeError>;
     // async fn fetch_balance(&self) -> Result<TokenBalance, ExchangeError>; // TODO
     // async fn fetch_positions(&self) -> Result<AccountPositions, ExchangeError>;  // TODO
     async fn open_orders(&self, open_requests: Vec<Order<RequestOpen>>) -> Vec<Result<Order<Open>, ExchangeError>>;
@@ -0,0 +1,267 @@
+use crate::error::ExchangeError;
+use chrono::Utc;
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// 请求头：API key。
+pub const API_KEY_HEADER: &str = "x-hg-apikey";
+/// 请求头：签名时的时间戳（毫秒）。
+pub const TIMESTAMP_HEADER: &str = "x-hg-timestamp";
+/// 请求头：十六进制的 HMAC-SHA256 签名。
+pub const SIGNATURE_HEADER: &str = "x-hg-signature";
+/// 默认允许的请求时间戳与服务器时间的最大偏差。
+pub const DEFAULT_RECV_WINDOW: Duration = Duration::from_secs(5);
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// API key 的权限范围。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiPermission
+{
+    ReadOnly, // 查询挂单、余额与仓位，所有 API key 都具备
+    Trade,    // 开单、撤单与推进回测
+    Deposit,  // 充值
+}
+
+/// 属于某个用户的 API key 与签名用的密钥。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKey
+{
+    pub key: String,
+    pub secret: String,
+    pub username: String,
+    pub permissions: Vec<ApiPermission>,
+}
+
+impl ApiKey
+{
+    pub fn allows(&self, permission: ApiPermission) -> bool
+    {
+        permission == ApiPermission::ReadOnly || self.permissions.contains(&permission)
+    }
+}
+
+/// 计算 `timestamp + method + path + body` 的 HMAC-SHA256 签名，`path` 包含查询参数。
+pub fn sign(secret: &str, timestamp: i64, method: &str, path: &str, body: &[u8]) -> String
+{
+    hex::encode(signature_mac(secret, timestamp, method, path, body).finalize().into_bytes())
+}
+
+fn signature_mac(secret: &str, timestamp: i64, method: &str, path: &str, body: &[u8]) -> HmacSha256
+{
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(method.as_bytes());
+    mac.update(path.as_bytes());
+    mac.update(body);
+    mac
+}
+
+/// 一个待校验的签名请求。
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedRequest<'a>
+{
+    pub api_key: &'a str,
+    pub timestamp: i64,
+    pub signature: &'a str,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub body: &'a [u8],
+}
+
+/// 通过签名校验的调用方，限流按 API key 计算额度。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiCaller
+{
+    pub username: String,
+    pub api_key: String,
+}
+
+/// 时间窗口内已经通过校验的签名，同一个签名只能使用一次。
+#[derive(Debug, Default)]
+struct ReplayCache
+{
+    seen: HashSet<(String, String)>,           // API key 与签名
+    expiry: VecDeque<(i64, (String, String))>, // 按记录顺序排列，时间戳离开窗口后移除
+}
+
+impl ReplayCache
+{
+    /// 记录一次签名，已经记录过时返回 `false`。
+    fn insert(&mut self, api_key: &str, signature: &[u8], now: i64, recv_window: i64) -> bool
+    {
+        while let Some((expires_at, _)) = self.expiry.front() {
+            if *expires_at > now {
+                break;
+            }
+            if let Some((_, entry)) = self.expiry.pop_front() {
+                self.seen.remove(&entry);
+            }
+        }
+        // 签名按字节记录，大小写不同的十六进制视为同一个签名
+        let entry = (api_key.to_string(), hex::encode(signature));
+        if !self.seen.insert(entry.clone()) {
+            return false;
+        }
+        // 请求的时间戳最晚在 `now + 2 * recv_window` 离开窗口
+        self.expiry.push_back((now + 2 * recv_window, entry));
+        true
+    }
+}
+
+/// 在线模式的 API key 存储与请求签名校验。
+#[derive(Debug)]
+pub struct ApiKeyStore
+{
+    keys: DashMap<String, ApiKey>,
+    replays: Mutex<ReplayCache>,
+    pub recv_window: Duration,
+}
+
+impl Default for ApiKeyStore
+{
+    fn default() -> Self
+    {
+        Self::new(DEFAULT_RECV_WINDOW)
+    }
+}
+
+impl ApiKeyStore
+{
+    pub fn new(recv_window: Duration) -> Self
+    {
+        Self { keys: DashMap::new(),
+               replays: Mutex::new(ReplayCache::default()),
+               recv_window }
+    }
+
+    /// 为用户生成新的 API key，密钥只在此处返回一次。
+    pub fn create_key(&self, username: impl Into<String>, permissions: Vec<ApiPermission>) -> ApiKey
+    {
+        let api_key = ApiKey { key: Uuid::new_v4().simple().to_string(),
+                               secret: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+                               username: username.into(),
+                               permissions };
+        self.insert(api_key.clone());
+        api_key
+    }
+
+    pub fn insert(&self, api_key: ApiKey)
+    {
+        self.keys.insert(api_key.key.clone(), api_key);
+    }
+
+    pub fn revoke(&self, key: &str) -> Option<ApiKey>
+    {
+        self.keys.remove(key).map(|(_, api_key)| api_key)
+    }
+
+    /// 校验签名、时间窗口与权限，通过时返回 API key 所属的用户名。
+    ///
+    /// 时间窗口内重复出现的签名视为重放，返回 [`ExchangeError::InvalidSignature`]。
+    pub fn verify(&self, request: &SignedRequest, permission: ApiPermission) -> Result<String, ExchangeError>
+    {
+        let api_key = self.keys.get(request.api_key).ok_or(ExchangeError::AuthenticationFailed)?;
+
+        // 时间戳超出窗口的请求视为重放
+        let now = Utc::now().timestamp_millis();
+        let recv_window = self.recv_window.as_millis() as i64;
+        if (now - request.timestamp).abs() > recv_window {
+            return Err(ExchangeError::InvalidSignature);
+        }
+
+        let signature = hex::decode(request.signature).map_err(|_| ExchangeError::InvalidSignature)?;
+        signature_mac(&api_key.secret, request.timestamp, request.method, request.path, request.body).verify_slice(&signature)
+                                                                                                     .map_err(|_| ExchangeError::InvalidSignature)?;
+
+        if !api_key.allows(permission) {
+            return Err(ExchangeError::InsufficientPermissions);
+        }
+        // 只记录完全通过的请求，被拒绝的请求重放后同样会被拒绝
+        if !self.replays.lock().expect("replay cache lock poisoned").insert(request.api_key, &signature, now, recv_window) {
+            return Err(ExchangeError::InvalidSignature);
+        }
+        Ok(api_key.username.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn signed<'a>(api_key: &'a ApiKey, timestamp: i64, signature: &'a str) -> SignedRequest<'a>
+    {
+        SignedRequest { api_key: &api_key.key,
+                        timestamp,
+                        signature,
+                        method: "POST",
+                        path: "/orders",
+                        body: b"[]" }
+    }
+
+    #[test]
+    fn signature_should_match_known_vector()
+    {
+        // RFC 4231 测试用例 2
+        let mut mac = HmacSha256::new_from_slice(b"Jefe").unwrap();
+        mac.update(b"what do ya want for nothing?");
+        assert_eq!(hex::encode(mac.finalize().into_bytes()), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert_eq!(sign("secret", 1, "GET", "/balances", b""), sign("secret", 1, "GET", "/balances", b""));
+        assert_ne!(sign("secret", 1, "GET", "/balances", b""), sign("secret", 2, "GET", "/balances", b""));
+    }
+
+    #[test]
+    fn verify_should_check_signature_window_and_permissions()
+    {
+        let store = ApiKeyStore::default();
+        let reader = store.create_key("alice", vec![ApiPermission::ReadOnly]);
+        let trader = store.create_key("alice", vec![ApiPermission::Trade]);
+        let now = Utc::now().timestamp_millis();
+
+        let signature = sign(&trader.secret, now, "POST", "/orders", b"[]");
+        assert_eq!(store.verify(&signed(&trader, now, &signature), ApiPermission::Deposit), Err(ExchangeError::InsufficientPermissions));
+        assert_eq!(store.verify(&signed(&trader, now, &signature), ApiPermission::Trade), Ok("alice".to_string()));
+        // 同一个签名在时间窗口内只能使用一次，改变大小写也不行
+        assert_eq!(store.verify(&signed(&trader, now, &signature), ApiPermission::ReadOnly), Err(ExchangeError::InvalidSignature));
+        assert_eq!(store.verify(&signed(&trader, now, &signature.to_uppercase()), ApiPermission::ReadOnly), Err(ExchangeError::InvalidSignature));
+        let signature = sign(&trader.secret, now - 1, "POST", "/orders", b"[]");
+        assert_eq!(store.verify(&signed(&trader, now - 1, &signature), ApiPermission::ReadOnly), Ok("alice".to_string()));
+
+        // 篡改时间戳、使用别的密钥或过期的时间戳都会导致签名无效
+        assert_eq!(store.verify(&signed(&trader, now + 1, &signature), ApiPermission::Trade), Err(ExchangeError::InvalidSignature));
+        let signature = sign(&trader.secret, now, "POST", "/orders", b"[]");
+        assert_eq!(store.verify(&signed(&reader, now, &signature), ApiPermission::ReadOnly), Err(ExchangeError::InvalidSignature));
+        let stale = now - 60_000;
+        let signature = sign(&trader.secret, stale, "POST", "/orders", b"[]");
+        assert_eq!(store.verify(&signed(&trader, stale, &signature), ApiPermission::Trade), Err(ExchangeError::InvalidSignature));
+
+        let reader_signature = sign(&reader.secret, now, "POST", "/orders", b"[]");
+        assert_eq!(store.verify(&signed(&reader, now, &reader_signature), ApiPermission::Trade), Err(ExchangeError::InsufficientPermissions));
+
+        store.revoke(&trader.key);
+        let signature = sign(&trader.secret, now, "POST", "/orders", b"[]");
+        assert_eq!(store.verify(&signed(&trader, now, &signature), ApiPermission::Trade), Err(ExchangeError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn replay_cache_should_forget_signatures_outside_the_window()
+    {
+        let mut cache = ReplayCache::default();
+        assert!(cache.insert("key", b"signature", 0, 5_000));
+        assert!(!cache.insert("key", b"signature", 9_999, 5_000));
+        assert!(cache.insert("other", b"signature", 9_999, 5_000));
+        // 此时原请求的时间戳已经超出窗口，不会再通过时间校验
+        assert!(cache.insert("key", b"signature", 10_000, 5_000));
+        assert_eq!(cache.seen.len(), 2);
+    }
+}
@@ -1,134 +1,427 @@
-/// # NetworkEvent 结构体
-///
-/// `NetworkEvent` 是一个用于在网络中传递事件的结构体，包含了事件的类型和相关的负载数据（payload）。
-/// 客户端可以通过 `NetworkEvent` 来发送不同类型的事件到服务器，以触发相应的处理逻辑。
-///
-/// ## 示例
-///
-/// 下面是一个创建并发送 `"OpenOrders"` 事件的示例：
-///
-/// ```rust
-/// use hourglass::{
-///     common::{
-///         instrument::{kind::InstrumentKind, Instrument},
-///         order::{order_instructions::OrderInstruction, Order},
-///         Side,
-///     },
-///     network::event::NetworkEvent,
-///     Exchange,
-/// };
-/// use serde_json::json;
-/// use std::net::Ipv4Addr;
-/// use uuid::Uuid;
-///
-/// ///
-/// use hourglass::common::order::identification::client_order_id::ClientOrderId;
-/// use hourglass::common::order::states::request_open::RequestOpen;
-///
-/// fn create_open_orders_event() -> NetworkEvent
-/// {
-///     // 1. 确定事件类型
-///     let event_type = "OpenOrders";
-///
-///     // 2. 构建 payload
-///     let orders = vec![Order { instruction: OrderInstruction::Limit,                                  // 订单类型，例如限价单
-///                               exchange: Exchange::Binance,                                           // 交易所名称
-///                               instrument: Instrument::new("BTC", "USDT", InstrumentKind::Perpetual), // 交易对
-///                               timestamp: chrono::Utc::now().timestamp_millis(),                      // 客户端下单时间戳
-///                               cid: Some(ClientOrderId("OJBK".to_string())),                          // 客户端订单 ID
-///                               side: Side::Buy,                                                       // 买卖方向
-///                               state: RequestOpen { reduce_only: false, // 非减仓订单
-///                                                    price: 50000.0,     // 下单价格
-///                                                    size: 1.0           /* 下单数量 */ } }];
-///
-///     // 序列化 orders 为 JSON 字符串
-///     let payload = serde_json::to_string(&orders).expect("Failed to serialize orders");
-///     // 3. 定义 source 和 destination 为 IP 地址
-///     let source = Ipv4Addr::new(192, 168, 110, 95).to_string(); // 假设这是客户端的 IP
-///     let destination = Ipv4Addr::new(192, 168, 110, 130).to_string(); // 假设这是服务器的 IP
-///
-///     // 4. 创建 NetworkEvent
-///     NetworkEvent { event_type: event_type.to_string(),
-///                    payload,
-///                    timestamp: chrono::Utc::now().timestamp(),
-///                    source,
-///                    destination,
-///                    event_id: uuid::Uuid::new_v4().to_string() /* 其他字段可以根据需要添加，例如 `version`, `correlation_id`, `priority`, `retry_count` 等 */ }
-/// }
-/// ```
-///
-/// ## 参数
-///
-/// - `event_type`: 表示事件的类型，例如 `"OpenOrders"`。
-/// - `payload`: 事件的负载数据，通常是 JSON 序列化后的字符串。
-///
-/// ## 方法
-///
-/// - `add_event_type(event_type: &str, payload: &str) -> Self`:
-///   构建并返回一个新的 `NetworkEvent` 实例，设置事件类型和负载数据。
-///
-/// ## 注意事项
-///
-/// `NetworkEvent` 结构体旨在简化事件的创建和传递。使用 `NetworkEvent` 可以确保事件的数据格式统一，便于服务器端的解析和处理。
-///
-/// 客户端在构建 `NetworkEvent` 时，需要确保提供的 `event_type` 是有效的，并且 `payload` 是与该事件类型匹配的有效数据。
-use crate::common::order::Order;
+//! # 网络事件协议
+//!
+//! 客户端通过 `POST /event` 发送 [`NetworkEvent`]，交易所处理后返回带有同一个 `correlation_id` 的 [`NetworkResponse`]。
+//! [`NetworkRequest`] 覆盖了 [`HourglassClientEvent`] 的全部请求，响应体 [`NetworkResponseBody`] 与请求一一对应。
+//!
+//! ## 示例
+//!
+//! 下面是一个创建 `open_orders` 事件的示例：
+//!
+//! ```rust
+//! use hourglass::{
+//!     common::{
+//!         instrument::{kind::InstrumentKind, Instrument},
+//!         order::{identification::client_order_id::ClientOrderId, order_instructions::OrderInstruction, states::request_open::RequestOpen, Order},
+//!         Side,
+//!     },
+//!     network::event::{NetworkEvent, NetworkRequest, PROTOCOL_VERSION},
+//!     Exchange,
+//! };
+//!
+//! let orders = vec![Order { instruction: OrderInstruction::Limit,
+//!                           exchange: Exchange::Binance,
+//!                           instrument: Instrument::new("BTC", "USDT", InstrumentKind::Perpetual),
+//!                           timestamp: chrono::Utc::now().timestamp_millis(),
+//!                           cid: Some(ClientOrderId("OJBK".to_string())),
+//!                           side: Side::Buy,
+//!                           state: RequestOpen { reduce_only: false,
+//!                                                price: 50000.0,
+//!                                                size: 1.0 } }];
+//! let event = NetworkEvent::new(NetworkRequest::OpenOrders(orders));
+//! assert_eq!(event.version, PROTOCOL_VERSION);
+//!
+//! // {"version":1,"correlation_id":"...","timestamp":...,"priority":"normal","retry_count":0,"session_token":null,
+//! //  "request":{"type":"open_orders","payload":[...]}}
+//! let json = serde_json::to_string(&event).unwrap();
+//! assert_eq!(NetworkEvent::decode(json.as_bytes()).unwrap(), event);
+//! ```
+//!
+//! ## 注意事项
+//!
+//! - 需要登录的请求可以在 `session_token` 中携带 session 令牌，与请求头 `Authorization: Bearer <token>` 等价，后者优先。
+//! - API key 签名只能通过请求头携带，见 [`routes`](crate::network::rest_api::routes)。
+//! - 无法解析的事件同样返回 [`NetworkResponse`]，能读出 `correlation_id` 时会原样带回。
+
 use crate::{
-    common::order::states::{request_cancel::RequestCancel, request_open::RequestOpen},
-    hourglass::hourglass_client_local_mode::HourglassClientEvent,
+    common::{
+        account_positions::{AccountPositions, Position, PositionConfig},
+        balance::TokenBalance,
+        instrument::Instrument,
+        order::{
+            states::{cancelled::Cancelled, open::Open, request_cancel::RequestCancel, request_open::RequestOpen},
+            Order,
+        },
+        token::Token,
+    },
+    dashboard::summary::attribution::AttributionSummary,
+    error::ExchangeError,
+    hourglass::{config_request::ConfigurationRequest, hourglass_client_local_mode::HourglassClientEvent},
+    network::{
+        api_key::ApiPermission,
+        login::{LoginForm, LoginRequest, LoginResponse, LogoutRequest, RegisterForm, RegisterRequest},
+        rest_api::{received, status_code, EventSender},
+    },
 };
-use log::error;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// 当前的协议版本，交易所接受 `1..=PROTOCOL_VERSION` 的事件。
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// 事件的优先级，交易所目前按到达顺序处理，仅作为元数据保留。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventPriority
+{
+    Low,
+    #[default]
+    Normal,
+    High,
+}
 
-#[allow(dead_code)]
-#[derive(Debug, Deserialize, Clone, Eq, Ord, PartialEq, PartialOrd)]
+/// 客户端发送的网络事件。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NetworkEvent
 {
-    pub event_type: String,
-    pub payload: String,     // TODO 解析payload的方法未实现。
-    pub timestamp: i64,      // UNIX 时间戳
-    pub source: String,      // 事件来源
-    pub destination: String, // 事件目的地
-    pub event_id: String,    // 唯一事件 ID
+    pub version: u16,
+    pub correlation_id: String, // 由客户端生成，原样带回响应
+    pub timestamp: i64,         // 客户端发送时间（毫秒）
+    #[serde(default)]
+    pub priority: EventPriority,
+    #[serde(default)]
+    pub retry_count: u32, // 客户端重发的次数
+    #[serde(default)]
+    pub session_token: Option<String>,
+    pub request: NetworkRequest,
+}
+
+/// 网络事件中的请求，与 [`HourglassClientEvent`] 的变体一一对应。
+///
+/// [`HourglassClientEvent::Authenticated`] 对应 [`NetworkEvent::session_token`]，[`HourglassClientEvent::Signed`] 由服务器校验请求头后生成。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum NetworkRequest
+{
+    DepositTokens(Vec<(Token, f64)>),
+    FetchOrdersOpen,
+    FetchTokenBalances,
+    FetchTokenBalance(Token),
+    FetchLongPosition(Instrument),
+    FetchShortPosition(Instrument),
+    FetchAllPositions,
+    FetchPnLAttribution,
+    OpenOrders(Vec<Order<RequestOpen>>),
+    CancelOrders(Vec<Order<RequestCancel>>),
+    CancelOrdersAll,
+    ConfigureInstruments(Vec<ConfigurationRequest>),
+    LetItRoll,
+    Register(RegisterForm),
+    Login(LoginForm),
+    Logout
+    {
+        session_token: String,
+    },
+}
+
+/// 结构化的错误响应，`code` 与 REST 接口的 HTTP 状态码一致。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkError
+{
+    pub code: u16,
+    pub message: String,
+    pub error: Option<ExchangeError>, // 交易所返回的原始错误，事件无法解析时为空
+}
+
+impl NetworkError
+{
+    /// 事件本身无效，例如无法解析或协议版本不受支持。
+    pub fn invalid_event(message: impl Into<String>) -> Self
+    {
+        Self { code: 400,
+               message: message.into(),
+               error: None }
+    }
+}
+
+impl From<ExchangeError> for NetworkError
+{
+    fn from(error: ExchangeError) -> Self
+    {
+        Self { code: status_code(&error).as_u16(),
+               message: error.to_string(),
+               error: Some(error) }
+    }
+}
+
+/// 与 [`NetworkRequest`] 对应的响应体，批量请求逐个返回结果。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum NetworkResponseBody
+{
+    DepositTokens(Vec<TokenBalance>),
+    FetchOrdersOpen(Vec<Order<Open>>),
+    FetchTokenBalances(Vec<TokenBalance>),
+    FetchTokenBalance(TokenBalance),
+    FetchLongPosition(Option<Position>),
+    FetchShortPosition(Option<Position>),
+    FetchAllPositions(AccountPositions),
+    FetchPnLAttribution(AttributionSummary),
+    OpenOrders(Vec<Result<Order<Open>, NetworkError>>),
+    CancelOrders(Vec<Result<Order<Cancelled>, NetworkError>>),
+    CancelOrdersAll(Vec<Order<Cancelled>>),
+    ConfigureInstruments(Vec<Result<PositionConfig, NetworkError>>),
+    LetItRoll,
+    Register,
+    Login(LoginResponse),
+    Logout,
+}
+
+/// 交易所对一个 [`NetworkEvent`] 的响应。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkResponse
+{
+    pub version: u16,
+    pub correlation_id: String,
+    pub timestamp: i64, // 交易所响应时间（毫秒）
+    pub result: Result<NetworkResponseBody, NetworkError>,
+}
+
+impl NetworkResponse
+{
+    pub fn new(correlation_id: impl Into<String>, result: Result<NetworkResponseBody, NetworkError>) -> Self
+    {
+        Self { version: PROTOCOL_VERSION,
+               correlation_id: correlation_id.into(),
+               timestamp: chrono::Utc::now().timestamp_millis(),
+               result }
+    }
+}
+
+/// `NetworkRequest` 对应的响应接收端，交易所处理完事件后从这里取回结果。
+#[derive(Debug)]
+pub enum PendingResponse
+{
+    DepositTokens(oneshot::Receiver<Result<Vec<TokenBalance>, ExchangeError>>),
+    FetchOrdersOpen(oneshot::Receiver<Result<Vec<Order<Open>>, ExchangeError>>),
+    FetchTokenBalances(oneshot::Receiver<Result<Vec<TokenBalance>, ExchangeError>>),
+    FetchTokenBalance(oneshot::Receiver<Result<TokenBalance, ExchangeError>>),
+    FetchLongPosition(oneshot::Receiver<Result<Option<Position>, ExchangeError>>),
+    FetchShortPosition(oneshot::Receiver<Result<Option<Position>, ExchangeError>>),
+    FetchAllPositions(oneshot::Receiver<Result<AccountPositions, ExchangeError>>),
+    FetchPnLAttribution(oneshot::Receiver<Result<AttributionSummary, ExchangeError>>),
+    OpenOrders(oneshot::Receiver<Vec<Result<Order<Open>, ExchangeError>>>),
+    CancelOrders(oneshot::Receiver<Vec<Result<Order<Cancelled>, ExchangeError>>>),
+    CancelOrdersAll(oneshot::Receiver<Result<Vec<Order<Cancelled>>, ExchangeError>>),
+    ConfigureInstruments(oneshot::Receiver<Vec<Result<PositionConfig, ExchangeError>>>),
+    LetItRoll, // 推进回测数据没有响应
+    Register(oneshot::Receiver<Result<(), ExchangeError>>),
+    Login(oneshot::Receiver<Result<LoginResponse, ExchangeError>>),
+    Logout(oneshot::Receiver<Result<(), ExchangeError>>),
+}
+
+fn invalid_event(correlation_id: String, message: String) -> Box<NetworkResponse>
+{
+    Box::new(NetworkResponse::new(correlation_id, Err(NetworkError::invalid_event(message))))
+}
+
+fn batch<T>(results: Vec<Result<T, ExchangeError>>) -> Vec<Result<T, NetworkError>>
+{
+    results.into_iter().map(|result| result.map_err(NetworkError::from)).collect()
+}
+
+impl PendingResponse
+{
+    /// 等待交易所返回结果。
+    pub async fn recv(self) -> Result<NetworkResponseBody, ExchangeError>
+    {
+        match self {
+            | PendingResponse::DepositTokens(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::DepositTokens),
+            | PendingResponse::FetchOrdersOpen(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::FetchOrdersOpen),
+            | PendingResponse::FetchTokenBalances(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::FetchTokenBalances),
+            | PendingResponse::FetchTokenBalance(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::FetchTokenBalance),
+            | PendingResponse::FetchLongPosition(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::FetchLongPosition),
+            | PendingResponse::FetchShortPosition(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::FetchShortPosition),
+            | PendingResponse::FetchAllPositions(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::FetchAllPositions),
+            | PendingResponse::FetchPnLAttribution(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::FetchPnLAttribution),
+            | PendingResponse::OpenOrders(response_rx) => Ok(NetworkResponseBody::OpenOrders(batch(received(response_rx.await)?))),
+            | PendingResponse::CancelOrders(response_rx) => Ok(NetworkResponseBody::CancelOrders(batch(received(response_rx.await)?))),
+            | PendingResponse::CancelOrdersAll(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::CancelOrdersAll),
+            | PendingResponse::ConfigureInstruments(response_rx) => Ok(NetworkResponseBody::ConfigureInstruments(batch(received(response_rx.await)?))),
+            | PendingResponse::LetItRoll => Ok(NetworkResponseBody::LetItRoll),
+            | PendingResponse::Register(response_rx) => received(response_rx.await)?.map(|_| NetworkResponseBody::Register),
+            | PendingResponse::Login(response_rx) => received(response_rx.await)?.map(NetworkResponseBody::Login),
+            | PendingResponse::Logout(response_rx) => received(response_rx.await)?.map(|_| NetworkResponseBody::Logout),
+        }
+    }
+}
+
+impl NetworkRequest
+{
+    /// 通过 `POST /event` 发送时 API key 需要具备的权限，与对应的 REST 路由一致。注册、登录与注销不需要 API key。
+    pub fn permission(&self) -> Option<ApiPermission>
+    {
+        match self {
+            | NetworkRequest::DepositTokens(_) => Some(ApiPermission::Deposit),
+            | NetworkRequest::FetchOrdersOpen
+            | NetworkRequest::FetchTokenBalances
+            | NetworkRequest::FetchTokenBalance(_)
+            | NetworkRequest::FetchLongPosition(_)
+            | NetworkRequest::FetchShortPosition(_)
+            | NetworkRequest::FetchAllPositions
+            | NetworkRequest::FetchPnLAttribution => Some(ApiPermission::ReadOnly),
+            | NetworkRequest::OpenOrders(_) | NetworkRequest::CancelOrders(_) | NetworkRequest::CancelOrdersAll | NetworkRequest::ConfigureInstruments(_) | NetworkRequest::LetItRoll => {
+                Some(ApiPermission::Trade)
+            }
+            | NetworkRequest::Register(_) | NetworkRequest::Login(_) | NetworkRequest::Logout { .. } => None,
+        }
+    }
+
+    /// [`NetworkRequest::into_client_event`] 的逆转换，丢弃响应通道，只保留请求内容。
+    ///
+    /// 携带会话或签名的请求返回 `None`，需先由 [`Authenticator::authorise`](crate::network::login::Authenticator::authorise) 拆开。
+    pub fn from_client_event(event: &HourglassClientEvent) -> Option<Self>
+    {
+        let request = match event {
+            | HourglassClientEvent::DepositTokens((deposits, _)) => NetworkRequest::DepositTokens(deposits.clone()),
+            | HourglassClientEvent::FetchOrdersOpen(_) => NetworkRequest::FetchOrdersOpen,
+            | HourglassClientEvent::FetchTokenBalances(_) => NetworkRequest::FetchTokenBalances,
+            | HourglassClientEvent::FetchTokenBalance(token, _) => NetworkRequest::FetchTokenBalance(token.clone()),
+            | HourglassClientEvent::FetchLongPosition(instrument, _) => NetworkRequest::FetchLongPosition(instrument.clone()),
+            | HourglassClientEvent::FetchShortPosition(instrument, _) => NetworkRequest::FetchShortPosition(instrument.clone()),
+            | HourglassClientEvent::FetchAllPositions(_) => NetworkRequest::FetchAllPositions,
+            | HourglassClientEvent::FetchPnLAttribution(_) => NetworkRequest::FetchPnLAttribution,
+            | HourglassClientEvent::OpenOrders((orders, _)) => NetworkRequest::OpenOrders(orders.clone()),
+            | HourglassClientEvent::CancelOrders((orders, _)) => NetworkRequest::CancelOrders(orders.clone()),
+            | HourglassClientEvent::CancelOrdersAll(_) => NetworkRequest::CancelOrdersAll,
+            | HourglassClientEvent::ConfigureInstruments(configs, _) => NetworkRequest::ConfigureInstruments(configs.clone()),
+            | HourglassClientEvent::LetItRoll => NetworkRequest::LetItRoll,
+            | HourglassClientEvent::Register(request) => NetworkRequest::Register(RegisterForm { username: request.username.clone(),
+                                                                                                 email: request.email.clone(),
+                                                                                                 password: request.password.clone() }),
+            | HourglassClientEvent::Login(request) => NetworkRequest::Login(LoginForm { username: request.username.clone(),
+                                                                                        password: request.password.clone() }),
+            | HourglassClientEvent::Logout(request) => NetworkRequest::Logout { session_token: request.session_token.clone() },
+            | HourglassClientEvent::Authenticated(..) | HourglassClientEvent::Signed(..) | HourglassClientEvent::Remote(..) => return None,
+        };
+        Some(request)
+    }
+
+    /// 转换为 [`HourglassClientEvent`]，同时返回用于等待处理结果的 [`PendingResponse`]。
+    pub fn into_client_event(self) -> (HourglassClientEvent, PendingResponse)
+    {
+        match self {
+            | NetworkRequest::DepositTokens(deposits) => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::DepositTokens((deposits, response_tx)), PendingResponse::DepositTokens(response_rx))
+            }
+            | NetworkRequest::FetchOrdersOpen => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::FetchOrdersOpen(response_tx), PendingResponse::FetchOrdersOpen(response_rx))
+            }
+            | NetworkRequest::FetchTokenBalances => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::FetchTokenBalances(response_tx), PendingResponse::FetchTokenBalances(response_rx))
+            }
+            | NetworkRequest::FetchTokenBalance(token) => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::FetchTokenBalance(token, response_tx), PendingResponse::FetchTokenBalance(response_rx))
+            }
+            | NetworkRequest::FetchLongPosition(instrument) => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::FetchLongPosition(instrument, response_tx), PendingResponse::FetchLongPosition(response_rx))
+            }
+            | NetworkRequest::FetchShortPosition(instrument) => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::FetchShortPosition(instrument, response_tx), PendingResponse::FetchShortPosition(response_rx))
+            }
+            | NetworkRequest::FetchAllPositions => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::FetchAllPositions(response_tx), PendingResponse::FetchAllPositions(response_rx))
+            }
+            | NetworkRequest::FetchPnLAttribution => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::FetchPnLAttribution(response_tx), PendingResponse::FetchPnLAttribution(response_rx))
+            }
+            | NetworkRequest::OpenOrders(orders) => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::OpenOrders((orders, response_tx)), PendingResponse::OpenOrders(response_rx))
+            }
+            | NetworkRequest::CancelOrders(orders) => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::CancelOrders((orders, response_tx)), PendingResponse::CancelOrders(response_rx))
+            }
+            | NetworkRequest::CancelOrdersAll => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::CancelOrdersAll(response_tx), PendingResponse::CancelOrdersAll(response_rx))
+            }
+            | NetworkRequest::ConfigureInstruments(configs) => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::ConfigureInstruments(configs, response_tx), PendingResponse::ConfigureInstruments(response_rx))
+            }
+            | NetworkRequest::LetItRoll => (HourglassClientEvent::LetItRoll, PendingResponse::LetItRoll),
+            | NetworkRequest::Register(form) => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::Register(RegisterRequest { username: form.username,
+                                                                  email: form.email,
+                                                                  password: form.password,
+                                                                  response_tx }),
+                 PendingResponse::Register(response_rx))
+            }
+            | NetworkRequest::Login(form) => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::Login(LoginRequest { username: form.username,
+                                                            password: form.password,
+                                                            response_tx }),
+                 PendingResponse::Login(response_rx))
+            }
+            | NetworkRequest::Logout { session_token } => {
+                let (response_tx, response_rx) = oneshot::channel();
+                (HourglassClientEvent::Logout(LogoutRequest { session_token, response_tx }), PendingResponse::Logout(response_rx))
+            }
+        }
+    }
 }
 
-/// FIXME : the current parsing methods of the payload are only for demonstration purposes.
-#[allow(dead_code)]
 impl NetworkEvent
 {
-    pub(crate) fn parse_payload(&self) -> Result<HourglassClientEvent, String>
+    /// 使用当前协议版本与新的 `correlation_id` 创建事件。
+    pub fn new(request: NetworkRequest) -> Self
     {
-        match self.event_type.as_str() {
-            | "FetchOrdersOpen" => {
-                let (response_tx, _response_rx) = oneshot::channel();
-                Ok(HourglassClientEvent::FetchOrdersOpen(response_tx))
-            }
-            | "FetchBalances" => {
-                let (response_tx, _response_rx) = oneshot::channel();
-                Ok(HourglassClientEvent::FetchTokenBalances(response_tx))
-            }
-            | "OpenOrders" => {
-                // 解析 payload 为 Vec<Order<RequestOpen>> 类型
-                let orders: Vec<Order<RequestOpen>> = serde_json::from_str(&self.payload).map_err(|e| format!("Failed to parse OpenOrders payload: {}", e))?;
-                let (response_tx, _response_rx) = oneshot::channel();
-                Ok(HourglassClientEvent::OpenOrders((orders, response_tx)))
-            }
-            | "CancelOrders" => {
-                // 解析 payload 为 Vec<Order<RequestCancel>> 类型
-                let orders: Vec<Order<RequestCancel>> = serde_json::from_str(&self.payload).map_err(|e| format!("Failed to parse CancelOrders payload: {}", e))?;
-                let (response_tx, _response_rx) = oneshot::channel();
-                Ok(HourglassClientEvent::CancelOrders((orders, response_tx)))
-            }
-            | "CancelOrdersAll" => {
-                let (response_tx, _response_rx) = oneshot::channel();
-                Ok(HourglassClientEvent::CancelOrdersAll(response_tx))
-            }
-            | _ => {
-                error!("Unknown event type");
-                Err("Unknown event type".to_string())
+        Self { version: PROTOCOL_VERSION,
+               correlation_id: Uuid::new_v4().to_string(),
+               timestamp: chrono::Utc::now().timestamp_millis(),
+               priority: EventPriority::default(),
+               retry_count: 0,
+               session_token: None,
+               request }
+    }
+
+    /// 解析 JSON 编码的事件，先检查协议版本，失败时返回可以直接发给客户端的错误响应。
+    pub fn decode(bytes: &[u8]) -> Result<Self, Box<NetworkResponse>>
+    {
+        let value: Value = serde_json::from_slice(bytes).map_err(|e| invalid_event(String::new(), format!("Invalid network event: {}", e)))?;
+        let correlation_id = value.get("correlation_id").and_then(Value::as_str).unwrap_or_default().to_string();
+
+        match value.get("version").and_then(Value::as_u64) {
+            | Some(version) if (1..=u64::from(PROTOCOL_VERSION)).contains(&version) => {}
+            | version => {
+                return Err(invalid_event(correlation_id, format!("Unsupported protocol version: {:?}", version)));
             }
         }
+        serde_json::from_value(value).map_err(|e| invalid_event(correlation_id, format!("Invalid network event: {}", e)))
+    }
+
+    /// 交给交易所事件循环处理并等待响应。
+    pub(crate) async fn dispatch(self, event_tx: &EventSender) -> NetworkResponse
+    {
+        let (event, pending) = self.request.into_client_event();
+        let result = match event_tx.send(event) {
+            | Ok(()) => pending.recv().await,
+            | Err(e) => Err(e),
+        };
+        NetworkResponse::new(self.correlation_id, result.map_err(NetworkError::from))
     }
 }
 
@@ -144,17 +437,11 @@ mod tests
         },
         Exchange,
     };
-    use std::net::Ipv4Addr;
-    use uuid::Uuid;
 
-    /// 测试 `NetworkEvent` 的创建和有效性
+    /// 测试 `NetworkEvent` 的编码与转换
     #[test]
     fn test_create_open_orders_event()
     {
-        // 1. 确定事件类型
-        let event_type = "OpenOrders";
-
-        // 2. 构建 payload
         let orders = vec![Order { instruction: OrderInstruction::Limit,                                  // 订单类型，例如限价单
                                   exchange: Exchange::Binance,                                           // 交易所名称
                                   instrument: Instrument::new("BTC", "USDT", InstrumentKind::Perpetual), // 交易对
@@ -164,33 +451,15 @@ mod tests
                                   state: RequestOpen { reduce_only: false, // 非减仓订单
                                                        price: 50000.0,     // 下单价格
                                                        size: 1.0           /* 下单数量 */ } }];
+        let network_event = NetworkEvent::new(NetworkRequest::OpenOrders(orders));
 
-        // 序列化 orders 为 JSON 字符串
-        let payload = serde_json::to_string(&orders).expect("Failed to serialize orders");
-
-        // 3. 定义 source 和 destination 为 IP 地址
-        let source = Ipv4Addr::new(192, 168, 110, 95).to_string(); // 假设这是客户端的 IP
-        let destination = Ipv4Addr::new(192, 168, 110, 130).to_string(); // 假设这是服务器的 IP
-
-        // 4. 创建 NetworkEvent
-        let network_event = NetworkEvent { event_type: event_type.to_string(),
-                                           payload: payload.clone(),
-                                           timestamp: chrono::Utc::now().timestamp(),
-                                           source,
-                                           destination,
-                                           event_id: Uuid::new_v4().to_string() };
-
-        // 验证 `NetworkEvent` 的各个字段
-        assert_eq!(network_event.event_type, event_type);
-        assert_eq!(network_event.payload, payload);
-        assert_eq!(network_event.source, "192.168.110.95");
-        assert_eq!(network_event.destination, "192.168.110.130");
-
-        // 验证事件的解析功能
-        let parsed_event = network_event.parse_payload();
-        assert!(parsed_event.is_ok());
+        // 编码后再解析得到同样的事件
+        let json = serde_json::to_value(&network_event).unwrap();
+        assert_eq!(json["request"]["type"], "open_orders");
+        assert_eq!(NetworkEvent::decode(json.to_string().as_bytes()).unwrap(), network_event);
 
-        if let Ok(HourglassClientEvent::OpenOrders((parsed_orders, _))) = parsed_event {
+        // 验证事件的转换功能
+        if let (HourglassClientEvent::OpenOrders((parsed_orders, _)), PendingResponse::OpenOrders(_)) = network_event.request.into_client_event() {
             assert_eq!(parsed_orders.len(), 1);
             assert_eq!(parsed_orders[0].instruction, OrderInstruction::Limit);
             assert_eq!(parsed_orders[0].exchange, Exchange::Binance);
@@ -203,18 +472,37 @@ mod tests
         }
     }
 
-    /// 测试解析未知事件类型
+    /// 测试解析未知事件类型与不受支持的协议版本
     #[test]
     fn test_unknown_event_type()
     {
-        let network_event = NetworkEvent { event_type: "UnknownEvent".to_string(),
-                                           payload: "".to_string(),
-                                           timestamp: chrono::Utc::now().timestamp(),
-                                           source: "192.168.110.95".to_string(),
-                                           destination: "192.168.110.130".to_string(),
-                                           event_id: Uuid::new_v4().to_string() };
-
-        let parsed_event = network_event.parse_payload();
-        assert!(parsed_event.is_err());
+        let unknown = br#"{"version":1,"correlation_id":"abc","timestamp":0,"request":{"type":"unknown_event"}}"#;
+        let response = NetworkEvent::decode(unknown).unwrap_err();
+        assert_eq!(response.correlation_id, "abc");
+        assert_eq!(response.result.unwrap_err().code, 400);
+
+        let future_version = br#"{"version":99,"correlation_id":"abc","timestamp":0,"request":{"type":"let_it_roll"}}"#;
+        let error = NetworkEvent::decode(future_version).unwrap_err().result.unwrap_err();
+        assert_eq!(error.message, "Unsupported protocol version: Some(99)");
+
+        assert!(NetworkEvent::decode(b"not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn pending_response_should_carry_structured_errors()
+    {
+        let (event, pending) = NetworkRequest::CancelOrders(vec![]).into_client_event();
+        let HourglassClientEvent::CancelOrders((_, response_tx)) = event
+        else {
+            panic!("unexpected event");
+        };
+        response_tx.send(vec![Err(ExchangeError::InvalidID)]).unwrap();
+        let NetworkResponseBody::CancelOrders(results) = pending.recv().await.unwrap()
+        else {
+            panic!("unexpected response body");
+        };
+        assert_eq!(results, vec![Err(NetworkError { code: 400,
+                                                    message: ExchangeError::InvalidID.to_string(),
+                                                    error: Some(ExchangeError::InvalidID) })]);
     }
 }
@@ -1,9 +1,26 @@
-use crate::{error::ExchangeError, hourglass::HourglassExchange};
-use bcrypt::{hash, verify, DEFAULT_COST};
-use chrono::Utc;
-use tokio::sync::oneshot;
+use crate::{
+    error::ExchangeError,
+    hourglass::{
+        clickhouse_api::{queries_operations::ClickHouseClient, query_builder::ClickHouseQueryBuilder},
+        hourglass_client_local_mode::HourglassClientEvent,
+    },
+};
+use async_trait::async_trait;
+use bcrypt::{hash, verify};
+use chrono::{DateTime, Utc};
+use clickhouse::Client;
+use dashmap::{mapref::entry::Entry, DashMap};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{oneshot, Mutex};
 use uuid::Uuid;
 
+/// 默认的会话有效期。
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// bcrypt 允许的最低强度，用于加快测试，不要在生产环境使用。
+pub const TEST_PASSWORD_COST: u32 = 4;
+
 /// 定义用户注册请求
 #[derive(Debug)]
 pub struct RegisterRequest
@@ -24,11 +41,57 @@ pub struct LoginRequest
 }
 
 /// 登录响应结构体
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LoginResponse
 {
     pub session_token: String, // 成功登录后返回的 session 令牌
+    pub expires_at: i64,       // 会话过期的时间戳（毫秒）
+}
+
+/// 注销请求结构体
+#[derive(Debug)]
+pub struct LogoutRequest
+{
+    pub session_token: String,
+    pub response_tx: oneshot::Sender<Result<(), ExchangeError>>,
+}
+
+/// `POST /register` 的请求体
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisterForm
+{
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+/// `POST /login` 的请求体
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoginForm
+{
+    pub username: String,
+    pub password: String,
+}
+
+/// 已登录的会话
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session
+{
+    pub username: String,
+    pub expires_at: i64, // 过期时间戳（毫秒）
+}
+
+/// `{database_prefix}accounts.user_info` 中的一个用户
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserRecord
+{
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub password_hash: String,
+    pub created_at: DateTime<Utc>,
 }
+
 // 定义查询结果的数据结构
 #[derive(Debug, clickhouse::Row, serde::Deserialize)]
 struct UserInfo
@@ -36,70 +99,198 @@ struct UserInfo
     pub(crate) password_hash: String,
 }
 
-/// 注销请求结构体
-#[derive(Debug)]
-pub struct LogoutRequest
+/// 用户信息的存储。默认使用 ClickHouse，测试与本地沙箱可以换成 [`InMemoryUserStore`]。
+#[async_trait]
+pub trait UserStore: Send + Sync
 {
-    pub session_token: String,
-    pub response_tx: oneshot::Sender<Result<(), ExchangeError>>,
+    /// 保存新用户，用户名已存在时返回 [`ExchangeError::UserAlreadyExists`]。
+    async fn insert_user(&self, user: UserRecord) -> Result<(), ExchangeError>;
+    /// 按用户名查询加密后的密码，用户不存在时返回 `None`。
+    async fn fetch_password_hash(&self, username: &str) -> Result<Option<String>, ExchangeError>;
 }
 
-impl Authentication for HourglassExchange
+/// 用户表位于 `{database_prefix}accounts.user_info`。
+///
+/// ClickHouse 没有唯一约束，`insert_user` 在查询与写入期间一直持有客户端的写锁，同一进程内的并发注册依次执行。
+/// 读取时总是取最早注册的一条，其他进程写入的重复记录不会影响登录。
+#[async_trait]
+impl UserStore for ClickHouseClient
 {
-    #[allow(unused)]
-    async fn handle_register(&self, username: String, email: String, password: String) -> Result<(), ExchangeError>
+    async fn insert_user(&self, user: UserRecord) -> Result<(), ExchangeError>
     {
-        // 加密密码
-        let password_hash = hash(password, DEFAULT_COST).map_err(|_| ExchangeError::PasswordHashError)?;
+        let client = self.client.write().await;
+        if self.query_password_hash(&client, &user.username).await?.is_some() {
+            return Err(ExchangeError::UserAlreadyExists(user.username));
+        }
 
-        // 创建插入用户信息的 SQL
-        let insert_query = format!(
-                                   "INSERT INTO accounts.user_info (id, username, email, password_hash, created_at) \
-            VALUES ('{}', '{}', '{}', '{}', '{}')",
-                                   Uuid::new_v4(),
-                                   username,
-                                   email,
-                                   password_hash,
-                                   Utc::now()
-        );
+        // 创建插入用户信息的 SQL，所有用户输入均以参数形式绑定
+        let database = self.construct_accounts_database_name();
+        let insert_query = ClickHouseQueryBuilder::new().insert_into(&database, "user_info", &["id", "username", "email", "password_hash", "created_at"])
+                                                        .values(vec![user.id.into(),
+                                                                     user.username.into(),
+                                                                     user.email.into(),
+                                                                     user.password_hash.into(),
+                                                                     user.created_at.format("%Y-%m-%d %H:%M:%S%.3f").to_string().into()]);
 
         // 执行插入操作
-        self.clickhouse_client.client.read().await.query(&insert_query).execute().await.map_err(|_| ExchangeError::DatabaseError)?;
+        insert_query.query(&client).execute().await.map_err(|_| ExchangeError::DatabaseError)
+    }
 
-        Ok(())
+    async fn fetch_password_hash(&self, username: &str) -> Result<Option<String>, ExchangeError>
+    {
+        let client = self.client.read().await;
+        self.query_password_hash(&client, username).await
     }
+}
 
-    #[allow(unused)]
-    async fn handle_login(&self, username: String, password: String) -> Result<String, ExchangeError>
+impl ClickHouseClient
+{
+    /// 用调用方已经持有锁的 `client` 查询密码，同名的重复记录只认最早注册的一条。
+    async fn query_password_hash(&self, client: &Client, username: &str) -> Result<Option<String>, ExchangeError>
     {
-        // 查询用户的加密密码
-        let select_query = format!("SELECT password_hash FROM accounts.user_info WHERE username = '{}'", username);
+        let database = self.construct_accounts_database_name();
+        let select_query = ClickHouseQueryBuilder::new().select("password_hash")
+                                                        .from(&database, "user_info")
+                                                        .where_eq("username", username)
+                                                        .order("created_at", Some("ASC"))
+                                                        .limit(1);
 
-        // 执行查询并解析结果
-        let result = self.clickhouse_client
-                         .client
-                         .read()
-                         .await
-                         .query(&select_query)
-                         .fetch_one::<UserInfo>()
-                         .await
-                         .map_err(|_| ExchangeError::InvalidCredentials)?;
+        let result = select_query.query(client).fetch_optional::<UserInfo>().await.map_err(|_| ExchangeError::DatabaseError)?;
+        Ok(result.map(|info| info.password_hash))
+    }
+}
 
-        let password_hash = result.password_hash;
+/// 进程内的用户存储，不依赖数据库。
+#[derive(Debug, Default)]
+pub struct InMemoryUserStore
+{
+    users: DashMap<String, UserRecord>,
+}
 
-        // 验证密码
-        if verify(password, &password_hash).map_err(|_| ExchangeError::InvalidCredentials)? {
-            let session_token = Uuid::new_v4().to_string();
-            // 保存会话信息
-            self.active_sessions.lock().await.insert(session_token.clone(), username.parse().unwrap());
-            Ok(session_token)
+impl InMemoryUserStore
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl UserStore for InMemoryUserStore
+{
+    async fn insert_user(&self, user: UserRecord) -> Result<(), ExchangeError>
+    {
+        match self.users.entry(user.username.clone()) {
+            | Entry::Occupied(_) => Err(ExchangeError::UserAlreadyExists(user.username)),
+            | Entry::Vacant(entry) => {
+                entry.insert(user);
+                Ok(())
+            }
         }
-        else {
-            Err(ExchangeError::InvalidCredentials)
+    }
+
+    async fn fetch_password_hash(&self, username: &str) -> Result<Option<String>, ExchangeError>
+    {
+        Ok(self.users.get(username).map(|user| user.password_hash.clone()))
+    }
+}
+
+/// 交易所的用户注册、登录与会话管理。
+///
+/// 各字段都可以廉价复制，注册与登录时可以复制一份放到事件循环之外执行。
+#[derive(Clone)]
+pub struct Authenticator
+{
+    pub active_sessions: Arc<Mutex<HashMap<String, Session>>>, // 存储 session_token 和会话的映射
+    pub user_store: Arc<dyn UserStore>,
+    pub session_ttl: Duration,
+    pub password_cost: u32,    // bcrypt 的计算强度
+    pub require_session: bool, // 为 true 时，订单、余额与仓位请求必须携带有效会话或 API key 签名
+}
+
+impl Authenticator
+{
+    pub fn new(user_store: Arc<dyn UserStore>, session_ttl: Duration, password_cost: u32, require_session: bool) -> Self
+    {
+        Self { active_sessions: Arc::new(Mutex::new(HashMap::new())),
+               user_store,
+               session_ttl,
+               password_cost,
+               require_session }
+    }
+
+    /// 校验客户端事件携带的会话，通过时一并返回会话或 API key 所属的用户名，未通过时直接以错误响应该事件并返回 `None`。
+    pub async fn authorise(&self, event: HourglassClientEvent) -> Option<(Option<String>, HourglassClientEvent)>
+    {
+        match self.authenticate(event).await {
+            | Ok(authorised) => Some(authorised),
+            | Err((event, e)) => {
+                event.reject(e);
+                None
+            }
+        }
+    }
+
+    /// 与 [`Authenticator::authorise`] 相同，但未通过时把拆开会话后的事件与原因交还调用方，由调用方记录后再响应。
+    ///
+    /// [`HourglassClientEvent::Signed`] 已经在 REST 接口校验过签名，视为 API key 所属用户已经登录，不再检查会话。
+    pub async fn authenticate(&self, event: HourglassClientEvent) -> Result<(Option<String>, HourglassClientEvent), (HourglassClientEvent, ExchangeError)>
+    {
+        match event {
+            | HourglassClientEvent::Authenticated(session_token, event) => match self.validate_session(&session_token).await {
+                | Ok(username) => Ok((Some(username), *event)),
+                | Err(e) => Err((*event, e)),
+            },
+            | HourglassClientEvent::Signed(caller, event) => Ok((Some(caller.username), *event)),
+            | event if self.require_session && event.requires_session() => Err((event, ExchangeError::InvalidSession)),
+            | event => Ok((None, event)),
+        }
+    }
+}
+
+impl Authentication for Authenticator
+{
+    async fn handle_register(&self, username: String, email: String, password: String) -> Result<(), ExchangeError>
+    {
+        if username.is_empty() || password.is_empty() {
+            return Err(ExchangeError::InvalidCredentials);
+        }
+
+        // 加密密码，bcrypt 计算较慢，放到阻塞线程池中执行
+        let cost = self.password_cost;
+        let password_hash = tokio::task::spawn_blocking(move || hash(password, cost)).await
+                                                                                     .map_err(|_| ExchangeError::PasswordHashError)?
+                                                                                     .map_err(|_| ExchangeError::PasswordHashError)?;
+
+        self.user_store
+            .insert_user(UserRecord { id: Uuid::new_v4(),
+                                      username,
+                                      email,
+                                      password_hash,
+                                      created_at: Utc::now() })
+            .await
+    }
+
+    async fn handle_login(&self, username: String, password: String) -> Result<LoginResponse, ExchangeError>
+    {
+        // 用户不存在与密码错误返回同样的错误
+        let password_hash = self.user_store.fetch_password_hash(&username).await?.ok_or(ExchangeError::InvalidCredentials)?;
+
+        // 验证密码，与加密同样放到阻塞线程池中执行
+        let verified = tokio::task::spawn_blocking(move || verify(password, &password_hash)).await
+                                                                                            .map_err(|_| ExchangeError::PasswordHashError)?
+                                                                                            .map_err(|_| ExchangeError::InvalidCredentials)?;
+        if !verified {
+            return Err(ExchangeError::InvalidCredentials);
         }
+
+        let session_token = Uuid::new_v4().to_string();
+        let expires_at = Utc::now().timestamp_millis() + self.session_ttl.as_millis() as i64;
+        // 保存会话信息
+        self.active_sessions.lock().await.insert(session_token.clone(), Session { username, expires_at });
+        Ok(LoginResponse { session_token, expires_at })
     }
 
-    #[allow(unused)]
     /// 注销
     async fn handle_logout(&self, session_token: String) -> Result<(), ExchangeError>
     {
@@ -111,14 +302,68 @@ impl Authentication for HourglassExchange
             Err(ExchangeError::InvalidSession)
         }
     }
+
+    async fn validate_session(&self, session_token: &str) -> Result<String, ExchangeError>
+    {
+        let mut sessions = self.active_sessions.lock().await;
+        let session = sessions.get(session_token).ok_or(ExchangeError::InvalidSession)?;
+        if session.expires_at <= Utc::now().timestamp_millis() {
+            sessions.remove(session_token);
+            return Err(ExchangeError::SessionExpired);
+        }
+        Ok(session.username.clone())
+    }
 }
-#[allow(unused)]
-trait Authentication
+
+pub(crate) trait Authentication
 {
     async fn handle_register(&self, username: String, email: String, password: String) -> Result<(), ExchangeError>;
-    async fn handle_login(&self, username: String, password: String) -> Result<String, ExchangeError>;
+    async fn handle_login(&self, username: String, password: String) -> Result<LoginResponse, ExchangeError>;
     // 注销
     async fn handle_logout(&self, session_token: String) -> Result<(), ExchangeError>;
+    // 校验会话并返回用户名，过期的会话会被移除
+    async fn validate_session(&self, session_token: &str) -> Result<String, ExchangeError>;
     // 删除账户
     // async fn delete_account(&self) -> Result<(), ExchangeError>;
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn authenticator(session_ttl: Duration) -> Authenticator
+    {
+        Authenticator::new(Arc::new(InMemoryUserStore::new()), session_ttl, TEST_PASSWORD_COST, true)
+    }
+
+    #[tokio::test]
+    async fn registered_user_should_log_in_and_out()
+    {
+        let authenticator = authenticator(DEFAULT_SESSION_TTL);
+        authenticator.handle_register("alice".to_string(), "alice@example.com".to_string(), "secret".to_string()).await.unwrap();
+        assert_eq!(authenticator.handle_register("alice".to_string(), String::new(), "other".to_string()).await,
+                   Err(ExchangeError::UserAlreadyExists("alice".to_string())));
+
+        assert_eq!(authenticator.handle_login("alice".to_string(), "wrong".to_string()).await, Err(ExchangeError::InvalidCredentials));
+        assert_eq!(authenticator.handle_login("bob".to_string(), "secret".to_string()).await, Err(ExchangeError::InvalidCredentials));
+
+        let response = authenticator.handle_login("alice".to_string(), "secret".to_string()).await.unwrap();
+        assert_eq!(authenticator.validate_session(&response.session_token).await, Ok("alice".to_string()));
+
+        authenticator.handle_logout(response.session_token.clone()).await.unwrap();
+        assert_eq!(authenticator.validate_session(&response.session_token).await, Err(ExchangeError::InvalidSession));
+        assert_eq!(authenticator.handle_logout(response.session_token).await, Err(ExchangeError::InvalidSession));
+    }
+
+    #[tokio::test]
+    async fn expired_session_should_be_removed()
+    {
+        let authenticator = authenticator(Duration::ZERO);
+        authenticator.handle_register("alice".to_string(), "alice@example.com".to_string(), "secret".to_string()).await.unwrap();
+        let response = authenticator.handle_login("alice".to_string(), "secret".to_string()).await.unwrap();
+
+        assert_eq!(authenticator.validate_session(&response.session_token).await, Err(ExchangeError::SessionExpired));
+        assert!(authenticator.active_sessions.lock().await.is_empty());
+    }
+}
@@ -1,7 +1,11 @@
 use std::net::{Ipv4Addr, SocketAddrV4, TcpListener};
 
+pub mod api_key;
 pub mod event;
 pub mod login;
+pub mod rate_limit;
+pub mod rest_api;
+pub mod ws_stream;
 
 /// 检查端口是否已经被使用
 pub fn is_port_in_use(address: ([u8; 4], u16)) -> bool
@@ -0,0 +1,279 @@
+use crate::{error::ExchangeError, hourglass::hourglass_client_local_mode::HourglassClientEvent};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, net::IpAddr, sync::Mutex, time::Duration};
+
+/// 额度的归属：签名请求按 API key，登录请求按账户，其余请求按对端地址。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateLimitKey
+{
+    ApiKey(String),
+    Account(String),
+    Peer(IpAddr),
+    Local, // 进程内未登录的客户端
+}
+
+impl RateLimitKey
+{
+    pub fn new(api_key: Option<String>, username: Option<&str>, peer: Option<IpAddr>) -> Self
+    {
+        match (api_key, username, peer) {
+            | (Some(api_key), ..) => RateLimitKey::ApiKey(api_key),
+            | (None, Some(username), _) => RateLimitKey::Account(username.to_string()),
+            | (None, None, Some(peer)) => RateLimitKey::Peer(peer),
+            | (None, None, None) => RateLimitKey::Local,
+        }
+    }
+}
+
+/// 令牌桶的容量与每秒补充的令牌数。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BucketConfig
+{
+    pub capacity: f64,
+    pub refill_per_second: f64,
+}
+
+impl BucketConfig
+{
+    /// 每 `interval` 最多消耗 `limit` 个令牌。
+    pub fn per(limit: f64, interval: Duration) -> Self
+    {
+        Self { capacity: limit,
+               refill_per_second: limit / interval.as_secs_f64() }
+    }
+}
+
+/// 各类请求的权重，批量开单与撤单按订单数累计。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RequestWeights
+{
+    pub query: f64,      // 查询挂单、余额与仓位
+    pub order: f64,      // 每张开单
+    pub cancel: f64,     // 每张撤单
+    pub cancel_all: f64, // 撤销全部挂单
+    pub transfer: f64,   // 充值
+    pub configure: f64,  // 每个交易工具的配置
+    pub login: f64,      // 注册、登录与注销
+}
+
+impl Default for RequestWeights
+{
+    fn default() -> Self
+    {
+        Self { query: 1.0,
+               order: 1.0,
+               cancel: 1.0,
+               cancel_all: 5.0,
+               transfer: 1.0,
+               configure: 1.0,
+               login: 1.0 }
+    }
+}
+
+/// 限流配置：按权重计的请求限额，以及可选的下单数量限额。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig
+{
+    pub request_weight: BucketConfig,
+    pub order_count: Option<BucketConfig>,
+    pub weights: RequestWeights,
+}
+
+impl Default for RateLimitConfig
+{
+    /// 每分钟 1200 权重、每 10 秒 50 张订单。
+    fn default() -> Self
+    {
+        Self { request_weight: BucketConfig::per(1200.0, Duration::from_secs(60)),
+               order_count: Some(BucketConfig::per(50.0, Duration::from_secs(10))),
+               weights: RequestWeights::default() }
+    }
+}
+
+/// 令牌桶，令牌按交易所时钟连续补充，最多补满容量。
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBucket
+{
+    config: BucketConfig,
+    tokens: f64,
+    last_refill: i64, // 毫秒时间戳
+}
+
+impl TokenBucket
+{
+    pub fn new(config: BucketConfig, now: i64) -> Self
+    {
+        Self { config,
+               tokens: config.capacity,
+               last_refill: now }
+    }
+
+    /// 回放数据的时间戳可能回退，此时不补充令牌。
+    fn refill(&mut self, now: i64)
+    {
+        let elapsed = (now - self.last_refill).max(0) as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * self.config.refill_per_second).min(self.config.capacity);
+        self.last_refill = self.last_refill.max(now);
+    }
+
+    /// 令牌不足时返回需要等待的时间，不消耗令牌。超过容量的请求返回 [`ExchangeError::RequestTooHeavy`]。
+    fn wait_time(&mut self, cost: f64, now: i64) -> Result<Option<Duration>, ExchangeError>
+    {
+        if cost > self.config.capacity {
+            return Err(ExchangeError::RequestTooHeavy(format!("cost {} exceeds capacity {}", cost, self.config.capacity)));
+        }
+        self.refill(now);
+        if cost <= self.tokens {
+            Ok(None)
+        }
+        else if self.config.refill_per_second <= 0.0 {
+            Ok(Some(Duration::MAX))
+        }
+        else {
+            Ok(Some(Duration::from_secs_f64((cost - self.tokens) / self.config.refill_per_second)))
+        }
+    }
+
+    /// 尝试消耗 `cost` 个令牌，令牌不足时返回 [`ExchangeError::ApiLimitReached`]。
+    pub fn try_acquire(&mut self, cost: f64, now: i64) -> Result<(), ExchangeError>
+    {
+        match self.wait_time(cost, now)? {
+            | Some(wait) => Err(limit_reached(wait)),
+            | None => {
+                self.tokens -= cost;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn limit_reached(wait: Duration) -> ExchangeError
+{
+    ExchangeError::ApiLimitReached { retry_after_ms: wait.as_millis().min(u64::MAX as u128) as u64 }
+}
+
+/// 一个 [`RateLimitKey`] 的令牌桶
+#[derive(Debug)]
+struct AccountBuckets
+{
+    request_weight: TokenBucket,
+    order_count: Option<TokenBucket>,
+}
+
+/// 按 [`RateLimitKey`] 限流，本地客户端与 REST 请求都经过交易所事件循环，因此共用同一份额度。
+#[derive(Debug)]
+pub struct RateLimiter
+{
+    pub config: RateLimitConfig,
+    buckets: Mutex<HashMap<RateLimitKey, AccountBuckets>>,
+}
+
+impl RateLimiter
+{
+    pub fn new(config: RateLimitConfig) -> Self
+    {
+        Self { config,
+               buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// 请求的权重与其中包含的订单数量。
+    pub fn cost(&self, event: &HourglassClientEvent) -> (f64, f64)
+    {
+        let weights = &self.config.weights;
+        match event {
+            | HourglassClientEvent::FetchOrdersOpen(_)
+            | HourglassClientEvent::FetchTokenBalances(_)
+            | HourglassClientEvent::FetchTokenBalance(..)
+            | HourglassClientEvent::FetchLongPosition(..)
+            | HourglassClientEvent::FetchShortPosition(..)
+            | HourglassClientEvent::FetchAllPositions(_)
+            | HourglassClientEvent::FetchPnLAttribution(_) => (weights.query, 0.0),
+            | HourglassClientEvent::OpenOrders((requests, _)) => (weights.order * requests.len() as f64, requests.len() as f64),
+            | HourglassClientEvent::CancelOrders((requests, _)) => (weights.cancel * requests.len() as f64, 0.0),
+            | HourglassClientEvent::CancelOrdersAll(_) => (weights.cancel_all, 0.0),
+            | HourglassClientEvent::DepositTokens(_) => (weights.transfer, 0.0),
+            | HourglassClientEvent::ConfigureInstruments(requests, _) => (weights.configure * requests.len() as f64, 0.0),
+            | HourglassClientEvent::Register(_) | HourglassClientEvent::Login(_) | HourglassClientEvent::Logout(_) => (weights.login, 0.0),
+            // 推进回测数据不属于交易所接口
+            | HourglassClientEvent::LetItRoll => (0.0, 0.0),
+            | HourglassClientEvent::Authenticated(_, event) | HourglassClientEvent::Signed(_, event) | HourglassClientEvent::Remote(_, event) => self.cost(event),
+        }
+    }
+
+    /// 按交易所时钟 `now`（毫秒）为 `key` 扣除该请求的额度，超限时返回 [`ExchangeError::ApiLimitReached`]，其中带有建议的重试等待时间。
+    pub fn check(&self, key: RateLimitKey, event: &HourglassClientEvent, now: i64) -> Result<(), ExchangeError>
+    {
+        let (weight, orders) = self.cost(event);
+        self.acquire(key, weight, orders, now)
+    }
+
+    fn acquire(&self, key: RateLimitKey, weight: f64, orders: f64, now: i64) -> Result<(), ExchangeError>
+    {
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        let buckets = buckets.entry(key).or_insert_with(|| AccountBuckets { request_weight: TokenBucket::new(self.config.request_weight, now),
+                                                                            order_count: self.config.order_count.map(|config| TokenBucket::new(config, now)) });
+
+        // 两个桶都有足够的令牌时才同时扣除
+        let order_wait = match (&mut buckets.order_count, orders > 0.0) {
+            | (Some(bucket), true) => bucket.wait_time(orders, now)?,
+            | _ => None,
+        };
+        let wait = buckets.request_weight.wait_time(weight, now)?.max(order_wait);
+        if let Some(wait) = wait {
+            return Err(limit_reached(wait));
+        }
+
+        let _ = buckets.request_weight.try_acquire(weight, now);
+        if let (Some(bucket), true) = (&mut buckets.order_count, orders > 0.0) {
+            let _ = bucket.try_acquire(orders, now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn bucket_should_refill_over_time()
+    {
+        let start = 1_700_000_000_000;
+        let mut bucket = TokenBucket::new(BucketConfig::per(10.0, Duration::from_secs(1)), start);
+        assert_eq!(bucket.try_acquire(10.0, start), Ok(()));
+        assert_eq!(bucket.try_acquire(5.0, start), Err(ExchangeError::ApiLimitReached { retry_after_ms: 500 }));
+
+        let later = start + 500;
+        assert_eq!(bucket.try_acquire(5.0, later), Ok(()));
+        // 时间回退时不补充令牌
+        assert_eq!(bucket.try_acquire(5.0, start), Err(ExchangeError::ApiLimitReached { retry_after_ms: 500 }));
+        // 超过容量的请求永远无法满足，直接拒绝
+        assert!(matches!(bucket.try_acquire(11.0, later + 60_000), Err(ExchangeError::RequestTooHeavy(_))));
+    }
+
+    #[test]
+    fn limiter_should_track_weight_and_order_count_per_key()
+    {
+        let limiter = RateLimiter::new(RateLimitConfig { request_weight: BucketConfig::per(10.0, Duration::from_secs(1)),
+                                                         order_count: Some(BucketConfig::per(3.0, Duration::from_secs(10))),
+                                                         weights: RequestWeights::default() });
+        let now = 1_700_000_000_000;
+        let alice = || RateLimitKey::new(None, Some("alice"), None);
+
+        assert!(limiter.acquire(alice(), 2.0, 2.0, now).is_ok());
+        // 下单数量先超限，权重不会被扣除
+        assert_eq!(limiter.acquire(alice(), 2.0, 2.0, now), Err(ExchangeError::ApiLimitReached { retry_after_ms: 3333 }));
+        assert!(limiter.acquire(alice(), 8.0, 0.0, now).is_ok());
+        assert_eq!(limiter.acquire(alice(), 1.0, 0.0, now), Err(ExchangeError::ApiLimitReached { retry_after_ms: 100 }));
+        assert!(matches!(limiter.acquire(alice(), 0.0, 4.0, now + 60_000), Err(ExchangeError::RequestTooHeavy(_))));
+
+        // 每个账户、API key 与对端地址的额度相互独立
+        assert!(limiter.acquire(RateLimitKey::new(None, Some("bob"), None), 10.0, 3.0, now).is_ok());
+        assert!(limiter.acquire(RateLimitKey::new(Some("key".to_string()), Some("alice"), None), 10.0, 3.0, now).is_ok());
+        let peer = |ip: &str| RateLimitKey::new(None, None, Some(ip.parse().unwrap()));
+        assert!(limiter.acquire(peer("10.0.0.1"), 10.0, 0.0, now).is_ok());
+        assert!(limiter.acquire(peer("10.0.0.2"), 10.0, 0.0, now).is_ok());
+        assert!(limiter.acquire(peer("10.0.0.1"), 1.0, 0.0, now).is_err());
+    }
+}
@@ -0,0 +1,832 @@
+use crate::{
+    common::{
+        instrument::Instrument,
+        order::{
+            identification::OrderId,
+            states::{request_cancel::RequestCancel, request_open::RequestOpen},
+            Order,
+        },
+        token::Token,
+    },
+    error::ExchangeError,
+    hourglass::hourglass_client_local_mode::HourglassClientEvent,
+    network::{
+        api_key::{ApiCaller, ApiKeyStore, ApiPermission, SignedRequest, API_KEY_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+        event::NetworkEvent,
+        login::{LoginForm, LoginRequest, LogoutRequest, RegisterForm, RegisterRequest},
+    },
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::sync::{
+    mpsc::UnboundedSender,
+    oneshot::{self, error::RecvError, Sender},
+};
+use warp::{
+    filters::path::FullPath,
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        HeaderMap, HeaderValue, Method, StatusCode,
+    },
+    hyper::body::Bytes,
+    reply::Response,
+    Filter, Rejection, Reply,
+};
+
+/// 出错时返回的 JSON 响应体。
+///
+/// 交易所返回的错误会原样放在 `error` 中，请求本身无法解析等情况下 `error` 为空。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiError
+{
+    pub message: String,
+    pub error: Option<ExchangeError>,
+}
+
+/// [`ExchangeError`] 对应的 HTTP 状态码。
+pub fn status_code(error: &ExchangeError) -> StatusCode
+{
+    match error {
+        | ExchangeError::OrderNotFound { .. } | ExchangeError::RequestNotFound(_) | ExchangeError::AccountNotFound(_) => StatusCode::NOT_FOUND,
+        | ExchangeError::OrderAlreadyExists(_) | ExchangeError::RequestAlreadyExists(_) | ExchangeError::UserAlreadyExists(_) => StatusCode::CONFLICT,
+        | ExchangeError::InsufficientBalance(_) | ExchangeError::OrderRejected(_) | ExchangeError::PostOnlyViolation(_) | ExchangeError::ReduceOnlyViolation | ExchangeError::InvalidTradeSize => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        | ExchangeError::InvalidDirection
+        | ExchangeError::InvalidID
+        | ExchangeError::InvalidTradingPair(_)
+        | ExchangeError::InvalidDates(_)
+        | ExchangeError::InvalidInstrument(_)
+        | ExchangeError::InvalidRequestOpen(_)
+        | ExchangeError::InvalidRequestCancel(_)
+        | ExchangeError::RequestTooHeavy(_)
+        | ExchangeError::InvalidLeverage(_)
+        | ExchangeError::UnsupportedOrderKind(_)
+        | ExchangeError::UnsupportedInstrumentKind => StatusCode::BAD_REQUEST,
+        | ExchangeError::AuthenticationFailed | ExchangeError::InvalidCredentials | ExchangeError::InvalidSession | ExchangeError::SessionExpired | ExchangeError::InvalidSignature => StatusCode::UNAUTHORIZED,
+        | ExchangeError::InsufficientPermissions => StatusCode::FORBIDDEN,
+        | ExchangeError::ApiLimitReached { .. } => StatusCode::TOO_MANY_REQUESTS,
+        | ExchangeError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        | ExchangeError::ExchangeMaintenance | ExchangeError::ReponseSenderError | ExchangeError::MarketEventChannelClosed => StatusCode::SERVICE_UNAVAILABLE,
+        | ExchangeError::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
+        | _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// 发往交易所事件循环的通道，附带请求头 `Authorization: Bearer <token>` 中的 session 令牌。
+#[derive(Debug, Clone)]
+pub struct EventSender
+{
+    event_tx: UnboundedSender<HourglassClientEvent>,
+    session_token: Option<String>,
+    api_caller: Option<ApiCaller>, // 签名校验通过时的调用方
+    peer: Option<IpAddr>,
+}
+
+impl EventSender
+{
+    pub fn new(event_tx: UnboundedSender<HourglassClientEvent>, session_token: Option<String>) -> Self
+    {
+        Self { event_tx,
+               session_token,
+               api_caller: None,
+               peer: None }
+    }
+
+    /// 需要登录的请求会携带签名校验通过的调用方或 session 令牌，前者优先；知道对端地址时附带在最外层。
+    pub fn send(&self, event: HourglassClientEvent) -> Result<(), ExchangeError>
+    {
+        let event = match &self.api_caller {
+            | Some(caller) => event.signed_by(caller),
+            | None => event.with_session(self.session_token.as_ref()),
+        };
+        self.event_tx.send(event.from_peer(self.peer)).map_err(|_| ExchangeError::ReponseSenderError)
+    }
+}
+
+/// API key 校验失败时的拒绝原因。
+#[derive(Debug)]
+struct Unauthorised(ExchangeError);
+
+impl warp::reject::Reject for Unauthorised {}
+
+/// 请求体无法解析为 JSON 时的拒绝原因。
+#[derive(Debug)]
+struct InvalidBody(String);
+
+impl warp::reject::Reject for InvalidBody {}
+
+/// 为每个路由生成 [`EventSender`]，配置了 [`ApiKeyStore`] 时先校验请求签名与 API key 的权限。
+#[derive(Clone)]
+struct EventGate
+{
+    event_tx: UnboundedSender<HourglassClientEvent>,
+    api_keys: Option<Arc<ApiKeyStore>>,
+}
+
+impl EventGate
+{
+    /// `permission` 为空的路由（注册、登录与注销）不需要 API key。请求体原样返回，供签名后再解析。
+    fn authorise(&self, permission: Option<ApiPermission>) -> impl Filter<Extract = (EventSender, Bytes), Error = Rejection> + Clone
+    {
+        self.authorise_by(move |_| permission)
+    }
+
+    /// 所需权限由请求体决定的路由，例如 `POST /event`。
+    fn authorise_by<F>(&self, permission: F) -> impl Filter<Extract = (EventSender, Bytes), Error = Rejection> + Clone
+        where F: Fn(&Bytes) -> Option<ApiPermission> + Clone + Send + Sync + 'static
+    {
+        let gate = self.clone();
+        let query = warp::query::raw().or(warp::any().map(String::new)).unify();
+        warp::method().and(warp::path::full())
+                      .and(query)
+                      .and(warp::header::headers_cloned())
+                      .and(warp::body::bytes())
+                      .and(warp::addr::remote())
+                      .and_then(move |method: Method, path: FullPath, query: String, headers: HeaderMap, body: Bytes, peer: Option<SocketAddr>| {
+                          let gate = gate.clone();
+                          let permission = permission(&body);
+                          async move {
+                              let session_token = bearer_token(&headers).map_err(|e| warp::reject::custom(Unauthorised(e)))?.map(str::to_string);
+                              let mut event_tx = EventSender::new(gate.event_tx, session_token);
+                              event_tx.peer = peer.map(|peer| peer.ip());
+                              if let (Some(api_keys), Some(permission)) = (gate.api_keys, permission) {
+                                  let path = if query.is_empty() { path.as_str().to_string() } else { format!("{}?{}", path.as_str(), query) };
+                                  let caller = verify_signature(&api_keys, permission, method.as_str(), &path, &headers, &body).map_err(|e| warp::reject::custom(Unauthorised(e)))?;
+                                  event_tx.api_caller = Some(caller);
+                              }
+                              Ok::<_, Rejection>((event_tx, body))
+                          }
+                      })
+                      .untuple_one()
+    }
+
+    fn sender(&self, permission: Option<ApiPermission>) -> impl Filter<Extract = (EventSender,), Error = Rejection> + Clone
+    {
+        self.authorise(permission).map(|event_tx, _body| event_tx)
+    }
+
+    fn json<T>(&self, permission: Option<ApiPermission>) -> impl Filter<Extract = (T, EventSender), Error = Rejection> + Clone
+        where T: DeserializeOwned + Send
+    {
+        self.authorise(permission)
+            .and_then(|event_tx, body: Bytes| async move {
+                match serde_json::from_slice::<T>(&body) {
+                    | Ok(value) => Ok((value, event_tx)),
+                    | Err(e) => Err(warp::reject::custom(InvalidBody(e.to_string()))),
+                }
+            })
+            .untuple_one()
+    }
+}
+
+pub(crate) fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str>
+{
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// 取出 `Authorization: Bearer <token>` 中的会话令牌，没有该请求头时返回 `None`，格式不符时返回 [`ExchangeError::InvalidSession`]。
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Result<Option<&str>, ExchangeError>
+{
+    header_value(headers, AUTHORIZATION.as_str()).map(|value| value.strip_prefix("Bearer ").ok_or(ExchangeError::InvalidSession))
+                                                 .transpose()
+}
+
+/// 从请求头中取出 API key、时间戳与签名并交给 [`ApiKeyStore::verify`]。
+pub(crate) fn verify_signature(api_keys: &ApiKeyStore, permission: ApiPermission, method: &str, path: &str, headers: &HeaderMap, body: &[u8]) -> Result<ApiCaller, ExchangeError>
+{
+    let api_key = header_value(headers, API_KEY_HEADER).ok_or(ExchangeError::AuthenticationFailed)?;
+    let timestamp = header_value(headers, TIMESTAMP_HEADER).and_then(|value| value.parse().ok()).ok_or(ExchangeError::InvalidSignature)?;
+    let signature = header_value(headers, SIGNATURE_HEADER).ok_or(ExchangeError::InvalidSignature)?;
+    let username = api_keys.verify(&SignedRequest { api_key,
+                                                    timestamp,
+                                                    signature,
+                                                    method,
+                                                    path,
+                                                    body },
+                                   permission)?;
+    Ok(ApiCaller { username, api_key: api_key.to_string() })
+}
+
+/// 把事件发送给交易所事件循环，并等待其响应。
+pub async fn dispatch<T>(event_tx: &EventSender, event: impl FnOnce(Sender<T>) -> HourglassClientEvent) -> Result<T, ExchangeError>
+{
+    let (response_tx, response_rx) = oneshot::channel();
+    event_tx.send(event(response_tx))?;
+    received(response_rx.await)
+}
+
+/// 事件循环在响应前退出时，接收端会返回 [`RecvError`]。
+pub(crate) fn received<T>(response: Result<T, RecvError>) -> Result<T, ExchangeError>
+{
+    response.map_err(|_| ExchangeError::ReponseSenderError)
+}
+
+pub(crate) fn error_reply(error: ExchangeError) -> Response
+{
+    let status = status_code(&error);
+    let retry_after = retry_after(&error);
+    let body = ApiError { message: error.to_string(),
+                          error: Some(error) };
+    with_retry_after(warp::reply::with_status(warp::reply::json(&body), status).into_response(), retry_after)
+}
+
+/// 限流时通过 `Retry-After` 告知需要等待的秒数，向上取整。
+fn retry_after(error: &ExchangeError) -> Option<u64>
+{
+    match error {
+        | ExchangeError::ApiLimitReached { retry_after_ms } => Some(retry_after_ms.div_ceil(1000)),
+        | _ => None,
+    }
+}
+
+fn with_retry_after(mut response: Response, retry_after: Option<u64>) -> Response
+{
+    if let Some(seconds) = retry_after {
+        response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+    }
+    response
+}
+
+fn message_reply(message: String, status: StatusCode) -> Response
+{
+    warp::reply::with_status(warp::reply::json(&ApiError { message, error: None }), status).into_response()
+}
+
+pub(crate) fn result_reply<T>(result: Result<T, ExchangeError>) -> Response
+    where T: Serialize
+{
+    match result {
+        | Ok(value) => warp::reply::json(&value).into_response(),
+        | Err(error) => error_reply(error),
+    }
+}
+
+/// 批量请求逐个返回结果，只要有一个成功即为 `200`，全部失败时由第一个错误决定状态码。
+pub(crate) fn batch_reply<T>(results: Result<Vec<Result<T, ExchangeError>>, ExchangeError>) -> Response
+    where T: Serialize
+{
+    let results = match results {
+        | Ok(results) => results,
+        | Err(error) => return error_reply(error),
+    };
+
+    let status = match results.iter().find_map(|result| result.as_ref().err()) {
+        | Some(error) if !results.iter().any(Result::is_ok) => status_code(error),
+        | _ => StatusCode::OK,
+    };
+    warp::reply::with_status(warp::reply::json(&results), status).into_response()
+}
+
+/// 在线模式的 REST 路由：
+///
+/// - `GET /orders`：查询全部挂单。
+/// - `POST /orders`：批量开单，请求体为 `Vec<Order<RequestOpen>>`，返回每个订单的结果。
+/// - `POST /orders/cancel`：批量撤单，请求体为 `Vec<Order<RequestCancel>>`，返回每个订单的结果。
+/// - `DELETE /orders`：撤销全部挂单。
+/// - `DELETE /orders/{id}`：按 `OrderId` 撤销挂单。
+/// - `GET /balances`：查询全部余额。
+/// - `GET /positions`：查询全部仓位。
+/// - `GET /positions/long`、`GET /positions/short`：按 `?base=&quote=&instrument_kind=` 查询单个交易工具的多头或空头仓位。
+/// - `GET /attribution`：查询按交易工具、多空方向与策略拆分的盈亏。
+/// - `POST /deposits`：充值，请求体为 `Vec<(Token, f64)>`。
+/// - `POST /let_it_roll`：推进一条回测数据。`replay` 为 `false`（实时数据源）时返回 [`ExchangeError::NotImplemented`]。
+/// - `POST /event`：请求体为 [`NetworkEvent`]，返回带有同一个 `correlation_id` 的 [`NetworkResponse`](crate::network::event::NetworkResponse)，HTTP 状态码与其中的错误码一致。
+/// - `POST /register`：注册，请求体为 [`RegisterForm`]。
+/// - `POST /login`：登录，请求体为 [`LoginForm`]，返回 [`LoginResponse`](crate::network::login::LoginResponse)。
+/// - `POST /logout`：注销请求头中的会话。
+///
+/// 请求头 `Authorization: Bearer <token>` 中的 session 令牌会随订单、余额与仓位请求一起交给交易所校验。
+///
+/// 传入 [`ApiKeyStore`] 时，除注册、登录与注销外的请求都必须带上 API key 签名（见 [`sign`](crate::network::api_key::sign)），
+/// 查询类请求需要 [`ApiPermission::ReadOnly`]，下单、撤单与推进回测需要 [`ApiPermission::Trade`]，充值需要 [`ApiPermission::Deposit`]。
+/// `POST /event` 按其中的请求类型要求同样的权限，见 [`NetworkRequest::permission`](crate::network::event::NetworkRequest::permission)。
+/// 签名通过的请求视为 API key 所属用户已经登录，交易所要求会话时同样放行。
+pub fn routes(event_tx: UnboundedSender<HourglassClientEvent>, api_keys: Option<Arc<ApiKeyStore>>, replay: bool) -> impl Filter<Extract = (Response,), Error = Infallible> + Clone
+{
+    use ApiPermission::{Deposit, ReadOnly, Trade};
+    let gate = EventGate { event_tx, api_keys };
+
+    let orders_open = warp::path!("orders").and(warp::get()).and(gate.sender(Some(ReadOnly))).then(fetch_orders_open);
+    let open_orders = warp::path!("orders").and(warp::post()).and(gate.json(Some(Trade))).then(open_orders);
+    let cancel_orders = warp::path!("orders" / "cancel").and(warp::post()).and(gate.json(Some(Trade))).then(cancel_orders);
+    let cancel_orders_all = warp::path!("orders").and(warp::delete()).and(gate.sender(Some(Trade))).then(cancel_orders_all);
+    let cancel_order = warp::path!("orders" / u64).and(warp::delete()).and(gate.sender(Some(Trade))).then(cancel_order);
+    let balances = warp::path!("balances").and(warp::get()).and(gate.sender(Some(ReadOnly))).then(fetch_balances);
+    let positions = warp::path!("positions").and(warp::get()).and(gate.sender(Some(ReadOnly))).then(fetch_positions);
+    let long_position = warp::path!("positions" / "long").and(warp::get())
+                                                         .and(warp::query())
+                                                         .and(gate.sender(Some(ReadOnly)))
+                                                         .then(fetch_long_position);
+    let short_position = warp::path!("positions" / "short").and(warp::get())
+                                                           .and(warp::query())
+                                                           .and(gate.sender(Some(ReadOnly)))
+                                                           .then(fetch_short_position);
+    let attribution = warp::path!("attribution").and(warp::get()).and(gate.sender(Some(ReadOnly))).then(fetch_pnl_attribution);
+    let deposits = warp::path!("deposits").and(warp::post()).and(gate.json(Some(Deposit))).then(deposit);
+    let let_it_roll = warp::path!("let_it_roll").and(warp::post()).and(gate.sender(Some(Trade))).then(move |event_tx| let_it_roll(event_tx, replay));
+    let event = warp::path!("event").and(warp::post()).and(gate.authorise_by(event_permission)).then(network_event);
+    let register = warp::path!("register").and(warp::post()).and(gate.json(None)).then(register);
+    let login = warp::path!("login").and(warp::post()).and(gate.json(None)).then(login);
+    let logout = warp::path!("logout").and(warp::post()).and(gate.sender(None)).then(logout);
+
+    orders_open.or(open_orders)
+               .unify()
+               .or(cancel_orders)
+               .unify()
+               .or(cancel_orders_all)
+               .unify()
+               .or(cancel_order)
+               .unify()
+               .or(balances)
+               .unify()
+               .or(positions)
+               .unify()
+               .or(long_position)
+               .unify()
+               .or(short_position)
+               .unify()
+               .or(attribution)
+               .unify()
+               .or(deposits)
+               .unify()
+               .or(let_it_roll)
+               .unify()
+               .or(event)
+               .unify()
+               .or(register)
+               .unify()
+               .or(login)
+               .unify()
+               .or(logout)
+               .unify()
+               .recover(handle_rejection)
+               .unify()
+}
+
+async fn fetch_orders_open(event_tx: EventSender) -> Response
+{
+    result_reply(dispatch(&event_tx, HourglassClientEvent::FetchOrdersOpen).await.and_then(|result| result))
+}
+
+async fn open_orders(orders: Vec<Order<RequestOpen>>, event_tx: EventSender) -> Response
+{
+    batch_reply(dispatch(&event_tx, |response_tx| HourglassClientEvent::OpenOrders((orders, response_tx))).await)
+}
+
+async fn cancel_orders(orders: Vec<Order<RequestCancel>>, event_tx: EventSender) -> Response
+{
+    batch_reply(dispatch(&event_tx, |response_tx| HourglassClientEvent::CancelOrders((orders, response_tx))).await)
+}
+
+async fn cancel_orders_all(event_tx: EventSender) -> Response
+{
+    result_reply(dispatch(&event_tx, HourglassClientEvent::CancelOrdersAll).await.and_then(|result| result))
+}
+
+async fn cancel_order(id: u64, event_tx: EventSender) -> Response
+{
+    let order_id = OrderId(id);
+    let result = async {
+        // 撤单需要交易工具和方向，先从挂单中找到对应订单
+        let orders = dispatch(&event_tx, HourglassClientEvent::FetchOrdersOpen).await??;
+        let request = orders.iter()
+                            .find(|order| order.state.id == order_id)
+                            .map(Order::<RequestCancel>::from)
+                            .ok_or_else(|| ExchangeError::OrderNotFound { client_order_id: None,
+                                                                          order_id: Some(order_id.clone()) })?;
+
+        let mut results = dispatch(&event_tx, |response_tx| HourglassClientEvent::CancelOrders((vec![request], response_tx))).await?;
+        results.pop().unwrap_or_else(|| Err(ExchangeError::InternalError("Missing cancel result".to_string())))
+    };
+    result_reply(result.await)
+}
+
+async fn fetch_balances(event_tx: EventSender) -> Response
+{
+    result_reply(dispatch(&event_tx, HourglassClientEvent::FetchTokenBalances).await.and_then(|result| result))
+}
+
+async fn fetch_positions(event_tx: EventSender) -> Response
+{
+    match dispatch(&event_tx, HourglassClientEvent::FetchAllPositions).await.and_then(|result| result) {
+        | Ok(positions) => warp::reply::json(&positions.all_positions().await).into_response(),
+        | Err(error) => error_reply(error),
+    }
+}
+
+async fn fetch_pnl_attribution(event_tx: EventSender) -> Response
+{
+    result_reply(dispatch(&event_tx, HourglassClientEvent::FetchPnLAttribution).await.and_then(|result| result))
+}
+
+async fn fetch_long_position(instrument: Instrument, event_tx: EventSender) -> Response
+{
+    result_reply(dispatch(&event_tx, |response_tx| HourglassClientEvent::FetchLongPosition(instrument, response_tx)).await
+                                                                                                                    .and_then(|result| result))
+}
+
+async fn fetch_short_position(instrument: Instrument, event_tx: EventSender) -> Response
+{
+    result_reply(dispatch(&event_tx, |response_tx| HourglassClientEvent::FetchShortPosition(instrument, response_tx)).await
+                                                                                                                     .and_then(|result| result))
+}
+
+async fn deposit(deposits: Vec<(Token, f64)>, event_tx: EventSender) -> Response
+{
+    result_reply(dispatch(&event_tx, |response_tx| HourglassClientEvent::DepositTokens((deposits, response_tx))).await
+                                                                                                                .and_then(|result| result))
+}
+
+async fn let_it_roll(event_tx: EventSender, replay: bool) -> Response
+{
+    if !replay {
+        return error_reply(ExchangeError::NotImplemented("LetItRoll requires a replayed data source".to_string()));
+    }
+    result_reply(event_tx.send(HourglassClientEvent::LetItRoll))
+}
+
+/// `POST /event` 按其中的请求决定所需权限，无法解析的事件按交易权限校验。
+fn event_permission(body: &Bytes) -> Option<ApiPermission>
+{
+    NetworkEvent::decode(body).map_or(Some(ApiPermission::Trade), |event| event.request.permission())
+}
+
+async fn network_event(event_tx: EventSender, body: Bytes) -> Response
+{
+    let response = match NetworkEvent::decode(&body) {
+        | Ok(network_event) => {
+            // 没有 `Authorization` 请求头时使用事件中的 session 令牌
+            let event_tx = match (&event_tx.session_token, &network_event.session_token) {
+                | (None, Some(session_token)) => EventSender { session_token: Some(session_token.clone()),
+                                                               ..event_tx },
+                | _ => event_tx,
+            };
+            network_event.dispatch(&event_tx).await
+        }
+        | Err(response) => *response,
+    };
+
+    let (status, retry_after) = match &response.result {
+        | Ok(_) => (StatusCode::OK, None),
+        | Err(error) => (StatusCode::from_u16(error.code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR), error.error.as_ref().and_then(retry_after)),
+    };
+    with_retry_after(warp::reply::with_status(warp::reply::json(&response), status).into_response(), retry_after)
+}
+
+async fn register(form: RegisterForm, event_tx: EventSender) -> Response
+{
+    result_reply(dispatch(&event_tx, |response_tx| {
+                     HourglassClientEvent::Register(RegisterRequest { username: form.username,
+                                                                      email: form.email,
+                                                                      password: form.password,
+                                                                      response_tx })
+                 }).await
+                   .and_then(|result| result))
+}
+
+async fn login(form: LoginForm, event_tx: EventSender) -> Response
+{
+    result_reply(dispatch(&event_tx, |response_tx| {
+                     HourglassClientEvent::Login(LoginRequest { username: form.username,
+                                                                password: form.password,
+                                                                response_tx })
+                 }).await
+                   .and_then(|result| result))
+}
+
+async fn logout(event_tx: EventSender) -> Response
+{
+    let Some(session_token) = event_tx.session_token.clone()
+    else {
+        return error_reply(ExchangeError::InvalidSession);
+    };
+    result_reply(dispatch(&event_tx, |response_tx| HourglassClientEvent::Logout(LogoutRequest { session_token, response_tx })).await
+                                                                                                                              .and_then(|result| result))
+}
+
+async fn handle_rejection(rejection: Rejection) -> Result<Response, Infallible>
+{
+    let reply = if rejection.is_not_found() {
+        message_reply("Not found".to_string(), StatusCode::NOT_FOUND)
+    }
+    else if let Some(Unauthorised(e)) = rejection.find() {
+        error_reply(e.clone())
+    }
+    else if let Some(InvalidBody(e)) = rejection.find() {
+        message_reply(e.clone(), StatusCode::BAD_REQUEST)
+    }
+    else if let Some(e) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        message_reply(e.to_string(), StatusCode::BAD_REQUEST)
+    }
+    else if let Some(e) = rejection.find::<warp::reject::InvalidQuery>() {
+        message_reply(e.to_string(), StatusCode::BAD_REQUEST)
+    }
+    else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        message_reply("Method not allowed".to_string(), StatusCode::METHOD_NOT_ALLOWED)
+    }
+    else {
+        message_reply(format!("{:?}", rejection), StatusCode::INTERNAL_SERVER_ERROR)
+    };
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::{balance::TokenBalance, order::identification::client_order_id::ClientOrderId},
+        hourglass::{shutdown::IdlePolicy, DataSource, HourglassExchange},
+        network::{
+            api_key::ApiKey,
+            event::{NetworkRequest, NetworkResponse, NetworkResponseBody},
+            login::{InMemoryUserStore, LoginResponse, TEST_PASSWORD_COST},
+            rate_limit::{BucketConfig, RateLimitConfig, RequestWeights},
+        },
+        test_utils::create_test_account,
+    };
+    use std::time::Duration;
+    use tokio::sync::{mpsc, Mutex};
+
+    async fn spawn_exchange() -> UnboundedSender<HourglassClientEvent>
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let exchange = HourglassExchange::builder().event_hourglass_rx(event_rx)
+                                                   .account(Arc::new(Mutex::new(create_test_account().await)))
+                                                   .market_event_tx(market_tx)
+                                                   .data_source(DataSource::RealTime(feed_rx))
+                                                   .initiate()
+                                                   .unwrap();
+        tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+        event_tx
+    }
+
+    async fn spawn_session_gated_exchange() -> UnboundedSender<HourglassClientEvent>
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let exchange = HourglassExchange::builder().event_hourglass_rx(event_rx)
+                                                   .account(Arc::new(Mutex::new(create_test_account().await)))
+                                                   .market_event_tx(market_tx)
+                                                   .data_source(DataSource::RealTime(feed_rx))
+                                                   .user_store(Arc::new(InMemoryUserStore::new()))
+                                                   .password_cost(TEST_PASSWORD_COST)
+                                                   .require_session(true)
+                                                   .initiate()
+                                                   .unwrap();
+        tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+        event_tx
+    }
+
+    #[test]
+    fn status_code_should_follow_error_kind()
+    {
+        assert_eq!(status_code(&ExchangeError::OrderNotFound { client_order_id: Some(ClientOrderId("x".to_string())),
+                                                               order_id: None }),
+                   StatusCode::NOT_FOUND);
+        assert_eq!(status_code(&ExchangeError::InsufficientBalance(Token::from("USDT"))), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(status_code(&ExchangeError::InvalidDirection), StatusCode::BAD_REQUEST);
+        assert_eq!(status_code(&ExchangeError::ApiLimitReached { retry_after_ms: 1000 }), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(status_code(&ExchangeError::InternalError("boom".to_string())), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn balances_and_deposits_should_round_trip_through_event_loop()
+    {
+        let routes = routes(spawn_exchange().await, None, true);
+
+        let response = warp::test::request().method("GET").path("/balances").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let balances: Vec<TokenBalance> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(balances.len(), 2);
+
+        let response = warp::test::request().method("POST").path("/deposits").json(&vec![(Token::from("USDT"), 500.0)]).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let deposited: Vec<TokenBalance> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(deposited[0].balance.total, 10_500.0);
+    }
+
+    #[tokio::test]
+    async fn positions_should_be_listed()
+    {
+        let routes = routes(spawn_exchange().await, None, true);
+        let response = warp::test::request().method("GET").path("/positions").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().as_ref(), b"[]");
+    }
+
+    #[tokio::test]
+    async fn position_query_should_parse_instrument()
+    {
+        let routes = routes(spawn_exchange().await, None, true);
+        let response = warp::test::request().method("GET").path("/positions/long?base=BTC&quote=USDT&instrument_kind=perpetual").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().as_ref(), b"null");
+
+        let response = warp::test::request().method("GET").path("/positions/short?base=BTC").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn cancelling_unknown_order_should_return_not_found()
+    {
+        let routes = routes(spawn_exchange().await, None, true);
+        let response = warp::test::request().method("DELETE").path("/orders/42").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: ApiError = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body.error,
+                   Some(ExchangeError::OrderNotFound { client_order_id: None,
+                                                       order_id: Some(OrderId(42)) }));
+    }
+
+    #[tokio::test]
+    async fn malformed_requests_should_be_rejected()
+    {
+        let routes = routes(spawn_exchange().await, None, true);
+
+        let response = warp::test::request().method("POST").path("/orders").body("not json").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = warp::test::request().method("GET").path("/unknown").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn closed_event_loop_should_return_service_unavailable()
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        drop(event_rx);
+        let response = warp::test::request().method("GET").path("/balances").reply(&routes(event_tx, None, true)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn session_gated_requests_should_require_login()
+    {
+        let routes = routes(spawn_session_gated_exchange().await, None, true);
+
+        let response = warp::test::request().method("GET").path("/balances").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let form = RegisterForm { username: "alice".to_string(),
+                                  email: "alice@example.com".to_string(),
+                                  password: "secret".to_string() };
+        let response = warp::test::request().method("POST").path("/register").json(&form).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = warp::test::request().method("POST").path("/register").json(&form).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let form = LoginForm { username: "alice".to_string(),
+                               password: "wrong".to_string() };
+        let response = warp::test::request().method("POST").path("/login").json(&form).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let form = LoginForm { username: "alice".to_string(),
+                               password: "secret".to_string() };
+        let response = warp::test::request().method("POST").path("/login").json(&form).reply(&routes).await;
+        let login: LoginResponse = serde_json::from_slice(response.body()).unwrap();
+        let authorization = format!("Bearer {}", login.session_token);
+
+        let response = warp::test::request().method("GET").path("/balances").header("authorization", &authorization).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // 缺少 Bearer 前缀的令牌直接拒绝
+        let response = warp::test::request().method("GET").path("/balances").header("authorization", &login.session_token).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = warp::test::request().method("POST").path("/logout").header("authorization", &authorization).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = warp::test::request().method("GET").path("/balances").header("authorization", &authorization).reply(&routes).await;
+        let error: ApiError = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(error.error, Some(ExchangeError::InvalidSession));
+    }
+
+    fn signed_request(method: &str, path: &str, body: &str, api_key: &ApiKey) -> warp::test::RequestBuilder
+    {
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        warp::test::request().method(method)
+                             .path(path)
+                             .body(body)
+                             .header(API_KEY_HEADER, &api_key.key)
+                             .header(TIMESTAMP_HEADER, timestamp)
+                             .header(SIGNATURE_HEADER, crate::network::api_key::sign(&api_key.secret, timestamp, method, path, body.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn api_key_signatures_should_be_enforced()
+    {
+        let api_keys = Arc::new(ApiKeyStore::default());
+        let reader = api_keys.create_key("alice", vec![ApiPermission::ReadOnly]);
+        let trader = api_keys.create_key("alice", vec![ApiPermission::Trade]);
+        // 签名通过的请求无需登录
+        let routes = routes(spawn_session_gated_exchange().await, Some(api_keys), true);
+
+        let response = warp::test::request().method("GET").path("/balances").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = signed_request("GET", "/balances", "", &reader).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = signed_request("GET", "/positions/long?base=BTC&quote=USDT&instrument_kind=perpetual", "", &trader).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // 签名之后改动请求体
+        let response = signed_request("POST", "/orders", "[]", &trader).body("[{}]").reply(&routes).await;
+        let error: ApiError = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(error.error, Some(ExchangeError::InvalidSignature));
+
+        let response = signed_request("POST", "/orders", "[]", &reader).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = signed_request("POST", "/deposits", r#"[["USDT", 100.0]]"#, &trader).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = signed_request("POST", "/orders", "[]", &trader).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // `POST /event` 按其中的请求校验权限，不能借此绕过充值的权限
+        let deposit = serde_json::to_string(&NetworkEvent::new(NetworkRequest::DepositTokens(vec![(Token::from("USDT"), 100.0)]))).unwrap();
+        let response = signed_request("POST", "/event", &deposit, &trader).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let balances = serde_json::to_string(&NetworkEvent::new(NetworkRequest::FetchTokenBalances)).unwrap();
+        let response = signed_request("POST", "/event", &balances, &reader).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cancel = serde_json::to_string(&NetworkEvent::new(NetworkRequest::CancelOrdersAll)).unwrap();
+        let response = signed_request("POST", "/event", &cancel, &reader).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_should_carry_retry_after()
+    {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (market_tx, _market_rx) = mpsc::unbounded_channel();
+        let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+        let exchange = HourglassExchange::builder().event_hourglass_rx(event_rx)
+                                                   .account(Arc::new(Mutex::new(create_test_account().await)))
+                                                   .market_event_tx(market_tx)
+                                                   .data_source(DataSource::RealTime(feed_rx))
+                                                   .rate_limit(RateLimitConfig { request_weight: BucketConfig::per(2.0, Duration::from_secs(60)),
+                                                                                 order_count: None,
+                                                                                 weights: RequestWeights::default() })
+                                                   .initiate()
+                                                   .unwrap();
+        tokio::spawn(exchange.run_event_loop(IdlePolicy::Never));
+        let routes = routes(event_tx, None, true);
+
+        for _ in 0..2 {
+            let response = warp::test::request().method("GET").path("/balances").reply(&routes).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = warp::test::request().method("GET").path("/balances").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "30");
+        let error: ApiError = serde_json::from_slice(response.body()).unwrap();
+        assert!(matches!(error.error, Some(ExchangeError::ApiLimitReached { .. })));
+    }
+
+    #[tokio::test]
+    async fn network_events_should_be_answered_with_correlation_id()
+    {
+        let routes = routes(spawn_session_gated_exchange().await, None, true);
+
+        let event = NetworkEvent::new(NetworkRequest::FetchTokenBalances);
+        let response = warp::test::request().method("POST").path("/event").json(&event).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let reply: NetworkResponse = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(reply.correlation_id, event.correlation_id);
+        assert_eq!(reply.result.unwrap_err().error, Some(ExchangeError::InvalidSession));
+
+        // 注册、登录后在事件中携带 session 令牌
+        let register = NetworkEvent::new(NetworkRequest::Register(RegisterForm { username: "alice".to_string(),
+                                                                                 email: "alice@example.com".to_string(),
+                                                                                 password: "secret".to_string() }));
+        let response = warp::test::request().method("POST").path("/event").json(&register).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let login = NetworkEvent::new(NetworkRequest::Login(LoginForm { username: "alice".to_string(),
+                                                                        password: "secret".to_string() }));
+        let response = warp::test::request().method("POST").path("/event").json(&login).reply(&routes).await;
+        let reply: NetworkResponse = serde_json::from_slice(response.body()).unwrap();
+        let Ok(NetworkResponseBody::Login(login)) = reply.result
+        else {
+            panic!("unexpected login reply: {:?}", reply.result);
+        };
+
+        let event = NetworkEvent { session_token: Some(login.session_token),
+                                   ..NetworkEvent::new(NetworkRequest::FetchTokenBalances) };
+        let response = warp::test::request().method("POST").path("/event").json(&event).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let reply: NetworkResponse = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(reply.correlation_id, event.correlation_id);
+        assert!(matches!(reply.result, Ok(NetworkResponseBody::FetchTokenBalances(balances)) if balances.len() == 2));
+
+        let response = warp::test::request().method("POST").path("/event").body(r#"{"version":1,"correlation_id":"abc"}"#).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let reply: NetworkResponse = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(reply.correlation_id, "abc");
+    }
+}
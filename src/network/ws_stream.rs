@@ -0,0 +1,550 @@
+use crate::{
+    common::{
+        account_positions::Position,
+        event::{AccountEvent, AccountEventKind},
+    },
+    error::ExchangeError,
+    hourglass::clickhouse_api::datatype::clickhouse_trade_data::MarketTrade,
+    hourglass_log::warn,
+    network::{
+        api_key::{ApiKeyStore, ApiPermission, API_KEY_HEADER},
+        login::{Authentication, Authenticator},
+        rest_api::{bearer_token, error_reply, header_value, verify_signature},
+    },
+};
+use dashmap::{DashMap, DashSet};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::Arc};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{self, UnboundedSender},
+    },
+    time::{self, Duration},
+};
+use warp::{
+    filters::path::FullPath,
+    http::HeaderMap,
+    reply::Response,
+    ws::{Message, WebSocket, Ws},
+    Filter, Rejection, Reply,
+};
+
+/// 可订阅的推送频道，`market_trades` 需要附带交易对，例如 `{"channel": "market_trades", "symbol": "BTCUSDT"}`。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "channel", content = "symbol", rename_all = "snake_case")]
+pub enum StreamChannel
+{
+    Orders,
+    Trades,
+    Balances,
+    Positions,
+    MarketTrades(String),
+}
+
+impl StreamChannel
+{
+    /// 账户事件所属的频道，不需要推送的事件返回 `None`。
+    pub fn of_account_event(kind: &AccountEventKind) -> Option<Self>
+    {
+        match kind {
+            | AccountEventKind::OrdersOpen(_) | AccountEventKind::OrdersCancelled(_) | AccountEventKind::OrdersFilled(_) | AccountEventKind::OrdersPartiallyFilled(_) => Some(StreamChannel::Orders),
+            | AccountEventKind::Trade(_) => Some(StreamChannel::Trades),
+            | AccountEventKind::Balance(_) | AccountEventKind::Balances(_) => Some(StreamChannel::Balances),
+            | AccountEventKind::Positions(_) => Some(StreamChannel::Positions),
+            | AccountEventKind::AccountConfig(_) => None,
+        }
+    }
+
+    /// 市场成交为公开频道，其余频道只推送给所属账户的连接。
+    pub fn is_public(&self) -> bool
+    {
+        matches!(self, StreamChannel::MarketTrades(_))
+    }
+}
+
+/// 推送的数据。仓位表无法直接序列化，因此 `positions` 频道推送的是仓位快照。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StreamPayload
+{
+    Account(AccountEvent),
+    Positions(Vec<Position>),
+    Market(MarketTrade),
+}
+
+/// 客户端发送的请求。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StreamRequest
+{
+    Subscribe
+    {
+        channels: Vec<StreamChannel>,
+    },
+    Unsubscribe
+    {
+        channels: Vec<StreamChannel>,
+    },
+    Ping,
+}
+
+/// 服务端推送的消息。
+///
+/// 每个频道的 `sequence` 从 1 开始连续递增，客户端发现序号不连续时即可判断丢失了推送。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamMessage
+{
+    Subscribed
+    {
+        channels: Vec<StreamChannel>
+    },
+    Unsubscribed
+    {
+        channels: Vec<StreamChannel>
+    },
+    Heartbeat
+    {
+        timestamp: i64
+    },
+    Event
+    {
+        channel: StreamChannel, sequence: u64, payload: StreamPayload
+    },
+    Error
+    {
+        message: String
+    },
+}
+
+/// 广播中的一条推送，`owner` 为私有频道所属的租户，默认账户与公开频道为 `None`。
+#[derive(Debug, Clone)]
+pub struct Published
+{
+    pub owner: Option<String>,
+    pub channel: StreamChannel,
+    pub sequence: u64,
+    pub payload: StreamPayload,
+}
+
+/// 推送连接的身份，决定可以订阅哪些频道。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Viewer
+{
+    /// 未登录的连接只能订阅市场成交
+    Anonymous,
+    /// 已登录的连接还可以订阅所属账户的私有频道，默认账户为 `None`
+    Account(Option<String>),
+}
+
+impl Viewer
+{
+    pub fn permits(&self, channel: &StreamChannel) -> bool
+    {
+        channel.is_public() || matches!(self, Viewer::Account(_))
+    }
+
+    pub fn sees(&self, published: &Published) -> bool
+    {
+        match self {
+            | _ if published.channel.is_public() => true,
+            | Viewer::Account(owner) => *owner == published.owner,
+            | Viewer::Anonymous => false,
+        }
+    }
+}
+
+/// 在线模式的推送中心，把账户事件和市场成交广播给 WebSocket 客户端。
+///
+/// 私有频道的推送带有所属账户，只有该账户登录后的连接能收到。
+#[derive(Debug)]
+pub struct StreamHub
+{
+    sender: broadcast::Sender<Published>,
+    sequences: DashMap<(Option<String>, StreamChannel), u64>, // 每个账户的私有频道各自计数
+    tenants: DashSet<String>,
+    heartbeat_interval: Duration,
+}
+
+impl StreamHub
+{
+    /// `capacity` 为每个客户端最多积压的消息数，超出后最旧的消息会被丢弃。
+    pub fn new(capacity: usize, heartbeat_interval: Duration) -> Self
+    {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender,
+               sequences: DashMap::new(),
+               tenants: DashSet::new(),
+               heartbeat_interval }
+    }
+
+    /// 广播公开频道或默认账户的推送，返回分配的序号。
+    pub fn publish(&self, channel: StreamChannel, payload: StreamPayload) -> u64
+    {
+        self.publish_to(None, channel, payload)
+    }
+
+    /// 按账户与频道分配序号并广播，返回分配的序号。公开频道忽略 `owner`。
+    pub fn publish_to(&self, owner: Option<String>, channel: StreamChannel, payload: StreamPayload) -> u64
+    {
+        let owner = owner.filter(|_| !channel.is_public());
+        let sequence = {
+            let mut sequence = self.sequences.entry((owner.clone(), channel.clone())).or_insert(0);
+            *sequence += 1;
+            *sequence
+        };
+        // 没有客户端连接时发送会失败，直接忽略
+        let _ = self.sender.send(Published { owner, channel, sequence, payload });
+        sequence
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Published>
+    {
+        self.sender.subscribe()
+    }
+
+    /// 登录用户对应的推送身份，与交易所按用户名选择账户的规则一致：
+    /// 没有租户时都使用默认账户，有租户时只能查看自己的账户。
+    pub fn viewer(&self, username: String) -> Result<Viewer, ExchangeError>
+    {
+        if self.tenants.is_empty() {
+            Ok(Viewer::Account(None))
+        }
+        else if self.tenants.contains(&username) {
+            Ok(Viewer::Account(Some(username)))
+        }
+        else {
+            Err(ExchangeError::AccountNotFound(username))
+        }
+    }
+
+    /// 返回一个新的成交发送端，写入的成交先按交易对广播，再转发给 `downstream`。
+    pub fn tee_market_trades(self: &Arc<Self>, downstream: UnboundedSender<MarketTrade>) -> UnboundedSender<MarketTrade>
+    {
+        let (market_tx, mut market_rx) = mpsc::unbounded_channel::<MarketTrade>();
+        let hub = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some(trade) = market_rx.recv().await {
+                hub.publish(StreamChannel::MarketTrades(trade.symbol.clone()), StreamPayload::Market(trade.clone()));
+                let _ = downstream.send(trade);
+            }
+        });
+        market_tx
+    }
+
+    /// 返回一个新的账户事件发送端，写入的事件先以 `owner` 的名义广播，再转发给 `downstream`。
+    /// 默认账户的 `owner` 为 `None`，租户为其用户名。
+    ///
+    /// 成交之后的仓位快照由账户在处理成交时一并发送，见 [`HourglassAccount::publish_positions`](crate::hourglass::account::HourglassAccount::publish_positions)。
+    pub fn tee_account_events(self: &Arc<Self>, downstream: UnboundedSender<AccountEvent>, owner: Option<String>) -> UnboundedSender<AccountEvent>
+    {
+        if let Some(owner) = &owner {
+            self.tenants.insert(owner.clone());
+        }
+        let (account_tx, mut account_rx) = mpsc::unbounded_channel::<AccountEvent>();
+        let hub = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some(event) = account_rx.recv().await {
+                match (&event.kind, StreamChannel::of_account_event(&event.kind)) {
+                    | (AccountEventKind::Positions(positions), _) => {
+                        hub.publish_to(owner.clone(), StreamChannel::Positions, StreamPayload::Positions(positions.all_positions().await));
+                    }
+                    | (_, Some(channel)) => {
+                        hub.publish_to(owner.clone(), channel, StreamPayload::Account(event.clone()));
+                    }
+                    | (_, None) => {}
+                }
+                let _ = downstream.send(event);
+            }
+        });
+        account_tx
+    }
+}
+
+/// `GET /ws` 升级为 WebSocket 连接。
+///
+/// 请求头 `Authorization: Bearer <token>` 中的 session 令牌有效时可以订阅所属账户的私有频道，
+/// 令牌无效时拒绝升级，不带令牌的连接只能订阅市场成交。
+///
+/// 传入 [`ApiKeyStore`] 时与 REST 接口一样，私有频道需要 [`ApiPermission::ReadOnly`] 的 API key 签名（请求体为空），
+/// 连接的身份为 API key 所属的用户；不带 API key 的连接只能订阅市场成交。
+pub fn route(hub: Arc<StreamHub>, authenticator: Authenticator, api_keys: Option<Arc<ApiKeyStore>>) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+{
+    let query = warp::query::raw().or(warp::any().map(String::new)).unify();
+    warp::path!("ws").and(warp::ws())
+                     .and(warp::path::full())
+                     .and(query)
+                     .and(warp::header::headers_cloned())
+                     .then(move |ws: Ws, path: FullPath, query: String, headers: HeaderMap| {
+                         let hub = Arc::clone(&hub);
+                         let authenticator = authenticator.clone();
+                         let api_keys = api_keys.clone();
+                         async move {
+                             let path = if query.is_empty() { path.as_str().to_string() } else { format!("{}?{}", path.as_str(), query) };
+                             match identify(&hub, &authenticator, api_keys.as_deref(), &path, &headers).await {
+                                 | Ok(viewer) => ws.on_upgrade(move |socket| serve_client(socket, hub, viewer)).into_response(),
+                                 | Err(error) => error_reply(error),
+                             }
+                         }
+                     })
+}
+
+async fn identify(hub: &StreamHub, authenticator: &Authenticator, api_keys: Option<&ApiKeyStore>, path: &str, headers: &HeaderMap) -> Result<Viewer, ExchangeError>
+{
+    if let Some(api_keys) = api_keys {
+        if header_value(headers, API_KEY_HEADER).is_none() {
+            return Ok(Viewer::Anonymous);
+        }
+        let caller = verify_signature(api_keys, ApiPermission::ReadOnly, "GET", path, headers, &[])?;
+        return hub.viewer(caller.username);
+    }
+    match bearer_token(headers)? {
+        | Some(session_token) => {
+            let username = authenticator.validate_session(session_token).await?;
+            hub.viewer(username)
+        }
+        | None => Ok(Viewer::Anonymous),
+    }
+}
+
+async fn serve_client(socket: WebSocket, hub: Arc<StreamHub>, viewer: Viewer)
+{
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    let mut events = hub.subscribe();
+    let mut subscriptions = HashSet::new();
+    let mut heartbeat = time::interval(hub.heartbeat_interval);
+
+    loop {
+        let outgoing = tokio::select! {
+            message = ws_rx.next() => match message {
+                Some(Ok(message)) if message.is_text() => handle_request(message.to_str().unwrap_or_default(), &viewer, &mut subscriptions),
+                Some(Ok(message)) if message.is_close() => break,
+                Some(Ok(_)) => continue,
+                _ => break,
+            },
+            event = events.recv() => match event {
+                Ok(published) if subscriptions.contains(&published.channel) && viewer.sees(&published) => StreamMessage::Event { channel: published.channel,
+                                                                                                                                  sequence: published.sequence,
+                                                                                                                                  payload: published.payload },
+                Ok(_) => continue,
+                // 积压过多时跳过旧消息，客户端可通过序号发现缺口
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("WebSocket client lagged behind by {} messages", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = heartbeat.tick() => StreamMessage::Heartbeat { timestamp: chrono::Utc::now().timestamp_millis() },
+        };
+
+        match serde_json::to_string(&outgoing) {
+            | Ok(text) => {
+                if ws_tx.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            }
+            | Err(e) => warn!("Failed to serialise stream message: {}", e),
+        }
+    }
+}
+
+/// 订阅确认只列出允许订阅的频道，匿名连接订阅的私有频道会被忽略。
+fn handle_request(text: &str, viewer: &Viewer, subscriptions: &mut HashSet<StreamChannel>) -> StreamMessage
+{
+    match serde_json::from_str::<StreamRequest>(text) {
+        | Ok(StreamRequest::Subscribe { channels }) => {
+            let channels: Vec<_> = channels.into_iter().filter(|channel| viewer.permits(channel)).collect();
+            subscriptions.extend(channels.iter().cloned());
+            StreamMessage::Subscribed { channels }
+        }
+        | Ok(StreamRequest::Unsubscribe { channels }) => {
+            channels.iter().for_each(|channel| {
+                               subscriptions.remove(channel);
+                           });
+            StreamMessage::Unsubscribed { channels }
+        }
+        | Ok(StreamRequest::Ping) => StreamMessage::Heartbeat { timestamp: chrono::Utc::now().timestamp_millis() },
+        | Err(e) => StreamMessage::Error { message: format!("Invalid request: {}", e) },
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::balance::{Balance, TokenBalance},
+        network::login::{InMemoryUserStore, DEFAULT_SESSION_TTL, TEST_PASSWORD_COST},
+        Exchange,
+    };
+
+    fn balance_event(total: f64) -> AccountEvent
+    {
+        AccountEvent { exchange_timestamp: 0,
+                       exchange: Exchange::Hourglass,
+                       kind: AccountEventKind::Balance(TokenBalance::new("USDT", Balance::new(total, total))) }
+    }
+
+    fn market_trade(symbol: &str) -> MarketTrade
+    {
+        MarketTrade { exchange: "binance-futures".to_string(),
+                      symbol: symbol.to_string(),
+                      side: "buy".to_string(),
+                      price: 100.0,
+                      timestamp: 1,
+                      amount: 1.0 }
+    }
+
+    async fn recv_message(client: &mut warp::test::WsClient) -> StreamMessage
+    {
+        let message = client.recv().await.unwrap();
+        serde_json::from_str(message.to_str().unwrap()).unwrap()
+    }
+
+    fn authenticator() -> Authenticator
+    {
+        Authenticator::new(Arc::new(InMemoryUserStore::new()), DEFAULT_SESSION_TTL, TEST_PASSWORD_COST, false)
+    }
+
+    /// 注册并登录，返回 session 令牌。
+    async fn session_token(authenticator: &Authenticator, username: &str) -> String
+    {
+        authenticator.handle_register(username.to_string(), String::new(), "secret".to_string()).await.unwrap();
+        authenticator.handle_login(username.to_string(), "secret".to_string()).await.unwrap().session_token
+    }
+
+    /// 建立连接并订阅全部私有频道与 `ETHUSDT` 的成交，返回订阅确认中的频道数。
+    async fn connect(hub: &Arc<StreamHub>, authenticator: &Authenticator, session_token: Option<&str>) -> (warp::test::WsClient, usize)
+    {
+        let mut request = warp::test::ws().path("/ws");
+        if let Some(session_token) = session_token {
+            request = request.header("authorization", format!("Bearer {}", session_token));
+        }
+        let mut client = request.handshake(route(Arc::clone(hub), authenticator.clone(), None)).await.unwrap();
+        // 第一次心跳会立即触发
+        assert!(matches!(recv_message(&mut client).await, StreamMessage::Heartbeat { .. }));
+
+        client.send_text(r#"{"op":"subscribe","channels":[{"channel":"orders"},{"channel":"balances"},{"channel":"positions"},{"channel":"market_trades","symbol":"ETHUSDT"}]}"#)
+              .await;
+        match recv_message(&mut client).await {
+            | StreamMessage::Subscribed { channels } => (client, channels.len()),
+            | other => panic!("Unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn channel_should_serialise_with_symbol()
+    {
+        let channel = StreamChannel::MarketTrades("BTCUSDT".to_string());
+        assert_eq!(serde_json::to_string(&channel).unwrap(), r#"{"channel":"market_trades","symbol":"BTCUSDT"}"#);
+        assert_eq!(serde_json::from_str::<StreamChannel>(r#"{"channel":"orders"}"#).unwrap(), StreamChannel::Orders);
+    }
+
+    #[test]
+    fn sequences_should_be_counted_per_channel()
+    {
+        let hub = StreamHub::new(16, Duration::from_secs(60));
+        assert_eq!(hub.publish(StreamChannel::Balances, StreamPayload::Account(balance_event(1.0))), 1);
+        assert_eq!(hub.publish(StreamChannel::Balances, StreamPayload::Account(balance_event(2.0))), 2);
+        assert_eq!(hub.publish(StreamChannel::MarketTrades("BTCUSDT".to_string()), StreamPayload::Market(market_trade("BTCUSDT"))), 1);
+    }
+
+    #[tokio::test]
+    async fn client_should_only_receive_subscribed_channels()
+    {
+        let hub = Arc::new(StreamHub::new(16, Duration::from_secs(60)));
+        let authenticator = authenticator();
+        let session_token = session_token(&authenticator, "alice").await;
+        let mut client = warp::test::ws().path("/ws")
+                                         .header("authorization", format!("Bearer {}", session_token))
+                                         .handshake(route(Arc::clone(&hub), authenticator, None))
+                                         .await
+                                         .unwrap();
+        // 第一次心跳会立即触发
+        assert!(matches!(recv_message(&mut client).await, StreamMessage::Heartbeat { .. }));
+
+        client.send_text(r#"{"op":"subscribe","channels":[{"channel":"balances"},{"channel":"market_trades","symbol":"ETHUSDT"}]}"#)
+              .await;
+        assert!(matches!(recv_message(&mut client).await, StreamMessage::Subscribed { channels } if channels.len() == 2));
+
+        let (downstream_tx, mut downstream_rx) = mpsc::unbounded_channel();
+        let market_tx = hub.tee_market_trades(downstream_tx);
+        market_tx.send(market_trade("BTCUSDT")).unwrap();
+        market_tx.send(market_trade("ETHUSDT")).unwrap();
+        // 本地客户端仍然能收到全部成交
+        assert_eq!(downstream_rx.recv().await.unwrap().symbol, "BTCUSDT");
+        assert_eq!(downstream_rx.recv().await.unwrap().symbol, "ETHUSDT");
+        hub.publish(StreamChannel::Balances, StreamPayload::Account(balance_event(10.0)));
+
+        match recv_message(&mut client).await {
+            | StreamMessage::Event { channel,
+                                     sequence,
+                                     payload: StreamPayload::Market(trade), } => {
+                assert_eq!(channel, StreamChannel::MarketTrades("ETHUSDT".to_string()));
+                assert_eq!(sequence, 1);
+                assert_eq!(trade.symbol, "ETHUSDT");
+            }
+            | other => panic!("Unexpected message: {:?}", other),
+        }
+        match recv_message(&mut client).await {
+            | StreamMessage::Event { channel,
+                                     sequence,
+                                     payload: StreamPayload::Account(event), } => {
+                assert_eq!(channel, StreamChannel::Balances);
+                assert_eq!(sequence, 1);
+                assert!(matches!(event.kind, AccountEventKind::Balance(balance) if balance.balance.total == 10.0));
+            }
+            | other => panic!("Unexpected message: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_request_should_return_error_message()
+    {
+        let hub = Arc::new(StreamHub::new(16, Duration::from_secs(60)));
+        let mut client = warp::test::ws().path("/ws").handshake(route(hub, authenticator(), None)).await.unwrap();
+        assert!(matches!(recv_message(&mut client).await, StreamMessage::Heartbeat { .. }));
+
+        client.send_text(r#"{"op":"subscribe","channels":[{"channel":"unknown"}]}"#).await;
+        assert!(matches!(recv_message(&mut client).await, StreamMessage::Error { .. }));
+
+        client.send_text(r#"{"op":"ping"}"#).await;
+        assert!(matches!(recv_message(&mut client).await, StreamMessage::Heartbeat { .. }));
+    }
+
+    #[tokio::test]
+    async fn private_channels_should_only_reach_owning_account()
+    {
+        let hub = Arc::new(StreamHub::new(16, Duration::from_secs(60)));
+        let authenticator = authenticator();
+        let (alice_tx, _alice_rx) = mpsc::unbounded_channel();
+        let (bob_tx, _bob_rx) = mpsc::unbounded_channel();
+        let alice_events = hub.tee_account_events(alice_tx, Some("alice".to_string()));
+        hub.tee_account_events(bob_tx, Some("bob".to_string()));
+
+        // 匿名连接只能订阅市场成交，无效的令牌无法升级
+        let (mut anonymous, channels) = connect(&hub, &authenticator, None).await;
+        assert_eq!(channels, 1);
+        let (mut bob, channels) = connect(&hub, &authenticator, Some(&session_token(&authenticator, "bob").await)).await;
+        assert_eq!(channels, 4);
+        let (mut alice, _) = connect(&hub, &authenticator, Some(&session_token(&authenticator, "alice").await)).await;
+        let rejected = warp::test::ws().path("/ws")
+                                       .header("authorization", "Bearer unknown")
+                                       .handshake(route(Arc::clone(&hub), authenticator.clone(), None))
+                                       .await;
+        assert!(rejected.is_err());
+
+        alice_events.send(balance_event(10.0)).unwrap();
+        assert!(matches!(recv_message(&mut alice).await, StreamMessage::Event { channel: StreamChannel::Balances,
+                                                                                sequence: 1,
+                                                                                .. }));
+        // 余额先于成交广播，其他连接下一条收到的就是成交
+        hub.publish(StreamChannel::MarketTrades("ETHUSDT".to_string()), StreamPayload::Market(market_trade("ETHUSDT")));
+        for client in [&mut alice, &mut bob, &mut anonymous] {
+            assert!(matches!(recv_message(client).await, StreamMessage::Event { channel: StreamChannel::MarketTrades(_),
+                                                                                .. }));
+        }
+    }
+}
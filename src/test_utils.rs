@@ -1,6 +1,7 @@
 use crate::{
     common::{
         account_positions::{
+            exited_position::PositionExit,
             exited_positions::AccountExitedPositions,
             future::{FuturePosition, FuturePositionConfig},
             perpetual::{PerpetualPosition, PerpetualPositionConfig},
@@ -22,15 +23,19 @@ use crate::{
         token::Token,
         Side,
     },
+    dashboard::summary::attribution::PnLAttribution,
     hourglass::{
         account::{
             account_config::{AccountConfig, CommissionLevel, CommissionRates, HourglassMode, MarginMode},
             account_latency::{AccountLatency, FluctuationMode},
             account_orders::AccountOrders,
+            account_vault::AccountVault,
             HourglassAccount,
         },
         clickhouse_api::datatype::single_level_order_book::SingleLevelOrderBook,
+        simulation::{SimClock, SimRng},
     },
+    vault::{in_memory::InMemoryVault, Vault, VaultConfig},
     Exchange,
 };
 use dashmap::DashMap;
@@ -66,7 +71,9 @@ pub fn create_test_account_configuration() -> AccountConfig
                     execution_mode: HourglassMode::Backtest,
                     max_price_deviation: 0.05,
                     lazy_account_positions: false,
-                    liquidation_threshold: 0.9 }
+                    liquidation_threshold: 0.9,
+                    vault: VaultConfig::InMemory,
+                    equity_sample_interval: None }
 }
 // 帮助函数，用于创建测试用的 AccountOrders 实例
 pub async fn create_test_account_orders() -> AccountOrders
@@ -135,7 +142,9 @@ pub async fn create_test_account() -> HourglassAccount
                                              fees_book: HashMap::new(),
                                              execution_mode: HourglassMode::Backtest,
                                              lazy_account_positions: false,
-                                             liquidation_threshold: 0.9 };
+                                             liquidation_threshold: 0.9,
+                                             vault: VaultConfig::InMemory,
+                                             equity_sample_interval: None };
 
     account_config.fees_book.insert(Perpetual, commission_rates);
 
@@ -156,19 +165,25 @@ pub async fn create_test_account() -> HourglassAccount
     HourglassAccount { current_session: Uuid::new_v4(),
                        machine_id,
                        client_trade_counter: 0.into(),
-                       exchange_timestamp: AtomicI64::new(1234567),
+                       exchange_timestamp: Arc::new(AtomicI64::new(1234567)),
                        account_event_tx: tokio::sync::mpsc::unbounded_channel().0,
                        config: account_config,
                        balances,
                        positions,
                        exited_positions: closed_positions,
+                       vault: AccountVault::new(Vault::InMemory(InMemoryVault::new())),
                        account_open_book: Arc::new(RwLock::new(AccountOrders::new(machine_id, vec![Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual))], AccountLatency { fluctuation_mode:
                                                                                                                                                                                        FluctuationMode::Sine,
                                                                                                                                                                                    maximum: 300,
                                                                                                                                                                                    minimum: 0,
                                                                                                                                                                                    current_value: 0 }).await)),
                        single_level_order_book: Arc::new(Mutex::new(single_level_order_books)),
-                       account_margin: Arc::new(0.0.into()) }
+                       account_margin: Arc::new(0.0.into()),
+                       rng: SimRng::from_entropy(),
+                       clock: SimClock::System,
+                       equity_tracker: None,
+                       pnl_attribution: PnLAttribution::default(),
+                       publish_positions: false }
 }
 
 /// 创建一个测试用的 `PerpetualPosition` 实例。
@@ -222,3 +237,26 @@ pub fn create_test_future_position_with_side(instrument: Instrument, side: Side)
                      isolated_margin: None,
                      funding_fee: 0.0 }
 }
+
+/// 创建一个测试用的 ETH/USDT 永续合约 `PositionExit`，数量为 1、无手续费，盈亏按方向由开平仓价格计算。
+pub fn create_test_exited_position(side: Side, enter_ts: i64, exit_ts: i64, entry_price: f64, exit_price: f64) -> PositionExit
+{
+    let realised_pnl = match side {
+        | Side::Buy => exit_price - entry_price,
+        | Side::Sell => entry_price - exit_price,
+    };
+    PositionExit { exchange: Exchange::Hourglass,
+                   instrument: Instrument::from(("ETH", "USDT", Perpetual)),
+                   side,
+                   position_id: PositionId(enter_ts as u64),
+                   enter_ts,
+                   exit_ts,
+                   quantity: 1.0,
+                   exit_fees: 0.0,
+                   exit_fees_total: 0.0,
+                   exit_avg_price_gross: entry_price,
+                   exit_value_gross: exit_price,
+                   realised_pnl,
+                   liquidation_price: exit_price,
+                   exit_isolated_margin: None }
+}
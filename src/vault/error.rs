@@ -18,4 +18,7 @@ pub enum VaultError
 
     #[error("Failed to retrieve expected data due to it not being present")]
     ExpectedDataNotPresentError,
+
+    #[error("Failed to initialise the repository: {0}")]
+    InitialisationError(String),
 }
@@ -0,0 +1,216 @@
+use crate::{
+    common::{
+        account_positions::{position_id::PositionId, Position},
+        balance::Balance,
+        instrument::Instrument,
+        token::Token,
+    },
+    hourglass::account::account_config::HourglassMode,
+    hourglass_log::warn,
+    vault::{error::VaultError, in_memory::InMemoryVault, summariser::PositionSummariser, BalanceProcessor, PositionProcessor, StatisticHandler},
+    Exchange,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// 追加写入日志中的一条记录，每行一个 JSON 对象。
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum VaultRecord<Statistic>
+{
+    OpenPosition
+    {
+        session_id: Uuid, position: Position
+    },
+    RemovePosition
+    {
+        position_id: PositionId
+    },
+    ExitedPosition
+    {
+        session_id: Uuid, position: Position
+    },
+    Balance
+    {
+        session_id: Uuid,
+        #[serde(default = "Token::usdt")]
+        token: Token, // 早期的记录只保存 USDT 余额，没有这个字段
+        balance: Balance,
+    },
+    Statistics
+    {
+        exchange: Exchange, instrument: Instrument, statistic: Statistic
+    },
+}
+
+/// 基于 JSON Lines 文件的仓库，所有写操作都追加到文件末尾，打开时按顺序重放，适用于单机运行。
+///
+/// 回测模式打开时清空文件，与 [`RedisVault`](crate::vault::redis::RedisVault) 一样保证每次回测从空仓库开始；
+/// 在线模式打开时重放已有记录以恢复状态。
+#[derive(Debug)]
+pub struct FileVault<Statistic>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    path: PathBuf,
+    writer: BufWriter<File>,
+    state: InMemoryVault<Statistic>, // 重放后的当前状态，读操作直接从这里获取
+}
+
+impl<Statistic> FileVault<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    /// 打开或创建 `path` 处的日志文件。
+    pub fn open(path: impl AsRef<Path>, hourglass_mode: HourglassMode) -> Result<Self, VaultError>
+    {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|_| VaultError::WriteError)?;
+        }
+
+        let mut state = InMemoryVault::new();
+        let mut options = OpenOptions::new();
+        match hourglass_mode {
+            | HourglassMode::Backtest => options.create(true).write(true).truncate(true),
+            | HourglassMode::Online => {
+                if path.exists() {
+                    Self::replay(&path, &mut state)?;
+                }
+                options.create(true).append(true)
+            }
+        };
+        let file = options.open(&path).map_err(|_| VaultError::WriteError)?;
+
+        Ok(Self { path,
+                  writer: BufWriter::new(file),
+                  state })
+    }
+
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    /// 按顺序重放日志中的记录。
+    ///
+    /// 写入中途退出会在文件末尾留下不完整的一行：最后一行无法解析时把它截掉，之前的记录照常恢复；
+    /// 中间的记录损坏仍然报错。
+    fn replay(path: &Path, state: &mut InMemoryVault<Statistic>) -> Result<(), VaultError>
+    {
+        let content = std::fs::read(path).map_err(|_| VaultError::ReadError)?;
+        let mut offset = 0;
+        while offset < content.len() {
+            let end = content[offset..].iter().position(|byte| *byte == b'\n').map_or(content.len(), |index| offset + index + 1);
+            let line = &content[offset..end];
+            if !line.trim_ascii().is_empty() {
+                match serde_json::from_slice(line) {
+                    | Ok(record) => Self::apply(state, record)?,
+                    | Err(error) if end == content.len() => {
+                        warn!("FileVault drops a torn record at the end of {:?}: {}", path, error);
+                        return Self::truncate(path, offset);
+                    }
+                    | Err(error) => return Err(error.into()),
+                }
+            }
+            offset = end;
+        }
+        // 最后一条记录完整但缺少换行时补上，避免下一条记录接在同一行
+        if content.last().is_some_and(|byte| *byte != b'\n') {
+            OpenOptions::new().append(true).open(path).and_then(|mut file| file.write_all(b"\n")).map_err(|_| VaultError::WriteError)?;
+        }
+        Ok(())
+    }
+
+    /// 把日志截断到 `len` 字节。
+    fn truncate(path: &Path, len: usize) -> Result<(), VaultError>
+    {
+        OpenOptions::new().write(true).open(path).and_then(|file| file.set_len(len as u64)).map_err(|_| VaultError::WriteError)
+    }
+
+    fn apply(state: &mut InMemoryVault<Statistic>, record: VaultRecord<Statistic>) -> Result<(), VaultError>
+    {
+        match record {
+            | VaultRecord::OpenPosition { session_id, position } => state.add_open_position(session_id, position),
+            | VaultRecord::RemovePosition { position_id } => state.remove_position(&position_id).map(|_| ()),
+            | VaultRecord::ExitedPosition { session_id, position } => state.set_exited_position(session_id, position),
+            | VaultRecord::Balance { session_id, token, balance } => state.set_balance(session_id, &token, balance),
+            | VaultRecord::Statistics { exchange, instrument, statistic } => state.set_statistics(exchange, instrument, statistic),
+        }
+    }
+
+    /// 先写入文件再更新内存状态，写入失败时状态保持不变。
+    fn append(&mut self, record: VaultRecord<Statistic>) -> Result<(), VaultError>
+    {
+        let line = serde_json::to_string(&record)?;
+        writeln!(self.writer, "{}", line).and_then(|_| self.writer.flush()).map_err(|_| VaultError::WriteError)?;
+        Self::apply(&mut self.state, record)
+    }
+}
+
+impl<Statistic> PositionProcessor for FileVault<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    fn add_open_position(&mut self, session_id: Uuid, position: Position) -> Result<(), VaultError>
+    {
+        self.append(VaultRecord::OpenPosition { session_id, position })
+    }
+
+    fn get_open_position(&mut self, position_id: &PositionId) -> Result<Option<Position>, VaultError>
+    {
+        self.state.get_open_position(position_id)
+    }
+
+    fn get_open_positions(&mut self, session_id: Uuid, exchange: Exchange, instrument: Instrument) -> Result<Vec<Position>, VaultError>
+    {
+        self.state.get_open_positions(session_id, exchange, instrument)
+    }
+
+    fn remove_position(&mut self, position_id: &PositionId) -> Result<Option<Position>, VaultError>
+    {
+        let position = self.state.get_open_position(position_id)?;
+        if position.is_some() {
+            self.append(VaultRecord::RemovePosition { position_id: position_id.clone() })?;
+        }
+        Ok(position)
+    }
+
+    fn set_exited_position(&mut self, session_id: Uuid, position: Position) -> Result<(), VaultError>
+    {
+        self.append(VaultRecord::ExitedPosition { session_id, position })
+    }
+
+    fn get_exited_positions(&mut self, session_id: Uuid) -> Result<Vec<Position>, VaultError>
+    {
+        self.state.get_exited_positions(session_id)
+    }
+}
+
+impl<Statistic> BalanceProcessor for FileVault<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    fn set_balance(&mut self, session_id: Uuid, token: &Token, balance: Balance) -> Result<(), VaultError>
+    {
+        self.append(VaultRecord::Balance { session_id,
+                                           token: token.clone(),
+                                           balance })
+    }
+
+    fn get_balance(&mut self, session_id: Uuid, token: &Token) -> Result<Balance, VaultError>
+    {
+        self.state.get_balance(session_id, token)
+    }
+}
+
+impl<Statistic> StatisticHandler<Statistic> for FileVault<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    fn set_statistics(&mut self, exchange: Exchange, instrument: Instrument, statistic: Statistic) -> Result<(), VaultError>
+    {
+        self.append(VaultRecord::Statistics { exchange, instrument, statistic })
+    }
+
+    fn get_statistics(&mut self, exchange: Exchange, instrument: Instrument) -> Result<Statistic, VaultError>
+    {
+        self.state.get_statistics(exchange, instrument)
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    common::{
+        account_positions::{position_id::PositionId, Position},
+        balance::Balance,
+        instrument::Instrument,
+        token::Token,
+    },
+    vault::{determine_exited_positions_id, error::VaultError, summariser::PositionSummariser, BalanceProcessor, ExitedPositionsId, PositionProcessor, StatisticHandler},
+    Exchange,
+};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// 只保存在内存中的仓库，不需要任何外部服务，适用于单元测试与不需要持久化的快速回测。
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryVault<Statistic>
+    where Statistic: PositionSummariser
+{
+    open_positions: HashMap<PositionId, (Uuid, Position)>,       // 打开的仓位及其所属的 session
+    exited_positions: HashMap<ExitedPositionsId, Vec<Position>>, // 按退出先后顺序保存
+    balances: HashMap<(Uuid, Token), Balance>,
+    statistics: HashMap<(Exchange, Instrument), Statistic>,
+}
+
+impl<Statistic> InMemoryVault<Statistic> where Statistic: PositionSummariser
+{
+    pub fn new() -> Self
+    {
+        Self { open_positions: HashMap::new(),
+               exited_positions: HashMap::new(),
+               balances: HashMap::new(),
+               statistics: HashMap::new() }
+    }
+}
+
+impl<Statistic> PositionProcessor for InMemoryVault<Statistic> where Statistic: PositionSummariser
+{
+    fn add_open_position(&mut self, session_id: Uuid, position: Position) -> Result<(), VaultError>
+    {
+        self.open_positions.insert(position.meta().position_id.clone(), (session_id, position));
+        Ok(())
+    }
+
+    fn get_open_position(&mut self, position_id: &PositionId) -> Result<Option<Position>, VaultError>
+    {
+        Ok(self.open_positions.get(position_id).map(|(_, position)| position.clone()))
+    }
+
+    fn get_open_positions(&mut self, session_id: Uuid, exchange: Exchange, instrument: Instrument) -> Result<Vec<Position>, VaultError>
+    {
+        let mut positions = self.open_positions
+                                .values()
+                                .filter(|(owner, position)| *owner == session_id && position.meta().exchange == exchange && position.meta().instrument == instrument)
+                                .map(|(_, position)| position.clone())
+                                .collect::<Vec<_>>();
+        // 与 Redis 仓库一致，按 PositionId 排序
+        positions.sort_unstable_by_key(|position| position.meta().position_id.0);
+        Ok(positions)
+    }
+
+    fn remove_position(&mut self, position_id: &PositionId) -> Result<Option<Position>, VaultError>
+    {
+        Ok(self.open_positions.remove(position_id).map(|(_, position)| position))
+    }
+
+    fn set_exited_position(&mut self, session_id: Uuid, position: Position) -> Result<(), VaultError>
+    {
+        self.exited_positions.entry(determine_exited_positions_id(session_id)).or_default().push(position);
+        Ok(())
+    }
+
+    fn get_exited_positions(&mut self, session_id: Uuid) -> Result<Vec<Position>, VaultError>
+    {
+        Ok(self.exited_positions.get(&determine_exited_positions_id(session_id)).cloned().unwrap_or_default())
+    }
+}
+
+impl<Statistic> BalanceProcessor for InMemoryVault<Statistic> where Statistic: PositionSummariser
+{
+    fn set_balance(&mut self, session_id: Uuid, token: &Token, balance: Balance) -> Result<(), VaultError>
+    {
+        self.balances.insert((session_id, token.clone()), balance);
+        Ok(())
+    }
+
+    fn get_balance(&mut self, session_id: Uuid, token: &Token) -> Result<Balance, VaultError>
+    {
+        self.balances.get(&(session_id, token.clone())).copied().ok_or(VaultError::ExpectedDataNotPresentError)
+    }
+}
+
+impl<Statistic> StatisticHandler<Statistic> for InMemoryVault<Statistic> where Statistic: PositionSummariser
+{
+    fn set_statistics(&mut self, exchange: Exchange, instrument: Instrument, statistic: Statistic) -> Result<(), VaultError>
+    {
+        self.statistics.insert((exchange, instrument), statistic);
+        Ok(())
+    }
+
+    fn get_statistics(&mut self, exchange: Exchange, instrument: Instrument) -> Result<Statistic, VaultError>
+    {
+        self.statistics.get(&(exchange, instrument)).copied().ok_or(VaultError::ExpectedDataNotPresentError)
+    }
+}
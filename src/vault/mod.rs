@@ -3,21 +3,32 @@ use crate::{
         account_positions::{position_id::PositionId, Position},
         balance::Balance,
         instrument::Instrument,
+        token::Token,
     },
-    vault::error::VaultError,
+    hourglass::account::account_config::HourglassMode,
+    vault::{error::VaultError, file::FileVault, in_memory::InMemoryVault, redis::RedisVault, summariser::PositionSummariser},
     Exchange,
 };
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt::{self, Debug, Formatter},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, PoisonError},
+};
 use uuid::Uuid;
 
 pub mod error;
+pub mod file;
+pub mod in_memory;
 pub mod redis;
 mod summariser;
 
 /// 处理 [`Position`] 在持久层的读写操作。
 pub trait PositionProcessor
 {
-    /// 使用 [`PositionId`] 更新或插入一个打开的 [`Position`]。
-    fn add_open_position(&mut self, position: Position) -> Result<(), VaultError>;
+    /// 使用 [`PositionId`] 更新或插入一个属于 session_id 的打开的 [`Position`]。
+    fn add_open_position(&mut self, session_id: Uuid, position: Position) -> Result<(), VaultError>;
 
     /// 使用提供的 [`PositionId`] 获取一个打开的 [`Position`]。
     fn get_open_position(&mut self, position_id: &PositionId) -> Result<Option<Position>, VaultError>;
@@ -38,10 +49,10 @@ pub trait PositionProcessor
 /// 处理投资组合当前余额在持久层的读写操作。
 pub trait BalanceProcessor
 {
-    /// 使用 session_id 更新或插入投资组合 [`Balance`]。
-    fn set_balance(&mut self, session_id: Uuid, balance: Balance) -> Result<(), VaultError>;
-    /// 使用提供的 session_id 获取投资组合 [`Balance`]。
-    fn get_balance(&mut self, session_id: Uuid) -> Result<Balance, VaultError>;
+    /// 使用 session_id 更新或插入投资组合中 `token` 的 [`Balance`]。
+    fn set_balance(&mut self, session_id: Uuid, token: &Token, balance: Balance) -> Result<(), VaultError>;
+    /// 使用提供的 session_id 获取投资组合中 `token` 的 [`Balance`]。
+    fn get_balance(&mut self, session_id: Uuid, token: &Token) -> Result<Balance, VaultError>;
 }
 
 /// 处理投资组合中每个市场的统计数据的读写操作。
@@ -60,3 +71,310 @@ pub fn determine_exited_positions_id(session_id: Uuid) -> ExitedPositionsId
 {
     format!("positions_exited_{}", session_id)
 }
+
+/// 仓库的存储方式，在 [`AccountConfig`](crate::hourglass::account::account_config::AccountConfig) 中配置，缺省为内存。
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VaultConfig
+{
+    #[default]
+    InMemory, // 不持久化
+    File
+    {
+        path: PathBuf, // JSON Lines 日志文件路径
+    },
+    Redis(redis::Config),
+}
+
+/// 本进程中已经清除过的 Redis 回测命名空间。同一次回测的账户共用一个命名空间，只有第一个打开它的账户清除旧数据。
+static CLEARED_REDIS_NAMESPACES: LazyLock<Mutex<HashSet<(String, String)>>> = LazyLock::new(Default::default);
+
+impl VaultConfig
+{
+    /// 按配置构造仓库，同一份回测代码可以根据环境选择是否持久化。
+    ///
+    /// 文件仓库在回测模式下按 `session_id` 各写一个文件（见 [`VaultConfig::session_path`]），
+    /// 多个账户共用同一份配置时不会互相清空。
+    pub fn build<Statistic>(&self, hourglass_mode: HourglassMode, session_id: Uuid) -> Result<Vault<Statistic>, VaultError>
+        where Statistic: PositionSummariser + Serialize + DeserializeOwned
+    {
+        match self {
+            | VaultConfig::InMemory => Ok(Vault::InMemory(InMemoryVault::new())),
+            | VaultConfig::File { path } => match hourglass_mode {
+                | HourglassMode::Backtest => Ok(Vault::File(FileVault::open(Self::session_path(path, session_id), hourglass_mode)?)),
+                | HourglassMode::Online => Ok(Vault::File(FileVault::open(path, hourglass_mode)?)),
+            },
+            | VaultConfig::Redis(config) => {
+                let conn = ::redis::Client::open(config.uri.as_str()).and_then(|client| client.get_connection())
+                                                                     .map_err(|error| VaultError::InitialisationError(error.to_string()))?;
+                let first_open = CLEARED_REDIS_NAMESPACES.lock().unwrap_or_else(PoisonError::into_inner).insert((config.uri.clone(), config.namespace.clone()));
+                RedisVault::builder().conn(conn)
+                                     .config(hourglass_mode)
+                                     .namespace(config.namespace.clone())
+                                     .clear_on_build(first_open)
+                                     .build()
+                                     .map(Vault::Redis)
+                                     .map_err(|error| VaultError::InitialisationError(error.to_string()))
+            }
+        }
+    }
+
+    /// 回测模式下 `session_id` 对应的文件：`records.jsonl` 变为 `records_<session_id>.jsonl`。
+    pub fn session_path(path: &Path, session_id: Uuid) -> PathBuf
+    {
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let file_name = match path.extension() {
+            | Some(extension) => format!("{}_{}.{}", stem, session_id, extension.to_string_lossy()),
+            | None => format!("{}_{}", stem, session_id),
+        };
+        path.with_file_name(file_name)
+    }
+}
+
+/// 由 [`VaultConfig`] 选择的仓库。
+pub enum Vault<Statistic>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    InMemory(InMemoryVault<Statistic>),
+    File(FileVault<Statistic>),
+    Redis(RedisVault<Statistic>),
+}
+
+impl<Statistic> Debug for Vault<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+    {
+        let kind = match self {
+            | Vault::InMemory(_) => "InMemory",
+            | Vault::File(_) => "File",
+            | Vault::Redis(_) => "Redis",
+        };
+        f.debug_tuple("Vault").field(&kind).finish()
+    }
+}
+
+/// 将调用转发给具体的仓库实现。
+macro_rules! delegate {
+    ($vault:expr, $inner:ident => $call:expr) => {
+        match $vault {
+            | Vault::InMemory($inner) => $call,
+            | Vault::File($inner) => $call,
+            | Vault::Redis($inner) => $call,
+        }
+    };
+}
+
+impl<Statistic> PositionProcessor for Vault<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    fn add_open_position(&mut self, session_id: Uuid, position: Position) -> Result<(), VaultError>
+    {
+        delegate!(self, vault => vault.add_open_position(session_id, position))
+    }
+
+    fn get_open_position(&mut self, position_id: &PositionId) -> Result<Option<Position>, VaultError>
+    {
+        delegate!(self, vault => vault.get_open_position(position_id))
+    }
+
+    fn get_open_positions(&mut self, session_id: Uuid, exchange: Exchange, instrument: Instrument) -> Result<Vec<Position>, VaultError>
+    {
+        delegate!(self, vault => vault.get_open_positions(session_id, exchange, instrument))
+    }
+
+    fn remove_position(&mut self, position_id: &PositionId) -> Result<Option<Position>, VaultError>
+    {
+        delegate!(self, vault => vault.remove_position(position_id))
+    }
+
+    fn set_exited_position(&mut self, session_id: Uuid, position: Position) -> Result<(), VaultError>
+    {
+        delegate!(self, vault => vault.set_exited_position(session_id, position))
+    }
+
+    fn get_exited_positions(&mut self, session_id: Uuid) -> Result<Vec<Position>, VaultError>
+    {
+        delegate!(self, vault => vault.get_exited_positions(session_id))
+    }
+}
+
+impl<Statistic> BalanceProcessor for Vault<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    fn set_balance(&mut self, session_id: Uuid, token: &Token, balance: Balance) -> Result<(), VaultError>
+    {
+        delegate!(self, vault => vault.set_balance(session_id, token, balance))
+    }
+
+    fn get_balance(&mut self, session_id: Uuid, token: &Token) -> Result<Balance, VaultError>
+    {
+        delegate!(self, vault => vault.get_balance(session_id, token))
+    }
+}
+
+impl<Statistic> StatisticHandler<Statistic> for Vault<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
+    fn set_statistics(&mut self, exchange: Exchange, instrument: Instrument, statistic: Statistic) -> Result<(), VaultError>
+    {
+        delegate!(self, vault => vault.set_statistics(exchange, instrument, statistic))
+    }
+
+    fn get_statistics(&mut self, exchange: Exchange, instrument: Instrument) -> Result<Statistic, VaultError>
+    {
+        delegate!(self, vault => vault.get_statistics(exchange, instrument))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{common::instrument::kind::InstrumentKind, test_utils::create_test_perpetual_position};
+
+    /// 测试用的统计量：仓位数量与已实现盈亏之和。
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+    pub(crate) struct PnlStatistic
+    {
+        pub(crate) positions: usize,
+        pub(crate) realised_pnl: f64,
+    }
+
+    impl PositionSummariser for PnlStatistic
+    {
+        fn update(&mut self, position: &Position)
+        {
+            self.positions += 1;
+            self.realised_pnl += position.meta().realised_pnl;
+        }
+    }
+
+    /// 写入一组数据并读回，所有仓库实现的行为应当一致。
+    fn exercise(vault: &mut Vault<PnlStatistic>, session_id: Uuid)
+    {
+        let instrument = Instrument::new("BTC", "USDT", InstrumentKind::Perpetual);
+        let mut open = create_test_perpetual_position(instrument.clone());
+        open.meta.position_id = PositionId(1);
+        let mut exited = create_test_perpetual_position(instrument.clone());
+        exited.meta.position_id = PositionId(2);
+        exited.meta.realised_pnl = 5.0;
+
+        vault.add_open_position(session_id, Position::Perpetual(open.clone())).unwrap();
+        vault.add_open_position(session_id, Position::Perpetual(exited.clone())).unwrap();
+        let removed = vault.remove_position(&PositionId(2)).unwrap().unwrap();
+        assert_eq!(removed.meta(), &exited.meta);
+        assert!(vault.remove_position(&PositionId(2)).unwrap().is_none());
+        vault.set_exited_position(session_id, removed).unwrap();
+
+        let open_positions = vault.get_open_positions(session_id, Exchange::Hourglass, instrument.clone()).unwrap();
+        assert_eq!(open_positions.len(), 1);
+        assert_eq!(open_positions[0].meta(), &open.meta);
+        assert!(vault.get_open_positions(Uuid::new_v4(), Exchange::Hourglass, instrument.clone()).unwrap().is_empty());
+
+        assert!(matches!(vault.get_balance(session_id, &Token::usdt()), Err(VaultError::ExpectedDataNotPresentError)));
+        vault.set_balance(session_id, &Token::usdt(), Balance::new(100.0, 80.0)).unwrap();
+
+        let mut statistic = PnlStatistic::default();
+        statistic.generate_summary(&vault.get_exited_positions(session_id).unwrap());
+        vault.set_statistics(Exchange::Hourglass, instrument, statistic).unwrap();
+    }
+
+    fn assert_persisted(vault: &mut Vault<PnlStatistic>, session_id: Uuid)
+    {
+        let instrument = Instrument::new("BTC", "USDT", InstrumentKind::Perpetual);
+        assert!(vault.get_open_position(&PositionId(1)).unwrap().is_some());
+        assert!(vault.get_open_position(&PositionId(2)).unwrap().is_none());
+        assert_eq!(vault.get_exited_positions(session_id).unwrap().len(), 1);
+        let balance = vault.get_balance(session_id, &Token::usdt()).unwrap();
+        assert_eq!((balance.total, balance.available), (100.0, 80.0));
+        assert_eq!(vault.get_statistics(Exchange::Hourglass, instrument).unwrap(), PnlStatistic { positions: 1, realised_pnl: 5.0 });
+    }
+
+    #[test]
+    fn in_memory_vault_should_be_the_default()
+    {
+        let config = VaultConfig::default();
+        assert_eq!(config, VaultConfig::InMemory);
+
+        let session_id = Uuid::new_v4();
+        let mut vault = config.build::<PnlStatistic>(HourglassMode::Backtest, session_id).unwrap();
+        assert!(matches!(vault, Vault::InMemory(_)));
+        exercise(&mut vault, session_id);
+        assert_persisted(&mut vault, session_id);
+    }
+
+    #[test]
+    fn file_vault_should_replay_in_online_mode_and_reset_in_backtest_mode()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let config = VaultConfig::File { path: dir.path().join("vault").join("records.jsonl") };
+        let session_id = Uuid::new_v4();
+
+        let mut vault = config.build::<PnlStatistic>(HourglassMode::Online, session_id).unwrap();
+        exercise(&mut vault, session_id);
+        drop(vault);
+
+        // 在线模式重新打开时恢复全部状态
+        let mut vault = config.build::<PnlStatistic>(HourglassMode::Online, session_id).unwrap();
+        assert_persisted(&mut vault, session_id);
+        drop(vault);
+
+        // 回测模式从空仓库开始，每个 session 写自己的文件，不影响在线模式的文件与其他账户
+        let mut vault = config.build::<PnlStatistic>(HourglassMode::Backtest, session_id).unwrap();
+        assert!(vault.get_open_position(&PositionId(1)).unwrap().is_none());
+        assert!(vault.get_exited_positions(session_id).unwrap().is_empty());
+        let other_session = Uuid::new_v4();
+        let mut other = config.build::<PnlStatistic>(HourglassMode::Backtest, other_session).unwrap();
+        exercise(&mut vault, session_id);
+        exercise(&mut other, other_session);
+        drop((vault, other));
+        let VaultConfig::File { path } = &config
+        else {
+            unreachable!()
+        };
+        for session_id in [session_id, other_session] {
+            let mut vault = Vault::File(FileVault::<PnlStatistic>::open(VaultConfig::session_path(path, session_id), HourglassMode::Online).unwrap());
+            assert_persisted(&mut vault, session_id);
+        }
+        let mut vault = config.build::<PnlStatistic>(HourglassMode::Online, session_id).unwrap();
+        assert_persisted(&mut vault, session_id);
+    }
+
+    #[test]
+    fn file_vault_should_drop_a_torn_final_record()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("records.jsonl");
+        let config = VaultConfig::File { path: path.clone() };
+        let session_id = Uuid::new_v4();
+
+        let mut vault = config.build::<PnlStatistic>(HourglassMode::Online, session_id).unwrap();
+        exercise(&mut vault, session_id);
+        drop(vault);
+
+        // 模拟写入中途退出：最后一行只写了一半
+        let complete = std::fs::read(&path).unwrap();
+        let mut torn = complete.clone();
+        torn.extend_from_slice(br#"{"op":"balance","session_id":"#);
+        std::fs::write(&path, torn).unwrap();
+
+        let mut vault = config.build::<PnlStatistic>(HourglassMode::Online, session_id).unwrap();
+        assert_persisted(&mut vault, session_id);
+        assert_eq!(std::fs::read(&path).unwrap(), complete);
+
+        // 截断后继续追加的记录可以正常重放
+        vault.set_balance(session_id, &Token::usdt(), Balance::new(120.0, 90.0)).unwrap();
+        drop(vault);
+        let mut vault = config.build::<PnlStatistic>(HourglassMode::Online, session_id).unwrap();
+        let balance = vault.get_balance(session_id, &Token::usdt()).unwrap();
+        assert_eq!((balance.total, balance.available), (120.0, 90.0));
+    }
+
+    #[test]
+    fn vault_config_should_deserialize_from_toml()
+    {
+        let config: VaultConfig = toml::from_str("kind = \"file\"\npath = \"data/vault.jsonl\"").unwrap();
+        assert_eq!(config, VaultConfig::File { path: PathBuf::from("data/vault.jsonl") });
+        let config: VaultConfig = toml::from_str("kind = \"redis\"\nuri = \"redis://127.0.0.1/\"").unwrap();
+        assert_eq!(config,
+                   VaultConfig::Redis(redis::Config { uri: "redis://127.0.0.1/".to_string(),
+                                                      namespace: "default".to_string() }));
+    }
+}
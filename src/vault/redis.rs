@@ -13,46 +13,84 @@
 ///
 /// `PhantomData<Statistic>` 用于标记 `RedisVault` 和 `RedisVaultBuilder` 结构体中的 `Statistic` 泛型参数。
 /// 这意味着即使 `Statistic` 在运行时没有被直接使用，Rust 的类型系统仍然会确保在编译时检查这个泛型参数的类型安全性。
-use crate::error::ExchangeError;
-use crate::{error::ExchangeError::RedisInitialisationError, hourglass::account::account_config::HourglassMode, vault::summariser::PositionSummariser};
-use redis::Connection;
+use crate::{
+    common::{
+        account_positions::{position_id::PositionId, Position},
+        balance::Balance,
+        instrument::Instrument,
+        token::Token,
+    },
+    error::{ExchangeError, ExchangeError::RedisInitialisationError},
+    hourglass::account::account_config::HourglassMode,
+    vault::{determine_exited_positions_id, error::VaultError, summariser::PositionSummariser, BalanceProcessor, PositionProcessor, StatisticHandler},
+    Exchange,
+};
+use redis::{Commands, Connection, ConnectionLike};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::marker::PhantomData;
-
-// use uuid::Uuid;
-// use crate::common::account_positions::Position;
-// use crate::common::account_positions::position_id::PositionId;
+use std::{fmt::Display, marker::PhantomData};
+use uuid::Uuid;
 
 /// 用于通过 new() 构造函数方法构造 [`RedisVault`] 的配置。
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Deserialize, Serialize)]
 pub struct Config
 {
     pub uri: String, // Redis连接的URI
+    #[serde(default = "Config::default_namespace")]
+    pub namespace: String, // 回测模式下键前缀中的命名空间，同一次回测的账户共用，缺省为 `default`
+}
+
+impl Config
+{
+    fn default_namespace() -> String
+    {
+        "default".to_string()
+    }
+}
+
+impl Default for Config
+{
+    fn default() -> Self
+    {
+        Self { uri: String::new(),
+               namespace: Self::default_namespace() }
+    }
 }
 
 /// 使用泛型类型 `Statistic` 的 Redis 仓库，`Statistic` 必须实现 `PositionSummariser`、`Serialize` 和 `DeserializeOwned`。
-pub struct RedisVault<Statistic>
-    where Statistic: PositionSummariser + Serialize + DeserializeOwned
+///
+/// 所有键都以运行模式为前缀，回测与在线数据互不干扰。回测的前缀还包含配置的命名空间，
+/// 同时运行的多个回测使用不同的命名空间即可互不影响。连接类型 `C` 默认为 [`Connection`]，
+/// 任何实现了 [`ConnectionLike`] 的连接都可以使用。
+pub struct RedisVault<Statistic, C = Connection>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned,
+          C: ConnectionLike
 {
     hourglass_mode: HourglassMode,             // 仓库的配置，存储在仓库中
+    namespace: String,                         // 回测模式下键前缀中的命名空间
     _statistic_marker: PhantomData<Statistic>, // 用于类型标记的幻象数据
-    #[allow(dead_code)]
-    conn: Connection,
+    conn: C,
 }
 
-impl<Statistic> RedisVault<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+/// 回测模式清理旧数据时每次 `SCAN` 的键数
+const SCAN_BATCH: usize = 500;
+
+impl<Statistic, C> RedisVault<Statistic, C>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned,
+          C: ConnectionLike
 {
     /// 使用提供的 Redis 连接和配置构造新的 [`RedisVault`] 组件。
     ///
     /// # 参数
     /// - `conn`: 与 Redis 数据库的连接。
     /// - `config`: 用于配置仓库的 `HourglassMode` 对象。
+    /// - `namespace`: 回测模式下键前缀中的命名空间，在线模式忽略。
     ///
     /// # 返回
     /// 返回一个新的 `RedisVault` 实例，该实例可以用于与 Redis 数据库交互。
-    pub fn new(conn: Connection, config: HourglassMode) -> Self
+    pub fn new(conn: C, config: HourglassMode, namespace: impl Into<String>) -> Self
     {
         Self { hourglass_mode: config, // 存储提供的配置
+               namespace: namespace.into(),
                _statistic_marker: PhantomData,
                conn }
     }
@@ -61,11 +99,101 @@ impl<Statistic> RedisVault<Statistic> where Statistic: PositionSummariser + Seri
     ///
     /// # 返回
     /// 返回一个新的 `RedisVaultBuilder` 实例，该实例可以逐步配置并最终构建 `RedisVault`。
-    pub fn builder() -> RedisVaultBuilder<Statistic>
+    pub fn builder() -> RedisVaultBuilder<Statistic, C>
     {
         RedisVaultBuilder::new()
     }
 
+    /// 根据执行模式执行不同的操作。
+    ///
+    /// # 说明
+    /// - 回测模式：清除同一命名空间之前遗留的数据，保证每次回测从空仓库开始，其他命名空间的数据不受影响。
+    ///   使用 `SCAN` 分批查找，不会像 `KEYS` 那样阻塞 Redis。
+    /// - 在线模式：保留已持久化的数据，只检查连接是否可用。
+    pub fn perform_action_based_on_mode(&mut self) -> Result<(), VaultError>
+    {
+        match self.hourglass_mode {
+            | HourglassMode::Backtest => {
+                let pattern = format!("{}:*", self.namespace());
+                let mut cursor = 0_u64;
+                loop {
+                    let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN").arg(cursor)
+                                                                             .arg("MATCH")
+                                                                             .arg(&pattern)
+                                                                             .arg("COUNT")
+                                                                             .arg(SCAN_BATCH)
+                                                                             .query(&mut self.conn)
+                                                                             .map_err(|_| VaultError::ReadError)?;
+                    if !keys.is_empty() {
+                        self.conn.del::<_, ()>(keys).map_err(|_| VaultError::DeleteError)?;
+                    }
+                    if next == 0 {
+                        return Ok(());
+                    }
+                    cursor = next;
+                }
+            }
+            | HourglassMode::Online => match self.conn.check_connection() {
+                | true => Ok(()),
+                | false => Err(VaultError::ReadError),
+            },
+        }
+    }
+
+    /// 当前运行模式的键前缀，回测模式按命名空间区分。
+    fn namespace(&self) -> String
+    {
+        match self.hourglass_mode {
+            | HourglassMode::Backtest => format!("hourglass_backtest:{}", self.namespace),
+            | HourglassMode::Online => "hourglass_online".to_string(),
+        }
+    }
+
+    fn key(&self, key: impl Display) -> String
+    {
+        format!("{}:{}", self.namespace(), key)
+    }
+
+    fn position_key(&self, position_id: &PositionId) -> String
+    {
+        self.key(format_args!("position_{}", position_id.0))
+    }
+
+    /// 记录打开的仓位属于哪个 session，移除仓位时用于维护索引。
+    fn position_session_key(&self, position_id: &PositionId) -> String
+    {
+        self.key(format_args!("position_session_{}", position_id.0))
+    }
+
+    /// 一个 session 在某个市场下打开的 [`PositionId`] 集合。
+    fn open_positions_key(&self, session_id: Uuid, exchange: &Exchange, instrument: &Instrument) -> String
+    {
+        self.key(format_args!("positions_open_{}_{}_{}", session_id, exchange, instrument))
+    }
+
+    fn exited_positions_key(&self, session_id: Uuid) -> String
+    {
+        self.key(determine_exited_positions_id(session_id))
+    }
+
+    fn balance_key(&self, session_id: Uuid, token: &Token) -> String
+    {
+        self.key(format_args!("balance_{}_{}", session_id, token))
+    }
+
+    fn statistics_key(&self, exchange: &Exchange, instrument: &Instrument) -> String
+    {
+        self.key(format_args!("statistics_{}_{}", exchange, instrument))
+    }
+
+    fn read_string(&mut self, key: &str) -> Result<Option<String>, VaultError>
+    {
+        self.conn.get(key).map_err(|_| VaultError::ReadError)
+    }
+}
+
+impl<Statistic> RedisVault<Statistic, Connection> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+{
     /// 建立并返回一个 Redis 连接。
     ///
     /// # 参数
@@ -77,45 +205,171 @@ impl<Statistic> RedisVault<Statistic> where Statistic: PositionSummariser + Seri
     {
         redis::Client::open(cfg.uri).expect("无法创建 Redis 客户端").get_connection().expect("无法连接到 Redis")
     }
+}
+
+impl<Statistic, C> PositionProcessor for RedisVault<Statistic, C>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned,
+          C: ConnectionLike
+{
+    fn add_open_position(&mut self, session_id: Uuid, position: Position) -> Result<(), VaultError>
+    {
+        let meta = position.meta();
+        let position_key = self.position_key(&meta.position_id);
+        let session_key = self.position_session_key(&meta.position_id);
+        let index_key = self.open_positions_key(session_id, &meta.exchange, &meta.instrument);
+        let position_id = meta.position_id.0;
+        let position_string = serde_json::to_string(&position)?;
+
+        // 仓位、所属 session 与索引在同一个事务中写入，其他客户端不会读到只写入一部分的状态。
+        // 事务中的命令出错时 Redis 不会回滚已经执行的命令，错误仍然返回给调用方
+        redis::pipe().atomic()
+                     .set(position_key, position_string)
+                     .ignore()
+                     .set(session_key, session_id.to_string())
+                     .ignore()
+                     .sadd(index_key, position_id)
+                     .ignore()
+                     .query::<()>(&mut self.conn)
+                     .map_err(|_| VaultError::WriteError)
+    }
 
-    /// 根据执行模式执行不同的操作。
-    ///
-    /// # 说明
-    /// 该方法检查当前配置的执行模式，并基于此执行不同的操作。
-    pub fn perform_action_based_on_mode(&self)
+    fn get_open_position(&mut self, position_id: &PositionId) -> Result<Option<Position>, VaultError>
     {
-        match self.hourglass_mode {
-            | HourglassMode::Backtest => {
-                todo!()
-            }
-            | HourglassMode::Online => {
-                todo!()
+        let position_key = self.position_key(position_id);
+        match self.read_string(&position_key)? {
+            | Some(position) => Ok(Some(serde_json::from_str::<Position>(&position)?)),
+            | None => Ok(None),
+        }
+    }
+
+    fn get_open_positions(&mut self, session_id: Uuid, exchange: Exchange, instrument: Instrument) -> Result<Vec<Position>, VaultError>
+    {
+        let index_key = self.open_positions_key(session_id, &exchange, &instrument);
+        let mut position_ids: Vec<u64> = self.conn.smembers(index_key).map_err(|_| VaultError::ReadError)?;
+        // 集合无序，按 PositionId 排序以保证结果稳定
+        position_ids.sort_unstable();
+
+        position_ids.into_iter().filter_map(|position_id| self.get_open_position(&PositionId(position_id)).transpose()).collect()
+    }
+
+    fn remove_position(&mut self, position_id: &PositionId) -> Result<Option<Position>, VaultError>
+    {
+        let Some(position) = self.get_open_position(position_id)?
+        else {
+            return Ok(None);
+        };
+
+        let session_key = self.position_session_key(position_id);
+        let index_key = match self.read_string(&session_key)? {
+            | Some(session_id) => {
+                let session_id = Uuid::parse_str(&session_id).map_err(|_| VaultError::ReadError)?;
+                Some(self.open_positions_key(session_id, &position.meta().exchange, &position.meta().instrument))
             }
+            | None => None,
+        };
+
+        // 索引与仓位在同一个事务中移除，其他客户端不会读到索引指向已删除的仓位
+        let position_key = self.position_key(position_id);
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if let Some(index_key) = index_key {
+            pipe.srem(index_key, position_id.0).ignore();
         }
+        pipe.del(&[position_key, session_key]).ignore().query::<()>(&mut self.conn).map_err(|_| VaultError::DeleteError)?;
+
+        Ok(Some(position))
+    }
+
+    fn set_exited_position(&mut self, session_id: Uuid, position: Position) -> Result<(), VaultError>
+    {
+        // 追加到列表末尾，读取时按退出的先后顺序返回
+        let exited_key = self.exited_positions_key(session_id);
+        self.conn.rpush::<_, _, ()>(exited_key, serde_json::to_string(&position)?).map_err(|_| VaultError::WriteError)
+    }
+
+    fn get_exited_positions(&mut self, session_id: Uuid) -> Result<Vec<Position>, VaultError>
+    {
+        let exited_key = self.exited_positions_key(session_id);
+        let positions: Vec<String> = self.conn.lrange(exited_key, 0, -1).map_err(|_| VaultError::ReadError)?;
+
+        positions.iter().map(|position| serde_json::from_str::<Position>(position).map_err(VaultError::from)).collect()
+    }
+}
+
+impl<Statistic, C> BalanceProcessor for RedisVault<Statistic, C>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned,
+          C: ConnectionLike
+{
+    fn set_balance(&mut self, session_id: Uuid, token: &Token, balance: Balance) -> Result<(), VaultError>
+    {
+        let balance_key = self.balance_key(session_id, token);
+        self.conn.set::<_, _, ()>(balance_key, serde_json::to_string(&balance)?).map_err(|_| VaultError::WriteError)
+    }
+
+    fn get_balance(&mut self, session_id: Uuid, token: &Token) -> Result<Balance, VaultError>
+    {
+        let balance_key = self.balance_key(session_id, token);
+        let balance = self.read_string(&balance_key)?.ok_or(VaultError::ExpectedDataNotPresentError)?;
+        Ok(serde_json::from_str::<Balance>(&balance)?)
+    }
+}
+
+impl<Statistic, C> StatisticHandler<Statistic> for RedisVault<Statistic, C>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned,
+          C: ConnectionLike
+{
+    fn set_statistics(&mut self, exchange: Exchange, instrument: Instrument, statistic: Statistic) -> Result<(), VaultError>
+    {
+        let statistics_key = self.statistics_key(&exchange, &instrument);
+        self.conn.set::<_, _, ()>(statistics_key, serde_json::to_string(&statistic)?).map_err(|_| VaultError::WriteError)
+    }
+
+    fn get_statistics(&mut self, exchange: Exchange, instrument: Instrument) -> Result<Statistic, VaultError>
+    {
+        let statistics_key = self.statistics_key(&exchange, &instrument);
+        let statistic = self.read_string(&statistics_key)?.ok_or(VaultError::ExpectedDataNotPresentError)?;
+        Ok(serde_json::from_str::<Statistic>(&statistic)?)
     }
 }
 
 /// RedisVault 的构建器，使用泛型类型 `Statistic`，`Statistic` 必须实现 `PositionSummariser`、`Serialize` 和 `DeserializeOwned`。
-#[derive(Default)]
-pub struct RedisVaultBuilder<Statistic>
-    where Statistic: PositionSummariser + Serialize + DeserializeOwned
+pub struct RedisVaultBuilder<Statistic, C = Connection>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned,
+          C: ConnectionLike
 {
-    conn: Option<Connection>,                  // Redis 连接的可选值
+    conn: Option<C>,                           // Redis 连接的可选值
     config: Option<HourglassMode>,             // 添加配置选项
+    namespace: Option<String>,                 // 未设置时为 `default`
+    clear_on_build: bool,                      // 回测模式下构建时是否清除命名空间中的旧数据
     _statistic_marker: PhantomData<Statistic>, // 用于类型标记的幻象数据
 }
-impl<Statistic> RedisVaultBuilder<Statistic> where Statistic: PositionSummariser + Serialize + DeserializeOwned
+
+impl<Statistic, C> Default for RedisVaultBuilder<Statistic, C>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned,
+          C: ConnectionLike
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl<Statistic, C> RedisVaultBuilder<Statistic, C>
+    where Statistic: PositionSummariser + Serialize + DeserializeOwned,
+          C: ConnectionLike
 {
     /// 构造新的 RedisVaultBuilder 实例。
     pub fn new() -> Self
     {
         Self { conn: None,
                config: None, // 初始化配置为 None
+               namespace: None,
+               clear_on_build: true,
                _statistic_marker: PhantomData }
     }
 
     /// 设置 Redis 连接。
-    pub fn conn(mut self, value: Connection) -> Self
+    pub fn conn(mut self, value: C) -> Self
     {
         self.conn = Some(value);
         self
@@ -128,99 +382,319 @@ impl<Statistic> RedisVaultBuilder<Statistic> where Statistic: PositionSummariser
         self
     }
 
-    /// 构建 RedisVault 实例。
-    pub fn build(self) -> Result<RedisVault<Statistic>, ExchangeError>
+    /// 设置回测模式下键前缀中的命名空间，沿用同一个命名空间重新回测时会先清除其旧数据。
+    pub fn namespace(mut self, value: impl Into<String>) -> Self
+    {
+        self.namespace = Some(value.into());
+        self
+    }
+
+    /// 回测模式下构建时是否清除命名空间中的旧数据，默认清除。同一次回测的其他账户打开同一个命名空间时不应再清除。
+    pub fn clear_on_build(mut self, value: bool) -> Self
+    {
+        self.clear_on_build = value;
+        self
+    }
+
+    /// 构建 RedisVault 实例，并执行 [`RedisVault::perform_action_based_on_mode`]。
+    pub fn build(self) -> Result<RedisVault<Statistic, C>, ExchangeError>
     {
-        Ok(RedisVault { hourglass_mode: self.config.ok_or(RedisInitialisationError("config".to_string()))?, // 处理配置
-                        _statistic_marker: PhantomData,
-                        conn: self.conn.ok_or(RedisInitialisationError("connection".to_string()))? /* 处理连接 */ })
+        let mut vault = RedisVault { hourglass_mode: self.config.ok_or(RedisInitialisationError("config".to_string()))?, // 处理配置
+                                     namespace: self.namespace.unwrap_or_else(Config::default_namespace),
+                                     _statistic_marker: PhantomData,
+                                     conn: self.conn.ok_or(RedisInitialisationError("connection".to_string()))? /* 处理连接 */ };
+        if self.clear_on_build || vault.hourglass_mode == HourglassMode::Online {
+            vault.perform_action_based_on_mode().map_err(|error| RedisInitialisationError(error.to_string()))?;
+        }
+        Ok(vault)
     }
 }
 
-// impl<Statistic> PositionProcessor for RedisVault<Statistic>
-// where
-//     Statistic: PositionSummariser + Serialize + DeserializeOwned,
-// {
-//     fn add_open_position(&mut self, position: Position) -> Result<(), ExchangeError> {
-//         // 将 Position 对象序列化为 JSON 字符串
-//         let position_string = serde_json::to_string(&position)?;
-//
-//         // 将序列化后的 Position 存入 Redis
-//         self.conn
-//             .set(position.position_id.to_string(), position_string)
-//             .map_err(|_| ExchangeError::WriteError)
-//     }
-//
-//     fn get_open_position(
-//         &mut self,
-//         position_id: &PositionId,
-//     ) -> Result<Option<Position>, ExchangeError> {
-//         // 从 Redis 中获取与 position_id 关联的 Position 字符串
-//         let position_value: String = self
-//             .conn
-//             .get(position_id.to_string())
-//             .map_err(|_| ExchangeError::ReadError)?;
-//
-//         // 将 JSON 字符串反序列化为 Position 对象
-//         Ok(Some(serde_json::from_str::<Position>(&position_value)?))
-//     }
-//
-//     fn get_open_positions(
-//         &mut self,
-//         session_id: Uuid,
-//         markets: impl Iterator<Item = Market>,
-//     ) -> Result<Vec<Position>, ExchangeError> {
-//         // 根据 markets 迭代器获取所有打开的 Position
-//         markets
-//             .filter_map(|market| {
-//                 self.get_open_position(&determine_position_id(
-//                     session_id,
-//                     &market.exchange,
-//                     &market.instrument,
-//                 ))
-//                     .transpose()
-//             })
-//             .collect()
-//     }
-//
-//     fn remove_position(
-//         &mut self,
-//         position_id: &PositionId,
-//     ) -> Result<Option<Position>, ExchangeError> {
-//         // 获取并删除 Redis 中对应的 Position
-//         let account_positions = self.get_open_position(position_id)?;
-//
-//         self.conn
-//             .del(position_id.to_string())
-//             .map_err(|_| ExchangeError::DeleteError)?;
-//
-//         Ok(account_positions)
-//     }
-//
-//     fn set_exited_position(
-//         &mut self,
-//         session_id: Uuid,
-//         account_positions: Position,
-//     ) -> Result<(), ExchangeError> {
-//         // 将已退出的 Position 推入 Redis 列表
-//         self.conn
-//             .lpush(
-//                 determine_exited_positions_id(session_id),
-//                 serde_json::to_string(&account_positions)?,
-//             )
-//             .map_err(|_| ExchangeError::WriteError)
-//     }
-//
-//     fn get_exited_positions(&mut self, session_id: Uuid) -> Result<Vec<Position>, ExchangeError> {
-//         // 获取 Redis 列表中的所有已退出 Position
-//         let positions: Vec<String> = self.conn
-//             .lrange(determine_exited_positions_id(session_id), 0, -1)
-//             .map_err(|_| ExchangeError::ReadError)?;
-//
-//         positions
-//             .iter()
-//             .map(|position_str| serde_json::from_str::<Position>(position_str))
-//             .collect::<Result<Vec<Position>, serde_json::Error>>()
-//             .map_err(|_| ExchangeError::JsonSerDeError)
-//     }
-// }
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        common::{instrument::kind::InstrumentKind, Side},
+        test_utils::{create_test_future_position_with_side, create_test_perpetual_position},
+        vault::tests::PnlStatistic,
+    };
+    use redis::{Cmd, ErrorKind, RedisResult, Value};
+    use std::collections::{BTreeSet, HashMap};
+
+    /// 内存中的 Redis 替身，只实现仓库用到的命令。`rejected` 中的命令总是返回错误。
+    #[derive(Default, Clone)]
+    struct FakeRedis
+    {
+        strings: HashMap<String, String>,
+        sets: HashMap<String, BTreeSet<String>>,
+        lists: HashMap<String, Vec<String>>,
+        rejected: Option<&'static str>,
+        scanning: Vec<String>, // 从游标 0 开始时的全部键，之后的分页不受删除影响
+    }
+
+    /// 解析 RESP 编码的命令，只支持客户端发送的数组格式。
+    fn parse_packed(mut bytes: &[u8]) -> Vec<Vec<String>>
+    {
+        let line = |bytes: &mut &[u8]| {
+            let end = bytes.windows(2).position(|window| window == b"\r\n").unwrap();
+            let line = String::from_utf8_lossy(&bytes[1..end]).into_owned();
+            *bytes = &bytes[end + 2..];
+            line
+        };
+        let mut commands = Vec::new();
+        while !bytes.is_empty() {
+            let count: usize = line(&mut bytes).parse().unwrap();
+            let args = (0..count).map(|_| {
+                                     let len: usize = line(&mut bytes).parse().unwrap();
+                                     let arg = String::from_utf8_lossy(&bytes[..len]).into_owned();
+                                     bytes = &bytes[len + 2..];
+                                     arg
+                                 })
+                                 .collect();
+            commands.push(args);
+        }
+        commands
+    }
+
+    impl FakeRedis
+    {
+        /// 与 Redis 的 `EXEC` 一样依次执行全部命令，出错的命令不会回滚其他命令，错误在全部执行后返回。
+        fn transaction(&mut self, commands: Vec<Vec<String>>) -> RedisResult<Value>
+        {
+            let results: Vec<_> = commands.into_iter().map(|args| self.execute(args)).collect();
+            results.into_iter().collect::<RedisResult<Vec<_>>>().map(Value::Array)
+        }
+
+        fn execute(&mut self, args: Vec<String>) -> RedisResult<Value>
+        {
+            let bulk = |value: &String| Value::BulkString(value.clone().into_bytes());
+            if self.rejected.is_some_and(|rejected| args[0].eq_ignore_ascii_case(rejected)) {
+                return Err((ErrorKind::ResponseError, "rejected").into());
+            }
+            match (args[0].to_uppercase().as_str(), &args[1..]) {
+                | ("PING", _) => Ok(Value::SimpleString("PONG".to_string())),
+                | ("GET", [key]) => Ok(self.strings.get(key).map_or(Value::Nil, bulk)),
+                | ("SET", [key, value]) => {
+                    self.strings.insert(key.clone(), value.clone());
+                    Ok(Value::Okay)
+                }
+                | ("DEL", keys) => {
+                    let removed = keys.iter()
+                                      .filter(|key| self.strings.remove(*key).is_some() | self.sets.remove(*key).is_some() | self.lists.remove(*key).is_some())
+                                      .count();
+                    Ok(Value::Int(removed as i64))
+                }
+                | ("SADD", [key, members @ ..]) => {
+                    let set = self.sets.entry(key.clone()).or_default();
+                    Ok(Value::Int(members.iter().filter(|member| set.insert((*member).clone())).count() as i64))
+                }
+                | ("SREM", [key, members @ ..]) => {
+                    let set = self.sets.entry(key.clone()).or_default();
+                    Ok(Value::Int(members.iter().filter(|member| set.remove(*member)).count() as i64))
+                }
+                | ("SMEMBERS", [key]) => Ok(Value::Array(self.sets.get(key).map(|set| set.iter().map(bulk).collect()).unwrap_or_default())),
+                | ("RPUSH", [key, values @ ..]) => {
+                    let list = self.lists.entry(key.clone()).or_default();
+                    list.extend(values.iter().cloned());
+                    Ok(Value::Int(list.len() as i64))
+                }
+                | ("LRANGE", [key, _, _]) => Ok(Value::Array(self.lists.get(key).map(|list| list.iter().map(bulk).collect()).unwrap_or_default())),
+                | ("SCAN", [cursor, _, pattern, _, count]) => {
+                    // 与 Redis 一样先按 `COUNT` 分页再按模式过滤
+                    let (cursor, count): (usize, usize) = (cursor.parse().unwrap(), count.parse().unwrap());
+                    if cursor == 0 {
+                        let keys: BTreeSet<_> = self.strings.keys().chain(self.sets.keys()).chain(self.lists.keys()).cloned().collect();
+                        self.scanning = keys.into_iter().collect();
+                    }
+                    let prefix = pattern.trim_end_matches('*');
+                    let page = self.scanning.iter().skip(cursor).take(count).filter(|key| key.starts_with(prefix)).map(bulk).collect();
+                    let next = if cursor + count >= self.scanning.len() { 0 } else { cursor + count };
+                    Ok(Value::Array(vec![bulk(&next.to_string()), Value::Array(page)]))
+                }
+                | _ => Err((ErrorKind::ClientError, "unsupported command").into()),
+            }
+        }
+    }
+
+    impl ConnectionLike for FakeRedis
+    {
+        fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value>
+        {
+            let args = parse_packed(cmd).pop().unwrap();
+            self.execute(args)
+        }
+
+        fn req_packed_commands(&mut self, cmd: &[u8], offset: usize, count: usize) -> RedisResult<Vec<Value>>
+        {
+            let mut responses = Vec::new();
+            let mut queued: Option<Vec<Vec<String>>> = None;
+            for args in parse_packed(cmd) {
+                match (args[0].to_uppercase().as_str(), &mut queued) {
+                    | ("MULTI", _) => {
+                        queued = Some(Vec::new());
+                        responses.push(Value::Okay);
+                    }
+                    | ("EXEC", _) => {
+                        let commands = queued.take().unwrap_or_default();
+                        responses.push(self.transaction(commands)?);
+                    }
+                    | (_, Some(commands)) => {
+                        commands.push(args);
+                        responses.push(Value::SimpleString("QUEUED".to_string()));
+                    }
+                    | (_, None) => responses.push(self.execute(args)?),
+                }
+            }
+            Ok(responses.into_iter().skip(offset).take(count).collect())
+        }
+
+        fn req_command(&mut self, cmd: &Cmd) -> RedisResult<Value>
+        {
+            let args = cmd.args_iter()
+                          .map(|arg| match arg {
+                              | redis::Arg::Simple(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+                              | redis::Arg::Cursor => "0".to_string(),
+                          })
+                          .collect();
+            self.execute(args)
+        }
+
+        fn get_db(&self) -> i64
+        {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool
+        {
+            true
+        }
+
+        fn is_open(&self) -> bool
+        {
+            true
+        }
+    }
+
+    fn vault(conn: FakeRedis, mode: HourglassMode) -> RedisVault<PnlStatistic, FakeRedis>
+    {
+        RedisVault::builder().conn(conn).config(mode).build().unwrap()
+    }
+
+    #[test]
+    fn open_positions_should_be_indexed_by_session_and_market()
+    {
+        let mut vault = vault(FakeRedis::default(), HourglassMode::Backtest);
+        let session_id = Uuid::new_v4();
+        let instrument = Instrument::new("BTC", "USDT", InstrumentKind::Perpetual);
+        let perpetual = Position::Perpetual(create_test_perpetual_position(instrument.clone()));
+        let mut future = create_test_future_position_with_side(instrument.clone(), Side::Sell);
+        future.meta.position_id = PositionId(7);
+        let future = Position::Future(future);
+
+        vault.add_open_position(session_id, perpetual.clone()).unwrap();
+        vault.add_open_position(session_id, future.clone()).unwrap();
+
+        let fetched = vault.get_open_position(&perpetual.meta().position_id).unwrap().unwrap();
+        assert_eq!(fetched.meta(), perpetual.meta());
+        let open = vault.get_open_positions(session_id, Exchange::Hourglass, instrument.clone()).unwrap();
+        assert_eq!(open.iter().map(|position| position.meta().position_id.clone()).collect::<Vec<_>>(), vec![PositionId(7),
+                                                                                                             perpetual.meta()
+                                                                                                                      .position_id
+                                                                                                                      .clone()]);
+        // 其他 session 看不到这些仓位
+        assert!(vault.get_open_positions(Uuid::new_v4(), Exchange::Hourglass, instrument.clone()).unwrap().is_empty());
+
+        let removed = vault.remove_position(&PositionId(7)).unwrap().unwrap();
+        assert_eq!(removed.meta(), future.meta());
+        assert!(vault.get_open_position(&PositionId(7)).unwrap().is_none());
+        assert!(vault.remove_position(&PositionId(7)).unwrap().is_none());
+        assert_eq!(vault.get_open_positions(session_id, Exchange::Hourglass, instrument).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn exited_positions_balances_and_statistics_should_round_trip()
+    {
+        let mut vault = vault(FakeRedis::default(), HourglassMode::Backtest);
+        let session_id = Uuid::new_v4();
+        let instrument = Instrument::new("ETH", "USDT", InstrumentKind::Perpetual);
+        let mut first = create_test_perpetual_position(instrument.clone());
+        first.meta.realised_pnl = 10.0;
+        let mut second = first.clone();
+        second.meta.position_id = PositionId(2);
+        second.meta.realised_pnl = -4.0;
+
+        vault.set_exited_position(session_id, Position::Perpetual(first)).unwrap();
+        vault.set_exited_position(session_id, Position::Perpetual(second)).unwrap();
+        let exited = vault.get_exited_positions(session_id).unwrap();
+        assert_eq!(exited.iter().map(|position| position.meta().realised_pnl).collect::<Vec<_>>(), vec![10.0, -4.0]);
+        assert!(vault.get_exited_positions(Uuid::new_v4()).unwrap().is_empty());
+
+        assert!(matches!(vault.get_balance(session_id, &Token::usdt()), Err(VaultError::ExpectedDataNotPresentError)));
+        let balance = Balance::new(1000.0, 900.0);
+        vault.set_balance(session_id, &Token::usdt(), balance).unwrap();
+        assert_eq!(vault.get_balance(session_id, &Token::usdt()).unwrap(), balance);
+
+        let mut statistic = PnlStatistic::default();
+        statistic.generate_summary(&exited);
+        vault.set_statistics(Exchange::Hourglass, instrument.clone(), statistic).unwrap();
+        assert_eq!(vault.get_statistics(Exchange::Hourglass, instrument).unwrap(), PnlStatistic { positions: 2, realised_pnl: 6.0 });
+    }
+
+    #[test]
+    fn backtest_mode_should_start_from_empty_namespace()
+    {
+        let session_id = Uuid::new_v4();
+        let balance = Balance::new(100.0, 100.0);
+
+        let mut online = vault(FakeRedis::default(), HourglassMode::Online);
+        online.set_balance(session_id, &Token::usdt(), balance).unwrap();
+        // 同时运行的另一个回测
+        let mut other = RedisVault::<PnlStatistic, FakeRedis>::new(online.conn, HourglassMode::Backtest, "other");
+        other.set_balance(session_id, &Token::usdt(), balance).unwrap();
+
+        // 写入的键超过一批 `SCAN` 的数量
+        let mut backtest = RedisVault::<PnlStatistic, FakeRedis>::new(other.conn, HourglassMode::Backtest, "run");
+        for _ in 0..=SCAN_BATCH {
+            backtest.set_balance(Uuid::new_v4(), &Token::usdt(), balance).unwrap();
+        }
+        backtest.set_balance(session_id, &Token::usdt(), balance).unwrap();
+
+        // 同一次回测的其他账户打开命名空间时不清除
+        let mut backtest = RedisVault::<PnlStatistic, FakeRedis>::builder().conn(backtest.conn)
+                                                                           .config(HourglassMode::Backtest)
+                                                                           .namespace("run")
+                                                                           .clear_on_build(false)
+                                                                           .build()
+                                                                           .unwrap();
+        assert_eq!(backtest.get_balance(session_id, &Token::usdt()).unwrap(), balance);
+
+        // 沿用同一个命名空间重新回测时只清除它自己的数据，账户的 session 每次都不同也不影响
+        let mut backtest = RedisVault::<PnlStatistic, FakeRedis>::builder().conn(backtest.conn)
+                                                                           .config(HourglassMode::Backtest)
+                                                                           .namespace("run")
+                                                                           .build()
+                                                                           .unwrap();
+        assert!(matches!(backtest.get_balance(session_id, &Token::usdt()), Err(VaultError::ExpectedDataNotPresentError)));
+        assert_eq!(backtest.conn.strings.len(), 2);
+        let mut other = RedisVault::<PnlStatistic, FakeRedis>::new(backtest.conn, HourglassMode::Backtest, "other");
+        assert_eq!(other.get_balance(session_id, &Token::usdt()).unwrap(), balance);
+        let mut online = vault(other.conn, HourglassMode::Online);
+        assert_eq!(online.get_balance(session_id, &Token::usdt()).unwrap(), balance);
+    }
+
+    #[test]
+    fn failed_command_in_transaction_should_not_roll_back_the_others()
+    {
+        let mut vault = vault(FakeRedis { rejected: Some("SADD"),
+                                          ..FakeRedis::default() },
+                              HourglassMode::Backtest);
+        let instrument = Instrument::new("BTC", "USDT", InstrumentKind::Perpetual);
+        let position = Position::Perpetual(create_test_perpetual_position(instrument));
+
+        // 索引写入失败时返回错误，但事务中的其他命令已经执行
+        assert!(matches!(vault.add_open_position(Uuid::new_v4(), position.clone()), Err(VaultError::WriteError)));
+        assert!(vault.get_open_position(&position.meta().position_id).unwrap().is_some());
+        assert!(vault.conn.sets.is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::common::account_positions::Position;
+use crate::{common::account_positions::Position, dashboard::metrics::EquitySnapshot};
 
 pub trait PositionSummariser: Copy
 {
@@ -10,3 +10,12 @@ pub trait PositionSummariser: Copy
         }
     }
 }
+
+/// 账户仓库的统计量，沿用看板中的权益快照汇总规则。
+impl PositionSummariser for EquitySnapshot
+{
+    fn update(&mut self, position: &Position)
+    {
+        crate::dashboard::summary::PositionSummariser::update(self, position)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::util::{initial_balances, order_cancel_request, order_request_limit, run_sample_online_exchange};
+use hourglass::{
+    common::{
+        event::{AccountEvent, AccountEventKind},
+        instrument::{kind::InstrumentKind, Instrument},
+        order::identification::client_order_id::ClientOrderId,
+        token::Token,
+        Side,
+    },
+    hourglass::hourglass_client_remote_mode::{HourglassRemoteClient, RemoteClientConfig},
+    network::is_port_in_use,
+    ClientExecution,
+};
+use std::{net::TcpListener, time::Duration};
+use tokio::{sync::mpsc, time};
+
+pub mod util;
+
+/// 找一个空闲端口，并等待在线模式的服务器开始监听
+async fn spawn_online_exchange(event_account_tx: mpsc::UnboundedSender<AccountEvent>) -> String
+{
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    tokio::spawn(run_sample_online_exchange(event_account_tx, ([127, 0, 0, 1], port)));
+    time::timeout(Duration::from_secs(5), async {
+        while !is_port_in_use(([127, 0, 0, 1], port)) {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await
+      .expect("Online exchange did not start listening");
+    format!("http://127.0.0.1:{}", port)
+}
+
+/// 等待第一条满足条件的账户事件
+async fn next_event_matching(account_rx: &mut mpsc::UnboundedReceiver<AccountEvent>, predicate: impl Fn(&AccountEventKind) -> bool) -> AccountEvent
+{
+    time::timeout(Duration::from_secs(5), async {
+        loop {
+            let event = account_rx.recv().await.expect("Account event stream closed");
+            if predicate(&event.kind) {
+                return event;
+            }
+        }
+    }).await
+      .expect("Did not receive expected account event")
+}
+
+#[tokio::test]
+async fn remote_client_should_trade_against_online_exchange()
+{
+    // 服务端自己的账户事件接收端需要保持存活
+    let (server_account_tx, _server_account_rx) = mpsc::unbounded_channel();
+    let base_url = spawn_online_exchange(server_account_tx).await;
+
+    let (account_tx, mut account_rx) = mpsc::unbounded_channel();
+    let mut client = HourglassRemoteClient::init(RemoteClientConfig { base_url,
+                                                                      market_symbols: vec!["ETHUSDT".to_string()] },
+                                                 account_tx).await;
+    let instrument = Instrument::from(("ETH", "USDT", InstrumentKind::Perpetual));
+
+    // 1. 初始挂单只有示例账户中的一张
+    let orders = client.fetch_orders_open().await.unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].cid, Some(ClientOrderId("test_cid".to_string())));
+
+    // 2. 余额与示例账户一致
+    let balances = client.fetch_balances().await.unwrap();
+    let initial_balances = initial_balances().await;
+    assert_eq!(balances.len(), initial_balances.len());
+    for balance in &balances {
+        assert_eq!(balance.balance.total, initial_balances[&balance.token].total);
+    }
+
+    // 3. 登录后推送连接才能收到账户事件
+    client.register("alice".to_string(), "alice@example.com".to_string(), "secret".to_string()).await.unwrap();
+    client.login("alice".to_string(), "secret".to_string()).await.unwrap();
+
+    // 4. 开单的结果通过 REST 返回，账户事件通过 WebSocket 推送
+    let cid = ClientOrderId("remote_cid".to_string());
+    let opened = client.open_orders(vec![order_request_limit(instrument.clone(), cid.clone(), Side::Buy, 16499.0, 1.0)]).await;
+    assert_eq!(opened.len(), 1);
+    let opened = opened[0].clone().unwrap();
+    assert_eq!(opened.cid, Some(cid.clone()));
+    let event = next_event_matching(&mut account_rx, |kind| matches!(kind, AccountEventKind::OrdersOpen(_))).await;
+    assert!(matches!(event.kind, AccountEventKind::OrdersOpen(orders) if orders[0].cid == Some(cid.clone())));
+
+    // 5. 撤销刚才的订单
+    let cancelled = client.cancel_orders(vec![order_cancel_request(instrument.clone(), cid.clone(), Side::Buy, opened.state.id.clone())]).await;
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0].clone().unwrap().state.id, opened.state.id);
+
+    // 6. 仓位查询
+    assert!(client.fetch_long_position(instrument.clone()).await.unwrap().is_none());
+    client.fetch_all_positions().await.unwrap();
+
+    // 7. 充值后余额增加
+    let deposited = client.deposit_tokens(vec![(Token::from("USDT"), 100.0)]).await.unwrap();
+    assert_eq!(deposited[0].balance.total, initial_balances[&Token::from("USDT")].total + 100.0);
+
+    // 8. 撤销剩余的全部挂单
+    let cancelled = client.cancel_orders_all().await.unwrap();
+    assert_eq!(cancelled.len(), 1);
+    assert!(client.fetch_orders_open().await.unwrap().is_empty());
+}
@@ -23,10 +23,12 @@ use hourglass::{
         token::Token,
         Side,
     },
+    dashboard::summary::attribution::PnLAttribution,
     hourglass::{
         account::{
             account_latency::{AccountLatency, FluctuationMode},
             account_orders::AccountOrders,
+            account_vault::AccountVault,
             HourglassAccount,
         },
         clickhouse_api::{
@@ -34,14 +36,17 @@ use hourglass::{
             queries_operations::ClickHouseClient,
         },
         hourglass_client_local_mode::HourglassClientEvent,
+        simulation::{SimClock, SimRng},
         DataSource, HourglassExchange,
     },
+    network::login::{InMemoryUserStore, TEST_PASSWORD_COST},
     test_utils::create_test_account_configuration,
+    vault::{in_memory::InMemoryVault, Vault},
     Exchange,
 };
 
-/// Initializes and runs a sample exchange with predefined settings and a test order.
-pub async fn run_sample_exchange(event_account_tx: mpsc::UnboundedSender<AccountEvent>, event_hourglass_rx: mpsc::UnboundedReceiver<HourglassClientEvent>, market_event_tx: mpsc::UnboundedSender<MarketTrade>)
+/// Builds the sample account with predefined balances and a test order.
+pub async fn sample_account(event_account_tx: mpsc::UnboundedSender<AccountEvent>) -> Arc<Mutex<HourglassAccount>>
 {
     // Creating initial balances
     let balances = DashMap::new();
@@ -91,18 +96,31 @@ pub async fn run_sample_exchange(event_account_tx: mpsc::UnboundedSender<Account
                                                            latest_price: 0.0 });
 
     // Instantiate HourglassAccount and wrap in Arc<Mutex> for shared access
-    let account_arc = Arc::new(Mutex::new(HourglassAccount { current_session: Uuid::new_v4(),
-                                                             machine_id: 0,
-                                                             client_trade_counter: 0.into(),
-                                                             exchange_timestamp: AtomicI64::new(1234567),
-                                                             config: create_test_account_configuration(),
-                                                             account_open_book: orders_arc,
-                                                             single_level_order_book: Arc::new(Mutex::new(single_level_order_books)),
-                                                             balances,
-                                                             positions,
-                                                             exited_positions: closed_positions,
-                                                             account_event_tx: event_account_tx,
-                                                             account_margin: Arc::new(Default::default()) }));
+    Arc::new(Mutex::new(HourglassAccount { current_session: Uuid::new_v4(),
+                                           machine_id: 0,
+                                           client_trade_counter: 0.into(),
+                                           exchange_timestamp: Arc::new(AtomicI64::new(1234567)),
+                                           config: create_test_account_configuration(),
+                                           account_open_book: orders_arc,
+                                           single_level_order_book: Arc::new(Mutex::new(single_level_order_books)),
+                                           balances,
+                                           positions,
+                                           exited_positions: closed_positions,
+                                           vault: AccountVault::new(Vault::InMemory(InMemoryVault::new())),
+                                           account_event_tx: event_account_tx,
+                                           account_margin: Arc::new(Default::default()),
+                                           rng: SimRng::from_entropy(),
+                                           clock: SimClock::System,
+                                           equity_tracker: None,
+                                           pnl_attribution: PnLAttribution::default(),
+                                           publish_positions: false }))
+}
+
+/// Initializes and runs a sample exchange with predefined settings and a test order.
+#[allow(dead_code)]
+pub async fn run_sample_exchange(event_account_tx: mpsc::UnboundedSender<AccountEvent>, event_hourglass_rx: mpsc::UnboundedReceiver<HourglassClientEvent>, market_event_tx: mpsc::UnboundedSender<MarketTrade>)
+{
+    let account_arc = sample_account(event_account_tx).await;
     let clickhouse_client = ClickHouseClient::new();
     let exchange = "binance";
     let instrument = "futures";
@@ -122,6 +140,28 @@ pub async fn run_sample_exchange(event_account_tx: mpsc::UnboundedSender<Account
     println!("[run_default_exchange] : Hourglass exchange run successfully on local mode.");
 }
 
+/// Runs the sample exchange in online mode on `address`, fed by a real-time source that never sends data.
+#[allow(dead_code)]
+pub async fn run_sample_online_exchange(event_account_tx: mpsc::UnboundedSender<AccountEvent>, address: ([u8; 4], u16))
+{
+    let account_arc = sample_account(event_account_tx).await;
+    // Keep both senders alive so the exchange keeps serving until the test ends
+    let (_event_hourglass_tx, event_hourglass_rx) = mpsc::unbounded_channel();
+    let (_feed_tx, feed_rx) = mpsc::unbounded_channel();
+    let (market_event_tx, _market_event_rx) = mpsc::unbounded_channel();
+
+    let hourglass_exchange = HourglassExchange::builder().event_hourglass_rx(event_hourglass_rx)
+                                                         .account(account_arc)
+                                                         .market_event_tx(market_event_tx)
+                                                         .data_source(DataSource::RealTime(feed_rx))
+                                                         .user_store(Arc::new(InMemoryUserStore::new()))
+                                                         .password_cost(TEST_PASSWORD_COST)
+                                                         .initiate()
+                                                         .expect("Failed to build HourglassExchange");
+
+    hourglass_exchange.run_online_at(address).await;
+}
+
 /// 定义沙箱交易所支持的Instrument
 #[allow(dead_code)]
 pub async fn initial_balances() -> HashMap<Token, Balance>